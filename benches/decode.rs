@@ -0,0 +1,83 @@
+//! Measures decoding the lines of an ASCII-only filing held in memory with `decode_line`, which
+//! borrows lines that are valid UTF-8, against the deprecated `decode_line_owned`, which copies
+//! each one, and parsing the whole filing with `parse_fec`.
+//!
+//! The filing is 10 MB by default; set `FEC_BENCH_BYTES` to bench a different size:
+//!
+//! ```sh
+//! cargo bench --bench decode
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::io::Cursor;
+
+#[allow(deprecated)]
+use fast_fec_rust::encoding::{decode_line, decode_line_owned};
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::writer::WriterContext;
+
+const DEFAULT_BENCH_BYTES: usize = 10 * 1000 * 1000;
+
+/// A comma-delimited, ASCII-only filing of at least `bytes` bytes.
+fn filing(bytes: usize) -> Vec<u8> {
+    let mut out = b"HDR,FEC,8.3,Vendor,1.0,,001,\n".to_vec();
+    out.extend_from_slice(b"F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1\n");
+    let mut i = 0;
+    while out.len() < bytes {
+        out.extend_from_slice(format!(
+            "SA11AI,C00123456,SA11AI.{i},,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,{i}.00,,TEACHER,SCHOOL\n"
+        ).as_bytes());
+        i += 1;
+    }
+    out
+}
+
+/// Parse `data` without writing any output.
+fn parse(data: &[u8]) {
+    let mut ctx = FecContext::new("bench".to_string(), false, true, false);
+    let mut writer = WriterContext::builder()
+        .filing_id("bench")
+        .write_to_disk(false)
+        .buffer_size(1024 * 1024)
+        .discard_output()
+        .build()
+        .expect("a discarding writer should build");
+    parse_fec(&mut ctx, &mut Cursor::new(data), &mut writer)
+        .expect("the synthetic filing should parse");
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let bytes = std::env::var("FEC_BENCH_BYTES")
+        .ok()
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(DEFAULT_BENCH_BYTES);
+    let data = filing(bytes);
+
+    let mut group = c.benchmark_group("decode");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("decode_line", |b| {
+        b.iter(|| {
+            data.split_inclusive(|b| *b == b'\n')
+                .map(|line| decode_line(line).0.len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("decode_line_owned", |b| {
+        b.iter(|| {
+            data.split_inclusive(|b| *b == b'\n')
+                .map(|line| {
+                    #[allow(deprecated)]
+                    let (decoded, _) = decode_line_owned(line);
+                    decoded.len()
+                })
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("parse_fec", |b| b.iter(|| parse(&data)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+//! Compares parsing a large synthetic filing read through a `BufReader` with reading it from a
+//! memory map.
+//!
+//! The filing is 1 GiB by default; set `FEC_BENCH_BYTES` to bench a different size:
+//!
+//! ```sh
+//! FEC_BENCH_BYTES=100000000 cargo bench --features mmap --bench input
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::input::MappedFile;
+use fast_fec_rust::writer::WriterContext;
+
+const DEFAULT_BENCH_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Write a comma-delimited filing of at least `bytes` bytes to `path`.
+fn write_filing(path: &Path, bytes: u64) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "HDR,FEC,8.3,Vendor,1.0,,001,")?;
+    writeln!(
+        out,
+        "F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1"
+    )?;
+    let mut written = 0;
+    let mut i = 0;
+    while written < bytes {
+        let mut line = format!(
+            "SA11AI,C00123456,SA11AI.{i},,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,{i}.00,,TEACHER,SCHOOL\n"
+        );
+        if i % 4 == 0 {
+            line.push_str(&format!(
+                "SB23,C00123456,SB23.{i},,,ORG,PRINT SHOP LLC,,,,4 MAPLE ST,,ATLANTA,GA,30303,20230116,1200.00,PRINTING\n"
+            ));
+        }
+        out.write_all(line.as_bytes())?;
+        written += line.len() as u64;
+        i += 1;
+    }
+    out.flush()
+}
+
+/// Parse the filing read through `reader` without writing any output.
+fn parse<R: BufRead>(mut reader: R) {
+    let mut ctx = FecContext::new("bench".to_string(), false, true, false);
+    let mut writer = WriterContext::builder()
+        .filing_id("bench")
+        .write_to_disk(false)
+        .buffer_size(1024 * 1024)
+        .discard_output()
+        .build()
+        .expect("a discarding writer should build");
+    parse_fec(&mut ctx, &mut reader, &mut writer).expect("the synthetic filing should parse");
+}
+
+fn bench_input(c: &mut Criterion) {
+    let bytes = std::env::var("FEC_BENCH_BYTES")
+        .ok()
+        .and_then(|b| b.parse().ok())
+        .unwrap_or(DEFAULT_BENCH_BYTES);
+    let dir = tempfile::tempdir().expect("failed to create a temporary directory");
+    let path = dir.path().join("synthetic.fec");
+    write_filing(&path, bytes).expect("failed to write the synthetic filing");
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    let mut group = c.benchmark_group("input");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(size));
+    group.bench_function("bufreader", |b| {
+        b.iter(|| parse(BufReader::new(File::open(&path).unwrap())))
+    });
+    group.bench_function("mmap", |b| {
+        b.iter(|| {
+            let file = File::open(&path).unwrap();
+            parse(MappedFile::new(&file).expect("the filing should be mappable"))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_input);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+//! Measures writing rows with `WriterContext::write_csv_record`, the writer's hot path, for
+//! plain rows and rows whose fields need quoting, flushing full buffers of a small and a large
+//! `buffer_size`, and single writes far larger than the buffer (e.g. long F99 texts).
+//!
+//! ```sh
+//! cargo bench --bench writer
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use fast_fec_rust::writer::WriterContext;
+
+const ROWS: usize = 100_000;
+/// How many bytes the flush benchmarks write, in 64 KiB chunks.
+const FLUSHED_BYTES: usize = 256 * 1024 * 1024;
+/// How many bytes each write of the large write benchmark writes.
+const LARGE_WRITE_BYTES: usize = 10 * 1024 * 1024;
+/// How many large writes each iteration makes.
+const LARGE_WRITES: usize = 8;
+
+/// A Schedule A row, its fields quoted if `quoted` is set.
+fn row(i: usize, quoted: bool) -> Vec<String> {
+    let name = if quoted { "SMITH, \"JJ\"" } else { "SMITH" };
+    let memo = if quoted { "LINE ONE\nLINE TWO" } else { "" };
+    [
+        "SA11AI",
+        "C00123456",
+        &format!("SA11AI.{i}"),
+        "IND",
+        name,
+        "JANE",
+        "1 ELM ST",
+        "DECATUR",
+        "GA",
+        "30030",
+        "20230115",
+        "250.00",
+        &format!("{i}.00"),
+        "TEACHER",
+        memo,
+    ]
+    .iter()
+    .map(|field| field.to_string())
+    .collect()
+}
+
+fn bench_writer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_csv_record");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (name, quoted) in [("plain", false), ("quoted", true)] {
+        let rows: Vec<Vec<String>> = (0..ROWS).map(|i| row(i, quoted)).collect();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut writer = WriterContext::builder()
+                    .write_to_disk(false)
+                    .buffer_size(64 * 1024)
+                    .custom_write_fn(|_, _, _| Ok(()))
+                    .build()
+                    .expect("the writer should build");
+                for row in &rows {
+                    writer
+                        .write_csv_record("SA", row)
+                        .expect("the row should be written");
+                }
+                writer.flush_all().expect("the rows should be flushed");
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_flush(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flush_buffer");
+    let chunk = "x".repeat(64 * 1024);
+    for buffer_size in [64 * 1024, 4 * 1024 * 1024] {
+        group.throughput(Throughput::Bytes(FLUSHED_BYTES as u64));
+        group.bench_function(format!("{}_KiB_buffer", buffer_size / 1024), |b| {
+            b.iter(|| {
+                let mut writer = WriterContext::builder()
+                    .write_to_disk(false)
+                    .buffer_size(buffer_size)
+                    .custom_write_fn(|_, _, _| Ok(()))
+                    .build()
+                    .expect("the writer should build");
+                for _ in 0..FLUSHED_BYTES / chunk.len() {
+                    writer
+                        .write_string("F99", "txt", &chunk)
+                        .expect("the chunk should be written");
+                }
+                writer.flush_all().expect("the chunks should be flushed");
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_large_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("large_write");
+    let text = "x".repeat(LARGE_WRITE_BYTES);
+    group.throughput(Throughput::Bytes((LARGE_WRITE_BYTES * LARGE_WRITES) as u64));
+    group.bench_function("10_MiB_writes", |b| {
+        b.iter(|| {
+            let mut writer = WriterContext::builder()
+                .write_to_disk(false)
+                .buffer_size(64 * 1024)
+                .custom_write_fn(|_, _, _| Ok(()))
+                .build()
+                .expect("the writer should build");
+            for _ in 0..LARGE_WRITES {
+                writer
+                    .write_string("F99", "txt", &text)
+                    .expect("the text should be written");
+            }
+            writer.flush_all().expect("the text should be flushed");
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_writer, bench_flush, bench_large_write);
+criterion_main!(benches);
@@ -2,107 +2,750 @@
 //!
 //! Uses `clap` to parse command-line arguments and return a `CliConfig`.
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{Arg, ArgAction, Command};
-use atty;
+use std::ffi::OsString;
+
+use crate::encoding::FallbackEncoding;
+use crate::fec::context::DEFAULT_MAX_LINE_LENGTH;
+use crate::writer::{
+    Compression, FilenameTemplate, OutputFormat, OutputMode, QuoteStyle, Terminator,
+};
+
+/// The buffer size used when `--buffer-size` is not given.
+pub const DEFAULT_BUFFER_SIZE: usize = 4096;
+
+/// The output directory used when `--output-directory` is not given.
+pub const DEFAULT_OUTPUT_DIRECTORY: &str = "output";
+
+/// Where filings are downloaded from when `--base-url` is not given (`http` feature).
+pub const DEFAULT_BASE_URL: &str = "https://docquery.fec.gov/dcdev/posted";
 
 /// A struct representing parsed command-line arguments.
 #[derive(Debug, PartialEq)] // Derive Debug and PartialEq
 pub struct CliConfig {
-    pub fec_id: String,           // Filing ID or file path
-    pub include_filing_id: bool,  // Whether to include a filing_id column
-    pub silent: bool,             // Suppress output messages
-    pub warn: bool,               // Show warning messages
-    pub use_stdin: bool,          // Whether to read from STDIN
-    pub show_usage: bool,         // Whether to show usage/help
-    pub output_directory: String, // Directory for output files
-    pub write_to_disk: bool,      // Whether to write output to disk
-    pub buffer_size: usize,       // Buffer size for WriterContext
+    pub fec_id: String,             // Filing ID or file path
+    pub include_filing_id: bool,    // Whether to include a filing_id column
+    pub row_numbers: bool,          // Whether to include a source_line column
+    pub silent: bool,               // Suppress output messages
+    pub warn: bool,                 // Show warning messages
+    pub verbose: bool,              // Also print the writer's flushes and I/O times
+    pub use_stdin: bool,            // Whether to read from STDIN
+    pub show_usage: bool,           // Whether to show usage/help
+    pub output_directory: String,   // Directory for output files
+    pub write_to_disk: bool,        // Whether to write output to disk
+    pub buffer_size: usize,         // Buffer size for WriterContext
+    pub compression: Compression,   // How output files are compressed
+    pub level: Option<i32>,         // Compression level (None = default)
+    pub format: OutputFormat,       // Format rows are written in
+    pub output_delimiter: u8,       // Byte separating the fields of CSV rows
+    pub terminator: Terminator,     // Line break ending each row
+    pub quote_style: QuoteStyle,    // Which fields of CSV rows are quoted
+    pub replace_with: Option<char>, // Replaces what unquoted fields can't hold
+    pub null_token: Option<String>, // Token written for empty fields
+    pub output: Option<String>,     // Database file for sqlite output
+    pub output_mode: OutputMode,    // What happens to existing output files
+    pub manifest: bool,             // Write manifest.csv listing each filing's files
+    pub atomic: bool,               // Write to temp files, renamed once parsed
+    pub fsync: bool,                // Sync output files to disk once written
+    pub min_free: Option<u64>,      // Free disk space needed before parsing
+    pub max_rows: Option<usize>,    // Rows per output file before splitting it
+    pub max_bytes: Option<u64>,     // Bytes per output file before splitting it
+    pub template: Option<String>,   // Where output files go under the output directory
+    pub summary: bool,              // Only parse the header and cover record
+    pub skip: usize,                // Lines after the header to skip unparsed
+    pub limit: usize,               // Maximum records to parse (0 = unlimited)
+    pub max_line_length: usize,     // Longest line read before truncating it
+    pub strict: bool,               // Fail on malformed input instead of warning
+    pub fallback: FallbackEncoding, // What lines that aren't UTF-8 are read as
+    pub base_url: String,           // Where filings are downloaded from by ID
+    pub threads: usize,             // Threads to parse on (1 = sequential)
+    pub print: bool,                // Stream rows to stdout instead of files
+    pub print_form: Option<String>, // Only print rows of this form
+    pub forms: Vec<String>,         // Only write rows of these forms (empty = all)
+    pub combined: bool,             // Write every form's rows to one filing.csv
+    pub ragged: bool,               // Don't pad short rows to their columns
+    pub validate: bool,             // Only report problems, writing no output
+    pub f99_text: bool,             // Also write F99 text blocks to F99.txt
+    pub full_form_names: bool,      // Name output files by full form code
+    pub raw_headers: bool,          // Keep header row column names as mapped
+    pub paper: bool,                // Map rows as a paper filing conversion
+    pub truncate: bool,             // Drop fields past a form's mapped columns
+    pub map_csv_exports: bool,      // Read CSV exports by column name
+    pub single_filing: bool,        // Don't split concatenated filings apart
+}
+
+impl Default for CliConfig {
+    /// The configuration produced when no arguments are given and STDIN is not piped.
+    fn default() -> Self {
+        CliConfig {
+            fec_id: String::new(),
+            include_filing_id: false,
+            row_numbers: false,
+            silent: false,
+            warn: false,
+            verbose: false,
+            use_stdin: false,
+            show_usage: false,
+            output_directory: DEFAULT_OUTPUT_DIRECTORY.to_string(),
+            write_to_disk: false,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            compression: Compression::None,
+            level: None,
+            format: OutputFormat::Csv,
+            output_delimiter: b',',
+            terminator: Terminator::Lf,
+            quote_style: QuoteStyle::Necessary,
+            replace_with: None,
+            null_token: None,
+            output: None,
+            output_mode: OutputMode::Truncate,
+            manifest: false,
+            atomic: false,
+            fsync: false,
+            min_free: None,
+            max_rows: None,
+            max_bytes: None,
+            template: None,
+            summary: false,
+            skip: 0,
+            limit: 0,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            strict: false,
+            fallback: FallbackEncoding::Windows1252,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            threads: 1,
+            print: false,
+            print_form: None,
+            forms: Vec::new(),
+            combined: false,
+            ragged: false,
+            validate: false,
+            f99_text: false,
+            full_form_names: false,
+            raw_headers: false,
+            paper: false,
+            truncate: false,
+            map_csv_exports: false,
+            single_filing: false,
+        }
+    }
+}
+
+/// Build the `clap` command describing every supported argument.
+pub fn build_command() -> Command {
+    let command = Command::new("fast-fec-rust")
+        .version("0.1.0")
+        .about("Rust port of FastFEC with no persistent memory context")
+        .arg(
+            Arg::new("filing-id-or-file")
+                .help("Filing ID or file path")
+                .required(false)
+                .index(1),
+        )
+        .arg(
+            Arg::new("include-filing-id")
+                .long("include-filing-id")
+                .short('f')
+                .help("Include filing_id, is_amendment and original_filing_id columns in the output CSV")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("row-numbers")
+                .long("row-numbers")
+                .help("Include a source_line column with each row's line in the input")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("silent")
+                .long("silent")
+                .short('s')
+                .help("Suppress output messages")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("warn")
+                .long("warn")
+                .short('w')
+                .help("Show warning messages")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .help("Also print how often each output file was flushed and the time spent writing, after the run summary")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("disable-stdin")
+                .long("disable-stdin")
+                .help("Force reading from a file even if STDIN is piped")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("usage")
+                .long("usage")
+                .help("Show usage information")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output-directory")
+                .long("output-directory")
+                .short('o')
+                .help("Specify the directory for output files, or an s3:// or gs:// URL to upload them to (`object_store` feature) (default: 'output')")
+                .default_value(DEFAULT_OUTPUT_DIRECTORY),
+        )
+        .arg(
+            Arg::new("write-to-disk")
+                .long("write-to-disk")
+                .help("Write output to disk (default: true)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("buffer-size")
+                .long("buffer-size")
+                .help("Set the buffer size for WriterContext (default: 4096)")
+                .default_value("4096"),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Write rows as csv (SA.csv), ndjson (SA.ndjson, one JSON object per row) or sqlite (one table per form) (default: csv)"),
+        )
+        .arg(
+            Arg::new("output-delimiter")
+                .long("output-delimiter")
+                .value_name("DELIMITER")
+                .help("Separate CSV fields with tab (SA.tsv), or any other single character (default: ,)"),
+        )
+        .arg(
+            Arg::new("quote-style")
+                .long("quote-style")
+                .value_name("STYLE")
+                .help("Quote always (every field), necessary (fields needing it) or never (default: necessary)"),
+        )
+        .arg(
+            Arg::new("replace-unquotable")
+                .long("replace-unquotable")
+                .value_name("CHAR")
+                .help("With --quote-style never, replace delimiters and line breaks in fields with CHAR instead of failing"),
+        )
+        .arg(
+            Arg::new("null-string")
+                .long("null-string")
+                .value_name("TOKEN")
+                .help("Write empty fields as TOKEN, unquoted, e.g. NULL or \\N (default: nothing)"),
+        )
+        .arg(
+            Arg::new("crlf")
+                .long("crlf")
+                .help("End rows and F99 text lines with CRLF instead of LF")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .value_name("FILE")
+                .help("Database file sqlite output is written to (default: filings.db in the output directory)"),
+        )
+        .arg(
+            Arg::new("compress")
+                .long("compress")
+                .value_name("FORMAT")
+                .help("Compress output files: gzip (SA.csv.gz), zstd (SA.csv.zst) or none (default: none)"),
+        )
+        .arg(
+            Arg::new("compress-level")
+                .long("compress-level")
+                .value_name("LEVEL")
+                .help("Compression level: 0-9 for gzip, 1-22 for zstd (default: the compression's own)"),
+        )
+        .arg(
+            Arg::new("overwrite")
+                .long("overwrite")
+                .help("Replace output files left by an earlier run (the default)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("append")
+                .long("append")
+                .help("Add rows to output files left by an earlier run instead of replacing them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-overwrite")
+                .long("no-overwrite")
+                .help("Fail before parsing if output files from an earlier run exist")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("summary")
+                .long("summary")
+                .help("Only parse the header and cover record, then stop reading")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("skip")
+                .long("skip")
+                .help("Skip this many lines after the header without parsing them (default: 0)")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .help("Stop after parsing this many records; 0 means no limit (default: 0)")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::new("max-line-length")
+                .long("max-line-length")
+                .help("Truncate lines longer than this many bytes (default: 67108864)"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .help("Fail on the first malformed line instead of warning about it")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fallback-encoding")
+                .long("fallback-encoding")
+                .value_name("ENCODING")
+                .help("Read lines that aren't valid UTF-8 as windows-1252, latin1 or utf8-lossy, or fail on them with error (default: windows-1252)"),
+        )
+        .arg(
+            Arg::new("threads")
+                .long("threads")
+                .help("Parse the filing body on this many threads (default: 1)")
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("print")
+                .long("print")
+                .value_name("FORM")
+                .num_args(0..=1)
+                .require_equals(true)
+                .help("Stream rows as CSV to stdout instead of writing files, optionally only one form's (--print=FORM)"),
+        )
+        .arg(
+            Arg::new("forms")
+                .long("forms")
+                .value_name("FORMS")
+                .help("Only write rows of these comma-separated forms (e.g. SA,SB), besides cover records"),
+        )
+        .arg(
+            Arg::new("combined")
+                .long("combined")
+                .help("Write every form's rows to one filing.csv, each led by its form, under a generic form_type,col_1..col_N header")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ragged")
+                .long("ragged")
+                .help("Leave short rows with the fields they were filed with instead of padding them to their form's columns (with --combined, the widest form's)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("validate")
+                .long("validate")
+                .help("Check the filing and list its problems without writing any output")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("manifest")
+                .long("manifest")
+                .help("Also write manifest.csv to each filing's directory, listing its files with their row counts and sizes")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("filename-template")
+                .long("filename-template")
+                .value_name("TEMPLATE")
+                .help("Name output files by TEMPLATE under the output directory, from {filing_id}, {form}, {extension} and {date} (default: {filing_id}/{form}.{extension})"),
+        )
+        .arg(
+            Arg::new("max-rows-per-file")
+                .long("max-rows-per-file")
+                .value_name("N")
+                .help("Split row files every N rows, continuing in SA_002.csv, SA_003.csv and so on, each with the header row"),
+        )
+        .arg(
+            Arg::new("max-bytes-per-file")
+                .long("max-bytes-per-file")
+                .value_name("BYTES")
+                .help("Split row files before a row that would take them past BYTES bytes (before compression)"),
+        )
+        .arg(
+            Arg::new("fsync")
+                .long("fsync")
+                .help("Sync output files and their directories to disk as they're flushed and closed, before exiting")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("min-free-space")
+                .long("min-free-space")
+                .value_name("BYTES")
+                .help("Fail before parsing unless at least BYTES bytes of disk space are free in the output directory"),
+        )
+        .arg(
+            Arg::new("atomic")
+                .long("atomic")
+                .help("Write output files under temporary .tmp names, renamed only once the filing is parsed")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("f99-text")
+                .long("f99-text")
+                .help("Also write F99 text blocks verbatim to F99.txt")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("full-form-names")
+                .long("full-form-names")
+                .help("Name output files by full form code (SA11AI.csv) instead of schedule (SA.csv)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("paper")
+                .long("paper")
+                .help("Map rows with the paper filing conversion layouts, whatever version the header names")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("raw-headers")
+                .long("raw-headers")
+                .help("Keep header row column names as mapped instead of lowercasing them and turning spaces and punctuation into underscores")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("truncate-extra-fields")
+                .long("truncate-extra-fields")
+                .help("Drop fields past the columns of a row's form, warning about what is dropped")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("map-csv-exports")
+                .long("map-csv-exports")
+                .help("Read CSV exports with a column-name header row by column name instead of failing")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("single-filing")
+                .long("single-filing")
+                .help("Read rows after a second HDR record as part of the first filing instead of a new one")
+                .action(ArgAction::SetTrue),
+        );
+
+    #[cfg(feature = "http")]
+    let command = command.arg(
+        Arg::new("base-url")
+            .long("base-url")
+            .help("Download numeric filing IDs from this URL")
+            .default_value(DEFAULT_BASE_URL),
+    );
+
+    command
 }
 
 /// Parse command-line arguments and return a `CliConfig`.
 pub fn parse_args() -> Result<CliConfig> {
-     // Use Clap to define arguments and flags.
-     let matches = Command::new("fast-fec-rust")
-         .version("0.1.0")
-         .about("Rust port of FastFEC with no persistent memory context")
-         .arg(Arg::new("filing-id-or-file")
-              .help("Filing ID or file path")
-              .required(false)
-              .index(1))
-         .arg(Arg::new("include-filing-id")
-              .long("include-filing-id")
-              .short('f')
-              .help("Include a filing_id column in the output CSV")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("silent")
-              .long("silent")
-              .short('s')
-              .help("Suppress output messages")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("warn")
-              .long("warn")
-              .short('w')
-              .help("Show warning messages")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("disable-stdin")
-              .long("disable-stdin")
-              .help("Force reading from a file even if STDIN is piped")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("usage")
-              .long("usage")
-              .help("Show usage information")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("output-directory")
-              .long("output-directory")
-              .short('o')
-              .help("Specify the directory for output files (default: 'output')")
-              .default_value("output"))
-         .arg(Arg::new("write-to-disk")
-              .long("write-to-disk")
-              .help("Write output to disk (default: true)")
-              .action(ArgAction::SetTrue))
-         .arg(Arg::new("buffer-size")
-              .long("buffer-size")
-              .help("Set the buffer size for WriterContext (default: 4096)")
-              .default_value("4096"))
-         .get_matches();
- 
-     // Parse values into a CliConfig struct.
-     let fec_id = matches.get_one::<String>("filing-id-or-file")
-         .cloned()
-         .unwrap_or_else(|| "".to_string());
- 
-     let include_filing_id = matches.get_flag("include-filing-id");
-     let silent = matches.get_flag("silent");
-     let warn = matches.get_flag("warn");
-     let disable_stdin = matches.get_flag("disable-stdin");
-     let show_usage = matches.get_flag("usage");
-     let output_directory = matches
-         .get_one::<String>("output-directory")
-         .cloned()
-         .unwrap_or_else(|| "output".to_string());
-     let write_to_disk = matches.get_flag("write-to-disk");
-     let buffer_size = matches
-         .get_one::<String>("buffer-size")
-         .unwrap_or(&"4096".to_string())
-         .parse::<usize>()
-         .unwrap_or(4096);
- 
-     // Determine if STDIN is piped.
-     let stdin_piped = !atty::is(atty::Stream::Stdin);
-     let use_stdin = stdin_piped && !disable_stdin && fec_id.is_empty();
- 
-     // Return the configuration.
-     Ok(CliConfig {
-         fec_id: if use_stdin && fec_id.is_empty() { "STDIN_DATA".to_string() } else { fec_id },
-         include_filing_id,
-         silent,
-         warn,
-         use_stdin,
-         show_usage,
-         output_directory,
-         write_to_disk,
-         buffer_size,
-     })
- }
\ No newline at end of file
+    // Determine if STDIN is piped.
+    let stdin_piped = !atty::is(atty::Stream::Stdin);
+    parse_args_from(std::env::args_os(), stdin_piped)
+}
+
+/// Parse the given arguments (including the program name) into a `CliConfig`.
+///
+/// - `stdin_piped`: whether STDIN is piped, which decides if input is read from it.
+pub fn parse_args_from<I, T>(args: I, stdin_piped: bool) -> Result<CliConfig>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let matches = build_command().try_get_matches_from(args)?;
+
+    // Parse values into a CliConfig struct.
+    let fec_id = matches
+        .get_one::<String>("filing-id-or-file")
+        .cloned()
+        .unwrap_or_default();
+
+    let include_filing_id = matches.get_flag("include-filing-id");
+    let row_numbers = matches.get_flag("row-numbers");
+    let silent = matches.get_flag("silent");
+    let warn = matches.get_flag("warn");
+    let verbose = matches.get_flag("verbose");
+    let disable_stdin = matches.get_flag("disable-stdin");
+    let show_usage = matches.get_flag("usage");
+    let output_directory = matches
+        .get_one::<String>("output-directory")
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_OUTPUT_DIRECTORY.to_string());
+    let write_to_disk = matches.get_flag("write-to-disk");
+    let buffer_size = matches
+        .get_one::<String>("buffer-size")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid buffer size"))?
+        .unwrap_or(DEFAULT_BUFFER_SIZE);
+    let format = matches
+        .get_one::<String>("format")
+        .map(|s| s.parse::<OutputFormat>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid output format"))?
+        .unwrap_or_default();
+    if !format.is_available() {
+        return Err(anyhow!(
+            "Writing {:?} output requires building with the `sqlite` feature",
+            format
+        ));
+    }
+    let output_delimiter = matches
+        .get_one::<String>("output-delimiter")
+        .map(|s| parse_output_delimiter(s))
+        .transpose()?
+        .unwrap_or(b',');
+    let output = matches.get_one::<String>("output").cloned();
+    let compression = matches
+        .get_one::<String>("compress")
+        .map(|s| s.parse::<Compression>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid compression"))?
+        .unwrap_or_default();
+    if !compression.is_available() {
+        return Err(anyhow!(
+            "Writing {:?} output requires building with the `zstd` feature",
+            compression
+        ));
+    }
+    let level = matches
+        .get_one::<String>("compress-level")
+        .map(|s| s.parse::<i32>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid compression level"))?;
+    if let Some(level) = level {
+        compression.check_level(level)?;
+    }
+    let output_mode = match (
+        matches.get_flag("overwrite"),
+        matches.get_flag("append"),
+        matches.get_flag("no-overwrite"),
+    ) {
+        (_, false, false) => OutputMode::Truncate,
+        (false, true, false) => OutputMode::Append,
+        (false, false, true) => OutputMode::FailIfExists,
+        _ => {
+            return Err(anyhow!(
+                "Only one of --overwrite, --append and --no-overwrite can be given"
+            ))
+        }
+    };
+    let summary = matches.get_flag("summary");
+    let skip = matches
+        .get_one::<String>("skip")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid skip count"))?
+        .unwrap_or(0);
+    let limit = matches
+        .get_one::<String>("limit")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid limit"))?
+        .unwrap_or(0);
+    let max_line_length = matches
+        .get_one::<String>("max-line-length")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid maximum line length"))?
+        .unwrap_or(DEFAULT_MAX_LINE_LENGTH);
+    let strict = matches.get_flag("strict");
+    let fallback = matches
+        .get_one::<String>("fallback-encoding")
+        .map(|s| s.parse::<FallbackEncoding>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid fallback encoding"))?
+        .unwrap_or_default();
+    let threads = matches
+        .get_one::<String>("threads")
+        .map(|s| s.parse::<usize>())
+        .transpose()
+        .ok()
+        .flatten()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| anyhow!("Invalid thread count"))?;
+    let print = matches.contains_id("print");
+    let print_form = matches
+        .get_one::<String>("print")
+        .map(|form| form.trim().to_uppercase());
+    let forms = matches
+        .get_one::<String>("forms")
+        .map(|forms| {
+            forms
+                .split(',')
+                .map(|form| form.trim().to_uppercase())
+                .filter(|form| !form.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let combined = matches.get_flag("combined");
+    if combined && print {
+        return Err(anyhow!(
+            "--combined can't be used with --print, which already leads rows with their form"
+        ));
+    }
+    let ragged = matches.get_flag("ragged");
+    let validate = matches.get_flag("validate");
+    let f99_text = matches.get_flag("f99-text");
+    let manifest = matches.get_flag("manifest");
+    let atomic = matches.get_flag("atomic");
+    let fsync = matches.get_flag("fsync");
+    let min_free = matches
+        .get_one::<String>("min-free-space")
+        .map(|s| s.parse::<u64>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid minimum free space"))?;
+    let template = matches.get_one::<String>("filename-template").cloned();
+    if let Some(template) = &template {
+        template.parse::<FilenameTemplate>()?;
+    }
+    let max_rows = matches
+        .get_one::<String>("max-rows-per-file")
+        .map(|s| s.parse::<usize>().ok().filter(|&max| max > 0))
+        .map(|max| max.ok_or_else(|| anyhow!("Invalid max rows per file")))
+        .transpose()?;
+    let max_bytes = matches
+        .get_one::<String>("max-bytes-per-file")
+        .map(|s| s.parse::<u64>().ok().filter(|&max| max > 0))
+        .map(|max| max.ok_or_else(|| anyhow!("Invalid max bytes per file")))
+        .transpose()?;
+    let quote_style = matches
+        .get_one::<String>("quote-style")
+        .map(|s| s.parse::<QuoteStyle>())
+        .transpose()
+        .map_err(|_| anyhow!("Invalid quote style"))?
+        .unwrap_or_default();
+    let replace_with = matches
+        .get_one::<String>("replace-unquotable")
+        .map(|s| parse_replacement(s, output_delimiter))
+        .transpose()?;
+    if replace_with.is_some() && quote_style != QuoteStyle::Never {
+        return Err(anyhow!(
+            "Invalid replacement: --replace-unquotable only applies with --quote-style never"
+        ));
+    }
+    let null_token = matches
+        .get_one::<String>("null-string")
+        .map(|s| parse_null_string(s, output_delimiter))
+        .transpose()?;
+    let terminator = if matches.get_flag("crlf") {
+        Terminator::Crlf
+    } else {
+        Terminator::Lf
+    };
+    let full_form_names = matches.get_flag("full-form-names");
+    let raw_headers = matches.get_flag("raw-headers");
+    let paper = matches.get_flag("paper");
+    let truncate = matches.get_flag("truncate-extra-fields");
+    let map_csv_exports = matches.get_flag("map-csv-exports");
+    let single_filing = matches.get_flag("single-filing");
+    // Only defined with the `http` feature
+    let base_url = matches
+        .try_get_one::<String>("base-url")
+        .ok()
+        .flatten()
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+
+    let use_stdin = stdin_piped && !disable_stdin && fec_id.is_empty();
+
+    // Return the configuration.
+    Ok(CliConfig {
+        fec_id: if use_stdin && fec_id.is_empty() {
+            "STDIN_DATA".to_string()
+        } else {
+            fec_id
+        },
+        include_filing_id,
+        row_numbers,
+        silent,
+        warn,
+        verbose,
+        use_stdin,
+        show_usage,
+        output_directory,
+        write_to_disk,
+        buffer_size,
+        compression,
+        level,
+        format,
+        output_delimiter,
+        terminator,
+        quote_style,
+        replace_with,
+        null_token,
+        output,
+        output_mode,
+        manifest,
+        atomic,
+        fsync,
+        min_free,
+        max_rows,
+        max_bytes,
+        template,
+        summary,
+        skip,
+        limit,
+        max_line_length,
+        strict,
+        fallback,
+        base_url,
+        threads,
+        print,
+        print_form,
+        forms,
+        combined,
+        ragged,
+        validate,
+        f99_text,
+        full_form_names,
+        raw_headers,
+        paper,
+        truncate,
+        map_csv_exports,
+        single_filing,
+    })
+}
+
+/// Parse a `--null-string` value: a non-empty token that, written unquoted, can't be mistaken
+/// for more than one field, i.e. without the delimiter, quotes or line breaks.
+fn parse_null_string(value: &str, delimiter: u8) -> Result<String> {
+    let breaks_field = |c: char| c == char::from(delimiter) || matches!(c, '"' | '\n' | '\r');
+    if value.is_empty() || value.contains(breaks_field) {
+        return Err(anyhow!("Invalid null string {:?}", value));
+    }
+    Ok(value.to_string())
+}
+
+/// Parse a `--replace-unquotable` value: one character, which can't be the delimiter or a line
+/// break it replaces.
+fn parse_replacement(value: &str, delimiter: u8) -> Result<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != char::from(delimiter) && c != '\n' && c != '\r' => Ok(c),
+        _ => Err(anyhow!("Invalid replacement {:?}", value)),
+    }
+}
+
+/// Parse an `--output-delimiter` value: `tab` (or a literal tab), or any other single ASCII
+/// character that can't be mistaken for a quote or line break.
+fn parse_output_delimiter(value: &str) -> Result<u8> {
+    match value {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ => match value.as_bytes() {
+            [byte] if !matches!(byte, b'"' | b'\r' | b'\n') && byte.is_ascii() => Ok(*byte),
+            _ => Err(anyhow!("Invalid output delimiter {:?}", value)),
+        },
+    }
+}
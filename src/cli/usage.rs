@@ -7,16 +7,123 @@ r#"Usage:
   fast-fec-rust [FLAGS] <FILING_ID_OR_FILE>
 
 Flags:
-  -f, --include-filing-id  Include a filing_id column in the output CSV
+  -f, --include-filing-id  Include filing_id, is_amendment and original_filing_id columns
+                           in the output CSV
+      --row-numbers        Include a source_line column with each row's line in the input
   -s, --silent             Suppress output messages
   -w, --warn               Show warning messages
+      --verbose            Also print how often each output file was flushed, its
+                           overflowing writes and the time spent writing, after the summary
       --disable-stdin      Disable piped STDIN usage
+      --format <FORMAT>    Write rows as csv (default), ndjson: one JSON object per row,
+                           keyed by column name, in SA.ndjson, or sqlite: one table per
+                           form in a database (with the `sqlite` feature)
+      --output-delimiter <C>
+                           Separate CSV fields with tab (writing SA.tsv) or another single
+                           character, e.g. ; (default: ,)
+      --quote-style <STYLE>
+                           Quote always (every field), necessary (default: fields holding
+                           the delimiter, a quote or a line break) or never
+      --replace-unquotable <C>
+                           With --quote-style never, replace delimiters and line breaks in
+                           fields with C instead of failing on them
+      --null-string <TOKEN>
+                           Write empty fields as TOKEN, never quoted, e.g. NULL or \N
+                           (default: nothing)
+      --crlf               End rows and F99 text lines with CRLF instead of LF
+      --output <FILE>      Database sqlite output is written to (default: filings.db in
+                           the output directory)
+      --overwrite          Replace output files left by an earlier run (default)
+      --append             Add rows to output files left by an earlier run
+      --no-overwrite       Fail before parsing if output files already exist
+      --compress <FORMAT>  Compress output files: gzip (SA.csv.gz), zstd (SA.csv.zst, with
+                           the `zstd` feature) or none (default)
+      --compress-level <N> Compression level: 0-9 for gzip, 1-22 for zstd
+      --summary            Only parse the header and cover record, then stop
+      --skip <N>           Skip N lines after the header without parsing them
+      --limit <N>          Stop after parsing N records (0 = no limit)
+      --max-line-length <N>
+                           Truncate lines longer than N bytes (default: 64 MiB)
+      --strict             Fail on the first malformed line instead of warning
+      --fallback-encoding <ENCODING>
+                           Read lines that aren't valid UTF-8 as windows-1252 (default),
+                           latin1 or utf8-lossy (U+FFFD for invalid bytes), or fail on
+                           them with error
+      --threads <N>        Parse the filing body on N threads (default: 1)
+      --print[=FORM]       Stream rows to stdout instead of files (only FORM's rows, or
+                           all rows with a leading form column)
+      --forms <FORMS>      Only write rows of these comma-separated forms (e.g. SA,SB),
+                           besides cover records
+      --combined           Write every form's rows to one filing.csv, each led by its
+                           form, under a form_type,col_1..col_N header; header, cover
+                           and unknown form files are still written apart
+      --ragged             Leave short rows unpadded (with --combined, instead of
+                           padding them to the widest form's columns)
+      --validate           List the filing's problems without writing output; exits
+                           0 if valid, 2 with only warnings and 1 if invalid
+      --manifest           Also write manifest.csv to each filing's directory, listing its
+                           files with their form, row count, size and header row
+      --filename-template <TEMPLATE>
+                           Name output files by TEMPLATE under the output directory,
+                           from {{filing_id}}, {{form}}, {{extension}} and {{date}} (YYYYMMDD),
+                           e.g. "{{filing_id}}_{{form}}.csv" or "{{form}}/{{filing_id}}.csv"
+                           (default: {{filing_id}}/{{form}}.{{extension}})
+      --fsync              Sync output files and their directories to disk as they're
+                           flushed and closed, before exiting
+      --min-free-space <BYTES>
+                           Fail before parsing unless BYTES bytes of disk space are
+                           free where output goes (Unix only)
+      --max-rows-per-file <N>
+                           Split row files every N rows: SA.csv, then SA_002.csv and so
+                           on, each starting with the header row
+      --max-bytes-per-file <BYTES>
+                           Split row files before a row would take them past BYTES
+                           bytes (before compression); rows are never split
+      --atomic             Write output files as NAME.tmp, renamed to NAME once the filing
+                           has parsed; a failed parse leaves no output behind
+      --f99-text           Also write F99 text blocks verbatim to F99.txt
+      --full-form-names    Name output files by full form code (SA11AI.csv, not SA.csv)
+      --raw-headers        Keep header row column names as mapped, instead of lowercased
+                           with spaces and punctuation turned into underscores
+      --paper              Map rows with the paper filing conversion layouts, whatever
+                           version the header names
+      --truncate-extra-fields
+                           Drop fields past the columns of a row's form, with a warning
+      --map-csv-exports    Read CSV exports (a column-name header row instead of an HDR
+                           record) by column name instead of failing
+      --single-filing      Treat concatenated filings as one, instead of starting a new
+                           filing (and output directory) at each later HDR record
+      --base-url <URL>     Download numeric filing IDs from URL (`http` feature)
       --usage              Show usage information
 
 Examples:
   fast-fec-rust 12345
+  fast-fec-rust 1690664    # Downloads the filing with the `http` feature
   fast-fec-rust --include-filing-id 12345
+  fast-fec-rust --include-filing-id --row-numbers 12345
+  fast-fec-rust --summary 12345
+  fast-fec-rust --skip 1500000 --limit 100 bigfile.fec
+  fast-fec-rust bigfile.fec --print=SA | head -5
+  fast-fec-rust --forms SA,SB 12345
+  fast-fec-rust --combined 12345
+  fast-fec-rust --validate 12345
+  fast-fec-rust --f99-text 12345
+  fast-fec-rust --manifest 12345
+  fast-fec-rust --atomic 12345
+  fast-fec-rust --max-rows-per-file 1000000 12345
+  fast-fec-rust --filename-template "{{filing_id}}_{{form}}.csv" 12345
+  fast-fec-rust --write-to-disk -o s3://bucket/fec 12345    # With the `object_store` feature
+  fast-fec-rust --format ndjson 12345
+  fast-fec-rust --output-delimiter tab 12345
+  fast-fec-rust --crlf 12345
+  fast-fec-rust --quote-style always 12345
+  fast-fec-rust --null-string '\N' 12345
+  fast-fec-rust --format sqlite --output filings.db 12345
+  fast-fec-rust --compress gzip 12345
+  fast-fec-rust --append 12345
+  fast-fec-rust --compress zstd --compress-level 6 12345
   cat somefile.fec | fast-fec-rust --warn
+  cat 12345.fec 12346.fec | fast-fec-rust
 "#
     );
     std::process::exit(1);
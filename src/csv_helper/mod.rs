@@ -35,6 +35,12 @@ impl CsvParseContext {
     }
 }
 
+impl Default for CsvParseContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Example: a function to parse a single line that may or may not have ASCII28.
 /// If ASCII28 is present, we do a custom split. If not, we parse with the CSV crate.
 pub fn parse_line(line: &str) -> Result<(Vec<String>, CsvParseContext)> {
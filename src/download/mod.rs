@@ -0,0 +1,49 @@
+//! Fetching filings from the FEC by filing ID (behind the `http` feature).
+//!
+//! The response body is handed to the parser as a `BufRead` as it arrives, so a filing is
+//! never held in memory whole.
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::errors::FecError;
+
+/// Whether the positional argument should be fetched as a filing ID: it is purely numeric and
+/// no file of that name exists.
+pub fn is_filing_id(arg: &str) -> bool {
+    !arg.is_empty() && arg.chars().all(|c| c.is_ascii_digit()) && !Path::new(arg).exists()
+}
+
+/// The URL of filing `filing_id` under `base_url`, e.g. `<base_url>/1690664.fec`.
+pub fn filing_url(base_url: &str, filing_id: &str) -> String {
+    format!("{}/{}.fec", base_url.trim_end_matches('/'), filing_id)
+}
+
+/// Request filing `filing_id` from `base_url` and return a reader streaming its body.
+///
+/// - `silent`: suppresses the progress message.
+///
+/// Fails with `FecError::Download` if the request fails or the server answers with an error
+/// status.
+pub fn open_filing(base_url: &str, filing_id: &str, silent: bool) -> Result<Box<dyn BufRead>> {
+    let url = filing_url(base_url, filing_id);
+    if !silent {
+        eprintln!("Downloading filing {} from {}", filing_id, url);
+    }
+
+    let response = match ureq::get(&url).call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, _)) => {
+            return Err(FecError::Download {
+                url,
+                status: Some(status),
+            }
+            .into())
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e).context(FecError::Download { url, status: None }))
+        }
+    };
+    Ok(Box::new(BufReader::new(response.into_reader())))
+}
@@ -2,10 +2,13 @@
 //!
 //! - Checks each byte to see if ASCII28 is present.
 //! - Tracks ASCII-only vs. not (optional).
-//! - If the data is invalid UTF-8, fallback to ISO-8859-1 conversion.
+//! - If the data is invalid UTF-8, fallback to Windows-1252 (or ISO-8859-1) conversion.
 //!
 //! This matches the original C approach from `encoding.c`, but in safe, idiomatic Rust.
 
+use std::borrow::Cow;
+use std::str::FromStr;
+
 /// The Hoehrmann state machine's "ACCEPT" and "REJECT" states.
 const UTF8_ACCEPT: u32 = 0;
 const UTF8_REJECT: u32 = 1;
@@ -44,8 +47,75 @@ static UTF8D: [u8; 400] = [
     1, // s7..s8
 ];
 
+/// The UTF-8 byte-order mark some Windows tools write at the start of a file.
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// How far the bytes fed to a `Utf8Validator` are from being valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationState {
+    /// Valid UTF-8, ending on a character boundary.
+    Valid,
+    /// Valid so far, but ending partway through a multi-byte character.
+    Incomplete,
+    /// Not valid UTF-8, whatever is fed next.
+    Invalid,
+}
+
+/// The Hoehrmann state machine, checking that bytes fed to it a chunk at a time are valid
+/// UTF-8, with a multi-byte character free to be split between chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utf8Validator {
+    state: u32,
+}
+
+impl Default for Utf8Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Utf8Validator {
+    /// A validator that has been fed nothing.
+    pub fn new() -> Self {
+        Utf8Validator { state: UTF8_ACCEPT }
+    }
+
+    /// Feed the next bytes, returning the state of everything fed so far.
+    pub fn feed(&mut self, data: &[u8]) -> ValidationState {
+        for &byte in data {
+            self.step(byte);
+        }
+        self.state()
+    }
+
+    /// The state of everything fed so far.
+    pub fn state(&self) -> ValidationState {
+        match self.state {
+            UTF8_ACCEPT => ValidationState::Valid,
+            UTF8_REJECT => ValidationState::Invalid,
+            _ => ValidationState::Incomplete,
+        }
+    }
+
+    /// The state of the input once it has all been fed: a character it ends partway through
+    /// makes it `Invalid`.
+    pub fn finish(self) -> ValidationState {
+        match self.state() {
+            ValidationState::Incomplete => ValidationState::Invalid,
+            state => state,
+        }
+    }
+
+    /// Move the state machine past `byte`. A rejected input stays rejected.
+    #[inline]
+    fn step(&mut self, byte: u8) {
+        let t = UTF8D[byte as usize];
+        self.state = UTF8D[256 + (self.state * 16 + t as u32) as usize] as u32;
+    }
+}
+
 /// A structure to hold line information, mimicking `LINE_INFO` from C.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LineInfo {
     /// Whether ASCII 28 (the "file separator") was found.
     pub ascii28: bool,
@@ -55,6 +125,10 @@ pub struct LineInfo {
     pub valid_utf8: bool,
     /// The total number of bytes encountered (excluding the null terminator in C).
     pub length: usize,
+    /// Whether the data started with a UTF-8 byte-order mark.
+    pub had_bom: bool,
+    /// The number of NUL (0x00) bytes found.
+    pub nul_bytes: usize,
 }
 
 impl Default for LineInfo {
@@ -64,6 +138,61 @@ impl Default for LineInfo {
             ascii_only: true,
             valid_utf8: true,
             length: 0,
+            had_bom: false,
+            nul_bytes: 0,
+        }
+    }
+}
+
+/// Collects the `LineInfo` of a line fed to it a chunk at a time, as `collect_line_info` does
+/// for a whole line.
+#[derive(Debug, Clone, Default)]
+pub struct LineInfoAccumulator {
+    info: LineInfo,
+    validator: Utf8Validator,
+    bom_bytes: usize, // Leading bytes matching `UTF8_BOM`
+}
+
+impl LineInfoAccumulator {
+    /// An accumulator that has been fed nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next bytes of the line.
+    pub fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            if self.info.length < UTF8_BOM.len()
+                && self.bom_bytes == self.info.length
+                && byte == UTF8_BOM[self.bom_bytes]
+            {
+                self.bom_bytes += 1;
+            }
+            self.info.length += 1;
+
+            if byte == ASCII28 {
+                self.info.ascii28 = true;
+            }
+            if byte == 0 {
+                self.info.nul_bytes += 1;
+            }
+            if byte > 127 {
+                self.info.ascii_only = false;
+            }
+
+            // We won't stop at an invalid byte, because the original code just keeps reading
+            // the whole line
+            self.validator.step(byte);
+        }
+    }
+
+    /// The `LineInfo` of everything fed; a line ending partway through a character isn't valid
+    /// UTF-8.
+    pub fn finish(self) -> LineInfo {
+        LineInfo {
+            valid_utf8: self.validator.finish() == ValidationState::Valid,
+            had_bom: self.bom_bytes == UTF8_BOM.len(),
+            ..self.info
         }
     }
 }
@@ -73,30 +202,51 @@ impl Default for LineInfo {
 /// - `data`: raw bytes from the line
 /// - returns: a `LineInfo` with flags for ascii28, ascii_only, valid_utf8, and the length
 fn collect_line_info(data: &[u8]) -> LineInfo {
-    let mut info = LineInfo::default();
-    let mut state: u32 = UTF8_ACCEPT; // start in accept state
+    let mut accumulator = LineInfoAccumulator::new();
+    accumulator.feed(data);
+    accumulator.finish()
+}
 
-    for &byte in data {
-        info.length += 1;
+/// The ASCII "file separator" byte used as the field delimiter by modern filings.
+pub const ASCII28: u8 = 28;
 
-        if byte == 28 {
-            info.ascii28 = true;
-        }
-        if byte > 127 {
-            info.ascii_only = false;
-        }
+/// Remove a single trailing `\r\n` or `\n` line ending from `data` in place.
+///
+/// Carriage returns anywhere else (e.g. inside quoted field content) are left untouched.
+pub fn strip_line_ending(data: &mut Vec<u8>) {
+    let len = trim_line_ending(data).len();
+    data.truncate(len);
+}
 
-        let t = UTF8D[byte as usize];
-        // compute next state
-        state = UTF8D[256 + (state * 16 + t as u32) as usize] as u32;
-        if state == UTF8_REJECT {
-            info.valid_utf8 = false;
-            // We won't break early, because the original code
-            // just keeps reading the whole line. The final result is "invalid"
-        }
+/// `data` without a single trailing `\r\n` or `\n` line ending, like `strip_line_ending`
+/// for a borrowed line.
+pub fn trim_line_ending(data: &[u8]) -> &[u8] {
+    split_line_ending(data).0
+}
+
+/// Split a single trailing `\r\n` or `\n` line ending off `data`.
+///
+/// Returns the line's content and its ending, which is empty if the line has none.
+pub fn split_line_ending(data: &[u8]) -> (&[u8], &'static str) {
+    if let Some(content) = data.strip_suffix(b"\r\n") {
+        (content, "\r\n")
+    } else if let Some(content) = data.strip_suffix(b"\n") {
+        (content, "\n")
+    } else {
+        (data, "")
     }
+}
 
-    info
+/// Remove a leading UTF-8 byte-order mark from `data` in place.
+///
+/// Returns `true` if a BOM was found and stripped.
+pub fn strip_bom(data: &mut Vec<u8>) -> bool {
+    if data.starts_with(UTF8_BOM) {
+        data.drain(..UTF8_BOM.len());
+        true
+    } else {
+        false
+    }
 }
 
 /// Convert ISO-8859-1 bytes to UTF-8, storing the result in a new Vec<u8>.
@@ -109,42 +259,174 @@ fn iso_8859_1_to_utf8(data: &[u8]) -> Vec<u8> {
         if b < 128 {
             output.push(b);
         } else {
-            // "0xc2 + (b > 0xbf)" => if b > 0xBF, we use 0xc3, else 0xc2
-            let first = 0xc2 + ((b > 0xbf) as u8);
-            let second = (b & 0x3f) + 0x80;
-            output.push(first);
-            output.push(second);
+            push_latin1(&mut output, b);
         }
     }
     output
 }
 
+/// Push the two-byte UTF-8 encoding of the ISO-8859-1 byte `b` (0x80 or above) to `output`.
+fn push_latin1(output: &mut Vec<u8>, b: u8) {
+    // "0xc2 + (b > 0xbf)" => if b > 0xBF, we use 0xc3, else 0xc2
+    let first = 0xc2 + ((b > 0xbf) as u8);
+    let second = (b & 0x3f) + 0x80;
+    output.push(first);
+    output.push(second);
+}
+
+/// The characters of Windows-1252 bytes 0x80 to 0x9F, where it differs from ISO-8859-1. The
+/// five bytes it leaves undefined (0x81, 0x8D, 0x8F, 0x90 and 0x9D) keep their C1 control
+/// characters, as browsers decode them.
+const WINDOWS_1252_C1: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Convert Windows-1252 bytes to UTF-8: as `iso_8859_1_to_utf8` does, but for the smart
+/// quotes, dashes, euro sign and other characters Windows puts at 0x80 to 0x9F.
+fn windows_1252_to_utf8(data: &[u8]) -> Vec<u8> {
+    // As for ISO-8859-1, though the characters of 0x80..0x9F take three bytes
+    let mut output = Vec::with_capacity(data.len() * 2);
+
+    for &b in data {
+        if b < 0x80 {
+            output.push(b);
+        } else if b < 0xA0 {
+            let mut buf = [0; 4];
+            let c = WINDOWS_1252_C1[(b - 0x80) as usize].encode_utf8(&mut buf);
+            output.extend_from_slice(c.as_bytes());
+        } else {
+            push_latin1(&mut output, b);
+        }
+    }
+    output
+}
+
+/// What lines that aren't valid UTF-8 are decoded as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FallbackEncoding {
+    /// Windows-1252, which filings produced on Windows are written in: `0x93` is `“`, `0x80`
+    /// is `€`.
+    #[default]
+    Windows1252,
+    /// ISO-8859-1 (Latin-1), byte for code point, as FastFEC decodes: `0x93` is the C1 control
+    /// character U+0093.
+    Latin1,
+    /// UTF-8 still, with each invalid sequence replaced by U+FFFD, the Unicode replacement
+    /// character.
+    Utf8Lossy,
+    /// No fallback: parsing fails on the line with `FecError::InvalidEncoding`, as in strict
+    /// mode. `decode_line_with`, which can't fail, decodes it as `Utf8Lossy` does.
+    Error,
+}
+
+impl FallbackEncoding {
+    /// The encoding's name: `Windows-1252`, `ISO-8859-1`, `lossy UTF-8` or `an error`.
+    pub fn name(self) -> &'static str {
+        match self {
+            FallbackEncoding::Windows1252 => "Windows-1252",
+            FallbackEncoding::Latin1 => "ISO-8859-1",
+            FallbackEncoding::Utf8Lossy => "lossy UTF-8",
+            FallbackEncoding::Error => "an error",
+        }
+    }
+
+    /// Convert `data` from this encoding to UTF-8.
+    fn to_utf8(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            FallbackEncoding::Windows1252 => windows_1252_to_utf8(data),
+            FallbackEncoding::Latin1 => iso_8859_1_to_utf8(data),
+            FallbackEncoding::Utf8Lossy | FallbackEncoding::Error => {
+                String::from_utf8_lossy(data).into_owned().into_bytes()
+            }
+        }
+    }
+}
+
+impl FromStr for FallbackEncoding {
+    type Err = anyhow::Error;
+
+    /// Parse an encoding name as given on the command line: `windows-1252` (or `cp1252`),
+    /// `latin1` (or `iso-8859-1`), `utf8-lossy` or `error`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "windows-1252" | "cp1252" => Ok(FallbackEncoding::Windows1252),
+            "latin1" | "iso-8859-1" => Ok(FallbackEncoding::Latin1),
+            "utf8-lossy" => Ok(FallbackEncoding::Utf8Lossy),
+            "error" => Ok(FallbackEncoding::Error),
+            _ => Err(anyhow::anyhow!("Unknown fallback encoding {:?}", s)),
+        }
+    }
+}
+
+/// What to do with NUL (0x00) bytes in a line, which some mangled filings contain and which
+/// tools reading the output (Postgres `COPY`, for one) reject.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NulPolicy {
+    /// Drop them.
+    #[default]
+    Strip,
+    /// Replace each with U+FFFD, the Unicode replacement character.
+    Replace,
+}
+
 /// Decode a line from raw bytes, returning a `(decoded_string, ascii28_found)`.
 ///
 /// - We first apply `collect_line_info` to detect ASCII28, check validity, etc.
-/// - If it is invalid UTF-8, we fallback to ISO-8859-1 → UTF-8.
-/// - We return the final `String` plus a boolean if ASCII28 was found.
-pub fn decode_line(data: &[u8]) -> (String, bool) {
+/// - If it is valid UTF-8 without NUL bytes, the string borrows `data`; nothing is copied.
+/// - If it is invalid UTF-8, we fallback to Windows-1252 → UTF-8.
+/// - NUL bytes are stripped (see `decode_line_with` for other policies and encodings).
+/// - We return the final string plus a boolean if ASCII28 was found.
+pub fn decode_line(data: &[u8]) -> (Cow<'_, str>, bool) {
+    let (decoded, info) = decode_line_with(data, NulPolicy::default(), FallbackEncoding::default());
+    (decoded, info.ascii28)
+}
+
+/// Decode a line as `decode_line` does, always copying it into a new `String`.
+#[deprecated(note = "use `decode_line`, which borrows lines that are valid UTF-8")]
+pub fn decode_line_owned(data: &[u8]) -> (String, bool) {
+    let (decoded, ascii28) = decode_line(data);
+    (decoded.into_owned(), ascii28)
+}
+
+/// Decode a line from raw bytes as `decode_line` does, dealing with its NUL bytes according to
+/// `nul_policy` and decoding it as `fallback` if it isn't valid UTF-8.
+///
+/// Returns the decoded line, borrowed from `data` unless it had to be converted or scrubbed,
+/// and the `LineInfo` of the raw bytes, whose `nul_bytes` counts the NUL bytes that were
+/// stripped or replaced.
+pub fn decode_line_with(
+    data: &[u8],
+    nul_policy: NulPolicy,
+    fallback: FallbackEncoding,
+) -> (Cow<'_, str>, LineInfo) {
     // 1. Collect line info
     let info = collect_line_info(data);
 
-    // 2. If not valid UTF-8, fallback to ISO-8859-1
-    if !info.valid_utf8 {
-        let converted = iso_8859_1_to_utf8(data);
-        // Safe to unwrap because it's guaranteed valid now
-        let s = String::from_utf8(converted).unwrap();
-        return (s, info.ascii28);
+    // 2. If valid, we can interpret the original data as UTF-8 safely; otherwise fall back to
+    //    `fallback`
+    let decoded = match std::str::from_utf8(data) {
+        Ok(s) if info.valid_utf8 => Cow::Borrowed(s),
+        _ => Cow::Owned(String::from_utf8(fallback.to_utf8(data)).unwrap()),
+    };
+
+    // 3. NUL bytes decode to themselves either way, so they are dealt with afterwards
+    if info.nul_bytes == 0 {
+        return (decoded, info);
     }
+    let scrubbed = scrub_nul_bytes(&decoded, nul_policy).into_owned();
+    (Cow::Owned(scrubbed), info)
+}
 
-    // 3. If valid, we can interpret the original data as UTF-8 safely.
-    //    The original code would just do "copyString(in, out)", i.e. no transformation.
-    if let Ok(s) = std::str::from_utf8(data) {
-        (s.to_string(), info.ascii28)
-    } else {
-        // If we can't interpret as UTF-8 (unlikely if valid_utf8 == true, but just in case)
-        // fallback as well
-        let converted = iso_8859_1_to_utf8(data);
-        let s = String::from_utf8(converted).unwrap();
-        (s, info.ascii28)
+/// Strip or replace the NUL characters of an already decoded line according to `nul_policy`.
+pub fn scrub_nul_bytes(line: &str, nul_policy: NulPolicy) -> Cow<'_, str> {
+    if !line.contains('\0') {
+        return Cow::Borrowed(line);
+    }
+    match nul_policy {
+        NulPolicy::Strip => Cow::Owned(line.replace('\0', "")),
+        NulPolicy::Replace => Cow::Owned(line.replace('\0', "\u{FFFD}")),
     }
 }
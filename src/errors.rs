@@ -1,7 +1,7 @@
 //! Custom error types for Fast-FEC Rust, implemented using `thiserror`.
 
-use thiserror::Error;
 use std::io;
+use thiserror::Error;
 
 /// A general error type for the FEC parser.
 #[derive(Debug, Error)]
@@ -14,5 +14,82 @@ pub enum FecError {
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
 
+    /// For rows whose field count doesn't match their form's mapping (strict mode only).
+    #[error("line {line}: {form_type} row has {actual} fields, expected {expected}")]
+    FieldCountMismatch {
+        line: usize,
+        form_type: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// For lines longer than the configured maximum (strict mode only).
+    #[error("line {line}: line is longer than the maximum of {length} bytes")]
+    LineTooLong { line: usize, length: usize },
+
+    /// For lines that aren't valid UTF-8 (strict mode only; otherwise read as Windows-1252, or
+    /// the context's fallback encoding).
+    #[error("line {line}: line is not valid UTF-8")]
+    InvalidEncoding { line: usize },
+
+    /// For lines containing NUL bytes (strict mode only; otherwise they are stripped or replaced).
+    #[error("line {line}: line contains {count} NUL byte(s)")]
+    NulBytes { line: usize, count: usize },
+
+    /// For rows whose form type is empty or unparseable (strict mode only).
+    #[error("line {line}: unrecognized form type {form_type:?}")]
+    UnknownFormType { line: usize, form_type: String },
+
+    /// For rows that can't be split into fields cleanly (strict mode only).
+    #[error("line {line}: malformed row: {reason}")]
+    MalformedRow { line: usize, reason: String },
+
+    /// For inputs whose first line names CSV columns instead of being a filing header, unless
+    /// such exports are read by column name.
+    #[error("this looks like a CSV export, not a raw .fec filing: line {line} names columns ({first_column}, ...) instead of being a filing header")]
+    CsvExport { line: usize, first_column: String },
+
+    /// For inputs whose first line is neither a header record of a known variant, a legacy
+    /// header block nor a CSV export's column names; `start` holds its first 40 bytes.
+    #[error("line {line}: not a filing header: the line starts with {start:?}")]
+    UnrecognizedHeader { line: usize, start: String },
+
+    /// For filings that couldn't be downloaded; `status` is the HTTP status, if one was received.
+    #[error(
+        "failed to download {url}{}",
+        status.map(|s| format!(" (HTTP status {s})")).unwrap_or_default()
+    )]
+    Download { url: String, status: Option<u16> },
+
+    /// For output files whose buffered rows couldn't all be written, each named by its path under
+    /// the output directory with its error; the others were written all the same.
+    #[error(
+        "failed to write {} output file(s): {}",
+        files.len(),
+        files.iter().map(|(path, error)| format!("{path}: {error}")).collect::<Vec<_>>().join("; ")
+    )]
+    IncompleteOutput { files: Vec<(String, String)> },
+
+    /// For output directories found unusable before parsing (see `WriterContext::preflight`):
+    /// what couldn't be done at `path`, and the kind of I/O error it failed with.
+    #[error("can't {action} {path}: {kind}")]
+    OutputNotWritable {
+        path: String,
+        action: String,
+        kind: io::ErrorKind,
+    },
+
+    /// For output directories with less free disk space, in bytes, than asked for before
+    /// parsing (see `WriterContext::with_min_free_space`).
+    #[error("only {available} bytes are free in {path}, short of the {required} asked for")]
+    InsufficientSpace {
+        path: String,
+        available: u64,
+        required: u64,
+    },
+
+    /// For output collected in memory that would grow past its limit, in bytes.
+    #[error("the collected output would be larger than its limit of {limit} bytes")]
+    OutputLimitExceeded { limit: u64 },
     // Add more error types as needed.
 }
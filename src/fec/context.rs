@@ -1,45 +1,262 @@
 use regex::Regex;
+use std::fmt;
+use std::sync::OnceLock;
 
-#[derive(Debug)]
+use crate::encoding::{FallbackEncoding, NulPolicy};
+use crate::writer::DateFormat;
+
+use super::header::{AmendmentInfo, HeaderInfo};
+use super::mappings::MappingSet;
+use super::records::FecRecord;
+use super::validation::{Severity, ValidationIssue};
+
+/// The longest line read by default, so that a corrupt filing without line breaks isn't
+/// buffered whole.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024 * 1024;
+
+/// The most F99 text held in memory by default before the rest of its block is streamed.
+pub const DEFAULT_MAX_F99_TEXT: usize = 1024 * 1024;
+
+/// The field delimiter of a filing, decided once from its header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Delimiter {
+    /// Comma-separated values with optional quoting (older filings).
+    #[default]
+    Comma,
+    /// The ASCII 28 "file separator" character (modern filings).
+    Ascii28,
+}
+
+impl Delimiter {
+    /// The character separating fields.
+    pub fn as_char(self) -> char {
+        match self {
+            Delimiter::Comma => ',',
+            Delimiter::Ascii28 => '\x1C',
+        }
+    }
+
+    /// The delimiter's name, e.g. for the header file: `comma` or `ascii28`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Delimiter::Comma => "comma",
+            Delimiter::Ascii28 => "ascii28",
+        }
+    }
+}
+
+/// Version patterns and the delimiter filings of those versions use, in priority order.
+///
+/// The flag says whether the header's own delimiter wins over the rule's.
+pub static DELIMITER_RULES: &[(&str, Delimiter, bool)] = &[
+    // Versions before 6 are always comma-delimited, whatever control bytes the header holds
+    (r"^[1-5]", Delimiter::Comma, false),
+    // Versions 6 and up are specified as ASCII 28-delimited, but some filings use commas
+    // anyway, so the header decides; the delimiter only records what the format expects
+    (r"^[6-8]", Delimiter::Ascii28, true),
+];
+
+/// Choose the delimiter for a filing of `version` whose header used `header_delimiter`.
+///
+/// Versions matching no rule in `DELIMITER_RULES` keep the header's delimiter.
+pub fn delimiter_for_version(version: &str, header_delimiter: Delimiter) -> Delimiter {
+    static RULES: OnceLock<Vec<(Regex, Delimiter, bool)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        DELIMITER_RULES
+            .iter()
+            .map(|(pattern, delimiter, header_wins)| {
+                (Regex::new(pattern).unwrap(), *delimiter, *header_wins)
+            })
+            .collect()
+    });
+    match rules.iter().find(|(re, _, _)| re.is_match(version.trim())) {
+        Some((_, _, true)) | None => header_delimiter,
+        Some((_, delimiter, false)) => *delimiter,
+    }
+}
+
+/// A point in the input from which a parse can be resumed, just past a fully processed record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The offset in the input of the first byte not yet processed.
+    pub offset: u64,
+    /// The number of lines before `offset`, so that a resumed parse numbers lines the same.
+    pub line_number: usize,
+}
+
+impl Checkpoint {
+    /// The checkpoint just past `record`.
+    pub fn after(record: &FecRecord) -> Self {
+        Checkpoint {
+            offset: record.byte_end,
+            line_number: record.line_number,
+        }
+    }
+}
+
+/// Where the parser stands relative to F99 `[BEGINTEXT]`/`[ENDTEXT]` text blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextState {
+    /// Between records, where lines are split into fields.
+    #[default]
+    Outside,
+    /// Inside a text block, where lines are taken verbatim.
+    Inside,
+}
+
+/// What a line means to the F99 text block state machine (see `FecContext::text_event`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEvent {
+    /// A `[BEGINTEXT]` line starting a block.
+    Begin,
+    /// A line of text inside a block.
+    Text,
+    /// A second `[BEGINTEXT]` line inside a block, kept as text.
+    NestedBegin,
+    /// An `[ENDTEXT]` line ending a block.
+    End,
+    /// An `[ENDTEXT]` line outside any block, skipped.
+    StrayEnd,
+    /// Any other line outside a block.
+    Record,
+}
+
+impl TextEvent {
+    /// The state the event leaves the parser in.
+    pub fn next_state(self) -> TextState {
+        match self {
+            TextEvent::Begin | TextEvent::Text | TextEvent::NestedBegin => TextState::Inside,
+            TextEvent::End | TextEvent::StrayEnd | TextEvent::Record => TextState::Outside,
+        }
+    }
+}
+
+/// What a parse counted along the way, for an end-of-run summary (see `FecContext::stats`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// Problems reported with `FecContext::report`, whether printed or not.
+    pub warnings: usize,
+    /// Rows with a field count that didn't match their form's mapping.
+    pub mismatched_rows: usize,
+    /// Rows quarantined for an unknown form type.
+    pub unknown_form_rows: usize,
+    /// Rows whose fields past their form's columns were dropped.
+    pub truncated_rows: usize,
+    /// NUL bytes stripped or replaced.
+    pub nul_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct FecContext {
-    pub f99_text_start: Regex,     // Regex for detecting F99 text start
-    pub f99_text_end: Regex,       // Regex for detecting F99 text end
-    pub version: Option<String>,   // Parsed version (if any)
-    pub version_length: usize,     // Length of the version string
-    pub silent: bool,              // Suppress output messages
-    pub warn: bool,                // Show warning messages
-    pub use_ascii28: bool,         // Whether to use ASCII28 delimiters
-    pub summary: bool,             // Whether this is a summary parse
-    pub form_type: Option<String>, // Current form type
-    pub num_fields: usize,         // Number of fields in the form
-    pub include_filing_id: bool,   // Include filing ID in CSV output
-    pub fec_id: String,            // Filing ID or file name
+    pub f99_text_start: Regex,            // Regex for detecting F99 text start
+    pub f99_text_end: Regex,              // Regex for detecting F99 text end
+    pub version: Option<String>,          // Parsed version (if any)
+    pub version_length: usize,            // Length of the version string
+    pub silent: bool,                     // Suppress output messages
+    pub warn: bool,                       // Show warning messages
+    pub delimiter: Delimiter,             // Field delimiter, locked in by the header
+    pub summary: bool,                    // Whether this is a summary parse
+    pub form_type: Option<String>,        // Upper-cased form type of the last row
+    pub num_fields: usize,                // Mapped column count of that form (0 if none)
+    pub include_filing_id: bool,          // Include filing ID in CSV output
+    pub row_numbers: bool,                // Include each row's source line in CSV output
+    pub fec_id: String,                   // Filing ID or file name
+    pub split_filings: bool,              // Start a new filing at each later header
+    pub filings: Vec<String>,             // IDs of the filings read, in input order
+    pub header: Option<HeaderInfo>,       // Metadata parsed from the HDR record
+    pub amendment: Option<AmendmentInfo>, // Amendment status, set by the cover record
+    pub text_state: TextState,            // Whether inside an F99 [BEGINTEXT] block
+    pub f99_text: String,                 // Text collected from the current F99 block
+    pub f99_text_offset: u64,             // Input offset where that text starts
+    pub f99_text_line: usize,             // Line that offset is on
+    pub f99_text_streaming: bool,         // Whether the block's F99 record was handed out early
+    pub max_f99_text: usize,              // Most text collected before streaming the block
+    pub pending_f99: Option<FecRecord>,   // F99 record waiting for its text block
+    pub f99_text_file: bool,              // Also write F99 text blocks to F99.txt
+    pub full_form_names: bool,            // Name output files by full form code (SA11AI, not SA)
+    pub raw_headers: bool,                // Keep header row column names as mapped
+    pub paper: bool,                      // Map rows as a paper filing conversion
+    pub line_number: usize,               // 1-based number of the last line read
+    pub record_line: usize,               // Line the last logical line read starts on
+    pub line_start: u64,                  // Input offset of the last line read
+    pub line_end: u64,                    // Input offset just past that line
+    pub checkpoint: Checkpoint,           // Just past the last record handed to the output
+    pub summary_written: bool,            // Whether summary mode wrote the cover record
+    pub strict: bool,                     // Fail on malformed input instead of warning
+    pub warnings: usize,                  // Problems reported, printed or not
+    pub mismatched_rows: usize,           // Rows with a mismatched field count
+    pub unknown_form_rows: usize,         // Rows quarantined for an unknown form type
+    pub truncated_rows: usize,            // Rows whose extra fields were dropped
+    pub nul_policy: NulPolicy,            // What to do with NUL bytes in lines
+    pub fallback_encoding: FallbackEncoding, // What lines that aren't UTF-8 are decoded as
+    pub date_format: Option<DateFormat>,  // How date columns are rewritten (None: as filed)
+    pub nul_bytes: usize,                 // NUL bytes stripped or replaced
+    pub map_csv_exports: bool,            // Read CSV exports by column name instead of failing
+    pub export_columns: Option<Vec<String>>, // Column names of the CSV export being read
+    pub skip: usize,                      // Lines after the header to skip unparsed
+    pub limit: usize,                     // Maximum records to parse (0 = unlimited)
+    pub max_line_length: usize,           // Longest line read before truncating or failing
+    pub issues: Option<Vec<ValidationIssue>>, // Problems collected instead of printed, if validating
+    pub delimiter_override: Option<Delimiter>, // Caller-chosen delimiter, bypassing detection
 }
 
 impl PartialEq for FecContext {
     fn eq(&self, other: &Self) -> bool {
-        self.f99_text_start.as_str() == other.f99_text_start.as_str() &&
-        self.f99_text_end.as_str() == other.f99_text_end.as_str() &&
-        self.version == other.version &&
-        self.version_length == other.version_length &&
-        self.silent == other.silent &&
-        self.warn == other.warn &&
-        self.use_ascii28 == other.use_ascii28 &&
-        self.summary == other.summary &&
-        self.form_type == other.form_type &&
-        self.num_fields == other.num_fields &&
-        self.include_filing_id == other.include_filing_id &&
-        self.fec_id == other.fec_id
+        self.f99_text_start.as_str() == other.f99_text_start.as_str()
+            && self.f99_text_end.as_str() == other.f99_text_end.as_str()
+            && self.version == other.version
+            && self.version_length == other.version_length
+            && self.silent == other.silent
+            && self.warn == other.warn
+            && self.delimiter == other.delimiter
+            && self.delimiter_override == other.delimiter_override
+            && self.summary == other.summary
+            && self.form_type == other.form_type
+            && self.num_fields == other.num_fields
+            && self.include_filing_id == other.include_filing_id
+            && self.row_numbers == other.row_numbers
+            && self.fec_id == other.fec_id
+            && self.split_filings == other.split_filings
+            && self.filings == other.filings
+            && self.header == other.header
+            && self.amendment == other.amendment
+            && self.text_state == other.text_state
+            && self.f99_text == other.f99_text
+            && self.f99_text_offset == other.f99_text_offset
+            && self.f99_text_line == other.f99_text_line
+            && self.f99_text_streaming == other.f99_text_streaming
+            && self.max_f99_text == other.max_f99_text
+            && self.pending_f99 == other.pending_f99
+            && self.f99_text_file == other.f99_text_file
+            && self.full_form_names == other.full_form_names
+            && self.raw_headers == other.raw_headers
+            && self.paper == other.paper
+            && self.line_number == other.line_number
+            && self.record_line == other.record_line
+            && self.line_start == other.line_start
+            && self.line_end == other.line_end
+            && self.checkpoint == other.checkpoint
+            && self.summary_written == other.summary_written
+            && self.strict == other.strict
+            && self.warnings == other.warnings
+            && self.mismatched_rows == other.mismatched_rows
+            && self.unknown_form_rows == other.unknown_form_rows
+            && self.truncated_rows == other.truncated_rows
+            && self.nul_policy == other.nul_policy
+            && self.fallback_encoding == other.fallback_encoding
+            && self.date_format == other.date_format
+            && self.nul_bytes == other.nul_bytes
+            && self.map_csv_exports == other.map_csv_exports
+            && self.export_columns == other.export_columns
+            && self.skip == other.skip
+            && self.limit == other.limit
+            && self.max_line_length == other.max_line_length
+            && self.issues == other.issues
     }
 }
 
 impl FecContext {
-    pub fn new(
-        fec_id: String,
-        include_filing_id: bool,
-        silent: bool,
-        warn: bool,
-    ) -> Self {
+    pub fn new(fec_id: String, include_filing_id: bool, silent: bool, warn: bool) -> Self {
         FecContext {
             f99_text_start: Regex::new(r"(?i)^\s*\[BEGIN ?TEXT\]\s*$").unwrap(),
             f99_text_end: Regex::new(r"(?i)^\s*\[END ?TEXT\]\s*$").unwrap(),
@@ -47,12 +264,254 @@ impl FecContext {
             version_length: 0,
             silent,
             warn,
-            use_ascii28: false,
+            delimiter: Delimiter::default(),
+            delimiter_override: None,
             summary: false,
             form_type: None,
             num_fields: 0,
             include_filing_id,
+            row_numbers: false,
             fec_id,
+            split_filings: true,
+            filings: Vec::new(),
+            header: None,
+            amendment: None,
+            text_state: TextState::Outside,
+            f99_text: String::new(),
+            f99_text_offset: 0,
+            f99_text_line: 0,
+            f99_text_streaming: false,
+            max_f99_text: DEFAULT_MAX_F99_TEXT,
+            pending_f99: None,
+            f99_text_file: false,
+            full_form_names: false,
+            raw_headers: false,
+            paper: false,
+            line_number: 0,
+            record_line: 0,
+            line_start: 0,
+            line_end: 0,
+            checkpoint: Checkpoint::default(),
+            summary_written: false,
+            strict: false,
+            warnings: 0,
+            mismatched_rows: 0,
+            unknown_form_rows: 0,
+            truncated_rows: 0,
+            nul_policy: NulPolicy::default(),
+            fallback_encoding: FallbackEncoding::default(),
+            date_format: None,
+            nul_bytes: 0,
+            map_csv_exports: false,
+            export_columns: None,
+            skip: 0,
+            limit: 0,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            issues: None,
+        }
+    }
+
+    /// The upper-cased form type (e.g. `SA11AI`) of the last body row processed, if it had one.
+    pub fn form_type(&self) -> Option<&str> {
+        self.form_type.as_deref()
+    }
+
+    /// Whether the parser is inside an F99 text block.
+    pub fn in_f99_text(&self) -> bool {
+        self.text_state == TextState::Inside
+    }
+
+    /// What `line` means to the F99 text block state machine when in `state`; the event's
+    /// `next_state` is the state after it.
+    ///
+    /// Markers are whole bracketed lines. A second `[BEGINTEXT]` inside a block is text, and an
+    /// `[ENDTEXT]` outside one is stray.
+    pub fn text_event(&self, state: TextState, line: &str) -> TextEvent {
+        let line = line.trim();
+        let (begin, end) = match line.contains('[') {
+            true => (
+                self.f99_text_start.is_match(line),
+                self.f99_text_end.is_match(line),
+            ),
+            false => (false, false),
+        };
+        match (state, begin, end) {
+            (TextState::Outside, true, _) => TextEvent::Begin,
+            (TextState::Outside, _, true) => TextEvent::StrayEnd,
+            (TextState::Outside, _, _) => TextEvent::Record,
+            (TextState::Inside, _, true) => TextEvent::End,
+            (TextState::Inside, true, _) => TextEvent::NestedBegin,
+            (TextState::Inside, _, _) => TextEvent::Text,
+        }
+    }
+
+    /// The counts kept so far by the parse.
+    pub fn stats(&self) -> ParseStats {
+        ParseStats {
+            warnings: self.warnings,
+            mismatched_rows: self.mismatched_rows,
+            unknown_form_rows: self.unknown_form_rows,
+            truncated_rows: self.truncated_rows,
+            nul_bytes: self.nul_bytes,
         }
     }
-}
\ No newline at end of file
+
+    /// The number of columns the mapping defines for the last processed form, or `0` if the
+    /// form has no mapping for the filing's version.
+    pub fn num_fields(&self) -> usize {
+        self.num_fields
+    }
+
+    /// Enable or disable summary mode, where `parse_fec` only writes the header's cover
+    /// record (the first F3/F3X/F3P row) and stops reading once it has been emitted.
+    pub fn with_summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    /// Force the delimiter of the filing instead of deriving it from the header and version.
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiter_override = Some(delimiter);
+        self
+    }
+
+    /// Skip this many physical lines after the header without decoding or parsing them.
+    pub fn with_skip(mut self, skip: usize) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Stop after this many records past the header; `0` means no limit.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Set the longest line, in bytes, that is read before the line is truncated (or, in strict
+    /// mode, fails the parse).
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Also write the text of F99 text blocks, unescaped and with its original line endings, to
+    /// an `F99.txt` file next to the F99 CSV. Several blocks are written one after another.
+    pub fn with_f99_text_file(mut self, f99_text_file: bool) -> Self {
+        self.f99_text_file = f99_text_file;
+        self
+    }
+
+    /// Set how many bytes of an F99 text block are collected before the block is streamed: its
+    /// F99 record is handed out without the text, which follows in `RecordKind::TextChunk`
+    /// records of about this size, so text blocks of any length take constant memory.
+    ///
+    /// Parses on several threads still read each block whole into one batch, and a custom line
+    /// function still receives the F99 row whole.
+    pub fn with_max_f99_text(mut self, max_f99_text: usize) -> Self {
+        self.max_f99_text = max_f99_text;
+        self
+    }
+
+    /// Write header rows' column names as the mapping (or a mapped CSV export) has them, instead
+    /// of normalizing them with `mappings::normalize_column_names`.
+    pub fn with_raw_headers(mut self, raw_headers: bool) -> Self {
+        self.raw_headers = raw_headers;
+        self
+    }
+
+    /// Prefix each output row with a `source_line` column holding the input line its record starts
+    /// on, as warnings number it, after the `filing_id` column if `include_filing_id` is set.
+    /// Quarantined rows keep their own layout, which already leads with the line number.
+    pub fn with_row_numbers(mut self, row_numbers: bool) -> Self {
+        self.row_numbers = row_numbers;
+        self
+    }
+
+    /// Name output files by each row's full form type code (`SA11AI.csv`) instead of the mapped
+    /// form it resolves to (`SA.csv`). Columns come from the same mapping either way.
+    pub fn with_full_form_names(mut self, full_form_names: bool) -> Self {
+        self.full_form_names = full_form_names;
+        self
+    }
+
+    /// Map every filing read as a conversion of a paper filing, with `mappings::PAPER_MAPPINGS`,
+    /// even if its header names an electronic version. Filings whose version starts with `P` are
+    /// mapped that way regardless.
+    pub fn with_paper(mut self, paper: bool) -> Self {
+        self.paper = paper;
+        self
+    }
+
+    /// The set of mappings rows of the current filing are looked up in, from its version.
+    pub fn mapping_set(&self) -> MappingSet {
+        MappingSet::for_version(self.version.as_deref().unwrap_or(""))
+    }
+
+    /// Read inputs that are CSV exports, whose first line names their columns instead of being a
+    /// filing header, by moving each row's fields by column name into the columns of its form
+    /// for `NEWEST_VERSION`. Otherwise such inputs fail with `FecError::CsvExport`.
+    pub fn with_map_csv_exports(mut self, map_csv_exports: bool) -> Self {
+        self.map_csv_exports = map_csv_exports;
+        self
+    }
+
+    /// Choose what happens to NUL bytes in lines: they are stripped by default, before lines are
+    /// split into fields, and counted in `nul_bytes`. Strict mode fails on them instead.
+    pub fn with_nul_policy(mut self, nul_policy: NulPolicy) -> Self {
+        self.nul_policy = nul_policy;
+        self
+    }
+
+    /// Choose what lines that aren't valid UTF-8 are decoded as: Windows-1252 by default,
+    /// ISO-8859-1 as FastFEC does, or UTF-8 with invalid sequences replaced. With
+    /// `FallbackEncoding::Error`, parsing fails on them instead, naming the line.
+    pub fn with_fallback_encoding(mut self, fallback_encoding: FallbackEncoding) -> Self {
+        self.fallback_encoding = fallback_encoding;
+        self
+    }
+
+    /// Rewrite the values of rows' date columns that read as dates (`YYYYMMDD` or
+    /// `MM/DD/YYYY`) in `format`, via `format_date_with`, warning about those that don't. By
+    /// default dates are written as filed.
+    pub fn with_date_format(mut self, format: DateFormat) -> Self {
+        self.date_format = Some(format);
+        self
+    }
+
+    /// Enable or disable splitting concatenated filings, where a header record (or legacy
+    /// header block) after the first starts a new filing under its own ID. Disable it to parse
+    /// everything after the first header as body rows of one filing.
+    pub fn with_split_filings(mut self, split_filings: bool) -> Self {
+        self.split_filings = split_filings;
+        self
+    }
+
+    /// Report a problem with the input found on `line`.
+    ///
+    /// When collecting issues (validate-only mode) it is recorded with `severity`; otherwise it
+    /// is printed as a warning if warnings are enabled.
+    pub fn report(&mut self, severity: Severity, line: usize, message: fmt::Arguments<'_>) {
+        self.warnings += 1;
+        match &mut self.issues {
+            Some(issues) => issues.push(ValidationIssue {
+                line,
+                severity,
+                message: message.to_string(),
+            }),
+            None => {
+                if self.warn && !self.silent {
+                    eprintln!("(Warn) line {}: {}", line, message);
+                }
+            }
+        }
+    }
+
+    /// Enable or disable strict mode, where malformed input fails the parse with a `FecError`
+    /// naming the line instead of being warned about: field-count mismatches, lines that aren't
+    /// valid UTF-8, are too long or contain NUL bytes, unrecognized form types and malformed CSV
+    /// rows.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
@@ -0,0 +1,431 @@
+//! Filing metadata parsed from the header (`HDR`) record.
+//!
+//! The header record looks like `HDR,FEC,8.3,NGP VAN,8.3.0.0,FEC-1234,001,` (comma or ASCII28
+//! delimited). Field positions vary by version, so they are resolved through the `HDR` mapping.
+//! Some exports glue the first two fields together (`HDRFEC\x1C8.3\x1C...`), and very old
+//! filings leave out the `HDR` field (`FEC,5.00,...`); see `HeaderVariant`.
+//!
+//! Older filings instead open with a multi-line legacy block of `key = value` pairs:
+//!
+//! ```text
+//! /* Header
+//! FEC_Ver_# = 2.02
+//! Soft_Name = FECfile
+//! Schedule_Counts:
+//! SA11A1 = 00006
+//! /* End Header */
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::writer::{format_date_with, DateFormat};
+
+use super::context::Delimiter;
+use super::mappings::{get_columns, MAPPINGS};
+
+/// The shapes a filing's header takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderVariant {
+    /// `HDR,FEC,8.3,...`, with separate record type and electronic filing type fields.
+    #[default]
+    Hdr,
+    /// `HDRFEC\x1C8.3\x1C...`, with the two glued into one field.
+    HdrFec,
+    /// `FEC,5.00,...`, without a record type field.
+    Fec,
+    /// A multi-line `/* Header` block of `key = value` pairs.
+    Legacy,
+}
+
+impl HeaderVariant {
+    /// The variant of a header record with `first_field`, or `None` if it opens no header record.
+    pub fn from_first_field(first_field: &str) -> Option<Self> {
+        match clean_field(first_field).to_ascii_uppercase().as_str() {
+            "HDR" => Some(HeaderVariant::Hdr),
+            "HDRFEC" => Some(HeaderVariant::HdrFec),
+            "FEC" => Some(HeaderVariant::Fec),
+            _ => None,
+        }
+    }
+
+    /// Rewrite the fields of a header record of this variant into the `HDR,FEC,version,...`
+    /// layout of the `HDR` mapping.
+    pub fn normalize_fields(self, fields: &mut Vec<String>) {
+        match self {
+            HeaderVariant::HdrFec => {
+                fields[0] = "HDR".to_string();
+                fields.insert(1, "FEC".to_string());
+            }
+            HeaderVariant::Fec => fields.insert(0, "HDR".to_string()),
+            HeaderVariant::Hdr | HeaderVariant::Legacy => {}
+        }
+    }
+}
+
+impl fmt::Display for HeaderVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HeaderVariant::Hdr => "HDR",
+            HeaderVariant::HdrFec => "HDRFEC",
+            HeaderVariant::Fec => "FEC",
+            HeaderVariant::Legacy => LEGACY_RECORD_TYPE,
+        })
+    }
+}
+
+/// Metadata describing a filing, taken from its header record.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HeaderInfo {
+    /// The record type, normally `HDR`.
+    pub record_type: String,
+    /// The electronic filing type, normally `FEC`.
+    pub ef_type: String,
+    /// The FEC version exactly as it appeared in the header.
+    pub fec_version: String,
+    /// The trimmed FEC version used for mapping lookups, e.g. `8.3`.
+    pub version: String,
+    /// The name of the software that produced the filing.
+    pub soft_name: Option<String>,
+    /// The version of the software that produced the filing.
+    pub soft_ver: Option<String>,
+    /// The report id, e.g. `FEC-123456` for amendments.
+    pub report_id: Option<String>,
+    /// The report number within the filing sequence.
+    pub report_number: Option<String>,
+    /// The free-form header comment.
+    pub comment: Option<String>,
+    /// Per-schedule row counts declared by a legacy header, in declaration order.
+    pub schedule_counts: Vec<(String, u64)>,
+    /// The field delimiter of the filing's body, decided from the header and version.
+    pub delimiter: Delimiter,
+    /// The byte offset in the input of the first line after the header.
+    pub body_offset: u64,
+    /// Whether the input opened with a UTF-8 byte-order mark.
+    pub byte_order_mark: bool,
+    /// The shape the header took in the input.
+    pub variant: HeaderVariant,
+}
+
+impl HeaderInfo {
+    /// Build a `HeaderInfo` from the already-split fields of a header record.
+    ///
+    /// Any `HeaderVariant` of the record is accepted. Returns `None` if the fields don't describe
+    /// a header record (no `HDR`/`FEC` marker or no version). Missing trailing fields are left as
+    /// `None`.
+    pub fn from_fields(fields: &[String]) -> Option<Self> {
+        let variant = HeaderVariant::from_first_field(fields.first()?)?;
+        let fields = match variant {
+            HeaderVariant::Hdr => Cow::Borrowed(fields),
+            _ => {
+                let mut fields = fields.to_vec();
+                variant.normalize_fields(&mut fields);
+                Cow::Owned(fields)
+            }
+        };
+        let field = |i: usize| fields.get(i).map(|f| clean_field(f)).unwrap_or_default();
+
+        let record_type = field(0);
+        let fec_version = fields.get(2)?.clone();
+        let version = clean_field(&fec_version);
+        if version.is_empty() {
+            return None;
+        }
+
+        let mut info = HeaderInfo {
+            record_type,
+            ef_type: field(1),
+            fec_version,
+            version,
+            variant,
+            ..Default::default()
+        };
+
+        // Resolve the remaining positions by column name, since older versions add `name_delim`
+        if let Some(columns) = get_columns("HDR", &info.version) {
+            for (column, value) in columns.iter().zip(fields.iter()) {
+                let value = clean_field(value);
+                if value.is_empty() {
+                    continue;
+                }
+                match *column {
+                    "soft_name" => info.soft_name = Some(value),
+                    "soft_ver" => info.soft_ver = Some(value),
+                    "report_id" => info.report_id = Some(value),
+                    "report_number" => info.report_number = Some(value),
+                    "comment" => info.comment = Some(value),
+                    _ => {}
+                }
+            }
+        }
+
+        Some(info)
+    }
+}
+
+/// Whether a filing amends an earlier report, taken from its cover record and header.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AmendmentInfo {
+    /// Whether the cover record's form type carries the `A` (amended) suffix, e.g. `F3XA`.
+    pub is_amendment: bool,
+    /// The numeric id of the filing being amended, from the header's report id.
+    pub original_filing_id: Option<String>,
+}
+
+impl AmendmentInfo {
+    /// Build the amendment info for a filing whose cover record has form type `form_type`.
+    ///
+    /// Only amendments refer back to an original filing; a new filing's report id is ignored.
+    pub fn from_cover(form_type: &str, header: Option<&HeaderInfo>) -> Self {
+        let is_amendment = form_type.trim().to_ascii_uppercase().ends_with('A');
+        let original_filing_id = if is_amendment {
+            header
+                .and_then(|h| h.report_id.as_deref())
+                .and_then(parse_report_id)
+        } else {
+            None
+        };
+        AmendmentInfo {
+            is_amendment,
+            original_filing_id,
+        }
+    }
+}
+
+/// The most queried facts of a report, taken from its cover record (F3, F3X or F3P).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CoverInfo {
+    /// The cover record's form type, e.g. `F3XN`.
+    pub form_type: String,
+    /// The filing committee's ID.
+    pub committee_id: String,
+    /// The first day the report covers, as `YYYY-MM-DD` when it could be read as a date.
+    pub coverage_from: String,
+    /// The last day the report covers, as `coverage_from` is.
+    pub coverage_through: String,
+    /// The report's code, e.g. `Q1` or `YE`.
+    pub report_type: String,
+}
+
+impl CoverInfo {
+    /// Build the cover info from the `fields` of a cover record of `form_type` in a filing of
+    /// `version`, finding each field by its column name in the form's mapping.
+    ///
+    /// Returns `None` for forms whose mapping has no coverage dates, such as F99 or F24.
+    pub fn from_fields(form_type: &str, version: &str, fields: &[String]) -> Option<Self> {
+        let columns = get_columns(form_type, version)?;
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| columns.iter().position(|c| c == name))
+                .and_then(|i| fields.get(i))
+                .map(|f| clean_field(f))
+        };
+        let coverage_from = normalize_date(&field(&["coverage_from_date"])?);
+        Some(CoverInfo {
+            form_type: field(&["form_type"]).unwrap_or_default(),
+            committee_id: field(&["filer_committee_id_number"]).unwrap_or_default(),
+            coverage_from,
+            coverage_through: normalize_date(
+                &field(&["coverage_through_date"]).unwrap_or_default(),
+            ),
+            report_type: field(&["report_code", "report_type"]).unwrap_or_default(),
+        })
+    }
+}
+
+/// Rewrite a filing's date as `YYYY-MM-DD`: `YYYYMMDD` and `MM/DD/YYYY` dates are rewritten,
+/// anything else is returned trimmed but otherwise as is.
+pub fn normalize_date(value: &str) -> String {
+    match parse_date(value) {
+        Some((year, month, day)) => format_date_with(year, month, day, DateFormat::Iso),
+        None => value.trim().to_string(),
+    }
+}
+
+/// Read a filing's `YYYYMMDD` or `MM/DD/YYYY` date as `(year, month, day)`, or `None` for any
+/// other value. The parts aren't checked to make a real date.
+pub fn parse_date(value: &str) -> Option<(i32, u32, u32)> {
+    let value = value.trim();
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let (year, month, day) = if value.len() == 8 && digits(value) {
+        (&value[..4], &value[4..6], &value[6..])
+    } else {
+        match value.split('/').collect::<Vec<_>>()[..] {
+            [month, day, year]
+                if [month, day].iter().all(|s| s.len() <= 2 && digits(s))
+                    && year.len() == 4
+                    && digits(year) =>
+            {
+                (year, month, day)
+            }
+            _ => return None,
+        }
+    };
+    // Only digits, and few enough of them to parse
+    let number = |s: &str| s.parse::<u32>().unwrap_or_default();
+    Some((number(year) as i32, number(month), number(day)))
+}
+
+/// Whether the first line of an input is the header row of a CSV export, naming columns
+/// (`form_type,filer_committee_id_number,...`) rather than opening a filing: it has no ASCII28
+/// delimiter and no `HDR`/`FEC` marker field, and its first field is a column name of some
+/// mapping.
+pub fn is_csv_export_header(line: &str) -> bool {
+    if line.contains('\x1C') {
+        return false;
+    }
+    let fields: Vec<&str> = line
+        .split(',')
+        .map(|f| f.trim().trim_matches('"').trim())
+        .collect();
+    if fields
+        .iter()
+        .any(|f| f.eq_ignore_ascii_case("HDR") || f.eq_ignore_ascii_case("FEC"))
+    {
+        return false;
+    }
+    !fields[0].is_empty()
+        && MAPPINGS
+            .iter()
+            .any(|m| m.columns.iter().any(|c| c.eq_ignore_ascii_case(fields[0])))
+}
+
+/// Whether `column` is the first, form type column of some mapping (`form_type`, `rec_type`).
+pub fn is_form_type_column(column: &str) -> bool {
+    MAPPINGS.iter().any(|m| {
+        m.columns
+            .first()
+            .is_some_and(|c| c.eq_ignore_ascii_case(column))
+    })
+}
+
+/// Extract the filing id from a header report id, which is `FEC-123456` in newer versions and
+/// a bare `123456` in older ones.
+///
+/// Returns `None` if the report id holds no filing id.
+pub fn parse_report_id(report_id: &str) -> Option<String> {
+    let trimmed = report_id.trim();
+    let id = match trimmed.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("FEC-") => &trimmed[4..],
+        _ => trimmed,
+    };
+    let id = id.trim();
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+/// The record type recorded for filings that open with a legacy `/* Header` block.
+pub const LEGACY_RECORD_TYPE: &str = "/* Header";
+
+/// Whether a line opens a legacy `/* Header` block.
+pub fn is_legacy_header_start(line: &str) -> bool {
+    line.trim_start().starts_with("/*")
+}
+
+/// Whether a line opens a filing's header: a header record of any `HeaderVariant`, comma or
+/// ASCII28 delimited, or a legacy `/* Header` block.
+pub fn is_header_start(line: &str) -> bool {
+    let first = line.split([',', '\x1C']).next().unwrap_or_default();
+    HeaderVariant::from_first_field(first).is_some() || is_legacy_header_start(line)
+}
+
+/// Whether a line closes a legacy header block, either with `*/` or a `/* End Header` marker.
+pub fn is_legacy_header_end(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.ends_with("*/")
+        || trimmed
+            .trim_start_matches("/*")
+            .trim_start()
+            .to_ascii_lowercase()
+            .starts_with("end header")
+}
+
+/// Accumulates the `key = value` lines of a legacy header block into a `HeaderInfo`.
+#[derive(Debug, Default)]
+pub struct LegacyHeaderBuilder {
+    info: HeaderInfo,
+    in_schedule_counts: bool,
+}
+
+impl LegacyHeaderBuilder {
+    /// Create an empty builder.
+    pub fn new() -> Self {
+        Self {
+            info: HeaderInfo {
+                record_type: LEGACY_RECORD_TYPE.to_string(),
+                ef_type: "FEC".to_string(),
+                variant: HeaderVariant::Legacy,
+                ..Default::default()
+            },
+            in_schedule_counts: false,
+        }
+    }
+
+    /// Consume one line of the header block (markers and blank lines are ignored).
+    pub fn push_line(&mut self, line: &str) {
+        let trimmed = line
+            .trim()
+            .trim_start_matches("/*")
+            .trim_end_matches("*/")
+            .trim();
+        if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("header") {
+            return;
+        }
+
+        let Some((key, value)) = trimmed.split_once('=') else {
+            // A bare `Schedule_Counts:` line introduces the per-schedule counts
+            self.in_schedule_counts = normalize_key(trimmed) == "schedulecounts";
+            return;
+        };
+        let (key, value) = (key.trim(), clean_field(value));
+
+        match normalize_key(key).as_str() {
+            "fecver" => {
+                self.info.fec_version = value.clone();
+                self.info.version = value;
+            }
+            "softname" => self.info.soft_name = Some(value),
+            "softver" => self.info.soft_ver = Some(value),
+            "reportid" => self.info.report_id = Some(value),
+            "reportnumber" => self.info.report_number = Some(value),
+            "comment" => self.info.comment = Some(value),
+            _ if self.in_schedule_counts => {
+                if let Ok(count) = value.parse::<u64>() {
+                    self.info.schedule_counts.push((key.to_string(), count));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Finish the block, returning `None` if it never declared a version.
+    pub fn finish(self) -> Option<HeaderInfo> {
+        if self.info.version.is_empty() {
+            None
+        } else {
+            Some(self.info)
+        }
+    }
+}
+
+/// Lowercase a legacy header key and drop everything but letters, e.g. `FEC_Ver_#` => `fecver`.
+fn normalize_key(key: &str) -> String {
+    key.chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Trim whitespace and any surrounding quotes from a header field.
+fn clean_field(value: &str) -> String {
+    let trimmed = value.trim();
+    trimmed
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(trimmed)
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,1450 @@
+//! Column mappings for each FEC form type and version, replicating FastFEC's `mappings.json`.
+//!
+//! Each `FormMapping` ties a group of form type codes and a version pattern to the
+//! ordered column names used for the header row of that form's output file. Column types
+//! (string, date, float) are derived from the column names via `TYPE_RULES`, and how numeric
+//! values are written via `DOUBLE_FORMAT_RULES`.
+
+use crate::writer::DoubleFormat;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Column names shared by a group of form types across a set of versions.
+#[derive(Debug)]
+pub struct FormMapping {
+    /// The (normalized) form type codes these columns apply to, e.g. `["F3XN", "F3XA", "F3XT"]`.
+    pub forms: &'static [&'static str],
+    /// Whether longer codes starting with one of `forms` use these columns too, as schedule
+    /// itemizations carry their report line after the schedule (`SA11AI`, `SC1/10`).
+    pub prefix: bool,
+    /// A regex matching the versions these columns apply to, e.g. `^8\.` matches `8.0` through
+    /// `8.4`. Where several mappings of a form match a version, the one whose pattern matches
+    /// the most of it wins, so `^8\.[0-2]` takes precedence over `^[6-8]\.`.
+    pub version: &'static str,
+    /// The ordered column names.
+    pub columns: &'static [&'static str],
+}
+
+const HDR_8: &[&str] = &[
+    "record_type",
+    "ef_type",
+    "fec_version",
+    "soft_name",
+    "soft_ver",
+    "report_id",
+    "report_number",
+    "comment",
+];
+
+const HDR_OLD: &[&str] = &[
+    "record_type",
+    "ef_type",
+    "fec_version",
+    "soft_name",
+    "soft_ver",
+    "name_delim",
+    "report_id",
+    "report_number",
+    "comment",
+];
+
+const F3X: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "change_of_address",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "report_code",
+    "election_code",
+    "date_of_election",
+    "state_of_election",
+    "coverage_from_date",
+    "coverage_through_date",
+    "qualified_committee",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "date_signed",
+    "col_a_cash_on_hand_beginning_period",
+    "col_a_total_receipts",
+    "col_a_subtotal",
+    "col_a_total_disbursements",
+    "col_a_cash_on_hand_close_of_period",
+    "col_a_debts_to",
+    "col_a_debts_by",
+    "col_a_individuals_itemized",
+    "col_a_individuals_unitemized",
+    "col_a_individual_contribution_total",
+    "col_a_political_party_committees",
+    "col_a_other_political_committees_pacs",
+    "col_a_total_contributions",
+    "col_a_transfers_from_aff_other_party_cmttees",
+    "col_a_total_loans",
+    "col_a_total_loan_repayments_received",
+    "col_a_offsets_to_expenditures",
+    "col_a_federal_refunds",
+    "col_a_other_federal_receipts",
+    "col_a_transfers_from_nonfederal_h3",
+    "col_a_levin_funds",
+    "col_a_total_nonfederal_transfers",
+    "col_a_total_receipts_2",
+    "col_a_total_federal_receipts",
+    "col_a_shared_operating_expenditures_federal",
+    "col_a_shared_operating_expenditures_nonfederal",
+    "col_a_other_federal_operating_expenditures",
+    "col_a_total_operating_expenditures",
+    "col_a_transfers_to_affiliated",
+    "col_a_contributions_to_candidates",
+    "col_a_independent_expenditures",
+    "col_a_coordinated_expenditures_by_party_committees",
+    "col_a_total_loan_repayments_made",
+    "col_a_loans_made",
+    "col_a_refunds_to_individuals",
+    "col_a_refunds_to_party_committees",
+    "col_a_refunds_to_other_committees",
+    "col_a_total_refunds",
+    "col_a_other_disbursements",
+    "col_a_federal_election_activity_federal_share",
+    "col_a_federal_election_activity_levin_share",
+    "col_a_federal_election_activity_all_federal",
+    "col_a_federal_election_activity_total",
+    "col_a_total_disbursements_2",
+    "col_a_total_federal_disbursements",
+    "col_a_total_contributions_2",
+    "col_a_total_contribution_refunds",
+    "col_a_net_contributions",
+    "col_a_total_federal_operating_expenditures",
+    "col_a_total_offsets_to_expenditures",
+    "col_a_net_operating_expenditures",
+    "col_b_cash_on_hand_jan_1",
+    "col_b_year",
+    "col_b_total_receipts",
+    "col_b_subtotal",
+    "col_b_total_disbursements",
+    "col_b_cash_on_hand_close_of_period",
+    "col_b_individuals_itemized",
+    "col_b_individuals_unitemized",
+    "col_b_individual_contribution_total",
+    "col_b_political_party_committees",
+    "col_b_other_political_committees_pacs",
+    "col_b_total_contributions",
+    "col_b_transfers_from_aff_other_party_cmttees",
+    "col_b_total_loans",
+    "col_b_total_loan_repayments_received",
+    "col_b_offsets_to_expenditures",
+    "col_b_federal_refunds",
+    "col_b_other_federal_receipts",
+    "col_b_transfers_from_nonfederal_h3",
+    "col_b_levin_funds",
+    "col_b_total_nonfederal_transfers",
+    "col_b_total_receipts_2",
+    "col_b_total_federal_receipts",
+    "col_b_shared_operating_expenditures_federal",
+    "col_b_shared_operating_expenditures_nonfederal",
+    "col_b_other_federal_operating_expenditures",
+    "col_b_total_operating_expenditures",
+    "col_b_transfers_to_affiliated",
+    "col_b_contributions_to_candidates",
+    "col_b_independent_expenditures",
+    "col_b_coordinated_expenditures_by_party_committees",
+    "col_b_total_loan_repayments_made",
+    "col_b_loans_made",
+    "col_b_refunds_to_individuals",
+    "col_b_refunds_to_party_committees",
+    "col_b_refunds_to_other_committees",
+    "col_b_total_refunds",
+    "col_b_other_disbursements",
+    "col_b_federal_election_activity_federal_share",
+    "col_b_federal_election_activity_levin_share",
+    "col_b_federal_election_activity_all_federal",
+    "col_b_federal_election_activity_total",
+    "col_b_total_disbursements_2",
+    "col_b_total_federal_disbursements",
+    "col_b_total_contributions_2",
+    "col_b_total_contribution_refunds",
+    "col_b_net_contributions",
+    "col_b_total_federal_operating_expenditures",
+    "col_b_total_offsets_to_expenditures",
+    "col_b_net_operating_expenditures",
+];
+
+const F3: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "change_of_address",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "election_state",
+    "election_district",
+    "report_code",
+    "election_code",
+    "date_of_election",
+    "state_of_election",
+    "coverage_from_date",
+    "coverage_through_date",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "date_signed",
+    "candidate_id_number",
+    "candidate_last_name",
+    "candidate_first_name",
+    "candidate_middle_name",
+    "candidate_prefix",
+    "candidate_suffix",
+    "report_type",
+    "col_a_total_contributions_no_loans",
+    "col_a_total_contributions_refunds",
+    "col_a_net_contributions",
+    "col_a_total_operating_expenditures",
+    "col_a_total_offset_to_operating_expenditures",
+    "col_a_net_operating_expenditures",
+    "col_a_cash_on_hand_close_of_period",
+    "col_a_debts_to",
+    "col_a_debts_by",
+    "col_a_individual_contributions_itemized",
+    "col_a_individual_contributions_unitemized",
+    "col_a_total_individual_contributions",
+    "col_a_political_party_contributions",
+    "col_a_pac_contributions",
+    "col_a_candidate_contributions",
+    "col_a_total_contributions",
+    "col_a_transfers_from_authorized",
+    "col_a_candidate_loans",
+    "col_a_other_loans",
+    "col_a_total_loans",
+    "col_a_offset_to_operating_expenditures",
+    "col_a_other_receipts",
+    "col_a_total_receipts",
+    "col_a_operating_expenditures",
+    "col_a_transfers_to_authorized",
+    "col_a_candidate_loan_repayments",
+    "col_a_other_loan_repayments",
+    "col_a_total_loan_repayments",
+    "col_a_refunds_to_individuals",
+    "col_a_refunds_to_party_committees",
+    "col_a_refunds_to_other_committees",
+    "col_a_total_refunds",
+    "col_a_other_disbursements",
+    "col_a_total_disbursements",
+    "col_a_cash_beginning_reporting_period",
+    "col_a_total_receipts_period",
+    "col_a_subtotal",
+    "col_a_total_disbursements_period",
+    "col_a_cash_on_hand_close",
+    "col_b_total_contributions_no_loans",
+    "col_b_total_contributions_refunds",
+    "col_b_net_contributions",
+    "col_b_total_operating_expenditures",
+    "col_b_total_offset_to_operating_expenditures",
+    "col_b_net_operating_expenditures",
+    "col_b_cash_on_hand_close_of_period",
+    "col_b_debts_to",
+    "col_b_debts_by",
+    "col_b_individual_contributions_itemized",
+    "col_b_individual_contributions_unitemized",
+    "col_b_total_individual_contributions",
+    "col_b_political_party_contributions",
+    "col_b_pac_contributions",
+    "col_b_candidate_contributions",
+    "col_b_total_contributions",
+    "col_b_transfers_from_authorized",
+    "col_b_candidate_loans",
+    "col_b_other_loans",
+    "col_b_total_loans",
+    "col_b_offset_to_operating_expenditures",
+    "col_b_other_receipts",
+    "col_b_total_receipts",
+    "col_b_operating_expenditures",
+    "col_b_transfers_to_authorized",
+    "col_b_candidate_loan_repayments",
+    "col_b_other_loan_repayments",
+    "col_b_total_loan_repayments",
+    "col_b_refunds_to_individuals",
+    "col_b_refunds_to_party_committees",
+    "col_b_refunds_to_other_committees",
+    "col_b_total_refunds",
+    "col_b_other_disbursements",
+    "col_b_total_disbursements",
+];
+
+const F3P: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "change_of_address",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "activity_primary",
+    "activity_general",
+    "report_code",
+    "election_code",
+    "date_of_election",
+    "state_of_election",
+    "coverage_from_date",
+    "coverage_through_date",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "date_signed",
+    "col_a_cash_on_hand_beginning_period",
+    "col_a_total_receipts",
+    "col_a_subtotal",
+    "col_a_total_disbursements",
+    "col_a_cash_on_hand_close_of_period",
+    "col_a_debts_to",
+    "col_a_debts_by",
+    "col_a_expenditures_subject_to_limits",
+    "col_a_net_contributions",
+    "col_a_net_operating_expenditures",
+    "col_a_federal_funds",
+    "col_a_individuals_itemized",
+    "col_a_individuals_unitemized",
+    "col_a_individual_contribution_total",
+    "col_a_political_party_committees_receipts",
+    "col_a_other_political_committees_pacs",
+    "col_a_the_candidate",
+    "col_a_total_contributions",
+    "col_a_transfers_from_aff_other_party_cmttees",
+    "col_a_received_from_or_guaranteed_by_cand",
+    "col_a_other_loans",
+    "col_a_total_loans",
+    "col_a_operating",
+    "col_a_fundraising",
+    "col_a_legal_and_accounting",
+    "col_a_total_offsets_to_expenditures",
+    "col_a_other_receipts",
+    "col_a_total_receipts_2",
+    "col_a_operating_expenditures",
+    "col_a_transfers_to_other_authorized_committees",
+    "col_a_fundraising_disbursements",
+    "col_a_exempt_legal_accounting_disbursement",
+    "col_a_made_or_guaranteed_by_candidate",
+    "col_a_other_repayments",
+    "col_a_total_loan_repayments_made",
+    "col_a_individuals",
+    "col_a_political_party_committees_refunds",
+    "col_a_other_political_committees",
+    "col_a_total_contributions_refunds",
+    "col_a_other_disbursements",
+    "col_a_total_disbursements_2",
+    "col_a_items_on_hand_to_be_liquidated",
+    "col_b_federal_funds",
+    "col_b_individuals_itemized",
+    "col_b_individuals_unitemized",
+    "col_b_individual_contribution_total",
+    "col_b_political_party_committees_receipts",
+    "col_b_other_political_committees_pacs",
+    "col_b_the_candidate",
+    "col_b_total_contributions",
+    "col_b_transfers_from_aff_other_party_cmttees",
+    "col_b_received_from_or_guaranteed_by_cand",
+    "col_b_other_loans",
+    "col_b_total_loans",
+    "col_b_operating",
+    "col_b_fundraising",
+    "col_b_legal_and_accounting",
+    "col_b_total_offsets_to_expenditures",
+    "col_b_other_receipts",
+    "col_b_total_receipts_2",
+    "col_b_operating_expenditures",
+    "col_b_transfers_to_other_authorized_committees",
+    "col_b_fundraising_disbursements",
+    "col_b_exempt_legal_accounting_disbursement",
+    "col_b_made_or_guaranteed_by_candidate",
+    "col_b_other_repayments",
+    "col_b_total_loan_repayments_made",
+    "col_b_individuals",
+    "col_b_political_party_committees_refunds",
+    "col_b_other_political_committees",
+    "col_b_total_contributions_refunds",
+    "col_b_other_disbursements",
+    "col_b_total_disbursements_2",
+];
+
+const F99: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "date_signed",
+    "text_code",
+    "text",
+];
+
+const F24: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "report_type",
+    "original_amendment_date",
+    "committee_name",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "date_signed",
+];
+
+const SA_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "entity_type",
+    "contributor_organization_name",
+    "contributor_last_name",
+    "contributor_first_name",
+    "contributor_middle_name",
+    "contributor_prefix",
+    "contributor_suffix",
+    "contributor_street_1",
+    "contributor_street_2",
+    "contributor_city",
+    "contributor_state",
+    "contributor_zip_code",
+    "election_code",
+    "election_other_description",
+    "contribution_date",
+    "contribution_amount",
+    "contribution_aggregate",
+    "contribution_purpose_descrip",
+    "contributor_employer",
+    "contributor_occupation",
+    "donor_committee_fec_id",
+    "donor_committee_name",
+    "donor_candidate_fec_id",
+    "donor_candidate_last_name",
+    "donor_candidate_first_name",
+    "donor_candidate_middle_name",
+    "donor_candidate_prefix",
+    "donor_candidate_suffix",
+    "donor_candidate_office",
+    "donor_candidate_state",
+    "donor_candidate_district",
+    "conduit_name",
+    "conduit_street1",
+    "conduit_street2",
+    "conduit_city",
+    "conduit_state",
+    "conduit_zip_code",
+    "memo_code",
+    "memo_text_description",
+    "reference_code",
+];
+
+const SA_OLD: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "entity_type",
+    "contributor_name",
+    "contributor_street_1",
+    "contributor_street_2",
+    "contributor_city",
+    "contributor_state",
+    "contributor_zip_code",
+    "election_code",
+    "election_other_description",
+    "contributor_employer",
+    "contributor_occupation",
+    "contribution_aggregate",
+    "contribution_date",
+    "contribution_amount",
+    "contribution_purpose_code",
+    "contribution_purpose_descrip",
+    "donor_committee_fec_id",
+    "donor_candidate_fec_id",
+    "donor_candidate_name",
+    "donor_candidate_office",
+    "donor_candidate_state",
+    "donor_candidate_district",
+    "conduit_name",
+    "conduit_street1",
+    "conduit_street2",
+    "conduit_city",
+    "conduit_state",
+    "conduit_zip_code",
+    "memo_code",
+    "memo_text_description",
+    "amended_cd",
+    "transaction_id",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "reference_code",
+    "increased_limit_code",
+    "contributor_organization_name",
+    "contributor_last_name",
+    "contributor_first_name",
+    "contributor_middle_name",
+    "contributor_prefix",
+    "contributor_suffix",
+];
+
+const SB_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "entity_type",
+    "payee_organization_name",
+    "payee_last_name",
+    "payee_first_name",
+    "payee_middle_name",
+    "payee_prefix",
+    "payee_suffix",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "election_code",
+    "election_other_description",
+    "expenditure_date",
+    "expenditure_amount",
+    "semi_annual_refunded_bundled_amt",
+    "expenditure_purpose_descrip",
+    "category_code",
+    "beneficiary_committee_fec_id",
+    "beneficiary_committee_name",
+    "beneficiary_candidate_fec_id",
+    "beneficiary_candidate_last_name",
+    "beneficiary_candidate_first_name",
+    "beneficiary_candidate_middle_name",
+    "beneficiary_candidate_prefix",
+    "beneficiary_candidate_suffix",
+    "beneficiary_candidate_office",
+    "beneficiary_candidate_state",
+    "beneficiary_candidate_district",
+    "conduit_name",
+    "conduit_street_1",
+    "conduit_street_2",
+    "conduit_city",
+    "conduit_state",
+    "conduit_zip_code",
+    "memo_code",
+    "memo_text_description",
+    "reference_to_si_or_sl_system_code_that_identifies_the_account",
+];
+
+const SB_OLD: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "entity_type",
+    "payee_name",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "expenditure_purpose_code",
+    "expenditure_purpose_descrip",
+    "election_code",
+    "election_other_description",
+    "expenditure_date",
+    "expenditure_amount",
+    "beneficiary_committee_fec_id",
+    "beneficiary_candidate_fec_id",
+    "beneficiary_candidate_name",
+    "beneficiary_candidate_office",
+    "beneficiary_candidate_state",
+    "beneficiary_candidate_district",
+    "conduit_name",
+    "conduit_street_1",
+    "conduit_street_2",
+    "conduit_city",
+    "conduit_state",
+    "conduit_zip_code",
+    "memo_code",
+    "memo_text_description",
+    "amended_cd",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "reference_to_si_or_sl_system_code_that_identifies_the_account",
+    "refund_or_disposal_of_excess",
+    "category_code",
+    "communication_date",
+    "payee_organization_name",
+    "payee_last_name",
+    "payee_first_name",
+    "payee_middle_name",
+    "payee_prefix",
+    "payee_suffix",
+];
+
+const SC_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "receipt_line_number",
+    "entity_type",
+    "lender_organization_name",
+    "lender_last_name",
+    "lender_first_name",
+    "lender_middle_name",
+    "lender_prefix",
+    "lender_suffix",
+    "lender_street_1",
+    "lender_street_2",
+    "lender_city",
+    "lender_state",
+    "lender_zip_code",
+    "election_code",
+    "election_other_description",
+    "loan_amount_original",
+    "loan_payment_to_date",
+    "loan_balance",
+    "loan_incurred_date_terms",
+    "loan_due_date_terms",
+    "loan_interest_rate_terms",
+    "secured",
+    "personal_funds",
+    "lender_committee_id_number",
+    "lender_candidate_id_number",
+    "lender_candidate_last_name",
+    "lender_candidate_first_name",
+    "lender_candidate_middle_nm",
+    "lender_candidate_prefix",
+    "lender_candidate_suffix",
+    "lender_candidate_office",
+    "lender_candidate_state",
+    "lender_candidate_district",
+    "memo_code",
+    "memo_text_description",
+];
+
+const SC1_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "lender_organization_name",
+    "lender_street_1",
+    "lender_street_2",
+    "lender_city",
+    "lender_state",
+    "lender_zip_code",
+    "loan_amount",
+    "loan_interest_rate",
+    "loan_incurred_date",
+    "loan_due_date",
+    "loan_restructured",
+    "loan_incurred_date_original",
+    "credit_amount_this_draw",
+    "total_balance",
+    "others_liable",
+    "collateral",
+    "description",
+    "collateral_value_amount",
+    "perfected_interest",
+    "future_income",
+    "description_2",
+    "estimated_value",
+    "depository_account_established_date",
+    "ind_name_account_location",
+    "account_street_1",
+    "account_street_2",
+    "account_city",
+    "account_state",
+    "account_zip_code",
+    "dep_acct_auth_date_presidential",
+    "basis_of_loan_description",
+    "treasurer_last_name",
+    "treasurer_first_name",
+    "treasurer_middle_name",
+    "treasurer_prefix",
+    "treasurer_suffix",
+    "treasurer_date_signed",
+    "authorized_last_name",
+    "authorized_first_name",
+    "authorized_middle_name",
+    "authorized_prefix",
+    "authorized_suffix",
+    "authorized_title",
+    "authorized_date_signed",
+];
+
+const SC2_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "guarantor_last_name",
+    "guarantor_first_name",
+    "guarantor_middle_name",
+    "guarantor_prefix",
+    "guarantor_suffix",
+    "guarantor_street_1",
+    "guarantor_street_2",
+    "guarantor_city",
+    "guarantor_state",
+    "guarantor_zip_code",
+    "guarantor_employer",
+    "guarantor_occupation",
+    "guaranteed_amount",
+];
+
+const SD_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "entity_type",
+    "creditor_organization_name",
+    "creditor_last_name",
+    "creditor_first_name",
+    "creditor_middle_name",
+    "creditor_prefix",
+    "creditor_suffix",
+    "creditor_street_1",
+    "creditor_street_2",
+    "creditor_city",
+    "creditor_state",
+    "creditor_zip_code",
+    "purpose_of_debt_or_obligation",
+    "beginning_balance_this_period",
+    "incurred_amount_this_period",
+    "payment_amount_this_period",
+    "balance_at_close_this_period",
+];
+
+const SE_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "entity_type",
+    "payee_organization_name",
+    "payee_last_name",
+    "payee_first_name",
+    "payee_middle_name",
+    "payee_prefix",
+    "payee_suffix",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "election_code",
+    "election_other_description",
+    "dissemination_date",
+    "expenditure_amount",
+    "disbursement_date",
+    "calendar_y_t_d_per_election_office",
+    "expenditure_purpose_descrip",
+    "category_code",
+    "payee_cmtte_fec_id_number",
+    "support_oppose_code",
+    "so_candidate_id_number",
+    "so_candidate_last_name",
+    "so_candidate_first_name",
+    "so_candidate_middle_name",
+    "so_candidate_prefix",
+    "so_candidate_suffix",
+    "so_candidate_office",
+    "so_candidate_district",
+    "so_candidate_state",
+    "completing_last_name",
+    "completing_first_name",
+    "completing_middle_name",
+    "completing_prefix",
+    "completing_suffix",
+    "date_signed",
+    "memo_code",
+    "memo_text_description",
+];
+
+const SF_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "coordinated_expenditures",
+    "designating_committee_id_number",
+    "designating_committee_name",
+    "subordinate_committee_id_number",
+    "subordinate_committee_name",
+    "subordinate_street_1",
+    "subordinate_street_2",
+    "subordinate_city",
+    "subordinate_state",
+    "subordinate_zip_code",
+    "entity_type",
+    "payee_organization_name",
+    "payee_last_name",
+    "payee_first_name",
+    "payee_middle_name",
+    "payee_prefix",
+    "payee_suffix",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "expenditure_date",
+    "expenditure_amount",
+    "aggregate_general_elec_expended",
+    "expenditure_purpose_descrip",
+    "category_code",
+    "payee_committee_id_number",
+    "payee_candidate_id_number",
+    "payee_candidate_last_name",
+    "payee_candidate_first_name",
+    "payee_candidate_middle_name",
+    "payee_candidate_prefix",
+    "payee_candidate_suffix",
+    "payee_candidate_office",
+    "payee_candidate_state",
+    "payee_candidate_district",
+    "memo_code",
+    "memo_text_description",
+];
+
+const H4_8: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_name",
+    "payee_organization_name",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "accounting_or_designation",
+    "expenditure_date",
+    "total_amount",
+    "federal_share",
+    "nonfederal_share",
+    "event_year_to_date",
+    "expenditure_purpose_description",
+    "category_code",
+    "administrative_voter_drive_activity_indicator",
+    "fundraising_activity_indicator",
+    "exempt_activity_indicator",
+    "direct_candidate_support_activity_indicator",
+    "administrative_activity_indicator",
+    "general_voter_drive_activity_indicator",
+    "memo_code",
+    "memo_text_description",
+];
+
+/// Memo text records. `back_reference_tran_id_number` is the transaction id of the record the
+/// text belongs to (e.g. an itemization's `transaction_id`), and `back_reference_sched_form_name`
+/// that record's form type.
+const TEXT_8: &[&str] = &[
+    "rec_type",
+    "filer_committee_id_number",
+    "transaction_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_form_name",
+    "text",
+];
+
+/// Memo text records before version 6, which gave them no transaction id of their own.
+const TEXT_OLD: &[&str] = &[
+    "rec_type",
+    "filer_committee_id_number",
+    "back_reference_tran_id_number",
+    "back_reference_sched_form_name",
+    "text",
+];
+
+/// Headers of paper filing conversions, which name a `P` version (`P3.00`).
+const PAPER_HDR: &[&str] = &[
+    "record_type",
+    "ef_type",
+    "fec_version",
+    "soft_name",
+    "soft_ver",
+    "report_id",
+    "report_number",
+    "comment",
+];
+
+/// PAC and party summaries keyed in from paper, with only the period column of the main totals
+/// and the page image the row was read from.
+const PAPER_F3X: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "report_code",
+    "election_date",
+    "election_state",
+    "coverage_from_date",
+    "coverage_through_date",
+    "treasurer_name",
+    "date_signed",
+    "col_a_cash_on_hand_beginning_period",
+    "col_a_total_receipts",
+    "col_a_subtotal",
+    "col_a_total_disbursements",
+    "col_a_cash_on_hand_close_of_period",
+    "image_number",
+];
+
+/// Candidate committee summaries keyed in from paper.
+const PAPER_F3: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "committee_name",
+    "street_1",
+    "street_2",
+    "city",
+    "state",
+    "zip_code",
+    "report_code",
+    "election_date",
+    "election_state",
+    "election_district",
+    "coverage_from_date",
+    "coverage_through_date",
+    "treasurer_name",
+    "date_signed",
+    "col_a_total_contributions",
+    "col_a_total_receipts",
+    "col_a_total_disbursements",
+    "col_a_cash_on_hand_close_of_period",
+    "image_number",
+];
+
+/// Paper Schedule A, which keeps the contributor's name as written in one column.
+const PAPER_SA: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id",
+    "contributor_name",
+    "contributor_street_1",
+    "contributor_street_2",
+    "contributor_city",
+    "contributor_state",
+    "contributor_zip_code",
+    "contributor_employer",
+    "contributor_occupation",
+    "contribution_date",
+    "contribution_amount",
+    "contribution_aggregate",
+    "contribution_purpose_descrip",
+    "memo_code",
+    "memo_text_description",
+    "image_number",
+];
+
+/// Paper Schedule B, which keeps the payee's name as written in one column.
+const PAPER_SB: &[&str] = &[
+    "form_type",
+    "filer_committee_id_number",
+    "transaction_id",
+    "payee_name",
+    "payee_street_1",
+    "payee_street_2",
+    "payee_city",
+    "payee_state",
+    "payee_zip_code",
+    "expenditure_purpose_descrip",
+    "expenditure_date",
+    "expenditure_amount",
+    "memo_code",
+    "memo_text_description",
+    "image_number",
+];
+
+/// The newest FEC version the mappings cover, assumed for CSV exports, which declare none.
+pub const NEWEST_VERSION: &str = "8.4";
+
+/// All known mappings. Each form's mappings are listed newest first: filings of a version no
+/// mapping knows (say, a release newer than these mappings) use the first one listed.
+pub static MAPPINGS: &[FormMapping] = &[
+    FormMapping {
+        forms: &["HDR"],
+        prefix: false,
+        version: r"^[6-8]\.",
+        columns: HDR_8,
+    },
+    FormMapping {
+        forms: &["HDR"],
+        prefix: false,
+        version: r"^[1235]\.",
+        columns: HDR_OLD,
+    },
+    FormMapping {
+        forms: &["F3XN", "F3XA", "F3XT"],
+        prefix: false,
+        version: r"^8\.",
+        columns: F3X,
+    },
+    FormMapping {
+        forms: &["F3N", "F3A", "F3T"],
+        prefix: false,
+        version: r"^8\.",
+        columns: F3,
+    },
+    FormMapping {
+        forms: &["F3PN", "F3PA", "F3PT"],
+        prefix: false,
+        version: r"^8\.",
+        columns: F3P,
+    },
+    FormMapping {
+        forms: &["F99"],
+        prefix: false,
+        version: r"^[6-8]\.",
+        columns: F99,
+    },
+    FormMapping {
+        forms: &["F24N", "F24A"],
+        prefix: false,
+        version: r"^8\.",
+        columns: F24,
+    },
+    FormMapping {
+        forms: &["SA"],
+        prefix: true,
+        version: r"^[6-8]\.",
+        columns: SA_8,
+    },
+    FormMapping {
+        forms: &["SA"],
+        prefix: true,
+        version: r"^[35]\.",
+        columns: SA_OLD,
+    },
+    FormMapping {
+        forms: &["SB"],
+        prefix: true,
+        version: r"^[6-8]\.",
+        columns: SB_8,
+    },
+    FormMapping {
+        forms: &["SB"],
+        prefix: true,
+        version: r"^[35]\.",
+        columns: SB_OLD,
+    },
+    FormMapping {
+        forms: &["SC"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SC_8,
+    },
+    FormMapping {
+        forms: &["SC1"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SC1_8,
+    },
+    FormMapping {
+        forms: &["SC2"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SC2_8,
+    },
+    FormMapping {
+        forms: &["SD"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SD_8,
+    },
+    FormMapping {
+        forms: &["SE"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SE_8,
+    },
+    FormMapping {
+        forms: &["SF"],
+        prefix: true,
+        version: r"^8\.",
+        columns: SF_8,
+    },
+    FormMapping {
+        forms: &["H4"],
+        prefix: false,
+        version: r"^8\.",
+        columns: H4_8,
+    },
+    FormMapping {
+        forms: &["TEXT"],
+        prefix: false,
+        version: r"^[6-8]\.",
+        columns: TEXT_8,
+    },
+    FormMapping {
+        forms: &["TEXT"],
+        prefix: false,
+        version: r"^[35]\.",
+        columns: TEXT_OLD,
+    },
+];
+
+/// Mappings for conversions of paper filings, whose versions start with `P` (`P1`..`P3.x`).
+/// Only the forms and schedules the FEC keys in from paper are covered; rows of any other
+/// code are quarantined.
+pub static PAPER_MAPPINGS: &[FormMapping] = &[
+    FormMapping {
+        forms: &["HDR"],
+        prefix: false,
+        version: r"^P[1-3](\.|$)",
+        columns: PAPER_HDR,
+    },
+    FormMapping {
+        forms: &["F3XN", "F3XA", "F3XT"],
+        prefix: false,
+        version: r"^P[1-3](\.|$)",
+        columns: PAPER_F3X,
+    },
+    FormMapping {
+        forms: &["F3N", "F3A", "F3T"],
+        prefix: false,
+        version: r"^P[1-3](\.|$)",
+        columns: PAPER_F3,
+    },
+    FormMapping {
+        forms: &["SA"],
+        prefix: true,
+        version: r"^P[1-3](\.|$)",
+        columns: PAPER_SA,
+    },
+    FormMapping {
+        forms: &["SB"],
+        prefix: true,
+        version: r"^P[1-3](\.|$)",
+        columns: PAPER_SB,
+    },
+];
+
+/// Which set of mappings a filing's rows are looked up in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MappingSet {
+    /// Electronic filings, in `MAPPINGS`.
+    #[default]
+    Electronic,
+    /// Paper filing conversions, in `PAPER_MAPPINGS`.
+    Paper,
+}
+
+impl MappingSet {
+    /// The set a filing of `version` uses: paper for versions starting with `P`, electronic
+    /// otherwise.
+    pub fn for_version(version: &str) -> Self {
+        if is_paper_version(version) {
+            MappingSet::Paper
+        } else {
+            MappingSet::Electronic
+        }
+    }
+
+    /// The mappings in this set.
+    pub fn mappings(self) -> &'static [FormMapping] {
+        match self {
+            MappingSet::Electronic => MAPPINGS,
+            MappingSet::Paper => PAPER_MAPPINGS,
+        }
+    }
+}
+
+/// Whether `version` names a paper filing conversion (`P3.00`) rather than an electronic filing.
+pub fn is_paper_version(version: &str) -> bool {
+    version
+        .trim()
+        .chars()
+        .next()
+        .is_some_and(|c| c.eq_ignore_ascii_case(&'P'))
+}
+
+/// The type of a column's values, replicating FastFEC's `s`/`d`/`f` type codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Free text, written as-is.
+    String,
+    /// A date, usually `YYYYMMDD`.
+    Date,
+    /// A numeric amount.
+    Float,
+}
+
+impl ColumnType {
+    /// The single-character code FastFEC uses for this type.
+    pub fn code(self) -> char {
+        match self {
+            ColumnType::String => 's',
+            ColumnType::Date => 'd',
+            ColumnType::Float => 'f',
+        }
+    }
+}
+
+/// Column name patterns and their types, checked in order; unmatched columns are strings.
+static TYPE_RULES: &[(&str, ColumnType)] = &[
+    // Amounts whose names mention dates
+    (r"(^|_)to_date$|_y_t_d_", ColumnType::Float),
+    // Free-text loan terms and the report year are not amounts or dates
+    (r"_terms$|^col_b_year$", ColumnType::String),
+    (r"(^|_)date(_|$)", ColumnType::Date),
+    (r"^col_[ab]_", ColumnType::Float),
+    (
+        r"amount|aggregate|balance|_share$|^total_|_expended$|^estimated_value$|_this_period$|_amt$",
+        ColumnType::Float,
+    ),
+];
+
+/// Column name patterns of numeric columns that aren't currency amounts, and how their values
+/// are written; other numeric columns are amounts, written with two decimal places.
+static DOUBLE_FORMAT_RULES: &[(&str, DoubleFormat)] = &[
+    // Rates, percentages and coordinates keep their precision instead of being rounded to cents
+    (
+        r"(^|_)(rate|percent|percentage|pct|latitude|longitude)(_|$)",
+        DoubleFormat::RoundTrip,
+    ),
+];
+
+/// Normalize the raw form type field of a row to its canonical code.
+///
+/// Surrounding whitespace and double quotes are stripped and the code is upper-cased, so
+/// `sa11ai`, ` SA11AI ` and `"SA11AI"` all give `SA11AI`. Returns `None` if nothing is left or
+/// the code contains characters no form type uses.
+pub fn normalize_form_type(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    let unquoted = trimmed
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .unwrap_or(trimmed)
+        .trim();
+    let code = unquoted.to_ascii_uppercase();
+    if code.is_empty() || !code.chars().all(|c| c.is_ascii_alphanumeric() || c == '/') {
+        return None;
+    }
+    Some(code)
+}
+
+/// The compiled `version` pattern of each mapping in `set`, in the same order.
+fn version_matchers(set: MappingSet) -> &'static [Regex] {
+    static MATCHERS: OnceLock<[Vec<Regex>; 2]> = OnceLock::new();
+    let matchers = MATCHERS.get_or_init(|| {
+        [MappingSet::Electronic, MappingSet::Paper].map(|set| {
+            set.mappings()
+                .iter()
+                .map(|m| Regex::new(m.version).unwrap())
+                .collect()
+        })
+    });
+    &matchers[set as usize]
+}
+
+/// Whether any mapping's version pattern matches `version`, in the set the version belongs to.
+pub fn is_known_version(version: &str) -> bool {
+    let version = version.trim();
+    version_matchers(MappingSet::for_version(version))
+        .iter()
+        .any(|re| re.is_match(&version.to_ascii_uppercase()))
+}
+
+/// Find the mapping for a form type code, with its index and the form it matched.
+///
+/// The code is normalized with `normalize_form_type`, then tried whole and as progressively
+/// shorter prefixes against mappings that allow prefix matches, so the longest match wins
+/// (`SC1/10` is `SC1`, not `SC`). With a `version`, only mappings whose pattern matches it are
+/// considered, the most specific first; a version no mapping knows uses the newest mapping.
+/// Only mappings in `set` are searched.
+fn find_mapping(
+    form_type: &str,
+    set: MappingSet,
+    version: Option<&str>,
+) -> Option<(usize, &'static FormMapping, &'static str)> {
+    let form_type = normalize_form_type(form_type)?;
+    // A version no mapping knows is looked up like no version at all, finding the newest
+    let version = version
+        .map(|version| version.trim().to_ascii_uppercase())
+        .filter(|version| version.is_empty() || is_known_version(version));
+    let matchers = version_matchers(set);
+    (1..=form_type.len())
+        .rev()
+        .filter(|&len| form_type.is_char_boundary(len))
+        .find_map(|len| {
+            let candidate = &form_type[..len];
+            let exact = len == form_type.len();
+            let mut found = set.mappings().iter().enumerate().filter_map(|(index, m)| {
+                let form = m.forms.iter().find(|f| **f == candidate)?;
+                (exact || m.prefix).then_some((index, m, *form))
+            });
+            match version {
+                // The longest match is the most specific; ties go to the first listed
+                Some(ref version) => found
+                    .filter_map(|found| Some((matchers[found.0].find(version)?.len(), found)))
+                    .min_by_key(|(matched, _)| Reverse(*matched))
+                    .map(|(_, found)| found),
+                None => found.next(),
+            }
+        })
+}
+
+/// Resolve a form type code, in any case or quoting, to the mapped form whose columns it uses
+/// in any version: `SA11AI` => `SA`, `SC1/10` => `SC1`, `F3XN` => `F3XN`.
+///
+/// Returns `None` if no mapping matches the code or a prefix of it.
+pub fn resolve_form_type(form_type: &str) -> Option<&'static str> {
+    resolve_form_type_in(form_type, MappingSet::Electronic)
+}
+
+/// Resolve a form type code like `resolve_form_type`, against the mappings in `set`.
+pub fn resolve_form_type_in(form_type: &str, set: MappingSet) -> Option<&'static str> {
+    find_mapping(form_type, set, None).map(|(_, _, form)| form)
+}
+
+/// Look up the column names for a form type code and FEC version, matching the code by longest
+/// prefix as `resolve_form_type` does and the version by its most specific pattern.
+///
+/// Returns `None` if no mapping covers this form/version combination. A version no mapping
+/// knows at all gets the newest columns of the form. Paper versions (`P3.00`) are looked up in
+/// `PAPER_MAPPINGS`.
+pub fn get_columns(form_type: &str, version: &str) -> Option<&'static [&'static str]> {
+    find_mapping(form_type, MappingSet::for_version(version), Some(version))
+        .map(|(_, m, _)| m.columns)
+}
+
+/// Look up the column types for a form type code and FEC version.
+///
+/// The returned slice lines up with `get_columns` for the same arguments.
+pub fn get_column_types(form_type: &str, version: &str) -> Option<&'static [ColumnType]> {
+    static TYPES: OnceLock<[Vec<Vec<ColumnType>>; 2]> = OnceLock::new();
+    let types = TYPES.get_or_init(|| {
+        [MappingSet::Electronic, MappingSet::Paper].map(|set| {
+            set.mappings()
+                .iter()
+                .map(|m| m.columns.iter().map(|c| column_type(c)).collect())
+                .collect()
+        })
+    });
+    let set = MappingSet::for_version(version);
+    find_mapping(form_type, set, Some(version))
+        .map(|(index, _, _)| types[set as usize][index].as_slice())
+}
+
+/// Determine the type of a column from its name using `TYPE_RULES`.
+pub fn column_type(column: &str) -> ColumnType {
+    static RULES: OnceLock<Vec<(Regex, ColumnType)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        TYPE_RULES
+            .iter()
+            .map(|(pattern, ty)| (Regex::new(pattern).unwrap(), *ty))
+            .collect()
+    });
+    rules
+        .iter()
+        .find(|(re, _)| re.is_match(column))
+        .map(|(_, ty)| *ty)
+        .unwrap_or(ColumnType::String)
+}
+
+/// Determine how the values of numeric column `column` are written using
+/// `DOUBLE_FORMAT_RULES`: amounts get two decimal places (`3` => `3.00`), as FastFEC writes them.
+pub fn column_double_format(column: &str) -> DoubleFormat {
+    static RULES: OnceLock<Vec<(Regex, DoubleFormat)>> = OnceLock::new();
+    let rules = RULES.get_or_init(|| {
+        DOUBLE_FORMAT_RULES
+            .iter()
+            .map(|(pattern, format)| (Regex::new(pattern).unwrap(), *format))
+            .collect()
+    });
+    rules
+        .iter()
+        .find(|(re, _)| re.is_match(column))
+        .map(|(_, format)| *format)
+        .unwrap_or_default()
+}
+
+/// Normalize a column name for use in a header row: lowercased, with each run of spaces and
+/// punctuation turned into one underscore (`Contributor Organization Name` =>
+/// `contributor_organization_name`, `Date/Time` => `date_time`). Leading and trailing
+/// punctuation is dropped; a name with nothing else becomes `column`.
+pub fn normalize_column_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for word in name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        if !normalized.is_empty() {
+            normalized.push('_');
+        }
+        normalized.extend(word.chars().flat_map(char::to_lowercase));
+    }
+    if normalized.is_empty() {
+        normalized.push_str("column");
+    }
+    normalized
+}
+
+/// Normalize a header row's column names with `normalize_column_name`, telling apart names
+/// that end up the same with numeric suffixes: the second `amount` becomes `amount_2`.
+pub fn normalize_column_names<S: AsRef<str>>(columns: &[S]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    columns
+        .iter()
+        .map(|column| {
+            let base = normalize_column_name(column.as_ref());
+            let mut name = base.clone();
+            let mut suffix = 1;
+            while !seen.insert(name.clone()) {
+                suffix += 1;
+                name = format!("{base}_{suffix}");
+            }
+            name
+        })
+        .collect()
+}
+
+/// The number of columns of the widest mapping in `set`, which rows of every mapped form fit in.
+pub fn widest_columns(set: MappingSet) -> usize {
+    set.mappings()
+        .iter()
+        .map(|mapping| mapping.columns.len())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Build generic column names (`col_1`..`col_n`) for rows without a known mapping.
+pub fn generic_columns(num_fields: usize) -> Vec<String> {
+    (1..=num_fields).map(|i| format!("col_{i}")).collect()
+}
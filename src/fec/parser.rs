@@ -3,15 +3,111 @@
 //!
 //! We read raw bytes from a `BufRead`, use `decode_line` to ensure they're valid UTF-8
 //! (or convert from ISO-8859-1), then process them for version info, form types, etc.
+//! The line reading itself lives in `records`; this module turns lines into records and
+//! writes them out.
 
 use anyhow::{anyhow, Context, Result};
 use csv::ReaderBuilder;
-use std::io::BufRead;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 // Bring in our FecContext for parse state
-use crate::{encoding::decode_line, writer::WriterContext};
+use crate::{
+    encoding::{scrub_nul_bytes, split_line_ending, strip_bom, strip_line_ending},
+    errors::FecError,
+    writer::{format_date_with, format_double_with, WriterContext, CSV_EXTENSION, TEXT_EXTENSION},
+};
 
-use super::context::FecContext;
+use super::context::{
+    delimiter_for_version, Checkpoint, Delimiter, FecContext, TextEvent, TextState,
+};
+use super::header::{
+    is_csv_export_header, is_form_type_column, is_legacy_header_end, is_legacy_header_start,
+    parse_date, parse_report_id, AmendmentInfo, CoverInfo, HeaderInfo, HeaderVariant,
+    LegacyHeaderBuilder,
+};
+use super::mappings::{
+    column_double_format, generic_columns, get_column_types, get_columns, is_known_version,
+    is_paper_version, normalize_column_names, normalize_form_type, resolve_form_type,
+    resolve_form_type_in, widest_columns, ColumnType, MappingSet, NEWEST_VERSION,
+};
+use super::records::{
+    check_nul_bytes, decode_checked, finish_input, read_physical_line, CountingReader, FecRecord,
+    FecRecords, RawBatch, RecordKind,
+};
+use super::validation::{Severity, ValidationIssue, ValidationReport};
+use rayon::prelude::*;
+
+/// The output file name for rows whose form type is unparseable or matches no mapping of any
+/// version, which are quarantined there verbatim under `UNKNOWN_FORM_COLUMNS`.
+pub const UNKNOWN_FORM_FILENAME: &str = "unknown_forms";
+
+/// The columns of the quarantine file: where each row came from and its decoded line as read.
+pub const UNKNOWN_FORM_COLUMNS: &[&str] = &["line_number", "form_type", "line"];
+
+/// The output file name for a filing's header metadata, one row under `HEADER_COLUMNS`.
+pub const HEADER_FILENAME: &str = "header";
+
+/// The columns of the header file: the filing's ID, its `HDR` fields and how its input was
+/// encoded.
+pub const HEADER_COLUMNS: &[&str] = &[
+    "filing_id",
+    "record_type",
+    "ef_type",
+    "fec_version",
+    "soft_name",
+    "soft_ver",
+    "report_id",
+    "report_number",
+    "comment",
+    "delimiter",
+    "byte_order_mark",
+];
+
+/// The output file name for the coverage of a filing's report, one row under `COVER_COLUMNS`
+/// taken from its first cover record with coverage dates.
+pub const COVER_FILENAME: &str = "cover";
+
+/// The columns of the cover file (see `CoverInfo`).
+pub const COVER_COLUMNS: &[&str] = &[
+    "filing_id",
+    "form_type",
+    "filer_committee_id_number",
+    "coverage_from_date",
+    "coverage_through_date",
+    "report_type",
+];
+
+/// The column leading each output row with its filing's ID (see `FecContext::include_filing_id`).
+pub const FILING_ID_COLUMN: &str = "filing_id";
+
+/// The column after `FILING_ID_COLUMN` telling whether the filing amends an earlier report
+/// (`true` or `false`, from `FecContext::amendment`; empty before the cover record).
+pub const IS_AMENDMENT_COLUMN: &str = "is_amendment";
+
+/// The column after `IS_AMENDMENT_COLUMN` holding the ID of the filing an amendment amends,
+/// empty for new filings.
+pub const ORIGINAL_FILING_ID_COLUMN: &str = "original_filing_id";
+
+/// The column holding the input line each output row's record starts on (see
+/// `FecContext::with_row_numbers`), after any `FILING_ID_COLUMN` and its amendment columns.
+pub const SOURCE_LINE_COLUMN: &str = "source_line";
+
+/// The file every form's rows are written to with `ParseOptions::combined_output`
+/// (`filing.csv`).
+pub const COMBINED_FILENAME: &str = "filing";
+
+/// The column leading each row of the combined file with the form whose file it would otherwise
+/// go to, e.g. `SA` (see `ParseOptions::combined_output`).
+pub const FORM_TYPE_COLUMN: &str = "form_type";
+
+/// The form type whose records may be followed by a `[BEGINTEXT]` free-text block.
+const F99_FORM_TYPE: &str = "F99";
 
 /// Primary function to parse the FEC data stream.
 ///
@@ -19,105 +115,1457 @@ use super::context::FecContext;
 /// - `reader`: A buffered reader over the input data (file or STDIN).
 /// - `writer`: Manages output operations.
 ///
+/// Reads the filing through `FecRecords` and writes every record after the header to the
+/// output file for its form type, padding short rows (see `ParseOptions::pad_rows`).
+///
 /// Returns `Ok(())` on success or an error for unrecoverable issues.
 pub fn parse_fec<R: BufRead>(
     ctx: &mut FecContext,
     reader: &mut R,
     writer: &mut WriterContext,
 ) -> Result<()> {
-    let mut buffer = Vec::new();
+    let options = ParseOptions::default();
+    let mut filings = 0;
+    let mut records = FecRecords::new(ctx, reader);
+    while let Some(record) = records.next() {
+        let record = record?;
+        // The header only feeds the context
+        if record.kind == RecordKind::Header {
+            continue;
+        }
+        follow_filing(records.context(), writer, &mut filings)?;
+        write_record(records.context_mut(), writer, &record, &options).with_context(|| {
+            format!(
+                "Failed to write the record from line {}",
+                record.line_number
+            )
+        })?;
+        mark_written(records.context_mut(), &record);
+    }
+    // A filing with no records after its header still gets its header file
+    follow_filing(records.context(), writer, &mut filings)
+}
 
-    // ------------------------------------------------------------------
-    // Step 1: Read and decode the "header" line
-    // ------------------------------------------------------------------
-    buffer.clear();
-    let bytes_read = reader
-        .read_until(b'\n', &mut buffer)
-        .context("Failed to read the header line")?;
-    if bytes_read == 0 {
-        return Err(anyhow!("No data to parse."));
+/// The size of the batches of input handed to each thread by default.
+pub const DEFAULT_CHUNK_BYTES: usize = 4 * 1024 * 1024;
+
+/// How often the progress callback is invoked by default.
+pub const DEFAULT_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Rows written between checks of the clock for a progress report.
+const PROGRESS_CHECK_ROWS: usize = 1024;
+
+/// How far a parse has got, as passed to `ParseOptions::on_progress`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Bytes of (decompressed) input read so far.
+    pub bytes_read: u64,
+    /// The input's total size, if known (see `ParseOptions::with_total_bytes`).
+    pub total_bytes: Option<u64>,
+    /// Records written so far, not counting the header.
+    pub rows: usize,
+    /// Where the parse could be resumed from, just past the last record written (see
+    /// `ParseOptions::resume_from`).
+    pub checkpoint: Checkpoint,
+    /// Time since the parse started.
+    pub elapsed: Duration,
+}
+
+/// A callback receiving `Progress` reports.
+pub type ProgressFn = Arc<dyn Fn(Progress) + Send + Sync>;
+
+/// Options for `parse_fec_with_options`.
+#[derive(Clone)]
+pub struct ParseOptions {
+    /// Threads to parse the body of the filing on; `1` parses it sequentially.
+    pub threads: usize,
+    /// Roughly how many bytes of input each thread parses at a time.
+    pub chunk_bytes: usize,
+    /// Called with the parse's progress every `progress_interval`, and once at the end.
+    pub on_progress: Option<ProgressFn>,
+    /// The least time between two progress reports.
+    pub progress_interval: Duration,
+    /// The size of the input, reported as `Progress::total_bytes`.
+    pub total_bytes: Option<u64>,
+    /// Run the whole parse without writing anything, collecting problems in `ctx.issues`.
+    pub validate_only: bool,
+    /// Pad rows shorter than their form's mapping with empty fields, so every row of a file has
+    /// at least the header's width. Longer rows keep their extra fields unless
+    /// `truncate_extra_fields` is set.
+    pub pad_rows: bool,
+    /// Drop the fields of rows longer than their form's mapping past its width, warning about
+    /// (and counting in `ctx.truncated_rows`) rows that lose anything but empty fields. Rows of
+    /// forms without a mapping are never truncated.
+    pub truncate_extra_fields: bool,
+    /// Where to resume an interrupted parse, with the header its input began with.
+    pub resume: Option<(Checkpoint, HeaderInfo)>,
+    /// Only write rows of these forms, by mapped form (`SA`) or full code (`SA11AI`), if set.
+    /// Cover records are always written, and rows left out are still parsed and counted.
+    pub forms: Option<HashSet<String>>,
+    /// Write every form's rows to one `COMBINED_FILENAME` file instead of a file per form, each
+    /// led by a `FORM_TYPE_COLUMN`, under a generic header: `form_type`, the prefix columns
+    /// (`filing_id`, `is_amendment`, `original_filing_id`, `source_line`) if asked for, then
+    /// `col_1` to `col_N` for the widest mapping's `N` columns. With `pad_rows`, rows are padded
+    /// to all `N` columns; otherwise they are left ragged, with the fields they were filed with.
+    /// The header, cover and quarantine files are still written apart.
+    pub combined_output: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            threads: 1,
+            chunk_bytes: DEFAULT_CHUNK_BYTES,
+            on_progress: None,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            total_bytes: None,
+            validate_only: false,
+            pad_rows: true,
+            truncate_extra_fields: false,
+            resume: None,
+            forms: None,
+            combined_output: false,
+        }
+    }
+}
+
+impl fmt::Debug for ParseOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParseOptions")
+            .field("threads", &self.threads)
+            .field("chunk_bytes", &self.chunk_bytes)
+            .field("on_progress", &self.on_progress.is_some())
+            .field("progress_interval", &self.progress_interval)
+            .field("total_bytes", &self.total_bytes)
+            .field("validate_only", &self.validate_only)
+            .field("pad_rows", &self.pad_rows)
+            .field("truncate_extra_fields", &self.truncate_extra_fields)
+            .field("resume", &self.resume)
+            .field("forms", &self.forms)
+            .field("combined_output", &self.combined_output)
+            .finish()
+    }
+}
+
+impl ParseOptions {
+    /// Parse the body of the filing on `threads` threads.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Hand each thread about `chunk_bytes` of input at a time.
+    pub fn with_chunk_bytes(mut self, chunk_bytes: usize) -> Self {
+        self.chunk_bytes = chunk_bytes;
+        self
+    }
+
+    /// Report the parse's progress to `callback` at most every `progress_interval`, and once
+    /// more when the parse has finished.
+    pub fn on_progress(mut self, callback: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Report progress at most once per `interval`.
+    pub fn with_progress_interval(mut self, interval: Duration) -> Self {
+        self.progress_interval = interval;
+        self
+    }
+
+    /// Report `total_bytes` as the size of the input, e.g. the length of an uncompressed file.
+    pub fn with_total_bytes(mut self, total_bytes: u64) -> Self {
+        self.total_bytes = Some(total_bytes);
+        self
+    }
+
+    /// Enable or disable validate-only mode, where nothing is written and problems are
+    /// collected as `ValidationIssue`s instead of printed (see `validate_fec`).
+    pub fn with_validate_only(mut self, validate_only: bool) -> Self {
+        self.validate_only = validate_only;
+        self
+    }
+
+    /// Enable or disable padding short rows to their mapping's width; disable it to write rows
+    /// with exactly the fields they were filed with.
+    pub fn with_pad_rows(mut self, pad_rows: bool) -> Self {
+        self.pad_rows = pad_rows;
+        self
+    }
+
+    /// Enable or disable writing every form's rows to one combined file, led by their form (see
+    /// `combined_output`).
+    pub fn with_combined_output(mut self, combined_output: bool) -> Self {
+        self.combined_output = combined_output;
+        self
+    }
+
+    /// Enable or disable cutting rows longer than their mapping down to its width.
+    pub fn with_truncate_extra_fields(mut self, truncate_extra_fields: bool) -> Self {
+        self.truncate_extra_fields = truncate_extra_fields;
+        self
+    }
+
+    /// Only write rows of `forms`, e.g. `SA` and `SB` for the contribution and disbursement
+    /// schedules, besides the cover records. Codes are normalized like form types.
+    pub fn with_forms(mut self, forms: HashSet<String>) -> Self {
+        let forms = forms
+            .iter()
+            .filter_map(|f| normalize_form_type(f))
+            .collect();
+        self.forms = Some(forms);
+        self
+    }
+
+    /// Resume a parse that stopped at `checkpoint` (`ctx.checkpoint` or
+    /// `Progress::checkpoint`), using the header read from the start of the input before,
+    /// `saved_header`, instead of reading it again.
+    ///
+    /// The parse carries on from the record after the checkpoint, appending to the output files
+    /// of the earlier run without repeating their header rows, given a writer in
+    /// `OutputMode::Append`. `ctx.skip` is not applied again.
+    /// In a concatenated input, `ctx.fec_id` must be the ID of the filing the checkpoint is in.
+    pub fn resume_from(mut self, checkpoint: Checkpoint, saved_header: HeaderInfo) -> Self {
+        self.resume = Some((checkpoint, saved_header));
+        self
+    }
+}
+
+/// Like `parse_fec`, but parses the body of the filing on `options.threads` threads and reports
+/// progress to `options.on_progress`.
+///
+/// After the header, the input is read in newline-aligned chunks that never split a quoted
+/// record or an F99 text block. Chunks are decoded and split into records on a thread pool and
+/// written in their original order, so the output matches a sequential parse byte for byte;
+/// only warnings may be printed out of order.
+///
+/// Summary mode and `ctx.limit` stop reading early, so they always parse sequentially.
+///
+/// With `options.validate_only`, `writer` is never called and problems are collected in
+/// `ctx.issues` instead of printed; unrecoverable errors are still returned.
+///
+/// With `options.resume`, `reader` must be at the start of the input, and is read up to the
+/// checkpoint without parsing anything; seekable input can skip there with
+/// `parse_fec_seekable` instead.
+pub fn parse_fec_with_options<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    writer: &mut WriterContext,
+    options: &ParseOptions,
+) -> Result<()> {
+    if let Some((checkpoint, _)) = &options.resume {
+        let skipped = io::copy(&mut reader.take(checkpoint.offset), &mut io::sink())
+            .map_err(FecError::Io)
+            .context("Failed to read up to the resume checkpoint")?;
+        if skipped < checkpoint.offset {
+            return Err(anyhow!(
+                "The input ended at byte {}, before the resume checkpoint at byte {}.",
+                skipped,
+                checkpoint.offset
+            ));
+        }
+    }
+    parse_positioned(ctx, reader, writer, options)
+}
+
+/// Like `parse_fec_with_options`, but with `options.resume`, `reader` is sought straight to
+/// the checkpoint instead of being read up to it.
+///
+/// The checkpoint's offset counts bytes of the input as parsed, so the reader must not be a
+/// compressed file being decompressed on the fly.
+pub fn parse_fec_seekable<R: BufRead + Seek>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    writer: &mut WriterContext,
+    options: &ParseOptions,
+) -> Result<()> {
+    if let Some((checkpoint, _)) = &options.resume {
+        reader
+            .seek(SeekFrom::Start(checkpoint.offset))
+            .map_err(FecError::Io)
+            .context("Failed to seek to the resume checkpoint")?;
+    }
+    parse_positioned(ctx, reader, writer, options)
+}
+
+/// Parse as `parse_fec_with_options` does, with `reader` already at any resume checkpoint.
+fn parse_positioned<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    writer: &mut WriterContext,
+    options: &ParseOptions,
+) -> Result<()> {
+    let mut filings = 0;
+    if !options.validate_only {
+        run_parse(ctx, reader, options, &mut |ctx, record| {
+            follow_filing(ctx, writer, &mut filings)?;
+            write_record(ctx, writer, record, options).with_context(|| {
+                format!(
+                    "Failed to write the record from line {}",
+                    record.line_number
+                )
+            })
+        })?;
+        return follow_filing(ctx, writer, &mut filings);
+    }
+
+    ctx.issues.get_or_insert_with(Vec::new);
+    let mut checked_forms = HashSet::new();
+    run_parse(ctx, reader, options, &mut |ctx, record| {
+        // Forms without a mapping are warned about where their header row would be written;
+        // quarantined rows were warned about as they were parsed
+        if record.raw_line.is_some() {
+            return Ok(());
+        }
+        // Each filing of a concatenated input has its own files
+        if ctx.filings.len() > filings {
+            filings = ctx.filings.len();
+            checked_forms.clear();
+        }
+        let filename = output_filename(ctx, record);
+        if !checked_forms.contains(&filename) {
+            header_columns(ctx, &filename, record);
+            checked_forms.insert(filename);
+        }
+        Ok(())
+    })
+}
+
+/// Run the whole parse pipeline without writing anything, returning the problems found.
+///
+/// Problems that would be warned about are collected as `Severity::Warning` issues; those strict
+/// mode fails on are collected as `Severity::Error` issues and parsing carries on past them,
+/// unless `ctx.strict` is set. An unrecoverable error ends the parse as a final error issue.
+pub fn validate_fec<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    options: &ParseOptions,
+) -> ValidationReport {
+    let options = options.clone().with_validate_only(true);
+    let mut writer = WriterContext::builder()
+        .write_to_disk(false)
+        .buffer_size(0)
+        .discard_output()
+        .build()
+        .expect("a writer discarding uncompressed CSV always builds");
+    let result = parse_fec_with_options(ctx, reader, &mut writer, &options);
+
+    let mut issues = ctx.issues.take().unwrap_or_default();
+    // Batches parsed on other threads are checked for mappings after they are parsed
+    issues.sort_by_key(|issue| issue.line);
+    if let Err(e) = result {
+        issues.push(ValidationIssue {
+            line: ctx.line_number,
+            severity: Severity::Error,
+            message: format!("{e:#}"),
+        });
+    }
+    ValidationReport { issues }
+}
+
+/// Parse the filing read from `reader`, handing every record after the header to `emit`.
+fn run_parse<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    options: &ParseOptions,
+    emit: &mut EmitFn<'_>,
+) -> Result<()> {
+    let mut progress = ProgressTracker::new(options);
+    let mut records = FecRecords::new(ctx, reader);
+    if let Some((checkpoint, header)) = &options.resume {
+        let ctx = records.resume_at(*checkpoint);
+        ctx.delimiter = ctx.delimiter_override.unwrap_or(header.delimiter);
+        set_header(ctx, header.clone());
+        ctx.filings = vec![ctx.fec_id.clone()];
+    }
+    if options.threads <= 1 || records.context().summary || records.context().limit > 0 {
+        parse_sequential(&mut records, emit, &mut progress)?;
+    } else {
+        parse_parallel(&mut records, emit, options, &mut progress)?;
+    }
+    progress.finish(records.bytes_read(), records.context().checkpoint);
+    Ok(())
+}
+
+/// Receives each record after the header, with the parse state.
+type EmitFn<'a> = dyn FnMut(&mut FecContext, &FecRecord) -> Result<()> + 'a;
+
+/// Emit every record after the header, as `parse_fec` does, reporting progress.
+fn parse_sequential<R: BufRead>(
+    records: &mut FecRecords<'_, R>,
+    emit: &mut EmitFn<'_>,
+    progress: &mut ProgressTracker,
+) -> Result<()> {
+    while let Some(record) = records.next() {
+        let record = record?;
+        if record.kind == RecordKind::Header {
+            continue;
+        }
+        emit(records.context_mut(), &record)?;
+        mark_written(records.context_mut(), &record);
+        progress.add_rows(1, records.bytes_read(), records.context().checkpoint);
     }
+    Ok(())
+}
 
-    let (decoded_header, info_header) = decode_line(&buffer);
-    ctx.use_ascii28 = info_header;
-    parse_header(ctx, &decoded_header)?;
+/// Parse the body of the filing in batches on `options.threads` threads.
+fn parse_parallel<R: BufRead>(
+    records: &mut FecRecords<'_, R>,
+    emit: &mut EmitFn<'_>,
+    options: &ParseOptions,
+    progress: &mut ProgressTracker,
+) -> Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+        .context("Failed to start the parsing threads")?;
 
-    // ------------------------------------------------------------------
-    // Step 2: Main parse loop for all subsequent lines
-    // ------------------------------------------------------------------
+    records.skip_header()?;
+    let mut initial = batch_context(records.context());
     loop {
-        buffer.clear();
-        let bytes_read = reader
-            .read_until(b'\n', &mut buffer)
-            .context("Failed to read a line from the input")?;
-        if bytes_read == 0 {
-            break; // EOF
+        let mut batches = Vec::with_capacity(options.threads);
+        while batches.len() < options.threads {
+            let Some(batch) = records.read_batch(options.chunk_bytes) else {
+                break;
+            };
+            let last = batch.last || batch.next_filing;
+            batches.push(batch);
+            if last {
+                break;
+            }
+        }
+        if batches.is_empty() {
+            return Ok(());
+        }
+
+        let results: Vec<_> = pool.install(|| {
+            batches
+                .par_iter()
+                .map(|batch| {
+                    let mut worker = initial.clone();
+                    let (parsed, error) = process_batch(&mut worker, batch);
+                    (worker, parsed, error)
+                })
+                .collect()
+        });
+
+        for (batch, (mut worker, parsed, error)) in batches.into_iter().zip(results) {
+            merge_batch_context(records.context_mut(), &mut worker);
+            for record in &parsed {
+                emit(records.context_mut(), record)?;
+                mark_written(records.context_mut(), record);
+            }
+            let checkpoint = records.context().checkpoint;
+            progress.add_rows(parsed.len(), records.bytes_read(), checkpoint);
+            if let Some(e) = error.or(batch.error) {
+                return Err(e);
+            }
+            if batch.last {
+                return Ok(());
+            }
+            if batch.next_filing {
+                records.read_next_header()?;
+                initial = batch_context(records.context());
+            }
         }
+    }
+}
 
-        let (decoded_line, info_line) = decode_line(&buffer);
-        ctx.use_ascii28 = info_line;
-        parse_line(ctx, &decoded_line, writer)?;
+/// Move `ctx.checkpoint` past `record` once it has been handed out.
+///
+/// A parse can't resume inside a streamed F99 text block, so the checkpoint only moves past one
+/// once its last chunk is out.
+fn mark_written(ctx: &mut FecContext, record: &FecRecord) {
+    if !record.text_continues {
+        ctx.checkpoint = Checkpoint::after(record);
+    }
+}
+
+/// The context batches start from: the state the last header left behind, without its issues
+/// or counts, which `merge_batch_context` adds back.
+fn batch_context(ctx: &FecContext) -> FecContext {
+    let mut initial = ctx.clone();
+    if let Some(issues) = &mut initial.issues {
+        issues.clear();
+    }
+    initial.warnings = 0;
+    initial.mismatched_rows = 0;
+    initial.unknown_form_rows = 0;
+    initial.truncated_rows = 0;
+    initial.nul_bytes = 0;
+    initial
+}
+
+/// Rate-limits the calls to `ParseOptions::on_progress`.
+struct ProgressTracker {
+    callback: Option<ProgressFn>,
+    interval: Duration,
+    total_bytes: Option<u64>,
+    started: Instant,
+    last_report: Instant,
+    rows: usize,
+    unchecked_rows: usize, // Rows written since the clock was last checked
+}
+
+impl ProgressTracker {
+    fn new(options: &ParseOptions) -> Self {
+        let now = Instant::now();
+        ProgressTracker {
+            callback: options.on_progress.clone(),
+            interval: options.progress_interval,
+            total_bytes: options.total_bytes,
+            started: now,
+            last_report: now,
+            rows: 0,
+            unchecked_rows: 0,
+        }
+    }
+
+    /// Count `rows` more written rows, reporting progress if it's been long enough.
+    fn add_rows(&mut self, rows: usize, bytes_read: u64, checkpoint: Checkpoint) {
+        if self.callback.is_none() {
+            return;
+        }
+        self.rows += rows;
+        self.unchecked_rows += rows;
+        if self.unchecked_rows < PROGRESS_CHECK_ROWS {
+            return;
+        }
+        self.unchecked_rows = 0;
+        if self.last_report.elapsed() >= self.interval {
+            self.report(bytes_read, checkpoint);
+        }
+    }
+
+    /// Report the final progress of a parse.
+    fn finish(&mut self, bytes_read: u64, checkpoint: Checkpoint) {
+        self.report(bytes_read, checkpoint);
+    }
+
+    fn report(&mut self, bytes_read: u64, checkpoint: Checkpoint) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        self.last_report = Instant::now();
+        callback(Progress {
+            bytes_read,
+            total_bytes: self.total_bytes,
+            rows: self.rows,
+            checkpoint,
+            elapsed: self.started.elapsed(),
+        });
+    }
+}
+
+/// Parse a batch of raw lines into records with `ctx`, stopping at the first error.
+///
+/// Returns the records parsed before any error, and the error.
+fn process_batch(
+    ctx: &mut FecContext,
+    batch: &RawBatch,
+) -> (Vec<FecRecord>, Option<anyhow::Error>) {
+    let mut out = VecDeque::new();
+    for line in &batch.lines {
+        ctx.line_number = line.line_number;
+        (ctx.line_start, ctx.line_end) = (line.start, line.end);
+        let (bytes, ending) = split_line_ending(&line.bytes);
+        let result = decode_checked(ctx, bytes)
+            .and_then(|decoded| process_line(ctx, &decoded, ending, &mut out))
+            .with_context(|| format!("Failed to parse line {}", line.line_number));
+        if let Err(e) = result {
+            return (out.into(), Some(e));
+        }
+    }
+    if (batch.last || batch.next_filing) && batch.error.is_none() {
+        finish_input(ctx, &mut out);
+    }
+    (out.into(), None)
+}
+
+/// Fold the state a batch left in its worker context back into `ctx`.
+fn merge_batch_context(ctx: &mut FecContext, worker: &mut FecContext) {
+    ctx.warnings += worker.warnings;
+    ctx.mismatched_rows += worker.mismatched_rows;
+    ctx.unknown_form_rows += worker.unknown_form_rows;
+    ctx.nul_bytes += worker.nul_bytes;
+    if worker.form_type.is_some() {
+        ctx.form_type = worker.form_type.clone();
+        ctx.num_fields = worker.num_fields;
+    }
+    if ctx.amendment.is_none() {
+        ctx.amendment = worker.amendment.clone();
+    }
+    if let (Some(issues), Some(found)) = (&mut ctx.issues, &mut worker.issues) {
+        issues.append(found);
+    }
+}
+
+/// Parse the FEC data stream, handing each record after the header to `callback` instead of
+/// writing it out.
+///
+/// - `ctx`: Tracks state (version, form type, etc.); the header is in `ctx.header` once the
+///   first record arrives.
+/// - `reader`: A buffered reader over the input data (file or STDIN).
+/// - `callback`: Receives each record's form type, fields and line number. Returning
+///   `ControlFlow::Break` stops parsing without reading any further input.
+///
+/// Errors returned by `callback` are propagated with the line of the record that caused them.
+pub fn parse_fec_with_callback<R, F>(ctx: &mut FecContext, reader: R, mut callback: F) -> Result<()>
+where
+    R: BufRead,
+    F: FnMut(&FecRecord) -> Result<ControlFlow<()>>,
+{
+    let mut records = FecRecords::new(ctx, reader);
+    while let Some(record) = records.next() {
+        let record = record?;
+        if record.kind == RecordKind::Header {
+            continue;
+        }
+        let flow = callback(&record)
+            .with_context(|| format!("Record callback failed on line {}", record.line_number))?;
+        mark_written(records.context_mut(), &record);
+        if flow.is_break() {
+            break;
+        }
     }
 
     Ok(())
 }
 
-/// Parse a single non-header line.
+/// Parse a single non-header line and write the records it completes via `writer`.
 ///
 /// - Handles F99 text blocks.
-/// - Updates `ctx` based on parsed data.
-/// - Writes output via `writer`.
+/// - Pads short rows to their mapping's width, as `ParseOptions::default()` does.
+/// - Strips or replaces NUL characters by `ctx.nul_policy` before splitting the line.
+/// - Updates `ctx` based on parsed data; the version is only ever set by the header.
 pub fn parse_line(ctx: &mut FecContext, line: &str, writer: &mut WriterContext) -> Result<()> {
+    check_nul_bytes(ctx, line.matches('\0').count())?;
+    let line = scrub_nul_bytes(line, ctx.nul_policy);
+    let mut records = VecDeque::new();
+    process_line(ctx, &line, "\n", &mut records)?;
+    for record in &records {
+        write_record(ctx, writer, record, &ParseOptions::default())?;
+    }
+    Ok(())
+}
+
+/// Process a single decoded non-header line, queueing the records it completes in `out`.
+///
+/// - `ending`: the line ending the line was read with, kept in F99 text blocks.
+/// - Collects F99 text block lines, attaching the text to the pending F99 record at `[ENDTEXT]`,
+///   or streaming it in chunks once it grows past `ctx.max_f99_text`.
+/// - Splits other lines into fields, tracks the form type and validates/normalizes the fields.
+/// - F99 records are held back until it's known whether a text block follows.
+pub(crate) fn process_line(
+    ctx: &mut FecContext,
+    line: &str,
+    ending: &str,
+    out: &mut VecDeque<FecRecord>,
+) -> Result<()> {
+    let event = ctx.text_event(ctx.text_state, line);
+    ctx.text_state = event.next_state();
+    match event {
+        // Inside an F99 text block, lines are collected verbatim instead of split into fields
+        TextEvent::Text | TextEvent::NestedBegin => {
+            if event == TextEvent::NestedBegin {
+                ctx.report(
+                    Severity::Warning,
+                    ctx.line_number,
+                    format_args!("Keeping a [BEGINTEXT] inside an F99 text block as text."),
+                );
+            }
+            ctx.f99_text.push_str(line);
+            ctx.f99_text.push_str(ending);
+            if ctx.f99_text.len() > ctx.max_f99_text {
+                stream_f99_text(ctx, ending.len(), out);
+            }
+            return Ok(());
+        }
+        TextEvent::End => {
+            finish_f99_text(ctx, out);
+            return Ok(());
+        }
+        // Subsequent lines are collected until [ENDTEXT]
+        TextEvent::Begin => {
+            if ctx.pending_f99.is_none() {
+                ctx.report(
+                    Severity::Warning,
+                    ctx.line_number,
+                    format_args!("F99 text start encountered without a preceding F99 record."),
+                );
+            }
+            ctx.f99_text.clear();
+            ctx.f99_text_offset = ctx.line_end;
+            ctx.f99_text_line = ctx.line_number + 1;
+            return Ok(());
+        }
+        TextEvent::StrayEnd => {
+            ctx.report(
+                Severity::Warning,
+                ctx.line_number,
+                format_args!("Skipping an [ENDTEXT] outside any F99 text block."),
+            );
+            return Ok(());
+        }
+        TextEvent::Record => {}
+    }
+
     let trimmed_line = line.trim();
+    // A record with quoted line breaks started that many lines back
+    ctx.record_line = ctx.line_number.saturating_sub(line.matches('\n').count());
+
+    // Any other line means a pending F99 record has no text block
+    flush_pending_f99(ctx, out);
 
-    // Handle F99 text blocks
-    if ctx.f99_text_start.is_match(trimmed_line) {
-        if ctx.warn && !ctx.silent {
-            eprintln!("(Warn) F99 text start encountered.");
+    // Skip empty lines
+    if trimmed_line.is_empty() {
+        return Ok(());
+    }
+
+    // Parse fields based on the filing's delimiter; a stray ASCII28 byte in a comma-delimited
+    // filing is kept as part of its field
+    if ctx.delimiter == Delimiter::Comma && trimmed_line.contains(Delimiter::Ascii28.as_char()) {
+        if ctx.strict {
+            return Err(FecError::MalformedRow {
+                line: ctx.record_line,
+                reason: "stray ASCII28 byte in a comma-delimited filing".to_string(),
+            }
+            .into());
         }
+        ctx.report(
+            Severity::Warning,
+            ctx.record_line,
+            format_args!("Keeping a stray ASCII28 byte in a field of a comma-delimited filing."),
+        );
+    }
+    let mut fields = split_fields(trimmed_line, ctx.delimiter)?;
+
+    // A filing's form type is its first field; a CSV export's can be in any column
+    let form_field = ctx
+        .export_columns
+        .as_ref()
+        .and_then(|columns| columns.iter().position(|c| is_form_type_column(c)))
+        .unwrap_or(0);
+
+    // Track the row's form type and its mapping's column count
+    ctx.form_type = fields
+        .get(form_field)
+        .map(|f| normalize_form_type(f).unwrap_or_else(|| f.trim().to_uppercase()))
+        .filter(|f| !f.is_empty());
+    let normalized = ctx
+        .form_type
+        .as_deref()
+        .and_then(|f| form_filename(f, ctx.full_form_names, ctx.mapping_set()));
+    let (filename, kind) = match normalized {
+        Some(form_type) if form_type.starts_with('F') => (form_type, RecordKind::Cover),
+        Some(form_type) => (form_type, RecordKind::Itemization),
+        None => {
+            if ctx.strict {
+                return Err(FecError::UnknownFormType {
+                    line: ctx.record_line,
+                    form_type: fields.get(form_field).cloned().unwrap_or_default(),
+                }
+                .into());
+            }
+            ctx.report(
+                Severity::Error,
+                ctx.record_line,
+                format_args!(
+                    "Unrecognized form type, quarantining the row in {}.",
+                    UNKNOWN_FORM_FILENAME
+                ),
+            );
+            (UNKNOWN_FORM_FILENAME.to_string(), RecordKind::Unknown)
+        }
+    };
+    ctx.num_fields =
+        get_columns(&filename, ctx.version.as_deref().unwrap_or("")).map_or(0, |c| c.len());
 
-        // Continue parsing F99 text block until the end marker is found
-        while !ctx.f99_text_end.is_match(trimmed_line) {
-            // For now, this is a placeholder for handling multi-line F99 text.
-            // You can integrate additional logic here.
+    // Rows of forms no mapping knows, in any version, are kept as read rather than written with
+    // made-up columns. Paper filings only know the forms keyed in from paper.
+    let quarantined =
+        kind == RecordKind::Unknown || resolve_form_type_in(&filename, ctx.mapping_set()).is_none();
+    if quarantined && kind != RecordKind::Unknown {
+        if ctx.strict {
+            return Err(FecError::UnknownFormType {
+                line: ctx.record_line,
+                form_type: fields.get(form_field).cloned().unwrap_or_default(),
+            }
+            .into());
         }
+        ctx.report(
+            Severity::Error,
+            ctx.record_line,
+            format_args!(
+                "Unknown form type {}, quarantining the row in {}.",
+                filename, UNKNOWN_FORM_FILENAME
+            ),
+        );
+    }
+
+    // The filing's first cover record tells whether it is an amendment
+    if kind == RecordKind::Cover && ctx.amendment.is_none() {
+        ctx.amendment = Some(AmendmentInfo::from_cover(&filename, ctx.header.as_ref()));
+    }
+
+    // Summary mode skips everything but the first cover record
+    if ctx.summary && (ctx.summary_written || !is_summary_form(&filename)) {
         return Ok(());
     }
 
-    // Skip empty lines
-    if trimmed_line.is_empty() {
+    // Rows of CSV exports are moved into their form's columns by name
+    if let Some(export_columns) = ctx.export_columns.as_deref().filter(|_| !quarantined) {
+        if let Some(columns) = get_columns(&filename, ctx.version.as_deref().unwrap_or("")) {
+            fields = map_export_fields(export_columns, columns, fields);
+        }
+    }
+
+    check_field_count(ctx, &filename, &fields)?;
+    normalize_typed_fields(ctx, &filename, &mut fields)?;
+
+    let raw_line = quarantined.then(|| line.to_string());
+    if quarantined {
+        ctx.unknown_form_rows += 1;
+    }
+
+    let record = FecRecord {
+        form_type: ctx.form_type.clone().unwrap_or_default(),
+        fields,
+        line_number: ctx.line_number,
+        first_line: ctx.record_line,
+        kind,
+        raw_text: None,
+        raw_line,
+        byte_start: ctx.line_start,
+        byte_end: ctx.line_end,
+        text_continues: false,
+    };
+
+    if ctx.summary {
+        ctx.summary_written = true;
+    } else if filename == F99_FORM_TYPE {
+        ctx.pending_f99 = Some(record);
         return Ok(());
     }
 
-    // Parse fields based on the delimiter
-    let fields = if ctx.use_ascii28 {
-        parse_with_delimiter(trimmed_line, '\x1C')?
-    } else {
-        parse_csv_line(trimmed_line)?
+    out.push_back(record);
+    Ok(())
+}
+
+/// The name of the output file for a record: its normalized form type, or `unknown`.
+fn output_filename(ctx: &FecContext, record: &FecRecord) -> String {
+    form_filename(&record.form_type, ctx.full_form_names, ctx.mapping_set())
+        .unwrap_or_else(|| UNKNOWN_FORM_FILENAME.to_string())
+}
+
+/// Move `writer` on to the filing `ctx` is reading once a later header has started a new one,
+/// writing the header file of each filing it follows.
+///
+/// - `filings`: the number of filings `writer` has followed, starting from `0`.
+fn follow_filing(ctx: &FecContext, writer: &mut WriterContext, filings: &mut usize) -> Result<()> {
+    if ctx.filings.len() > *filings {
+        if *filings > 0 {
+            writer
+                .start_filing(ctx.fec_id.clone())
+                .context("Failed to finish writing the previous filing")?;
+        }
+        *filings = ctx.filings.len();
+        write_header_file(ctx, writer)?;
+    }
+    Ok(())
+}
+
+/// Write the coverage of the filing's report to its cover file from `record`, a cover record
+/// for `filename`, unless the file has already been written or the form has no coverage dates.
+/// Nothing is written in print mode.
+fn write_cover_file(
+    ctx: &FecContext,
+    writer: &mut WriterContext,
+    filename: &str,
+    record: &FecRecord,
+) -> Result<()> {
+    if writer.is_printing() || writer.header_written(COVER_FILENAME, CSV_EXTENSION) {
+        return Ok(());
+    }
+    let version = ctx.version.as_deref().unwrap_or("");
+    let Some(cover) = CoverInfo::from_fields(filename, version, &record.fields) else {
+        return Ok(());
     };
+    let columns: Vec<String> = COVER_COLUMNS.iter().map(|c| c.to_string()).collect();
+    if !writer
+        .write_header_once(COVER_FILENAME, &columns)
+        .context("Failed to write header row to output")?
+    {
+        return Ok(());
+    }
+    let row = [
+        ctx.fec_id.clone(),
+        cover.form_type,
+        cover.committee_id,
+        cover.coverage_from,
+        cover.coverage_through,
+        cover.report_type,
+    ];
+    writer
+        .write_csv_record(COVER_FILENAME, &row)
+        .context("Failed to write the filing's coverage to output")?;
+    writer.end_line("ssddss")
+}
+
+/// Write the filing's header metadata to its header file, unless it has no header or the file
+/// has content from an earlier run. Nothing is written in print mode.
+fn write_header_file(ctx: &FecContext, writer: &mut WriterContext) -> Result<()> {
+    let Some(header) = ctx.header.as_ref().filter(|_| !writer.is_printing()) else {
+        return Ok(());
+    };
+    let columns: Vec<String> = HEADER_COLUMNS.iter().map(|c| c.to_string()).collect();
+    if !writer
+        .write_header_once(HEADER_FILENAME, &columns)
+        .context("Failed to write header row to output")?
+    {
+        return Ok(());
+    }
+    let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+    let row = [
+        ctx.fec_id.clone(),
+        header.record_type.clone(),
+        header.ef_type.clone(),
+        header.version.clone(),
+        optional(&header.soft_name),
+        optional(&header.soft_ver),
+        optional(&header.report_id),
+        optional(&header.report_number),
+        optional(&header.comment),
+        header.delimiter.name().to_string(),
+        header.byte_order_mark.to_string(),
+    ];
+    writer
+        .write_csv_record(HEADER_FILENAME, &row)
+        .context("Failed to write the filing header to output")?;
+    writer.end_line(&"s".repeat(row.len()))
+}
 
-    // Process fields for specific information
-    if fields.len() >= 2 && fields[1].to_lowercase().contains("version") {
-        ctx.version = Some(fields[1].clone());
-        ctx.version_length = fields[1].len();
-        if !ctx.silent {
-            eprintln!("Discovered version: {}", fields[1]);
+/// Write a record to the output file for its form type, preceded by a header row if the file
+/// is new.
+fn write_record(
+    ctx: &mut FecContext,
+    writer: &mut WriterContext,
+    record: &FecRecord,
+    options: &ParseOptions,
+) -> Result<()> {
+    // Rows of forms that weren't asked for are dropped before they are serialized
+    if !is_selected(record, options) {
+        return Ok(());
+    }
+    if let Some(line) = &record.raw_line {
+        return write_quarantined(writer, record, line);
+    }
+    if record.kind == RecordKind::TextChunk {
+        return write_text_chunk(ctx, writer, record, options);
+    }
+
+    let filename = output_filename(ctx, record);
+    let filename = filename.as_str();
+    if record.kind == RecordKind::Cover {
+        write_cover_file(ctx, writer, filename, record)?;
+    }
+
+    // Vendors often leave off trailing empty fields; fill them back in up to the mapping's width,
+    // short of the text column of an F99 record whose text is streamed after it
+    let mut fields = Cow::Borrowed(&record.fields);
+    let width = get_columns(filename, ctx.version.as_deref().unwrap_or("")).map_or(0, |c| c.len());
+    let width = width.saturating_sub(usize::from(record.text_continues));
+    if options.pad_rows && fields.len() < width {
+        fields.to_mut().resize(width, String::new());
+    }
+    if options.truncate_extra_fields && width > 0 && fields.len() > width {
+        let dropped = fields.to_mut().split_off(width);
+        if dropped.iter().any(|f| !f.trim().is_empty()) {
+            ctx.truncated_rows += 1;
+            ctx.report(
+                Severity::Warning,
+                record.first_line,
+                format_args!(
+                    "Dropped {} field(s) past the {} columns of {}: {:?}.",
+                    dropped.len(),
+                    width,
+                    filename,
+                    dropped
+                ),
+            );
         }
     }
 
+    // Rows lead with their filing's ID and source line, if asked for
+    let mut types = row_types(ctx, filename, fields.len());
+    let prefix = prefix_fields(ctx, record);
+    if !prefix.is_empty() {
+        fields = Cow::Owned([prefix, fields.into_owned()].concat());
+    }
+
+    // Combined, every form's rows go to one file, led by their form
+    let target = output_target(filename, options);
+    if options.combined_output {
+        let mut row = vec![filename.to_string()];
+        row.extend(fields.into_owned());
+        let width = combined_header(ctx).len();
+        if options.pad_rows && !record.text_continues && row.len() < width {
+            row.resize(width, String::new());
+        }
+        types = format!("s{}{}", types, "s".repeat(row.len() - 1 - types.len()));
+        fields = Cow::Owned(row);
+    }
+
+    // The first row of a form's file is preceded by the column header row
+    if !writer.header_written(target, CSV_EXTENSION) {
+        let header = if options.combined_output {
+            combined_header(ctx)
+        } else {
+            let mut header: Vec<String> =
+                prefix_columns(ctx).iter().map(|c| c.to_string()).collect();
+            header.extend(header_columns(ctx, filename, record));
+            if !ctx.raw_headers {
+                header = normalize_column_names(&header);
+            }
+            header
+        };
+        writer
+            .write_header_once(target, &header)
+            .context("Failed to write header row to output")?;
+    }
+
+    if record.text_continues {
+        return writer
+            .start_streamed_row(target, &fields)
+            .context("Failed to write fields to output");
+    }
+
     // Write fields to the output writer context
     writer
-        .write_csv_record("output", &fields)
+        .write_csv_record(target, &fields)
         .context("Failed to write fields to output")?;
+    writer.end_line(&types)?;
+
+    // F99 text blocks are also written out verbatim, alongside the F99 CSV
+    if let (true, Some(raw_text)) = (ctx.f99_text_file, &record.raw_text) {
+        writer
+            .write_text(filename, TEXT_EXTENSION, raw_text)
+            .context("Failed to write F99 text to output")?;
+    }
 
     // Log warnings if enabled
     if ctx.warn && !ctx.silent {
-        eprintln!("(Warn) parse_line => Found {} fields.", fields.len());
+        eprintln!(
+            "(Warn) line {}: Found {} fields.",
+            record.line_number,
+            record.fields.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `record` is to be written under `options.forms`: cover records and their text always
+/// are, other rows if their full form code or the mapped form it resolves to was selected.
+fn is_selected(record: &FecRecord, options: &ParseOptions) -> bool {
+    let Some(forms) = &options.forms else {
+        return true;
+    };
+    matches!(record.kind, RecordKind::Cover | RecordKind::TextChunk)
+        || forms.contains(&record.form_type)
+        || resolve_form_type(&record.form_type).is_some_and(|form| forms.contains(form))
+}
+
+/// Write a chunk of a streamed F99 text block as the next piece of its F99 row's text column,
+/// ending the row after the last one.
+fn write_text_chunk(
+    ctx: &mut FecContext,
+    writer: &mut WriterContext,
+    record: &FecRecord,
+    options: &ParseOptions,
+) -> Result<()> {
+    let filename = output_filename(ctx, record);
+    let filename = filename.as_str();
+    let target = output_target(filename, options);
+    let raw_text = record.raw_text.as_deref().unwrap_or_default();
+
+    // As in `finish_f99_text`, without the line break before [ENDTEXT]
+    let mut text = raw_text.replace("\r\n", "\n");
+    if !record.text_continues && text.ends_with('\n') {
+        text.pop();
+    }
+    writer
+        .write_streamed_field(target, &text)
+        .context("Failed to write F99 text to output")?;
+    if !record.text_continues {
+        writer
+            .finish_streamed_row(target)
+            .context("Failed to write F99 text to output")?;
+        let width =
+            get_columns(filename, ctx.version.as_deref().unwrap_or("")).map_or(0, |c| c.len());
+        let types = row_types(ctx, filename, width);
+        let types = match options.combined_output {
+            true => format!("s{}", types),
+            false => types,
+        };
+        writer.end_line(&types)?;
+    }
+
+    if ctx.f99_text_file {
+        writer
+            .write_text(filename, TEXT_EXTENSION, raw_text)
+            .context("Failed to write F99 text to output")?;
+    }
+    Ok(())
+}
+
+/// Write a row of an unknown form to the quarantine file, as its line number, form type
+/// and decoded `line`. The file is only created once a row needs it.
+fn write_quarantined(writer: &mut WriterContext, record: &FecRecord, line: &str) -> Result<()> {
+    if !writer.header_written(UNKNOWN_FORM_FILENAME, CSV_EXTENSION) {
+        let header: Vec<String> = UNKNOWN_FORM_COLUMNS.iter().map(|c| c.to_string()).collect();
+        writer
+            .write_header_once(UNKNOWN_FORM_FILENAME, &header)
+            .context("Failed to write header row to output")?;
+    }
+    let row = [
+        record.first_line.to_string(),
+        record.form_type.clone(),
+        line.to_string(),
+    ];
+    writer
+        .write_csv_record(UNKNOWN_FORM_FILENAME, &row)
+        .context("Failed to write quarantined row to output")?;
+    writer.end_line(&"s".repeat(row.len()))
+}
+
+/// The header row of `filename`'s output file, which `record` is the first row of: the mapping's
+/// columns, or generic ones (with a warning) if the form has no mapping.
+fn header_columns(ctx: &mut FecContext, filename: &str, record: &FecRecord) -> Vec<String> {
+    let version = ctx.version.clone().unwrap_or_default();
+    match get_columns(filename, &version) {
+        Some(columns) => columns.iter().map(|c| c.to_string()).collect(),
+        None => {
+            ctx.report(
+                Severity::Warning,
+                record.first_line,
+                format_args!(
+                    "No mapping for form {} (version {:?}), using generic columns.",
+                    filename, version
+                ),
+            );
+            generic_columns(record.fields.len())
+        }
+    }
+}
+
+/// The FastFEC type codes (e.g. `ssdffs`) of an output row of `form_type` with `len` fields
+/// after the `prefix_columns`.
+///
+/// The prefix columns, fields past the mapping's columns and rows of unmapped forms are strings.
+fn row_types(ctx: &FecContext, form_type: &str, len: usize) -> String {
+    let types = get_column_types(form_type, ctx.version.as_deref().unwrap_or("")).unwrap_or(&[]);
+    let prefix = "s".repeat(prefix_columns(ctx).len());
+    prefix
+        + &(0..len)
+            .map(|i| types.get(i).map_or('s', |t| t.code()))
+            .collect::<String>()
+}
+
+/// The file rows of `filename`'s form are written to: its own, or the combined file.
+fn output_target<'a>(filename: &'a str, options: &ParseOptions) -> &'a str {
+    match options.combined_output {
+        true => COMBINED_FILENAME,
+        false => filename,
+    }
+}
+
+/// The header row of the combined file: the form, the prefix columns, then generic columns for
+/// the widest mapping of the filing's version.
+fn combined_header(ctx: &FecContext) -> Vec<String> {
+    let set = MappingSet::for_version(ctx.version.as_deref().unwrap_or(""));
+    let mut header = vec![FORM_TYPE_COLUMN.to_string()];
+    header.extend(prefix_columns(ctx).iter().map(|c| c.to_string()));
+    header.extend(generic_columns(widest_columns(set)));
+    header
+}
+
+/// The columns leading every output row but quarantined ones, as `ctx` asks for.
+fn prefix_columns(ctx: &FecContext) -> Vec<&'static str> {
+    let mut columns = Vec::new();
+    if ctx.include_filing_id {
+        columns.extend([
+            FILING_ID_COLUMN,
+            IS_AMENDMENT_COLUMN,
+            ORIGINAL_FILING_ID_COLUMN,
+        ]);
+    }
+    if ctx.row_numbers {
+        columns.push(SOURCE_LINE_COLUMN);
+    }
+    columns
+}
+
+/// The values of `record`'s `prefix_columns`.
+fn prefix_fields(ctx: &FecContext, record: &FecRecord) -> Vec<String> {
+    let mut fields = Vec::new();
+    if ctx.include_filing_id {
+        fields.push(ctx.fec_id.clone());
+        let amendment = ctx.amendment.as_ref();
+        fields.push(amendment.map_or(String::new(), |a| a.is_amendment.to_string()));
+        fields.push(
+            amendment
+                .and_then(|a| a.original_filing_id.clone())
+                .unwrap_or_default(),
+        );
+    }
+    if ctx.row_numbers {
+        fields.push(record.first_line.to_string());
+    }
+    fields
+}
+
+/// Compare a row's field count with `ctx.num_fields`, the column count of its form's mapping.
+///
+/// - Trailing empty fields past the mapping's end (trailing delimiters) are not counted.
+/// - F99 rows may omit the `text` column, which is filled from a following text block.
+/// - Mismatches are counted in `ctx.mismatched_rows` and warned about, or fail in strict mode.
+fn check_field_count(ctx: &mut FecContext, form_type: &str, fields: &[String]) -> Result<()> {
+    let expected = ctx.num_fields;
+    if expected == 0 {
+        return Ok(()); // No mapping to compare against
+    }
+
+    let mut actual = fields.len();
+    while actual > expected && fields[actual - 1].trim().is_empty() {
+        actual -= 1;
+    }
+    if actual == expected || (form_type == F99_FORM_TYPE && actual + 1 == expected) {
+        return Ok(());
+    }
+
+    ctx.mismatched_rows += 1;
+    let form_type = ctx
+        .form_type
+        .clone()
+        .unwrap_or_else(|| form_type.to_string());
+    if ctx.strict {
+        return Err(FecError::FieldCountMismatch {
+            line: ctx.record_line,
+            form_type,
+            expected,
+            actual,
+        }
+        .into());
+    }
+    ctx.report(
+        Severity::Error,
+        ctx.record_line,
+        format_args!(
+            "{} row has {} fields, expected {}.",
+            form_type, actual, expected
+        ),
+    );
+
+    Ok(())
+}
+
+/// Normalize the values of numeric (float) and date columns for `form_type`'s mapping in place.
+///
+/// - Strips stray `$` and `,` separators and rewrites the amount via `format_double_with`, in
+///   the column's format (`column_double_format`): two decimal places for amounts.
+/// - With `ctx.date_format`, rewrites dates via `format_date_with` in that format.
+/// - Values that still don't parse as numbers (or dates) are warned about and left as-is.
+fn normalize_typed_fields(
+    ctx: &mut FecContext,
+    form_type: &str,
+    fields: &mut [String],
+) -> Result<()> {
+    let version = ctx.version.as_deref().unwrap_or("");
+    let (Some(types), Some(columns)) = (
+        get_column_types(form_type, version),
+        get_columns(form_type, version),
+    ) else {
+        return Ok(());
+    };
+
+    for ((field, ty), column) in fields.iter_mut().zip(types).zip(columns) {
+        if *ty == ColumnType::Date {
+            let Some(format) = ctx.date_format.filter(|_| !field.trim().is_empty()) else {
+                continue;
+            };
+            match parse_date(field) {
+                Some((year, month, day)) => *field = format_date_with(year, month, day, format),
+                None => ctx.report(
+                    Severity::Warning,
+                    ctx.record_line,
+                    format_args!(
+                        "non-date value {:?} in date column {} of {}.",
+                        field, column, form_type
+                    ),
+                ),
+            }
+            continue;
+        }
+        if *ty != ColumnType::Float {
+            continue;
+        }
+        let cleaned: String = field
+            .trim()
+            .chars()
+            .filter(|c| *c != '$' && *c != ',')
+            .collect();
+        if cleaned.is_empty() {
+            continue;
+        }
+        match cleaned.parse::<f64>() {
+            Ok(value) if value.is_finite() => {
+                *field = format_double_with(value, column_double_format(column));
+            }
+            _ => {
+                ctx.report(
+                    Severity::Warning,
+                    ctx.record_line,
+                    format_args!(
+                        "non-numeric value {:?} in numeric column {} of {}.",
+                        field, column, form_type
+                    ),
+                );
+            }
+        }
     }
 
     Ok(())
 }
 
+/// End an F99 text block, attaching the collected text to the pending F99 record and
+/// queueing it in `out`.
+pub(crate) fn finish_f99_text(ctx: &mut FecContext, out: &mut VecDeque<FecRecord>) {
+    ctx.text_state = TextState::Outside;
+    let raw_text = std::mem::take(&mut ctx.f99_text);
+    if ctx.f99_text_streaming {
+        ctx.f99_text_streaming = false;
+        out.push_back(text_chunk(ctx, raw_text, ctx.line_end, false));
+        return;
+    }
+    // The CSV column joins the lines with `\n`, without the line break before [ENDTEXT]
+    let mut text = raw_text.replace("\r\n", "\n");
+    if text.ends_with('\n') {
+        text.pop();
+    }
+
+    match ctx.pending_f99.as_mut() {
+        Some(record) => {
+            // Place the text in the mapping's trailing `text` column when the row is short
+            pad_to_text_column(ctx.version.as_deref(), &mut record.fields);
+            record.fields.push(text);
+            record.raw_text = Some(raw_text);
+            record.line_number = ctx.line_number;
+            record.byte_end = ctx.line_end;
+        }
+        None => {
+            ctx.report(
+                Severity::Warning,
+                ctx.line_number,
+                format_args!("Discarding F99 text block with no F99 record to attach to."),
+            );
+        }
+    }
+
+    flush_pending_f99(ctx, out);
+}
+
+/// Hand out all but the last line ending of the F99 text collected so far, which has grown past
+/// `ctx.max_f99_text`, as a chunk queued in `out`.
+///
+/// - `keep`: the length of the last line's ending, kept so the last chunk can leave it out.
+/// - The pending F99 record is queued ahead of the first chunk, without its text column; text
+///   with no F99 record to follow is dropped as it comes.
+fn stream_f99_text(ctx: &mut FecContext, keep: usize, out: &mut VecDeque<FecRecord>) {
+    let ending = ctx.f99_text.split_off(ctx.f99_text.len() - keep);
+    let chunk = std::mem::replace(&mut ctx.f99_text, ending);
+
+    if let Some(mut record) = ctx.pending_f99.take() {
+        pad_to_text_column(ctx.version.as_deref(), &mut record.fields);
+        record.text_continues = true;
+        record.byte_end = ctx.f99_text_offset;
+        out.push_back(record);
+        ctx.f99_text_streaming = true;
+    }
+    if ctx.f99_text_streaming {
+        let end = ctx.line_end - keep as u64;
+        out.push_back(text_chunk(ctx, chunk, end, true));
+        ctx.f99_text_offset = end;
+        ctx.f99_text_line = ctx.line_number;
+    }
+}
+
+/// A `RecordKind::TextChunk` record for the F99 text read from `ctx.f99_text_offset` up to `end`.
+fn text_chunk(ctx: &FecContext, text: String, end: u64, text_continues: bool) -> FecRecord {
+    FecRecord {
+        form_type: F99_FORM_TYPE.to_string(),
+        fields: Vec::new(),
+        line_number: ctx.line_number,
+        first_line: ctx.f99_text_line,
+        kind: RecordKind::TextChunk,
+        raw_text: Some(text),
+        raw_line: None,
+        byte_start: ctx.f99_text_offset,
+        byte_end: end,
+        text_continues,
+    }
+}
+
+/// Pad a short F99 row's `fields` so its text goes in `version`'s trailing `text` column.
+fn pad_to_text_column(version: Option<&str>, fields: &mut Vec<String>) {
+    if let Some(columns) = get_columns(F99_FORM_TYPE, version.unwrap_or("")) {
+        if fields.len() + 1 < columns.len() {
+            fields.resize(columns.len() - 1, String::new());
+        }
+    }
+}
+
+/// Queue the pending F99 record, if any, in `out`.
+pub(crate) fn flush_pending_f99(ctx: &mut FecContext, out: &mut VecDeque<FecRecord>) {
+    if let Some(record) = ctx.pending_f99.take() {
+        out.push_back(record);
+    }
+}
+
+/// The name of the output file for a row's form type field.
+///
+/// The field is normalized with `normalize_form_type` and, unless `full_code` is set, resolved
+/// to the mapped form in `set` it shares columns with (`SA11AI` => `SA`, `SC1/10` => `SC1`);
+/// codes no mapping matches are kept whole. Returns `None` if the field isn't a form type code.
+fn form_filename(raw: &str, full_code: bool, set: MappingSet) -> Option<String> {
+    let code = normalize_form_type(raw)?;
+    match resolve_form_type_in(&code, set) {
+        Some(form) if !full_code => Some(form.to_string()),
+        _ => Some(code),
+    }
+}
+
+/// Whether `form_type` is a report cover/summary record (`F3`, `F3X` or `F3P`, with an
+/// optional `N`/`A`/`T` new/amended/termination suffix).
+fn is_summary_form(form_type: &str) -> bool {
+    let Some(rest) = form_type.strip_prefix("F3") else {
+        return false;
+    };
+    let suffix = rest
+        .strip_prefix('X')
+        .or_else(|| rest.strip_prefix('P'))
+        .unwrap_or(rest);
+    matches!(suffix, "" | "N" | "A" | "T")
+}
+
+/// Split a line into fields with the given filing delimiter.
+fn split_fields(line: &str, delimiter: Delimiter) -> Result<Vec<String>> {
+    match delimiter {
+        Delimiter::Ascii28 => parse_with_delimiter(line, delimiter.as_char()),
+        Delimiter::Comma => parse_csv_line(line),
+    }
+}
+
 /// Parse a line using a custom delimiter (e.g., ASCII28).
 ///
 /// - Splits the line into fields based on the delimiter.
@@ -142,23 +1590,350 @@ fn parse_csv_line(line: &str) -> Result<Vec<String>> {
     }
 }
 
+/// Read just the header of a filing from `reader`: the `HDR` line or a multi-line legacy
+/// `/* Header` block, after any byte-order mark.
+///
+/// The reader is left at the first body line, whose byte offset is `HeaderInfo::body_offset`,
+/// so a caller can stop there or go on to read the body. Fails if the input is empty or doesn't
+/// open with a header.
+pub fn read_header<R: BufRead>(reader: &mut R) -> Result<HeaderInfo> {
+    let mut ctx = FecContext::new(String::new(), false, true, false);
+    read_header_into(&mut ctx, reader)?;
+    ctx.header
+        .ok_or_else(|| anyhow!("The input does not start with a filing header."))
+}
+
+/// Read the header line or legacy header block from `reader` into `ctx`.
+///
+/// Returns the fields of a modern header record, which is yielded as a record of its own.
+pub(crate) fn read_header_into<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+) -> Result<Option<Vec<String>>> {
+    let mut reader = CountingReader::new(reader);
+    let mut buffer = Vec::new();
+    ctx.line_number = 0;
+    if !read_physical_line(ctx, &mut reader, &mut buffer)? {
+        return Err(anyhow!("No data to parse."));
+    }
+
+    // A byte-order mark would otherwise be glued to the header's first field
+    let byte_order_mark = strip_bom(&mut buffer);
+    if byte_order_mark {
+        ctx.report(
+            Severity::Warning,
+            1,
+            format_args!("Stripped a UTF-8 byte-order mark."),
+        );
+        // The BOM may sit on a line of its own, with the header on the next line
+        if buffer.iter().all(u8::is_ascii_whitespace) {
+            buffer.clear();
+            if !read_physical_line(ctx, &mut reader, &mut buffer)? {
+                return Err(anyhow!("No data to parse."));
+            }
+        }
+    }
+    strip_line_ending(&mut buffer);
+
+    let decoded_header = decode_checked(ctx, &buffer)?;
+    let fields = if is_csv_export_header(&decoded_header) {
+        read_csv_export_header(ctx, &decoded_header)?;
+        None
+    } else {
+        parse_filing_header(ctx, &decoded_header, &mut reader)?
+    };
+    ctx.filings = vec![ctx.fec_id.clone()];
+    (ctx.line_start, ctx.line_end) = (0, reader.bytes_read);
+    ctx.checkpoint = Checkpoint {
+        offset: reader.bytes_read,
+        line_number: ctx.line_number,
+    };
+    if let Some(header) = &mut ctx.header {
+        header.body_offset = reader.bytes_read;
+        header.byte_order_mark = byte_order_mark;
+    }
+    Ok(fields)
+}
+
+/// Parse the header starting at `line`, reading the rest of a legacy header block from `reader`.
+///
+/// Returns the fields of a modern header record.
+fn parse_filing_header<R: BufRead>(
+    ctx: &mut FecContext,
+    line: &str,
+    reader: &mut R,
+) -> Result<Option<Vec<String>>> {
+    if is_legacy_header_start(line) {
+        parse_legacy_header(ctx, line, reader)?;
+        return Ok(None);
+    }
+    let fields = parse_header(ctx, line)
+        .with_context(|| format!("Failed to parse the header on line {}", ctx.line_number))?;
+    Ok(ctx.header.is_some().then_some(fields))
+}
+
+/// Start the next filing of a concatenated input at its header `line`, reading the rest of a
+/// legacy header block from `reader`.
+///
+/// The state of the filing before (its header, version, delimiter and last form type) is
+/// dropped, and the new filing is named by `next_filing_id`. Row counts carry on across
+/// filings. Returns the fields of a modern header record.
+pub(crate) fn start_next_filing<R: BufRead>(
+    ctx: &mut FecContext,
+    line: &str,
+    reader: &mut R,
+) -> Result<Option<Vec<String>>> {
+    ctx.header = None;
+    ctx.version = None;
+    ctx.version_length = 0;
+    ctx.delimiter = ctx.delimiter_override.unwrap_or_default();
+    ctx.form_type = None;
+    ctx.num_fields = 0;
+    ctx.amendment = None;
+    ctx.export_columns = None;
+    let fields = parse_filing_header(ctx, line, reader)?;
+
+    ctx.fec_id = next_filing_id(ctx);
+    ctx.filings.push(ctx.fec_id.clone());
+    if !ctx.silent {
+        eprintln!(
+            "Detected another filing on line {}, continuing as {}",
+            ctx.line_number, ctx.fec_id
+        );
+    }
+    Ok(fields)
+}
+
+/// The ID of a filing found after others in the same input: the filing ID in its header's
+/// report ID, unless an earlier filing of the input has it, or else the first filing's ID
+/// followed by the filing's position (`STDIN_DATA-2`).
+fn next_filing_id(ctx: &FecContext) -> String {
+    ctx.header
+        .as_ref()
+        .and_then(|h| h.report_id.as_deref())
+        .and_then(parse_report_id)
+        .filter(|id| !ctx.filings.contains(id))
+        .unwrap_or_else(|| {
+            let first = ctx.filings.first().unwrap_or(&ctx.fec_id);
+            format!("{}-{}", first, ctx.filings.len() + 1)
+        })
+}
+
+/// Take in the column-name header row of a CSV export, or fail if exports aren't mapped.
+fn read_csv_export_header(ctx: &mut FecContext, line: &str) -> Result<()> {
+    let columns: Vec<String> = split_fields(line, Delimiter::Comma)?
+        .iter()
+        .map(|c| c.trim().to_ascii_lowercase())
+        .collect();
+    if !ctx.map_csv_exports {
+        return Err(FecError::CsvExport {
+            line: ctx.line_number,
+            first_column: columns[0].clone(),
+        }
+        .into());
+    }
+    if !columns.iter().any(|c| is_form_type_column(c)) {
+        return Err(anyhow!(
+            "The CSV export on line {} has no form type column to route its rows by.",
+            ctx.line_number
+        ));
+    }
+
+    if !ctx.silent {
+        eprintln!(
+            "Detected a CSV export: mapping its {} columns by name for version {}",
+            columns.len(),
+            NEWEST_VERSION
+        );
+    }
+    ctx.delimiter = ctx.delimiter_override.unwrap_or(Delimiter::Comma);
+    ctx.version_length = NEWEST_VERSION.len();
+    ctx.version = Some(NEWEST_VERSION.to_string());
+    ctx.export_columns = Some(columns);
+    Ok(())
+}
+
+/// Move the fields of a CSV export row, named by `export_columns`, into the order of
+/// `columns`. Columns the export lacks are left empty and those the mapping lacks are dropped.
+fn map_export_fields(
+    export_columns: &[String],
+    columns: &[&str],
+    mut fields: Vec<String>,
+) -> Vec<String> {
+    columns
+        .iter()
+        .map(|column| {
+            export_columns
+                .iter()
+                .position(|c| c == column)
+                .and_then(|i| fields.get_mut(i))
+                .map(std::mem::take)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
 /// Parse the header line.
 ///
-/// - Detects legacy headers or FEC-specific references.
-/// - Updates `ctx` with relevant information.
-fn parse_header(ctx: &mut FecContext, line: &str) -> Result<()> {
+/// - Splits modern `HDR` records into a `HeaderInfo` stored on `ctx`, which also sets the version.
+///   The `HDRFEC` and bare `FEC` variants are recognized too, and their fields rewritten into the
+///   `HDR,FEC,version,...` layout. A line that opens no header record fails with
+///   `FecError::UnrecognizedHeader`.
+/// - Locks in `ctx.delimiter` for the rest of the filing from the version's rule in
+///   `DELIMITER_RULES`, falling back to the header's own delimiter (unless overridden).
+/// - Legacy `/* Header` blocks are handled by `parse_legacy_header` instead.
+///
+/// Returns the header's fields.
+pub(crate) fn parse_header(ctx: &mut FecContext, line: &str) -> Result<Vec<String>> {
     let trimmed = line.trim();
 
-    if trimmed.starts_with("/*") {
-        if !ctx.silent {
-            eprintln!("Detected a legacy header: {}", trimmed);
+    // Split by the delimiter the header appears to use, retrying with commas in case the
+    // ASCII28 byte was a stray control character
+    let header_delimiter = match ctx.delimiter_override {
+        Some(delimiter) => delimiter,
+        None if trimmed.contains(Delimiter::Ascii28.as_char()) => Delimiter::Ascii28,
+        None => Delimiter::Comma,
+    };
+    let mut fields = split_fields(trimmed, header_delimiter)?;
+    let mut header_delimiter = header_delimiter;
+    if ctx.delimiter_override.is_none()
+        && header_delimiter == Delimiter::Ascii28
+        && HeaderInfo::from_fields(&fields).is_none()
+    {
+        let comma_fields = split_fields(trimmed, Delimiter::Comma)?;
+        if HeaderInfo::from_fields(&comma_fields).is_some() {
+            fields = comma_fields;
+            header_delimiter = Delimiter::Comma;
         }
-        // Optionally parse additional lines for multi-line legacy headers
-        return Ok(());
     }
+    ctx.delimiter = header_delimiter;
+
+    match HeaderInfo::from_fields(&fields) {
+        Some(header) => {
+            if !ctx.silent {
+                eprintln!(
+                    "Detected a modern {} header: version {}, software {}",
+                    header.variant,
+                    header.version,
+                    header.soft_name.as_deref().unwrap_or("unknown")
+                );
+            }
+            header.variant.normalize_fields(&mut fields);
+            if ctx.delimiter_override.is_none() {
+                ctx.delimiter = delimiter_for_version(&header.version, header_delimiter);
+            }
+            set_header(ctx, header);
+        }
+        None if fields
+            .first()
+            .and_then(|f| HeaderVariant::from_first_field(f))
+            .is_none() =>
+        {
+            return Err(FecError::UnrecognizedHeader {
+                line: ctx.line_number,
+                start: line_start(trimmed, 40).to_string(),
+            }
+            .into());
+        }
+        None => {
+            if !ctx.silent {
+                eprintln!("Detected a modern header without a version: {}", trimmed);
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+/// The longest prefix of `line` that is at most `max` bytes long.
+fn line_start(line: &str, max: usize) -> &str {
+    let mut end = line.len().min(max);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    &line[..end]
+}
+
+/// Record a filing's parsed header, its version and delimiter, warning if no mapping knows the
+/// version. When the caller asked for paper mappings, an electronic version is looked up as
+/// the paper version of the same number (`3.00` as `P3.00`).
+fn set_header(ctx: &mut FecContext, mut header: HeaderInfo) {
+    if !is_known_version(&header.version) {
+        ctx.report(
+            Severity::Warning,
+            ctx.line_number,
+            format_args!(
+                "Unknown version {}, using the newest column mappings.",
+                header.version
+            ),
+        );
+    }
+    header.delimiter = ctx.delimiter;
+    ctx.version_length = header.version.len();
+    ctx.version = Some(if ctx.paper && !is_paper_version(&header.version) {
+        format!("P{}", header.version.trim())
+    } else {
+        header.version.clone()
+    });
+    ctx.header = Some(header);
+}
+
+/// Parse a multi-line legacy `/* Header` block.
+///
+/// - `first_line`: the already-read line opening the block.
+/// - Reads from `reader` until the closing `*/` (or `/* End Header`) line, so none of the
+///   block's `key = value` lines reach `parse_line`.
+pub(crate) fn parse_legacy_header<R: BufRead>(
+    ctx: &mut FecContext,
+    first_line: &str,
+    reader: &mut R,
+) -> Result<()> {
+    if !ctx.silent {
+        eprintln!("Detected a legacy header: {}", first_line.trim());
+    }
+    // Filings with legacy headers predate the ASCII28 delimiter
+    ctx.delimiter = ctx.delimiter_override.unwrap_or(Delimiter::Comma);
+
+    let mut builder = LegacyHeaderBuilder::new();
+    builder.push_line(first_line);
 
-    if trimmed.contains("FEC") && !ctx.silent {
-        eprintln!("Detected a modern header referencing FEC: {}", trimmed);
+    let mut buffer = Vec::new();
+    let mut terminated = is_legacy_header_end(first_line);
+    while !terminated {
+        buffer.clear();
+        if !read_physical_line(ctx, reader, &mut buffer)
+            .context("Failed to read the legacy header")?
+        {
+            break;
+        }
+        strip_line_ending(&mut buffer);
+        let line = decode_checked(ctx, &buffer)?;
+        builder.push_line(&line);
+        terminated = is_legacy_header_end(&line);
+    }
+
+    if !terminated {
+        ctx.report(
+            Severity::Warning,
+            ctx.line_number,
+            format_args!("Legacy header block was not terminated before the end of input."),
+        );
+    }
+
+    match builder.finish() {
+        Some(header) => {
+            if !ctx.silent {
+                eprintln!("Detected legacy header version {}", header.version);
+            }
+            set_header(ctx, header);
+        }
+        None => {
+            ctx.report(
+                Severity::Warning,
+                ctx.line_number,
+                format_args!("Legacy header block did not declare a version."),
+            );
+        }
     }
 
     Ok(())
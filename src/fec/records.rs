@@ -0,0 +1,834 @@
+//! A streaming, record-at-a-time view of an FEC filing.
+//!
+//! `FecRecords` reads raw lines from a `BufRead`, handles the header, byte-order marks, line
+//! endings, quoted fields spanning lines and F99 text blocks, and yields one `FecRecord` per
+//! logical row. It is lazy: nothing past the returned record is read, so callers can stop early
+//! on very large filings. `parse_fec` is built on top of it.
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read};
+
+use crate::encoding::{
+    decode_line, decode_line_with, split_line_ending, FallbackEncoding, NulPolicy,
+};
+use crate::errors::FecError;
+use crate::input::Utf16Input;
+
+use super::context::{Checkpoint, Delimiter, FecContext, TextEvent, TextState};
+use super::header::is_header_start;
+use super::mappings::get_columns;
+use super::parser::{
+    finish_f99_text, flush_pending_f99, process_line, read_header_into, start_next_filing,
+};
+use super::validation::Severity;
+
+/// The most physical lines a single quoted CSV record may span before its opening quote is
+/// assumed to be stray.
+pub const MAX_QUOTED_RECORD_LINES: usize = 1000;
+
+/// Where in a filing a record comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    /// The `HDR` header record.
+    Header,
+    /// A form record such as the `F3X` cover/summary page or an `F99` miscellaneous text.
+    Cover,
+    /// A schedule itemization or other supporting record (`SA11AI`, `SB23`, `TEXT`, ...).
+    Itemization,
+    /// A row whose form type is empty or unparseable.
+    Unknown,
+    /// A piece of an F99 text block too long to hold in memory, following the F99 record it
+    /// belongs to (see `FecRecord::text_continues`).
+    TextChunk,
+}
+
+/// One logical record of a filing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FecRecord {
+    /// The upper-cased form type from the record's first field (e.g. `SA11AI`), or empty.
+    pub form_type: String,
+    /// The record's fields, with numeric columns normalized and F99 text attached.
+    pub fields: Vec<String>,
+    /// The 1-based input line the record ends on.
+    pub line_number: usize,
+    /// The 1-based input line the record starts on, which warnings about the record refer to.
+    pub first_line: usize,
+    /// Whether the record is the header, a cover form or an itemization.
+    pub kind: RecordKind,
+    /// For an F99 record followed by a text block, the block's text exactly as it was read,
+    /// line endings included. For a text chunk, its piece of the block.
+    pub raw_text: Option<String>,
+    /// For a row whose form type is unparseable or matches no mapping, the decoded line it was
+    /// split from, which is written to the quarantine file instead of its fields.
+    pub raw_line: Option<String>,
+    /// The offset in the input of the record's first byte, before any decoding.
+    pub byte_start: u64,
+    /// The offset in the input just past the record, line ending and any F99 text block
+    /// included, so `byte_start..byte_end` holds every input byte it was read from.
+    pub byte_end: u64,
+    /// Whether more of the record's F99 text follows in `RecordKind::TextChunk` records: set on
+    /// an F99 record whose text block is too long to hold in memory (see
+    /// `FecContext::max_f99_text`), which then lacks its text column, and on every chunk of the
+    /// block but the last.
+    pub text_continues: bool,
+}
+
+impl FecRecord {
+    /// The record's own transaction id (its `transaction_id` or `transaction_id_number`
+    /// column) under the mapping for `version`, if it has a non-empty one.
+    pub fn transaction_id(&self, version: &str) -> Option<&str> {
+        self.column(version, &["transaction_id", "transaction_id_number"])
+    }
+
+    /// The transaction id of the record this one belongs to (its
+    /// `back_reference_tran_id_number` column) under the mapping for `version`, if set.
+    ///
+    /// This is how a `TEXT` record's memo, or a memo itemization, is joined to its parent:
+    /// the parent is the record whose `transaction_id` equals it.
+    pub fn back_reference(&self, version: &str) -> Option<&str> {
+        self.column(version, &["back_reference_tran_id_number"])
+    }
+
+    /// The non-empty, trimmed value of the first of `names` the record's mapping has.
+    fn column(&self, version: &str, names: &[&str]) -> Option<&str> {
+        let columns = get_columns(&self.form_type, version)?;
+        let index = columns.iter().position(|c| names.contains(c))?;
+        let value = self.fields.get(index)?.trim();
+        (!value.is_empty()).then_some(value)
+    }
+}
+
+/// A lazy iterator over the records of a filing.
+///
+/// The header is parsed on the first call to `next`; it fills in `ctx` (version, delimiter,
+/// `HeaderInfo`) and is yielded as a `RecordKind::Header` record for modern `HDR` lines.
+/// Legacy `/* Header` blocks only fill in `ctx`. Errors end the iteration.
+///
+/// `ctx.skip` lines after the header are passed over unparsed, and iteration ends once
+/// `ctx.limit` records past the header have been yielded; the chunks of a streamed F99 text
+/// block only count as one, once it ends.
+///
+/// With `ctx.split_filings`, a header found after the first starts the next filing of a
+/// concatenated input (see `ctx.filings`), and a modern one is yielded as a header record too.
+pub struct FecRecords<'a, R: BufRead> {
+    ctx: &'a mut FecContext,
+    reader: CountingReader<Utf16Input<R>>,
+    buffer: Vec<u8>,
+    // Lines read ahead while looking for a closing quote, with the offsets they start at
+    replay: VecDeque<(u64, Vec<u8>)>,
+    ready: VecDeque<FecRecord>,
+    emitted: usize, // Whole records yielded after the header, for `ctx.limit`
+    batch_text_state: TextState, // Whether `read_batch` is inside an F99 text block
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, R: BufRead> FecRecords<'a, R> {
+    /// Iterate over the records read from `reader`, tracking parse state in `ctx`.
+    ///
+    /// Input saved as UTF-16 is converted to UTF-8 as it is read (see `Utf16Input`), so its
+    /// byte offsets count UTF-8 bytes and its checkpoints can't be resumed from.
+    pub fn new(ctx: &'a mut FecContext, reader: R) -> Self {
+        FecRecords {
+            ctx,
+            reader: CountingReader::new(Utf16Input::new(reader)),
+            buffer: Vec::new(),
+            replay: VecDeque::new(),
+            ready: VecDeque::new(),
+            emitted: 0,
+            batch_text_state: TextState::Outside,
+            started: false,
+            finished: false,
+        }
+    }
+
+    /// The parse state, e.g. the header and version once the first record has been read.
+    pub fn context(&self) -> &FecContext {
+        self.ctx
+    }
+
+    /// Read and decode the header line (or legacy header block).
+    fn read_header(&mut self) -> Result<()> {
+        let ctx = &mut *self.ctx;
+        if let Some(fields) = read_header_into(ctx, &mut self.reader)? {
+            self.ready.push_back(header_record(ctx, fields));
+        }
+
+        // Fast-forward past the skipped lines without decoding them
+        let skipped = skip_physical_lines(ctx, &mut self.reader, ctx.skip)?;
+        if skipped < ctx.skip {
+            let (line, skip) = (ctx.line_number, ctx.skip);
+            ctx.report(
+                Severity::Warning,
+                line,
+                format_args!("Input ended after skipping {} of {} lines.", skipped, skip),
+            );
+        }
+        Ok(())
+    }
+
+    /// Read the next logical line and process it, queueing any records it completes.
+    fn advance(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            return self.read_header();
+        }
+
+        if self.advance_in_place()? {
+            return Ok(());
+        }
+        let start = self.next_offset();
+        if !self.read_logical_line(self.ctx.in_f99_text())? {
+            finish_input(self.ctx, &mut self.ready);
+            self.finished = true;
+            return Ok(());
+        }
+
+        let end = self.next_offset();
+        let ctx = &mut *self.ctx;
+        (ctx.line_start, ctx.line_end) = (start, end);
+        let (line, ending) = split_line_ending(&self.buffer);
+        let decoded_line = decode_checked(ctx, line)?;
+        if starts_next_filing(ctx, &decoded_line) {
+            let decoded_line = decoded_line.into_owned();
+            let raw = std::mem::take(&mut self.buffer);
+            return self.begin_next_filing(&decoded_line, raw, start);
+        }
+        process_line(ctx, &decoded_line, ending, &mut self.ready)
+            .with_context(|| format!("Failed to parse line {}", ctx.line_number))?;
+
+        // Summary mode is done as soon as the cover record is out
+        if ctx.summary && ctx.summary_written {
+            self.finished = true;
+        }
+        Ok(())
+    }
+
+    /// Process the next line straight out of the reader's buffer, without copying it into
+    /// `self.buffer`, when it is there whole: always for in-memory and memory-mapped input, and
+    /// for most lines of buffered files.
+    ///
+    /// Returns `false`, having consumed nothing, when the line needs the general path: it spans
+    /// the end of the buffer, is too long, continues a quoted field or was read ahead.
+    fn advance_in_place(&mut self) -> Result<bool> {
+        if !self.replay.is_empty() {
+            return Ok(false);
+        }
+        let ctx = &mut *self.ctx;
+        let start = self.reader.bytes_read;
+        // Read errors are reported by the general path
+        let Ok(available) = self.reader.fill_buf() else {
+            return Ok(false);
+        };
+        let Some(end) = available.iter().position(|b| *b == b'\n') else {
+            return Ok(false);
+        };
+        let line = &available[..=end];
+        if end > ctx.max_line_length
+            || (!ctx.in_f99_text() && ctx.delimiter == Delimiter::Comma && ends_inside_quotes(line))
+        {
+            return Ok(false);
+        }
+
+        ctx.line_number += 1;
+        (ctx.line_start, ctx.line_end) = (start, start + end as u64 + 1);
+        let raw = line;
+        let (line, ending) = split_line_ending(line);
+        let decoded_line = decode_checked(ctx, line)?;
+        if starts_next_filing(ctx, &decoded_line) {
+            let decoded_line = decoded_line.into_owned();
+            let raw = raw.to_vec();
+            self.reader.consume(end + 1);
+            self.begin_next_filing(&decoded_line, raw, start)?;
+            return Ok(true);
+        }
+        // The decoded line borrows the reader's buffer, so it is consumed once processed
+        let result = process_line(ctx, &decoded_line, ending, &mut self.ready);
+        self.reader.consume(end + 1);
+        result.with_context(|| format!("Failed to parse line {}", ctx.line_number))?;
+
+        if ctx.summary && ctx.summary_written {
+            self.finished = true;
+        }
+        Ok(true)
+    }
+
+    /// Read the next logical line into `self.buffer`, including its line ending.
+    ///
+    /// - `in_f99_text`: whether the line is inside an F99 text block, where it is taken verbatim.
+    ///
+    /// Returns `false` at end of input.
+    fn read_logical_line(&mut self, in_f99_text: bool) -> Result<bool> {
+        let ctx = &mut *self.ctx;
+        self.buffer.clear();
+        if !next_physical_line(ctx, &mut self.reader, &mut self.replay, &mut self.buffer)? {
+            return Ok(false);
+        }
+
+        // A quoted CSV field may contain line breaks, which are kept as part of its content;
+        // keep reading until the record is complete
+        if !in_f99_text && ctx.delimiter == Delimiter::Comma && ends_inside_quotes(&self.buffer) {
+            read_quoted_continuation(ctx, &mut self.reader, &mut self.replay, &mut self.buffer)?;
+        }
+        Ok(true)
+    }
+
+    /// Start the next filing of a concatenated input at its header `line`, read as `raw` from
+    /// input offset `start`.
+    ///
+    /// Records of the filing before are handed out first: if an F99 record was still waiting for
+    /// a text block, the header is put back to be read again once it has been.
+    fn begin_next_filing(&mut self, line: &str, raw: Vec<u8>, start: u64) -> Result<()> {
+        flush_pending_f99(self.ctx, &mut self.ready);
+        if !self.ready.is_empty() {
+            self.unread(start, raw);
+            return Ok(());
+        }
+
+        let ctx = &mut *self.ctx;
+        let fields = start_next_filing(ctx, line, &mut self.reader)?;
+        // A legacy header block runs on past its first line
+        ctx.line_end = ctx.line_end.max(self.reader.bytes_read);
+        if let Some(fields) = fields {
+            self.ready.push_back(header_record(ctx, fields));
+        }
+        Ok(())
+    }
+
+    /// Put a logical line read from input offset `start` back, to be read again a physical line
+    /// at a time.
+    fn unread(&mut self, start: u64, raw: Vec<u8>) {
+        let mut offset = start;
+        let lines: Vec<_> = raw
+            .split_inclusive(|b| *b == b'\n')
+            .map(|line| {
+                let line_start = offset;
+                offset += line.len() as u64;
+                (line_start, line.to_vec())
+            })
+            .collect();
+        self.ctx.line_number -= lines.len();
+        for line in lines.into_iter().rev() {
+            self.replay.push_front(line);
+        }
+    }
+
+    /// Start the filing whose header ended the last batch, dropping the header's record.
+    pub(crate) fn read_next_header(&mut self) -> Result<()> {
+        self.advance()?;
+        self.ready.clear();
+        Ok(())
+    }
+
+    /// Carry on from `checkpoint` instead of reading the header, with the reader already at the
+    /// checkpoint's offset. Returns the context, for the caller to restore the header into.
+    pub(crate) fn resume_at(&mut self, checkpoint: Checkpoint) -> &mut FecContext {
+        self.started = true;
+        self.reader.bytes_read = checkpoint.offset;
+        self.ctx.line_number = checkpoint.line_number;
+        self.ctx.checkpoint = checkpoint;
+        self.ctx
+    }
+
+    /// The number of bytes consumed from the reader so far.
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.reader.bytes_read
+    }
+
+    /// The input offset of the next physical line to be read, which may have been read ahead.
+    fn next_offset(&self) -> u64 {
+        self.replay
+            .front()
+            .map_or(self.reader.bytes_read, |(start, _)| *start)
+    }
+
+    /// The parse state, for merging in the results of batches parsed elsewhere.
+    pub(crate) fn context_mut(&mut self) -> &mut FecContext {
+        self.ctx
+    }
+
+    /// Read the header if it hasn't been read yet, dropping its record.
+    pub(crate) fn skip_header(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            self.read_header()?;
+            self.ready.clear();
+        }
+        Ok(())
+    }
+
+    /// Read about `target_bytes` worth of logical lines, unparsed, for parsing on another thread.
+    ///
+    /// Batches only end where parsing can resume with a fresh context: never inside an F99 text
+    /// block or right after an F99 record that a text block may follow. A read error ends the
+    /// batch early, carrying the error after the lines read before it.
+    ///
+    /// Returns `None` once the input is exhausted.
+    pub(crate) fn read_batch(&mut self, target_bytes: usize) -> Option<RawBatch> {
+        if self.finished {
+            return None;
+        }
+
+        let mut batch = RawBatch::default();
+        let mut bytes = 0;
+        loop {
+            let start = self.next_offset();
+            match self.read_logical_line(self.batch_text_state == TextState::Inside) {
+                Ok(true) => {}
+                Ok(false) => {
+                    batch.last = true;
+                    break;
+                }
+                Err(e) => {
+                    batch.error = Some(e);
+                    batch.last = true;
+                    break;
+                }
+            }
+
+            let line = std::mem::take(&mut self.buffer);
+            // Another filing's header is left for the caller to read, on a fresh context
+            if self.ctx.split_filings
+                && self.batch_text_state == TextState::Outside
+                && may_start_filing(&line)
+                && is_header_start(&decode_line(&line).0)
+            {
+                self.unread(start, line);
+                batch.next_filing = true;
+                break;
+            }
+            let may_end = self.track_f99_text(&line);
+            bytes += line.len();
+            batch.lines.push(RawLine {
+                line_number: self.ctx.line_number,
+                start,
+                end: self.next_offset(),
+                bytes: line,
+            });
+            if may_end && bytes >= target_bytes {
+                break;
+            }
+        }
+        self.finished = batch.last;
+        Some(batch)
+    }
+
+    /// Follow F99 text blocks through raw lines the way `process_line` would, returning whether
+    /// a batch may end after `line`.
+    fn track_f99_text(&mut self, line: &[u8]) -> bool {
+        let ctx = &*self.ctx;
+        // Text block markers are whole bracketed lines, so only lines with a `[` can be one
+        if line.contains(&b'[') {
+            let (decoded, _) = decode_line(line);
+            let event = ctx.text_event(self.batch_text_state, &decoded);
+            self.batch_text_state = event.next_state();
+            if event == TextEvent::End {
+                return true;
+            }
+        }
+        self.batch_text_state == TextState::Outside && !starts_with_f99(line, ctx.delimiter)
+    }
+}
+
+/// Logical lines read by `FecRecords::read_batch`.
+#[derive(Default)]
+pub(crate) struct RawBatch {
+    pub lines: Vec<RawLine>,
+    pub last: bool,                   // Whether the batch ends the input
+    pub next_filing: bool,            // Whether another filing's header follows the batch
+    pub error: Option<anyhow::Error>, // A read error that ended the batch
+}
+
+/// A logical line read for parsing elsewhere, line ending included.
+pub(crate) struct RawLine {
+    pub line_number: usize, // The line the logical line ends on
+    pub start: u64,         // Input offset of the line's first byte
+    pub end: u64,           // Input offset just past the line
+    pub bytes: Vec<u8>,
+}
+
+/// Whether a raw line may be an F99 record, i.e. its first field looks like `F99`.
+///
+/// Errs on the side of `true`, as it only keeps batches from ending after the line.
+fn starts_with_f99(line: &[u8], delimiter: Delimiter) -> bool {
+    let delimiter = delimiter.as_char() as u8;
+    let end = line
+        .iter()
+        .position(|b| *b == delimiter)
+        .unwrap_or(line.len());
+    let first = line[..end].trim_ascii();
+    let first = first.strip_prefix(b"\"").unwrap_or(first);
+    first.len() >= 3 && first[..3].eq_ignore_ascii_case(b"F99")
+}
+
+/// Whether a raw line may open a filing's header, i.e. it starts with `HDR`, `FEC` or `/*`.
+///
+/// Errs on the side of `true`, as it only saves decoding lines for `is_header_start`.
+fn may_start_filing(line: &[u8]) -> bool {
+    let line = line.trim_ascii_start();
+    let first = line.strip_prefix(b"\"").unwrap_or(line);
+    line.starts_with(b"/*")
+        || (first.len() >= 3
+            && (first[..3].eq_ignore_ascii_case(b"HDR") || first[..3].eq_ignore_ascii_case(b"FEC")))
+}
+
+/// Whether a decoded line read after the header starts the next filing of a concatenated
+/// input, rather than being a row of the current one.
+fn starts_next_filing(ctx: &FecContext, line: &str) -> bool {
+    ctx.split_filings && !ctx.in_f99_text() && is_header_start(line)
+}
+
+/// The record yielded for a modern header record, from its `fields`.
+fn header_record(ctx: &FecContext, fields: Vec<String>) -> FecRecord {
+    FecRecord {
+        form_type: fields[0].trim().to_uppercase(),
+        fields,
+        line_number: ctx.line_number,
+        first_line: ctx.line_number,
+        kind: RecordKind::Header,
+        raw_text: None,
+        raw_line: None,
+        byte_start: ctx.line_start,
+        byte_end: ctx.line_end,
+        text_continues: false,
+    }
+}
+
+/// Handle the end of input: emit any F99 record still waiting for its text.
+pub(crate) fn finish_input(ctx: &mut FecContext, out: &mut VecDeque<FecRecord>) {
+    if ctx.in_f99_text() {
+        ctx.report(
+            Severity::Warning,
+            ctx.line_number,
+            format_args!("F99 text block was not terminated before the end of input."),
+        );
+        finish_f99_text(ctx, out);
+    }
+    flush_pending_f99(ctx, out);
+}
+
+impl<R: BufRead> Iterator for FecRecords<'_, R> {
+    type Item = Result<FecRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.ctx.limit > 0 && self.emitted >= self.ctx.limit {
+                return None;
+            }
+            if let Some(record) = self.ready.pop_front() {
+                if record.kind != RecordKind::Header && !record.text_continues {
+                    self.emitted += 1;
+                }
+                return Some(Ok(record));
+            }
+            if self.finished {
+                return None;
+            }
+            if let Err(e) = self.advance() {
+                self.finished = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// A reader counting the bytes consumed from it.
+pub(crate) struct CountingReader<R> {
+    inner: R,
+    pub bytes_read: u64,
+}
+
+impl<R> CountingReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        CountingReader {
+            inner,
+            bytes_read: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.bytes_read += amt as u64;
+    }
+}
+
+/// Append the next physical line (including its line ending) from `reader` to `buffer`.
+///
+/// The last line of the input may lack a line ending and is returned like any other; an empty
+/// read is the end of input, so a file ending in `\n` yields no extra empty line.
+///
+/// Lines are read a chunk at a time so that a line longer than `ctx.max_line_length` is caught
+/// before it is held in memory: in strict mode it fails with `FecError::LineTooLong`, otherwise
+/// the rest of the line is discarded with a warning.
+///
+/// Returns `false` at end of input; otherwise counts the line in `ctx.line_number`.
+pub(crate) fn read_physical_line<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+) -> Result<bool> {
+    let line = ctx.line_number + 1;
+    let mut length = 0; // Bytes of the line seen so far, including any discarded
+    let mut read_any = false;
+    loop {
+        let available = reader
+            .fill_buf()
+            .map_err(FecError::Io)
+            .with_context(|| format!("Failed to read line {}", line))?;
+        if available.is_empty() {
+            break;
+        }
+        read_any = true;
+        let (content_len, ended_line) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (i, true),
+            None => (available.len(), false),
+        };
+        let kept = content_len.min(ctx.max_line_length.saturating_sub(length));
+        if kept < content_len && ctx.strict {
+            return Err(FecError::LineTooLong {
+                line,
+                length: ctx.max_line_length,
+            }
+            .into());
+        }
+        buffer.extend_from_slice(&available[..kept]);
+        if ended_line {
+            buffer.push(b'\n');
+        }
+        length += content_len;
+        reader.consume(content_len + usize::from(ended_line));
+        if ended_line {
+            break;
+        }
+    }
+    if !read_any {
+        return Ok(false);
+    }
+    if length > ctx.max_line_length {
+        let max = ctx.max_line_length;
+        ctx.report(
+            Severity::Error,
+            line,
+            format_args!(
+                "Truncated a {}-byte line to the maximum of {} bytes.",
+                length, max
+            ),
+        );
+    }
+    ctx.line_number = line;
+    Ok(true)
+}
+
+/// Consume up to `count` physical lines from `reader` without copying or decoding them.
+///
+/// Returns the number of lines skipped, which is less than `count` at end of input.
+fn skip_physical_lines<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    count: usize,
+) -> Result<usize> {
+    let mut skipped = 0;
+    let mut in_line = false; // Whether bytes of an unterminated line have been consumed
+    while skipped < count {
+        let available = reader
+            .fill_buf()
+            .map_err(FecError::Io)
+            .with_context(|| format!("Failed to read line {}", ctx.line_number + 1))?;
+        if available.is_empty() {
+            // A final line without a line ending still counts
+            if in_line {
+                skipped += 1;
+                ctx.line_number += 1;
+            }
+            break;
+        }
+        let (consumed, ended_line) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), false),
+        };
+        reader.consume(consumed);
+        if ended_line {
+            skipped += 1;
+            ctx.line_number += 1;
+            in_line = false;
+        } else {
+            in_line = true;
+        }
+    }
+    Ok(skipped)
+}
+
+/// Append the next physical line to `buffer`, taking it from `replay` before `reader`.
+fn next_physical_line<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut R,
+    replay: &mut VecDeque<(u64, Vec<u8>)>,
+    buffer: &mut Vec<u8>,
+) -> Result<bool> {
+    match replay.pop_front() {
+        Some((_, line)) => {
+            buffer.extend_from_slice(&line);
+            ctx.line_number += 1;
+            Ok(true)
+        }
+        None => read_physical_line(ctx, reader, buffer),
+    }
+}
+
+/// Append physical lines to `buffer` until its open quoted field is closed.
+///
+/// If the quote is still open after `MAX_QUOTED_RECORD_LINES` lines, it is treated as closed
+/// at the end of the record's first line and the lines read past it are queued in `replay`
+/// to be parsed as records of their own.
+fn read_quoted_continuation<R: BufRead>(
+    ctx: &mut FecContext,
+    reader: &mut CountingReader<R>,
+    replay: &mut VecDeque<(u64, Vec<u8>)>,
+    buffer: &mut Vec<u8>,
+) -> Result<()> {
+    let first_line_len = buffer.len();
+    let first_line_number = ctx.line_number;
+    let mut lines = 1;
+    let mut starts = Vec::new(); // Input offsets of the lines read past the first
+    while ends_inside_quotes(buffer) {
+        if lines == MAX_QUOTED_RECORD_LINES {
+            if ctx.strict {
+                return Err(unclosed_quote(first_line_number));
+            }
+            ctx.report(
+                Severity::Error,
+                first_line_number,
+                format_args!(
+                    "Quoted field is still open after {} lines; treating it as closed at the end of the line.",
+                    MAX_QUOTED_RECORD_LINES
+                ),
+            );
+            let read_ahead = buffer.split_off(first_line_len);
+            let read_ahead: Vec<_> = starts
+                .iter()
+                .zip(read_ahead.split_inclusive(|b| *b == b'\n'))
+                .map(|(start, line)| (*start, line.to_vec()))
+                .collect();
+            for line in read_ahead.into_iter().rev() {
+                replay.push_front(line);
+            }
+            ctx.line_number = first_line_number;
+            break;
+        }
+        starts.push(
+            replay
+                .front()
+                .map_or(reader.bytes_read, |(start, _)| *start),
+        );
+        if !next_physical_line(ctx, reader, replay, buffer)? {
+            if ctx.strict {
+                return Err(unclosed_quote(first_line_number));
+            }
+            break;
+        }
+        lines += 1;
+    }
+    Ok(())
+}
+
+/// The strict-mode error for a quoted field opened on `line` that is never closed.
+fn unclosed_quote(line: usize) -> anyhow::Error {
+    FecError::MalformedRow {
+        line,
+        reason: "quoted field is never closed".to_string(),
+    }
+    .into()
+}
+
+/// Decode a line read by `read_physical_line`, which must be valid UTF-8 in strict mode or
+/// with `FallbackEncoding::Error`; otherwise invalid lines are read as `ctx.fallback_encoding`
+/// (and reported when collecting issues).
+///
+/// NUL bytes are dealt with by `ctx.nul_policy` (see `check_nul_bytes`).
+pub(crate) fn decode_checked<'a>(ctx: &mut FecContext, line: &'a [u8]) -> Result<Cow<'a, str>> {
+    let must_be_utf8 = ctx.strict || ctx.fallback_encoding == FallbackEncoding::Error;
+    if (must_be_utf8 || ctx.issues.is_some()) && std::str::from_utf8(line).is_err() {
+        if must_be_utf8 {
+            return Err(FecError::InvalidEncoding {
+                line: ctx.line_number,
+            }
+            .into());
+        }
+        ctx.report(
+            Severity::Error,
+            ctx.line_number,
+            format_args!(
+                "Line is not valid UTF-8; reading it as {}.",
+                ctx.fallback_encoding.name()
+            ),
+        );
+    }
+    let (decoded, info) = decode_line_with(line, ctx.nul_policy, ctx.fallback_encoding);
+    check_nul_bytes(ctx, info.nul_bytes)?;
+    Ok(decoded)
+}
+
+/// Count the `count` NUL bytes found on the current line in `ctx.nul_bytes` and warn about
+/// them, or fail in strict mode.
+pub(crate) fn check_nul_bytes(ctx: &mut FecContext, count: usize) -> Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    if ctx.strict {
+        return Err(FecError::NulBytes {
+            line: ctx.line_number,
+            count,
+        }
+        .into());
+    }
+    ctx.nul_bytes += count;
+    let action = match ctx.nul_policy {
+        NulPolicy::Strip => "Stripped",
+        NulPolicy::Replace => "Replaced",
+    };
+    ctx.report(
+        Severity::Warning,
+        ctx.line_number,
+        format_args!("{} {} NUL byte(s).", action, count),
+    );
+    Ok(())
+}
+
+/// Whether a comma-delimited record ends inside a quoted field, i.e. it continues on the
+/// next physical line.
+fn ends_inside_quotes(record: &[u8]) -> bool {
+    let mut in_quotes = false;
+    let mut at_field_start = true;
+    let mut bytes = record.iter().peekable();
+    while let Some(&b) = bytes.next() {
+        if in_quotes {
+            if b == b'"' {
+                if bytes.peek() == Some(&&b'"') {
+                    bytes.next(); // Escaped quote
+                } else {
+                    in_quotes = false;
+                }
+            }
+        } else if b == b'"' && at_field_start {
+            in_quotes = true;
+        }
+        at_field_start = !in_quotes && b == b',';
+    }
+    in_quotes
+}
@@ -0,0 +1,89 @@
+//! Problems found in a filing by a validate-only parse.
+//!
+//! Instead of being printed as warnings or failing the parse, problems are collected as
+//! `ValidationIssue`s. Errors are the problems strict mode fails on (field-count mismatches,
+//! invalid UTF-8, overlong lines, unknown form types and malformed rows); everything else that
+//! would be warned about is a warning.
+
+use std::fmt;
+
+/// How serious a `ValidationIssue` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Suspicious input that is parsed anyway, e.g. a value that isn't a number.
+    Warning,
+    /// Malformed input that strict mode rejects.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found on one line of a filing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// The 1-based input line the problem was found on.
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.severity, self.message)
+    }
+}
+
+/// The overall verdict on a filing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationStatus {
+    /// No issues were found.
+    Valid,
+    /// Only warnings were found.
+    ValidWithWarnings,
+    /// At least one error was found.
+    Invalid,
+}
+
+impl ValidationStatus {
+    /// The process exit code for this status: `0` if valid, `2` if valid with warnings and `1`
+    /// if invalid.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ValidationStatus::Valid => 0,
+            ValidationStatus::ValidWithWarnings => 2,
+            ValidationStatus::Invalid => 1,
+        }
+    }
+}
+
+/// The issues found by a validate-only parse, in input order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Whether the filing is valid, valid with warnings, or invalid.
+    pub fn status(&self) -> ValidationStatus {
+        match self.issues.iter().map(|issue| issue.severity).max() {
+            None => ValidationStatus::Valid,
+            Some(Severity::Warning) => ValidationStatus::ValidWithWarnings,
+            Some(Severity::Error) => ValidationStatus::Invalid,
+        }
+    }
+
+    /// The number of issues of `severity`.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+}
@@ -0,0 +1,428 @@
+//! Opening input files for parsing, including compressed archives.
+//!
+//! Compressed input is decompressed as it is read, so archives larger than memory can be
+//! parsed without extracting them to disk first. With the `mmap` feature, plain files are
+//! memory-mapped so the parser can read lines straight out of the map.
+//!
+//! Filings saved as UTF-16 are converted to UTF-8 as they are read (see `Utf16Input`).
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Open `path` for parsing, picking a decoder from its extension.
+///
+/// - `.zip`: the archive's single `.fec` member (requires the `zip` feature).
+/// - `.gz`: the gzip-compressed filing.
+/// - Anything else is read as a plain filing, memory-mapped with the `mmap` feature.
+pub fn open_file(path: &str) -> Result<Box<dyn BufRead>> {
+    if has_extension(path, "zip") {
+        return open_zip(path);
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    if has_extension(path, "gz") {
+        return Ok(Box::new(BufReader::new(GzipInput::new(file))));
+    }
+    #[cfg(feature = "mmap")]
+    if let Some(mapped) = MappedFile::new(&file) {
+        return Ok(Box::new(mapped));
+    }
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// A memory-mapped file, read through `BufRead` with the whole rest of the file as the buffer.
+///
+/// The map must not change while it is read, so the file is assumed not to be written to
+/// during the parse, as with any input file.
+#[cfg(feature = "mmap")]
+pub struct MappedFile {
+    map: memmap2::Mmap,
+    position: usize, // Bytes of the map consumed so far
+}
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    /// Map `file`, or return `None` if it can't be mapped (pipes, devices, empty files), in
+    /// which case it should be streamed instead.
+    pub fn new(file: &File) -> Option<Self> {
+        let metadata = file.metadata().ok()?;
+        if !metadata.is_file() || metadata.len() == 0 {
+            return None;
+        }
+        // SAFETY: the input file isn't modified while it is parsed
+        let map = unsafe { memmap2::Mmap::map(file) }.ok()?;
+        #[cfg(unix)]
+        let _ = map.advise(memmap2::Advice::Sequential);
+        Some(MappedFile { map, position: 0 })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl Read for MappedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (&self.map[self.position..]).read(buf)?;
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl BufRead for MappedFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.map[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.map.len());
+    }
+}
+
+/// How many bytes from the start of the input are looked at for NULs alternating with other
+/// bytes when it has no UTF-16 byte-order mark.
+const UTF16_SNIFF_BYTES: usize = 1024;
+
+/// How many bytes of UTF-16 input are converted to UTF-8 at a time.
+const UTF16_CHUNK_BYTES: usize = 64 * 1024;
+
+/// The byte order of UTF-16 input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// UTF-16LE, as Windows tools save "Unicode" text; its byte-order mark is `FF FE`.
+    Little,
+    /// UTF-16BE; its byte-order mark is `FE FF`.
+    Big,
+}
+
+/// Whether input starting with `prefix` is UTF-16, and in which byte order.
+///
+/// Input starting with a UTF-16 byte-order mark is. Without one, it is if nearly every pair of
+/// bytes in its first `UTF16_SNIFF_BYTES` holds one NUL and one other byte, always in the
+/// same place, as ASCII text encoded as UTF-16 does.
+pub fn detect_utf16(prefix: &[u8]) -> Option<ByteOrder> {
+    match prefix {
+        [0xFF, 0xFE, ..] => return Some(ByteOrder::Little),
+        [0xFE, 0xFF, ..] => return Some(ByteOrder::Big),
+        _ => {}
+    }
+    let pairs = prefix[..prefix.len().min(UTF16_SNIFF_BYTES)].chunks_exact(2);
+    let total = pairs.len();
+    if total < 4 {
+        return None;
+    }
+    let (mut little, mut big) = (0, 0);
+    for pair in pairs {
+        match (pair[0], pair[1]) {
+            (a, 0) if a != 0 => little += 1,
+            (0, b) if b != 0 => big += 1,
+            _ => {}
+        }
+    }
+    // Allow for a few characters past U+00FF
+    if little * 10 >= total * 9 {
+        Some(ByteOrder::Little)
+    } else if big * 10 >= total * 9 {
+        Some(ByteOrder::Big)
+    } else {
+        None
+    }
+}
+
+/// Whether a `Utf16Input` converts its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Utf16Mode {
+    Undetected, // Nothing has been read yet
+    PassThrough,
+    Convert(ByteOrder),
+}
+
+/// A reader passing its input through unchanged, unless it is UTF-16 (see `detect_utf16`), in
+/// which case it is converted to UTF-8 as it is read, its byte-order mark becoming a UTF-8 one.
+///
+/// Code units, and surrogate pairs, split across reads of the underlying reader are put back
+/// together; unpaired surrogates and a dangling last byte become U+FFFD. Offsets into converted
+/// input count its UTF-8 bytes.
+pub struct Utf16Input<R> {
+    inner: R,
+    mode: Utf16Mode,
+    sniffed: Vec<u8>, // Input read while detecting its encoding, not yet consumed
+    output: Vec<u8>,  // Converted input not yet consumed
+    position: usize,  // Bytes of `output` consumed so far
+    odd_byte: Option<u8>, // The first byte of a code unit split across reads
+    high_surrogate: Option<u16>, // The first half of a surrogate pair
+}
+
+impl<R: BufRead> Utf16Input<R> {
+    /// Read `inner`, converting it to UTF-8 if it turns out to be UTF-16.
+    pub fn new(inner: R) -> Self {
+        Utf16Input {
+            inner,
+            mode: Utf16Mode::Undetected,
+            sniffed: Vec::new(),
+            output: Vec::new(),
+            position: 0,
+            odd_byte: None,
+            high_surrogate: None,
+        }
+    }
+
+    /// The byte order of the input if it is being converted from UTF-16, once it has been
+    /// read from.
+    pub fn byte_order(&self) -> Option<ByteOrder> {
+        match self.mode {
+            Utf16Mode::Convert(order) => Some(order),
+            _ => None,
+        }
+    }
+
+    /// Look at the start of the input to decide whether it is UTF-16.
+    fn detect(&mut self) -> io::Result<Option<ByteOrder>> {
+        if self.mode == Utf16Mode::Undetected {
+            let order = loop {
+                let input = self.inner.fill_buf()?;
+                if input.is_empty() || self.sniffed.len() + input.len() >= 2 {
+                    if self.sniffed.is_empty() {
+                        break detect_utf16(input);
+                    }
+                    let mut prefix = self.sniffed.clone();
+                    prefix.extend_from_slice(&input[..input.len().min(UTF16_SNIFF_BYTES)]);
+                    break detect_utf16(&prefix);
+                }
+                // A single byte can't show a byte-order mark: keep it and read on
+                self.sniffed.push(input[0]);
+                self.inner.consume(1);
+            };
+            self.mode = match order {
+                Some(order) => {
+                    // The kept byte starts the first code unit
+                    self.odd_byte = self.sniffed.pop();
+                    Utf16Mode::Convert(order)
+                }
+                None => Utf16Mode::PassThrough,
+            };
+        }
+        Ok(self.byte_order())
+    }
+
+    /// Convert the next chunk of UTF-16 input into `self.output`, leaving it empty only at the
+    /// end of input.
+    fn convert(&mut self, order: ByteOrder) -> io::Result<()> {
+        let Utf16Input {
+            inner,
+            output,
+            odd_byte,
+            high_surrogate,
+            ..
+        } = self;
+        output.clear();
+        self.position = 0;
+        while output.is_empty() {
+            let input = inner.fill_buf()?;
+            if input.is_empty() {
+                // Whatever is left over can't be completed
+                let dangling_byte = odd_byte.take().is_some();
+                if high_surrogate.take().is_some() || dangling_byte {
+                    push_char(output, char::REPLACEMENT_CHARACTER);
+                }
+                return Ok(());
+            }
+            let chunk = &input[..input.len().min(UTF16_CHUNK_BYTES)];
+            for &byte in chunk {
+                let Some(first) = odd_byte.take() else {
+                    *odd_byte = Some(byte);
+                    continue;
+                };
+                let unit = match order {
+                    ByteOrder::Little => u16::from_le_bytes([first, byte]),
+                    ByteOrder::Big => u16::from_be_bytes([first, byte]),
+                };
+                push_unit(output, high_surrogate, unit);
+            }
+            let consumed = chunk.len();
+            inner.consume(consumed);
+        }
+        Ok(())
+    }
+}
+
+/// Append the character the UTF-16 code unit `unit` completes to `output`, holding on to the
+/// first half of a surrogate pair in `high_surrogate` until the second arrives.
+fn push_unit(output: &mut Vec<u8>, high_surrogate: &mut Option<u16>, unit: u16) {
+    if let Some(high) = high_surrogate.take() {
+        if (0xDC00..=0xDFFF).contains(&unit) {
+            let code = 0x10000 + ((u32::from(high) - 0xD800) << 10) + (u32::from(unit) - 0xDC00);
+            push_char(
+                output,
+                char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+            );
+            return;
+        }
+        push_char(output, char::REPLACEMENT_CHARACTER);
+    }
+    if (0xD800..=0xDBFF).contains(&unit) {
+        *high_surrogate = Some(unit);
+    } else {
+        // Lone low surrogates aren't characters
+        let c = char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER);
+        push_char(output, c);
+    }
+}
+
+/// Append `c` to `output` as UTF-8.
+fn push_char(output: &mut Vec<u8>, c: char) {
+    output.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+impl<R: BufRead> Read for Utf16Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Utf16Input<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let Some(order) = self.detect()? else {
+            if !self.sniffed.is_empty() {
+                return Ok(&self.sniffed);
+            }
+            return self.inner.fill_buf();
+        };
+        if self.position == self.output.len() {
+            self.convert(order)?;
+        }
+        Ok(&self.output[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        match self.mode {
+            Utf16Mode::Convert(_) => self.position = (self.position + amt).min(self.output.len()),
+            _ => {
+                let sniffed = amt.min(self.sniffed.len());
+                self.sniffed.drain(..sniffed);
+                self.inner.consume(amt - sniffed);
+            }
+        }
+    }
+}
+
+/// The number of bytes `open_file` will read from `path`, if known without reading it: the
+/// size of a plain file, but not of a compressed one.
+pub fn input_size(path: &str) -> Option<u64> {
+    if has_extension(path, "zip") || has_extension(path, "gz") {
+        return None;
+    }
+    std::fs::metadata(path)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+}
+
+/// A gzip decoder whose errors report how far into the decompressed data they happened.
+struct GzipInput<R: Read> {
+    decoder: MultiGzDecoder<BufReader<R>>,
+    offset: u64, // Decompressed bytes produced so far
+}
+
+impl<R: Read> GzipInput<R> {
+    fn new(reader: R) -> Self {
+        GzipInput {
+            decoder: MultiGzDecoder::new(BufReader::new(reader)),
+            offset: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for GzipInput<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.decoder.read(buf) {
+            Ok(n) => {
+                self.offset += n as u64;
+                Ok(n)
+            }
+            Err(e) => Err(io::Error::new(
+                e.kind(),
+                format!(
+                    "gzip decoding failed at decompressed byte {}: {}",
+                    self.offset, e
+                ),
+            )),
+        }
+    }
+}
+
+/// Whether `path` ends in `.<extension>`, ignoring case.
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+/// Open the single `.fec` member of the zip archive at `path`, decompressing it as it is read.
+///
+/// Fails if the archive holds no `.fec` member or more than one.
+#[cfg(feature = "zip")]
+pub fn open_zip(path: &str) -> Result<Box<dyn BufRead>> {
+    use flate2::read::DeflateDecoder;
+    use std::io::{Seek, SeekFrom};
+    use zip::{CompressionMethod, ZipArchive};
+
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut archive =
+        ZipArchive::new(file).with_context(|| format!("Failed to read zip archive {}", path))?;
+
+    let members: Vec<usize> = (0..archive.len())
+        .filter(|&i| {
+            archive
+                .name_for_index(i)
+                .is_some_and(|name| !name.ends_with('/') && has_extension(name, "fec"))
+        })
+        .collect();
+    let index = match members[..] {
+        [index] => index,
+        [] => return Err(anyhow!("Zip archive {} contains no .fec file", path)),
+        _ => {
+            return Err(anyhow!(
+                "Zip archive {} contains {} .fec files; expected exactly one",
+                path,
+                members.len()
+            ))
+        }
+    };
+
+    // Find where the member's compressed data lives, then stream it straight from the file
+    let member = archive.by_index_raw(index)?;
+    if member.encrypted() {
+        return Err(anyhow!("{} in {} is encrypted", member.name(), path));
+    }
+    let (start, size, method) = (
+        member.data_start(),
+        member.compressed_size(),
+        member.compression(),
+    );
+    drop(member);
+    let mut file = archive.into_inner();
+    file.seek(SeekFrom::Start(start))?;
+    let data = file.take(size);
+
+    match method {
+        CompressionMethod::Stored => Ok(Box::new(BufReader::new(data))),
+        CompressionMethod::Deflated => Ok(Box::new(BufReader::new(DeflateDecoder::new(data)))),
+        other => Err(anyhow!(
+            "Unsupported compression method {:?} in {}",
+            other,
+            path
+        )),
+    }
+}
+
+/// Without the `zip` feature, zip archives can't be read.
+#[cfg(not(feature = "zip"))]
+pub fn open_zip(path: &str) -> Result<Box<dyn BufRead>> {
+    Err(anyhow!(
+        "Reading {} requires building with the `zip` feature",
+        path
+    ))
+}
@@ -4,9 +4,12 @@
 
 pub mod cli; // Command-line interface logic
 pub mod csv_helper;
+#[cfg(feature = "http")]
+pub mod download; // Fetching filings by ID
 pub mod encoding; // Encoding-related utilities
 pub mod errors; // Custom error types
 pub mod fec; // FEC parsing logic
+pub mod input; // Opening (compressed) input files
 pub mod writer;
 
 // Re-export anything you want to expose at the crate root
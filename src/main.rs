@@ -3,20 +3,29 @@
 //! This file:
 //! - Parses command-line arguments.
 //! - Initializes the FecContext and WriterContext.
-//! - Decides whether to read from a file or STDIN.
-//! - Calls the FEC parser to process the input data.
+//! - Decides whether to read from a file, STDIN or (with the `http` feature) a download.
+//! - Calls the FEC parser to process the input data, or only to validate it.
 
 use anyhow::Result;
-use std::fs::File;
 use std::io::{self, BufReader};
+use std::time::{Duration, Instant};
 
-use fast_fec_rust::cli::args::parse_args;
+use fast_fec_rust::cli::args::{parse_args, CliConfig};
 use fast_fec_rust::cli::usage::print_usage_and_exit;
-use fast_fec_rust::fec::context::FecContext;
-use fast_fec_rust::fec::parser::parse_fec;
-use fast_fec_rust::writer::WriterContext;
+use fast_fec_rust::encoding::NulPolicy;
+use fast_fec_rust::fec::context::{FecContext, ParseStats};
+use fast_fec_rust::fec::parser::{
+    parse_fec_with_options, validate_fec, ParseOptions, Progress, UNKNOWN_FORM_FILENAME,
+};
+use fast_fec_rust::fec::validation::{Severity, ValidationReport, ValidationStatus};
+use fast_fec_rust::input::{input_size, open_file};
+use fast_fec_rust::writer::{
+    is_broken_pipe, FileStats, WriterContext, WriterContextBuilder, WriterMetrics,
+};
 
 fn main() -> Result<()> {
+    let started = Instant::now();
+
     // Step 1: Parse command-line arguments.
     let cli_config = match parse_args() {
         Ok(cfg) => cfg,
@@ -37,42 +46,335 @@ fn main() -> Result<()> {
         cli_config.include_filing_id,
         cli_config.silent,
         cli_config.warn,
-    );
+    )
+    .with_row_numbers(cli_config.row_numbers)
+    .with_summary(cli_config.summary)
+    .with_skip(cli_config.skip)
+    .with_limit(cli_config.limit)
+    .with_max_line_length(cli_config.max_line_length)
+    .with_strict(cli_config.strict)
+    .with_fallback_encoding(cli_config.fallback)
+    .with_f99_text_file(cli_config.f99_text)
+    .with_full_form_names(cli_config.full_form_names)
+    .with_raw_headers(cli_config.raw_headers)
+    .with_paper(cli_config.paper)
+    .with_map_csv_exports(cli_config.map_csv_exports)
+    .with_split_filings(!cli_config.single_filing);
 
-    // Step 4: Initialize WriterContext for managing output.
-    let mut writer_ctx = WriterContext::new(
-        cli_config.output_directory.clone(),
-        cli_config.fec_id.clone(),
-        cli_config.write_to_disk,
-        cli_config.buffer_size,
-        None, // Optionally, pass a custom write function
-        None, // Optionally, pass a custom line function
-    );
+    // Step 4: Initialize WriterContext for managing output, streaming it to stdout in print mode.
+    let mut builder = WriterContext::builder()
+        .output_directory(cli_config.output_directory.clone())
+        .filing_id(cli_config.fec_id.clone())
+        .write_to_disk(cli_config.write_to_disk)
+        .buffer_size(cli_config.buffer_size)
+        .format(cli_config.format)
+        .output_delimiter(cli_config.output_delimiter)
+        .terminator(cli_config.terminator)
+        .quote_style(cli_config.quote_style)
+        .unquotable_replacement(cli_config.replace_with)
+        .null_string(cli_config.null_token.clone())
+        .compression(cli_config.compression)
+        .compression_level(cli_config.level)
+        .output_mode(cli_config.output_mode)
+        .manifest(cli_config.manifest)
+        .atomic_writes(cli_config.atomic)
+        .sync_on_close(cli_config.fsync)
+        .min_free_space(cli_config.min_free)
+        .max_rows_per_file(cli_config.max_rows)
+        .max_bytes_per_file(cli_config.max_bytes)
+        .filename_template(cli_config.template.clone());
+    if let Some(output) = &cli_config.output {
+        builder = builder.database(output);
+    }
+    builder = with_object_store(builder, &cli_config.output_directory)?;
+    if cli_config.print {
+        let stdout = Box::new(io::BufWriter::new(io::stdout()));
+        builder = builder.print(stdout, cli_config.print_form.clone());
+    }
+    if !cli_config.write_to_disk && !cli_config.print {
+        // Parsing without an output still counts rows and reports problems
+        if !cli_config.silent && !cli_config.validate {
+            eprintln!("Warning: no output is written without --write-to-disk or --print.");
+        }
+        builder = builder.discard_output();
+    }
+    let mut writer_ctx = builder.build()?;
 
-    // Step 5: Determine input source: file or STDIN.
+    writer_ctx.check_existing_output()?;
+    // Fail now rather than on the first flush if output can't be written where it goes
+    writer_ctx.preflight()?;
+
+    // Step 5: Determine input source: file, download or STDIN.
     let mut reader: Box<dyn io::BufRead> = if cli_config.use_stdin {
         if !cli_config.silent {
             eprintln!("Reading from STDIN for: {}", cli_config.fec_id);
         }
         Box::new(BufReader::new(io::stdin()))
+    } else if let Some(reader) = open_download(&cli_config)? {
+        reader
     } else {
         if !cli_config.silent {
             eprintln!("Opening file: {}", cli_config.fec_id);
         }
-        let file = File::open(&cli_config.fec_id)?;
-        Box::new(BufReader::new(file))
+        open_file(&cli_config.fec_id)?
     };
 
-    // Step 6: Parse the FEC data.
-    parse_fec(&mut ctx, &mut reader, &mut writer_ctx)?;
-
-    // Step 7: Finalize WriterContext (flush all buffers).
-    writer_ctx.flush_all()?;
+    // Step 6: Parse the FEC data, with a status line on interactive terminals.
+    let mut options = ParseOptions::default()
+        .with_threads(cli_config.threads)
+        .with_truncate_extra_fields(cli_config.truncate)
+        .with_combined_output(cli_config.combined)
+        .with_pad_rows(!cli_config.ragged);
+    if !cli_config.forms.is_empty() {
+        options = options.with_forms(cli_config.forms.iter().cloned().collect());
+    }
+    let show_progress = !cli_config.silent && atty::is(atty::Stream::Stderr);
+    if show_progress {
+        options = options.on_progress(print_progress);
+        if !cli_config.use_stdin {
+            if let Some(size) = input_size(&cli_config.fec_id) {
+                options = options.with_total_bytes(size);
+            }
+        }
+    }
+    if cli_config.validate {
+        let report = validate_fec(&mut ctx, &mut reader, &options);
+        if show_progress {
+            eprintln!();
+        }
+        std::process::exit(print_validation_report(&report, &cli_config.fec_id));
+    }
+    let row_extension = writer_ctx.row_extension();
+    let result = parse_fec_with_options(&mut ctx, &mut reader, &mut writer_ctx, &options)
+        // Step 7: Close WriterContext (flush and close every file, rename atomic writes' temp
+        // files), failing if any file couldn't be written whole.
+        .and_then(|_| writer_ctx.close())
+        .and_then(|report| report.check().map(|_| report));
+    if show_progress {
+        eprintln!();
+    }
+    let report = match result {
+        // Whoever read the printed rows has stopped reading, which isn't a failure
+        Err(e) if cli_config.print && is_broken_pipe(&e) => return Ok(()),
+        result => result?,
+    };
 
     // Step 8: If parsing succeeds, print a success message (unless silent).
     if !cli_config.silent {
-        println!("Done; parsing successful for: {}", cli_config.fec_id);
+        if ctx.mismatched_rows > 0 {
+            eprintln!(
+                "Warning: {} row(s) had a field count that didn't match their form's mapping.",
+                ctx.mismatched_rows
+            );
+        }
+        if ctx.nul_bytes > 0 {
+            let action = match ctx.nul_policy {
+                NulPolicy::Strip => "stripped from",
+                NulPolicy::Replace => "replaced in",
+            };
+            eprintln!(
+                "Warning: {} NUL byte(s) were {} the input.",
+                ctx.nul_bytes, action
+            );
+        }
+        if ctx.truncated_rows > 0 {
+            eprintln!(
+                "Warning: {} row(s) had fields past their form's columns dropped.",
+                ctx.truncated_rows
+            );
+        }
+        for path in &report.header_mismatches {
+            eprintln!(
+                "Warning: the header row of {} doesn't match the columns of the rows appended to it.",
+                path
+            );
+        }
+        if ctx.unknown_form_rows > 0 {
+            eprintln!(
+                "Warning: {} row(s) had an unknown form type and were quarantined in {}{}.",
+                ctx.unknown_form_rows, UNKNOWN_FORM_FILENAME, row_extension
+            );
+        }
+        // Printed rows have stdout to themselves
+        if !cli_config.print {
+            println!("Done; parsing successful for: {}", ctx.filings.join(", "));
+        }
+        print_run_summary(&report.closed, &ctx.stats(), started.elapsed());
+        if cli_config.verbose {
+            print_writer_metrics(&report.metrics);
+        }
+        if cli_config.fsync {
+            eprintln!("Synced to disk in {:.2}s", report.sync_time.as_secs_f64());
+        }
     }
 
     Ok(())
 }
+
+/// List the issues of `report` and its verdict on stdout, returning the exit code for it.
+fn print_validation_report(report: &ValidationReport, fec_id: &str) -> i32 {
+    for issue in &report.issues {
+        println!("{}", issue);
+    }
+    let status = report.status();
+    match status {
+        ValidationStatus::Valid => println!("{}: valid", fec_id),
+        ValidationStatus::ValidWithWarnings => println!(
+            "{}: valid with {} warning(s)",
+            fec_id,
+            report.count(Severity::Warning)
+        ),
+        ValidationStatus::Invalid => println!(
+            "{}: invalid, {} error(s) and {} warning(s)",
+            fec_id,
+            report.count(Severity::Error),
+            report.count(Severity::Warning)
+        ),
+    }
+    status.exit_code()
+}
+
+/// Print a table of the output files with their row counts and sizes to stderr, sorted by path,
+/// followed by the totals, the number of warnings and the time the run took.
+fn print_run_summary(files: &[(String, FileStats)], stats: &ParseStats, elapsed: Duration) {
+    let rows: usize = files.iter().map(|(_, f)| f.rows).sum();
+    let bytes: u64 = files.iter().map(|(_, f)| f.bytes).sum();
+    let name_width = files
+        .iter()
+        .map(|(name, _)| name.len())
+        .chain(["Total".len()])
+        .max()
+        .unwrap_or(0);
+    let rows_width = rows.to_string().len().max("Rows".len());
+    let bytes_width = bytes.to_string().len().max("Bytes".len());
+
+    eprintln!(
+        "{:<name_width$}  {:>rows_width$}  {:>bytes_width$}",
+        "File", "Rows", "Bytes"
+    );
+    for (name, file) in files {
+        eprintln!(
+            "{:<name_width$}  {:>rows_width$}  {:>bytes_width$}",
+            name, file.rows, file.bytes
+        );
+    }
+    eprintln!(
+        "{:<name_width$}  {:>rows_width$}  {:>bytes_width$}",
+        "Total", rows, bytes
+    );
+    eprintln!(
+        "{} warning(s) in {:.2}s",
+        stats.warnings,
+        elapsed.as_secs_f64()
+    );
+}
+
+/// Print how each output was written out on stderr, for `--verbose`: its flushes, the writes
+/// that overflowed its buffer and its bytes, then the time spent writing.
+fn print_writer_metrics(metrics: &WriterMetrics) {
+    let names: Vec<String> = metrics
+        .files
+        .iter()
+        .map(|file| format!("{}/{}.{}", file.filing_id, file.filename, file.extension))
+        .collect();
+    let name_width = names
+        .iter()
+        .map(String::len)
+        .chain(["Total".len()])
+        .max()
+        .unwrap_or(0);
+    let flushes_width = metrics.flushes.to_string().len().max("Flushes".len());
+    let overflows_width = metrics.overflows.to_string().len().max("Overflows".len());
+    let bytes_width = metrics.bytes.to_string().len().max("Bytes".len());
+    let row = |name: &str, flushes: u64, overflows: u64, bytes: u64| {
+        eprintln!(
+            "{:<name_width$}  {:>flushes_width$}  {:>overflows_width$}  {:>bytes_width$}",
+            name, flushes, overflows, bytes
+        );
+    };
+
+    eprintln!(
+        "{:<name_width$}  {:>flushes_width$}  {:>overflows_width$}  {:>bytes_width$}",
+        "Output", "Flushes", "Overflows", "Bytes"
+    );
+    for (name, file) in names.iter().zip(&metrics.files) {
+        row(name, file.flushes, file.overflows, file.bytes);
+    }
+    row("Total", metrics.flushes, metrics.overflows, metrics.bytes);
+    eprintln!(
+        "Writing took {:.3}s, the custom write function {:.3}s and syncing {:.3}s",
+        metrics.write_time.as_secs_f64(),
+        metrics.custom_write_time.as_secs_f64(),
+        metrics.sync_time.as_secs_f64()
+    );
+}
+
+/// Overwrite the status line on stderr with `progress`.
+fn print_progress(progress: Progress) {
+    const MIB: f64 = 1024.0 * 1024.0;
+    let read = progress.bytes_read as f64 / MIB;
+    let size = match progress.total_bytes {
+        Some(total) if total > 0 => format!(
+            "{:.1} of {:.1} MiB ({:.0}%)",
+            read,
+            total as f64 / MIB,
+            100.0 * progress.bytes_read as f64 / total as f64
+        ),
+        _ => format!("{:.1} MiB", read),
+    };
+    eprint!(
+        "\rParsed {} rows, {} in {:.1}s\x1b[K",
+        progress.rows,
+        size,
+        progress.elapsed.as_secs_f64()
+    );
+}
+
+/// Stream the filing from the FEC if the positional argument is a filing ID rather than a file.
+#[cfg(feature = "http")]
+fn open_download(cli_config: &CliConfig) -> Result<Option<Box<dyn io::BufRead>>> {
+    use fast_fec_rust::download::{is_filing_id, open_filing};
+
+    if !is_filing_id(&cli_config.fec_id) {
+        return Ok(None);
+    }
+    open_filing(&cli_config.base_url, &cli_config.fec_id, cli_config.silent).map(Some)
+}
+
+/// Upload output files to the object store `output_directory` names, if it is a URL such as
+/// `s3://bucket/prefix` rather than a directory.
+#[cfg(feature = "object_store")]
+fn with_object_store(
+    builder: WriterContextBuilder,
+    output_directory: &str,
+) -> Result<WriterContextBuilder> {
+    use fast_fec_rust::writer::{is_object_store_url, ObjectStoreSinkFactory};
+
+    if !is_object_store_url(output_directory) {
+        return Ok(builder);
+    }
+    Ok(builder.sink_factory(ObjectStoreSinkFactory::from_url(output_directory)?))
+}
+
+/// Without the `object_store` feature, an object store URL can't be written to.
+#[cfg(not(feature = "object_store"))]
+fn with_object_store(
+    builder: WriterContextBuilder,
+    output_directory: &str,
+) -> Result<WriterContextBuilder> {
+    use fast_fec_rust::writer::is_object_store_url;
+
+    if is_object_store_url(output_directory) {
+        return Err(anyhow::anyhow!(
+            "Writing output to {} requires building with the `object_store` feature",
+            output_directory
+        ));
+    }
+    Ok(builder)
+}
+
+/// Without the `http` feature, the positional argument is always a file.
+#[cfg(not(feature = "http"))]
+fn open_download(_cli_config: &CliConfig) -> Result<Option<Box<dyn io::BufRead>>> {
+    Ok(None)
+}
@@ -0,0 +1,485 @@
+//! `WriterContextBuilder`: builds a `WriterContext` from named settings, each with a default,
+//! instead of `WriterContext::new`'s positional arguments.
+//!
+//! ```no_run
+//! use fast_fec_rust::writer::WriterContext;
+//!
+//! let writer = WriterContext::builder()
+//!     .output_directory("output")
+//!     .filing_id("12345")
+//!     .buffer_size(65536)
+//!     .build()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    BoolFormat, Compression, CustomLineFn, CustomWriteFn, DateFormat, DoubleFormat, FileCloseFn,
+    FileOpenFn, FilenameTemplate, FlushPolicy, OutputFormat, OutputMode, QuoteStyle, SinkFactory,
+    Terminator, WriterContext, DEFAULT_MAX_OPEN_FILES,
+};
+use crate::cli::args::{DEFAULT_BUFFER_SIZE, DEFAULT_OUTPUT_DIRECTORY};
+
+/// Settings for a `WriterContext`, from `WriterContext::builder`.
+///
+/// Unless set, rows are written to disk under `DEFAULT_OUTPUT_DIRECTORY`, as uncompressed CSV,
+/// with buffers of `DEFAULT_BUFFER_SIZE` bytes, size-based flushing, no syncing and no callbacks.
+/// These are library defaults: the command line sets the output mode, syncing and whether to
+/// write to disk at all from its own arguments, and only writes to disk with `--write-to-disk`.
+pub struct WriterContextBuilder {
+    output_directory: String,
+    filing_id: String,
+    write_to_disk: bool,
+    buffer_size: usize,
+    flush_policy: FlushPolicy,
+    file_flush_policies: HashMap<String, FlushPolicy>,
+    custom_write_fn: Option<Box<CustomWriteFn>>,
+    custom_line_fn: Option<Box<CustomLineFn>>,
+    on_file_open: Option<Box<FileOpenFn>>,
+    on_file_close: Option<Box<FileCloseFn>>,
+    sink_factory: Option<Box<dyn SinkFactory>>,
+    print: Option<(Box<dyn Write + Send>, Option<String>)>, // Print target and form filter
+    discard_output: bool, // Whether writing nothing anywhere is meant
+    compression: Compression,
+    compression_level: Option<i32>,
+    format: OutputFormat,
+    output_delimiter: u8,
+    double_format: DoubleFormat,
+    date_format: DateFormat,
+    bool_format: BoolFormat,
+    terminator: Terminator,
+    quote_style: QuoteStyle,
+    unquotable_replacement: Option<char>,
+    null_string: Option<String>,
+    output_mode: OutputMode,
+    max_open_files: usize,
+    manifest: bool,
+    atomic_writes: bool,
+    sync_on_close: bool,
+    min_free_space: Option<u64>,
+    max_rows_per_file: Option<usize>,
+    max_bytes_per_file: Option<u64>,
+    collect_outputs: bool,
+    max_collected_bytes: Option<u64>,
+    filename_template: Option<String>,
+    database: Option<PathBuf>,
+}
+
+impl Default for WriterContextBuilder {
+    fn default() -> Self {
+        Self {
+            output_directory: DEFAULT_OUTPUT_DIRECTORY.to_string(),
+            filing_id: String::new(),
+            write_to_disk: true,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            flush_policy: FlushPolicy::Size,
+            file_flush_policies: HashMap::new(),
+            custom_write_fn: None,
+            custom_line_fn: None,
+            on_file_open: None,
+            on_file_close: None,
+            sink_factory: None,
+            print: None,
+            discard_output: false,
+            compression: Compression::None,
+            compression_level: None,
+            format: OutputFormat::Csv,
+            output_delimiter: b',',
+            double_format: DoubleFormat::default(),
+            date_format: DateFormat::default(),
+            bool_format: BoolFormat::default(),
+            terminator: Terminator::Lf,
+            quote_style: QuoteStyle::Necessary,
+            unquotable_replacement: None,
+            null_string: None,
+            output_mode: OutputMode::Truncate,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            manifest: false,
+            atomic_writes: false,
+            sync_on_close: false,
+            min_free_space: None,
+            max_rows_per_file: None,
+            max_bytes_per_file: None,
+            collect_outputs: false,
+            max_collected_bytes: None,
+            filename_template: None,
+            database: None,
+        }
+    }
+}
+
+impl WriterContextBuilder {
+    /// The directory each filing's output directory is created in.
+    pub fn output_directory(mut self, output_directory: impl Into<String>) -> Self {
+        self.output_directory = output_directory.into();
+        self
+    }
+
+    /// The filing ID naming the directory output files are written to.
+    pub fn filing_id(mut self, filing_id: impl Into<String>) -> Self {
+        self.filing_id = filing_id.into();
+        self
+    }
+
+    /// Whether output files are written to disk.
+    pub fn write_to_disk(mut self, write_to_disk: bool) -> Self {
+        self.write_to_disk = write_to_disk;
+        self
+    }
+
+    /// The capacity of each file's buffer, in bytes.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// See `WriterContext::with_flush_policy`.
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    /// See `WriterContext::with_file_flush_policy`.
+    pub fn file_flush_policy(mut self, filename: impl Into<String>, policy: FlushPolicy) -> Self {
+        self.file_flush_policies.insert(filename.into(), policy);
+        self
+    }
+
+    /// Call `f` with each file's name, extension and uncompressed bytes as its buffer is
+    /// written out.
+    pub fn custom_write_fn(
+        mut self,
+        f: impl Fn(&str, &str, &[u8]) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.custom_write_fn = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each row's file name, text and type codes as the row is ended.
+    pub fn custom_line_fn(
+        mut self,
+        f: impl Fn(&str, &str, &str) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.custom_line_fn = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each file's name and extension as it is opened; see
+    /// `WriterContext::with_on_file_open`.
+    pub fn on_file_open(
+        mut self,
+        f: impl Fn(&str, &str) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.on_file_open = Some(Box::new(f));
+        self
+    }
+
+    /// Call `f` with each file's name, extension and total bytes once it is written whole; see
+    /// `WriterContext::with_on_file_close`.
+    pub fn on_file_close(
+        mut self,
+        f: impl Fn(&str, &str, u64) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.on_file_close = Some(Box::new(f));
+        self
+    }
+
+    /// Write each file to a sink from `factory` instead of to disk; see
+    /// `WriterContext::with_sink_factory`.
+    pub fn sink_factory(mut self, factory: impl SinkFactory + 'static) -> Self {
+        self.sink_factory = Some(Box::new(factory));
+        self
+    }
+
+    /// Stream rows to `out` instead of writing files; see `WriterContext::with_print`.
+    pub fn print(mut self, out: Box<dyn Write + Send>, form: Option<String>) -> Self {
+        self.print = Some((out, form));
+        self
+    }
+
+    /// Allow a writer that writes nothing to disk and has no callback or print target, whose
+    /// rows go nowhere, e.g. to only validate a filing. `build` fails on one otherwise.
+    pub fn discard_output(mut self) -> Self {
+        self.discard_output = true;
+        self
+    }
+
+    /// See `WriterContext::with_compression`.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// See `WriterContext::with_compression_level`.
+    pub fn compression_level(mut self, level: Option<i32>) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// See `WriterContext::with_format`.
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// See `WriterContext::with_output_delimiter`.
+    pub fn output_delimiter(mut self, delimiter: u8) -> Self {
+        self.output_delimiter = delimiter;
+        self
+    }
+
+    /// See `WriterContext::with_double_format`.
+    pub fn double_format(mut self, format: DoubleFormat) -> Self {
+        self.double_format = format;
+        self
+    }
+
+    /// See `WriterContext::with_date_format`.
+    pub fn date_format(mut self, format: DateFormat) -> Self {
+        self.date_format = format;
+        self
+    }
+
+    /// See `WriterContext::with_bool_format`.
+    pub fn bool_format(mut self, format: BoolFormat) -> Self {
+        self.bool_format = format;
+        self
+    }
+
+    /// See `WriterContext::with_terminator`.
+    pub fn terminator(mut self, terminator: Terminator) -> Self {
+        self.terminator = terminator;
+        self
+    }
+
+    /// See `WriterContext::with_quote_style`.
+    pub fn quote_style(mut self, style: QuoteStyle) -> Self {
+        self.quote_style = style;
+        self
+    }
+
+    /// See `WriterContext::with_unquotable_replacement`.
+    pub fn unquotable_replacement(mut self, replacement: Option<char>) -> Self {
+        self.unquotable_replacement = replacement;
+        self
+    }
+
+    /// See `WriterContext::with_null_string`.
+    pub fn null_string(mut self, null_string: Option<String>) -> Self {
+        self.null_string = null_string;
+        self
+    }
+
+    /// See `WriterContext::with_output_mode`.
+    pub fn output_mode(mut self, mode: OutputMode) -> Self {
+        self.output_mode = mode;
+        self
+    }
+
+    /// See `WriterContext::with_max_open_files`.
+    pub fn max_open_files(mut self, max: usize) -> Self {
+        self.max_open_files = max;
+        self
+    }
+
+    /// See `WriterContext::with_manifest`.
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// See `WriterContext::with_atomic_writes`.
+    pub fn atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// See `WriterContext::with_sync_on_close`.
+    pub fn sync_on_close(mut self, sync_on_close: bool) -> Self {
+        self.sync_on_close = sync_on_close;
+        self
+    }
+
+    /// See `WriterContext::with_durable`; the same as `sync_on_close`.
+    pub fn durable(self, durable: bool) -> Self {
+        self.sync_on_close(durable)
+    }
+
+    /// See `WriterContext::with_min_free_space`.
+    pub fn min_free_space(mut self, bytes: Option<u64>) -> Self {
+        self.min_free_space = bytes;
+        self
+    }
+
+    /// See `WriterContext::with_max_rows_per_file`.
+    pub fn max_rows_per_file(mut self, max: Option<usize>) -> Self {
+        self.max_rows_per_file = max;
+        self
+    }
+
+    /// See `WriterContext::with_max_bytes_per_file`.
+    pub fn max_bytes_per_file(mut self, max: Option<u64>) -> Self {
+        self.max_bytes_per_file = max;
+        self
+    }
+
+    /// See `WriterContext::with_collect_outputs`.
+    pub fn collect_outputs(mut self, collect_outputs: bool) -> Self {
+        self.collect_outputs = collect_outputs;
+        self
+    }
+
+    /// See `WriterContext::with_max_collected_bytes`.
+    pub fn max_collected_bytes(mut self, max: Option<u64>) -> Self {
+        self.max_collected_bytes = max;
+        self
+    }
+
+    /// Name output files by this template (see `FilenameTemplate`), which `build` parses.
+    pub fn filename_template(mut self, template: Option<String>) -> Self {
+        self.filename_template = template;
+        self
+    }
+
+    /// See `WriterContext::with_database`.
+    pub fn database(mut self, path: impl Into<PathBuf>) -> Self {
+        self.database = Some(path.into());
+        self
+    }
+
+    /// Build the `WriterContext`.
+    ///
+    /// Fails on settings that can't work together: a compression, compression level or format
+    /// this build can't write, a writer whose rows would silently go nowhere (see
+    /// `discard_output`), atomic writes to or split files that are appended to, atomic writes,
+    /// compression, a manifest or SQLite output with a sink factory, or a filename template that
+    /// doesn't parse or expands to no usable path for the filing ID.
+    pub fn build(self) -> Result<WriterContext> {
+        if !self.write_to_disk
+            && self.custom_write_fn.is_none()
+            && self.custom_line_fn.is_none()
+            && self.print.is_none()
+            && !self.collect_outputs
+            && !self.discard_output
+        {
+            return Err(anyhow!(
+                "The writer would discard every row: it doesn't write to disk or collect output \
+                 and has no custom write function, line function or print target (use \
+                 discard_output to allow it)"
+            ));
+        }
+        if !self.compression.is_available() {
+            return Err(anyhow!(
+                "Writing {:?} output requires building with the `zstd` feature",
+                self.compression
+            ));
+        }
+        if let Some(level) = self.compression_level {
+            self.compression.check_level(level)?;
+        }
+        if !self.format.is_available() {
+            return Err(anyhow!(
+                "Writing {:?} output requires building with the `sqlite` feature",
+                self.format
+            ));
+        }
+
+        if self.atomic_writes && self.output_mode == OutputMode::Append {
+            return Err(anyhow!(
+                "Atomic writes can't append to existing output files (use another output mode)"
+            ));
+        }
+        if (self.max_rows_per_file.is_some() || self.max_bytes_per_file.is_some())
+            && self.output_mode == OutputMode::Append
+        {
+            return Err(anyhow!(
+                "Split output files can't be appended to (use another output mode)"
+            ));
+        }
+        if self.atomic_writes && (self.format == OutputFormat::Sqlite || self.database.is_some()) {
+            return Err(anyhow!("Atomic writes aren't supported for SQLite output"));
+        }
+        if self.sink_factory.is_some() && self.atomic_writes {
+            return Err(anyhow!(
+                "Atomic writes rename files on disk, which a sink factory doesn't write"
+            ));
+        }
+        if self.sink_factory.is_some() && self.compression != Compression::None {
+            return Err(anyhow!(
+                "Compression applies to files on disk; a sink factory's sinks compress their \
+                 output themselves"
+            ));
+        }
+        if self.sink_factory.is_some() && (self.manifest || self.format == OutputFormat::Sqlite) {
+            return Err(anyhow!(
+                "Manifests and SQLite output are written to disk, which a sink factory doesn't \
+                 write"
+            ));
+        }
+
+        let filename_template = match &self.filename_template {
+            Some(template) => {
+                let template: FilenameTemplate = template.parse()?;
+                template.check(&self.filing_id)?;
+                Some(template)
+            }
+            None => None,
+        };
+
+        let mut writer = WriterContext::create(
+            self.output_directory,
+            self.filing_id,
+            self.write_to_disk,
+            self.buffer_size,
+            self.custom_write_fn,
+            self.custom_line_fn,
+        )
+        .with_flush_policy(self.flush_policy)
+        .with_compression(self.compression)
+        .with_compression_level(self.compression_level)
+        .with_format(self.format)
+        .with_output_delimiter(self.output_delimiter)
+        .with_terminator(self.terminator)
+        .with_quote_style(self.quote_style)
+        .with_unquotable_replacement(self.unquotable_replacement)
+        .with_null_string(self.null_string)
+        .with_double_format(self.double_format)
+        .with_date_format(self.date_format)
+        .with_bool_format(self.bool_format)
+        .with_output_mode(self.output_mode)
+        .with_max_open_files(self.max_open_files)
+        .with_manifest(self.manifest)
+        .with_atomic_writes(self.atomic_writes)
+        .with_sync_on_close(self.sync_on_close)
+        .with_min_free_space(self.min_free_space)
+        .with_max_rows_per_file(self.max_rows_per_file)
+        .with_max_bytes_per_file(self.max_bytes_per_file)
+        .with_collect_outputs(self.collect_outputs)
+        .with_max_collected_bytes(self.max_collected_bytes)
+        .with_filename_template(filename_template);
+        for (filename, policy) in self.file_flush_policies {
+            writer = writer.with_file_flush_policy(filename, policy);
+        }
+        if let Some(path) = self.database {
+            writer = writer.with_database(path);
+        }
+        if let Some(factory) = self.sink_factory {
+            writer = writer.with_sink_factory(factory);
+        }
+        if let Some(f) = self.on_file_open {
+            writer = writer.with_on_file_open(f);
+        }
+        if let Some(f) = self.on_file_close {
+            writer = writer.with_on_file_close(f);
+        }
+        if let Some((out, form)) = self.print {
+            writer = writer.with_print(out, form);
+        }
+        Ok(writer)
+    }
+}
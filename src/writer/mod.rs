@@ -6,21 +6,382 @@
 //! - A `WriterContext` that can manage multiple files (by name), custom callbacks, etc.
 //! - Methods for writing strings, characters, doubles, and flushing/closing resources.
 //! - An optional `write_csv_record` method using the `csv` crate to properly escape fields.
+//! - Optional gzip or zstd compression of the files written to disk (`Compression`).
+//! - Rows written as CSV, as newline-delimited JSON objects or to SQLite tables (`OutputFormat`).
+//! - Existing output files emptied, appended to or refused (`OutputMode`).
+//! - CSV rows separated by commas, tabs (`SA.tsv`) or another single byte.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::Write as FmtWrite;
 use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::path::Path;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use flate2::write::GzEncoder;
 
 // NEW: import the csv crate
 use csv::WriterBuilder;
 
 use anyhow::{anyhow, Result};
 
+use crate::errors::FecError;
+
+pub mod builder;
+#[cfg(feature = "object_store")]
+pub mod object_store;
+pub mod sink;
+pub mod sqlite;
+pub mod sync;
+pub mod template;
+
+#[cfg(feature = "object_store")]
+pub use self::object_store::ObjectStoreSinkFactory;
+pub use builder::WriterContextBuilder;
+pub use sink::{is_object_store_url, OutputSink, SinkFactory};
+pub use sync::SyncWriter;
+pub use template::FilenameTemplate;
+
+use sqlite::SqliteWriter;
+
 /// The default CSV extension, as in the original code.
 pub const CSV_EXTENSION: &str = ".csv";
 
+/// The extension of row files written with a tab delimiter.
+pub const TSV_EXTENSION: &str = ".tsv";
+
+/// The extension of row files written as newline-delimited JSON.
+pub const NDJSON_EXTENSION: &str = ".ndjson";
+
+/// The name of the database SQLite output goes to in the output directory, unless another path
+/// is given with `WriterContext::with_database`.
+pub const DEFAULT_DATABASE: &str = "filings.db";
+
+/// How many output files are kept open at once, unless set with
+/// `WriterContext::with_max_open_files`.
+pub const DEFAULT_MAX_OPEN_FILES: usize = 64;
+
+/// The name of the file listing a filing's output files, written to its output directory by
+/// `WriterContext::flush_all` if enabled with `WriterContext::with_manifest`.
+pub const MANIFEST_FILENAME: &str = "manifest.csv";
+
+/// The columns of `MANIFEST_FILENAME`.
+pub const MANIFEST_COLUMNS: [&str; 5] = ["file", "form_type", "rows", "bytes", "header"];
+
+/// The suffix of the temporary names output files are written under with atomic writes, until
+/// `WriterContext::finalize` renames them (`SA.csv.tmp`).
+pub const TEMP_SUFFIX: &str = ".tmp";
+
+/// The extension of the file F99 text blocks are written to verbatim.
+pub const TEXT_EXTENSION: &str = ".txt";
+
+/// The name of the leading column holding each row's form in print mode without a form filter:
+/// the name its file would have (e.g. `SA` for an `SA11AI` row). Rows keep their own `form_type`
+/// column after it.
+pub const PRINT_FORM_COLUMN: &str = "form";
+
+/// Whether `error` was caused by the reader of a print mode stream going away, e.g. output
+/// piped into `head` that has read enough. Callers can treat it as a clean end of the output.
+pub fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe)
+    })
+}
+
+/// Format a double the way `write_double` writes it by default, with two decimal places.
+pub fn format_double(value: f64) -> Result<String> {
+    let mut s = String::new();
+    write!(&mut s, "{:.2}", value)?; // Format with two decimal places
+    Ok(s)
+}
+
+/// How a double is written as text (see `format_double_with`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoubleFormat {
+    /// Exactly this many decimal places, as FastFEC writes amounts: `3` => `3.00` for 2.
+    Fixed(usize),
+    /// The shortest text that parses back to the same value, never in exponent notation: `3`,
+    /// `12.5`, `0.30000000000000004`. Suits rates and coordinates.
+    RoundTrip,
+    /// At most this many decimal places, without trailing zeros or a trailing point:
+    /// `3.10` => `3.1` and `3.00` => `3` for 2.
+    Trimmed(usize),
+}
+
+impl Default for DoubleFormat {
+    fn default() -> Self {
+        DoubleFormat::Fixed(2)
+    }
+}
+
+/// Format `value` as `format` says.
+pub fn format_double_with(value: f64, format: DoubleFormat) -> String {
+    match format {
+        DoubleFormat::Fixed(decimals) => format!("{:.*}", decimals, value),
+        DoubleFormat::RoundTrip => value.to_string(),
+        DoubleFormat::Trimmed(decimals) => {
+            let mut s = format!("{:.*}", decimals, value);
+            if s.contains('.') {
+                s.truncate(s.trim_end_matches('0').trim_end_matches('.').len());
+            }
+            if s == "-0" {
+                s.remove(0); // A negative value rounded to zero
+            }
+            s
+        }
+    }
+}
+
+/// How a date is written as text (see `format_date_with`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// ISO 8601, `YYYY-MM-DD`: `2024-01-15`.
+    #[default]
+    Iso,
+    /// `YYYYMMDD`, as filings write dates: `20240115`.
+    Compact,
+}
+
+/// Format the date of `year`, `month` and `day` as `format` says, zero-padding each part.
+pub fn format_date_with(year: i32, month: u32, day: u32, format: DateFormat) -> String {
+    match format {
+        DateFormat::Iso => format!("{:04}-{:02}-{:02}", year, month, day),
+        DateFormat::Compact => format!("{:04}{:02}{:02}", year, month, day),
+    }
+}
+
+/// Format an integer as `write_integer` writes it: all its digits, never in exponent notation.
+pub fn format_integer(value: i64) -> String {
+    value.to_string()
+}
+
+/// The tokens a boolean is written as (see `write_bool`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoolFormat {
+    /// Written for `true`.
+    pub true_token: String,
+    /// Written for `false`.
+    pub false_token: String,
+}
+
+impl BoolFormat {
+    /// Write `true` and `false` as these tokens, e.g. `Y` and `N`.
+    pub fn new(true_token: impl Into<String>, false_token: impl Into<String>) -> Self {
+        Self {
+            true_token: true_token.into(),
+            false_token: false_token.into(),
+        }
+    }
+
+    /// The token `value` is written as.
+    pub fn format(&self, value: bool) -> &str {
+        match value {
+            true => &self.true_token,
+            false => &self.false_token,
+        }
+    }
+}
+
+impl Default for BoolFormat {
+    fn default() -> Self {
+        Self::new("true", "false")
+    }
+}
+
+/// The format rows are written to their form's file in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// CSV (`SA.csv`), starting with a header row of column names.
+    #[default]
+    Csv,
+    /// Newline-delimited JSON (`SA.ndjson`): one object per row, keyed by the column names the
+    /// header row would have, with no header row.
+    Ndjson,
+    /// A table per form (`SA`) in an SQLite database, named by the header row's column names.
+    /// Writing it requires the `sqlite` feature.
+    Sqlite,
+}
+
+impl OutputFormat {
+    /// The extension of row files written in this format; none for SQLite, whose rows go to
+    /// tables.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => CSV_EXTENSION,
+            OutputFormat::Ndjson => NDJSON_EXTENSION,
+            OutputFormat::Sqlite => "",
+        }
+    }
+
+    /// Whether this build can write output in this format.
+    pub fn is_available(self) -> bool {
+        self != OutputFormat::Sqlite || cfg!(feature = "sqlite")
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    /// Parse a format name as given on the command line: `csv`, `ndjson` or `sqlite`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "sqlite" => Ok(OutputFormat::Sqlite),
+            _ => Err(anyhow!("Unknown output format {:?}", s)),
+        }
+    }
+}
+
+/// What happens to an output file that already exists when a run first opens it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Rows are added after the file's content; a non-empty file keeps its header row.
+    Append,
+    /// The file is emptied and written from scratch.
+    #[default]
+    Truncate,
+    /// The run fails with an error naming the file.
+    FailIfExists,
+}
+
+/// The line break ending each row (and each line of F99 text files) written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Terminator {
+    /// `\n`, as FastFEC writes. F99 text files keep the filing's own line breaks.
+    #[default]
+    Lf,
+    /// `\r\n`, for tools that expect Windows line breaks.
+    Crlf,
+}
+
+impl Terminator {
+    /// The line break's bytes.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Terminator::Lf => "\n",
+            Terminator::Crlf => "\r\n",
+        }
+    }
+}
+
+/// When a file's buffer is written out, besides whenever it fills up.
+///
+/// Rows otherwise sit in the buffer until `buffer_size` bytes have been written, which a
+/// consumer reading a file as it's written (`tail -f`) waits on for the whole of a slow parse.
+/// A line counts as written once its `\n` is, whether it ends a row or a line of text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Only when the buffer fills up, or the file is flushed or closed.
+    #[default]
+    Size,
+    /// As soon as each line is written.
+    PerLine,
+    /// Once this many lines have been written since the buffer was last written out (at least
+    /// one).
+    PerNRows(u32),
+}
+
+/// Which fields of CSV rows are quoted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// Every field, empty ones included.
+    Always,
+    /// Only fields holding the delimiter, a quote or a line break, and a row's only field if it
+    /// is empty.
+    #[default]
+    Necessary,
+    /// No field. A field holding the delimiter or a line break can't be written unquoted, so
+    /// writing it fails unless a replacement is set (see
+    /// `WriterContext::with_unquotable_replacement`); quotes are written as is.
+    Never,
+}
+
+impl FromStr for QuoteStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(QuoteStyle::Always),
+            "necessary" => Ok(QuoteStyle::Necessary),
+            "never" => Ok(QuoteStyle::Never),
+            _ => Err(anyhow!(
+                "Unknown quote style: {} (expected always, necessary or never)",
+                s
+            )),
+        }
+    }
+}
+
+/// How output files written to disk are compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Files are written as plain text.
+    #[default]
+    None,
+    /// Files are gzip streams, named with a trailing `.gz` (`SA.csv.gz`).
+    Gzip,
+    /// Files are zstd streams, named with a trailing `.zst` (`SA.csv.zst`). Writing them
+    /// requires the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// The suffix added to the names of files written with this compression.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Whether this build can write files with this compression.
+    pub fn is_available(self) -> bool {
+        self != Compression::Zstd || cfg!(feature = "zstd")
+    }
+
+    /// Check that `level` is a compression level this compression accepts: 0 to 9 for gzip and
+    /// 1 to 22 for zstd. Uncompressed output takes no level.
+    pub fn check_level(self, level: i32) -> Result<()> {
+        let (name, levels) = match self {
+            Compression::None => return Err(anyhow!("A compression level needs a compression")),
+            Compression::Gzip => ("gzip", 0..=9),
+            Compression::Zstd => ("zstd", 1..=22),
+        };
+        if !levels.contains(&level) {
+            return Err(anyhow!(
+                "Invalid {} compression level {}; expected {} to {}",
+                name,
+                level,
+                levels.start(),
+                levels.end()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    /// Parse a compression name as given on the command line: `gzip`, `zstd` or `none`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(anyhow!("Unknown compression {:?}", s)),
+        }
+    }
+}
+
 /// An optional custom write callback, akin to the old `CustomWriteFunction`.
 /// In Rust, we store it as a boxed closure returning `Result<()>`.
 pub type CustomWriteFn = dyn Fn(&str, &str, &[u8]) -> Result<()> + Send + Sync;
@@ -28,6 +389,14 @@ pub type CustomWriteFn = dyn Fn(&str, &str, &[u8]) -> Result<()> + Send + Sync;
 /// An optional custom line callback, akin to the old `CustomLineFunction`.
 pub type CustomLineFn = dyn Fn(&str, &str, &str) -> Result<()> + Send + Sync;
 
+/// An optional callback told when a file is opened, with its name and extension; see
+/// `WriterContext::with_on_file_open`.
+pub type FileOpenFn = dyn Fn(&str, &str) -> Result<()> + Send + Sync;
+
+/// An optional callback told when a file is closed, with its name, extension and the total bytes
+/// written to it; see `WriterContext::with_on_file_close`.
+pub type FileCloseFn = dyn Fn(&str, &str, u64) -> Result<()> + Send + Sync;
+
 /// A buffered file that replicates `BUFFER_FILE`.
 /// - We store `buffer` as a `Vec<u8>` rather than a raw pointer.
 /// - We track `position` within this vector.
@@ -47,22 +416,18 @@ impl BufferFile {
         }
     }
 
-    /// Write raw bytes into this buffer, flushing if we exceed capacity.
+    /// Write raw bytes into this buffer, as many as fit.
     /// Returns the leftover bytes if buffer is full.
-    fn write_bytes(&mut self, data: &[u8]) -> Vec<u8> {
-        let space_left = self.capacity - self.position;
-        if data.len() <= space_left {
-            // Fits in buffer
-            self.buffer.extend_from_slice(data);
-            self.position += data.len();
-            Vec::new()
-        } else {
-            // Part of data fits, the rest is overflow
-            let (first, second) = data.split_at(space_left);
-            self.buffer.extend_from_slice(first);
-            self.position += first.len();
-            second.to_vec()
-        }
+    fn write_bytes<'a>(&mut self, data: &'a [u8]) -> &'a [u8] {
+        let (first, second) = data.split_at(data.len().min(self.space_left()));
+        self.buffer.extend_from_slice(first);
+        self.position += first.len();
+        second
+    }
+
+    /// How many more bytes fit before the buffer is full.
+    fn space_left(&self) -> usize {
+        self.capacity - self.position
     }
 
     /// Check if buffer is empty.
@@ -77,19 +442,415 @@ impl BufferFile {
     }
 }
 
+/// What was written to one output file during a run (see `WriterContext::file_stats`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// CSV rows written after the header row.
+    pub rows: usize,
+    /// Bytes written, header row included. In print mode, the bytes the file would have held.
+    pub bytes: u64,
+}
+
+/// What was written to the output of one form of one filing during a run, across the parts of
+/// its file if split (see `WriterContext::stats`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputStats {
+    /// The filing the output belongs to.
+    pub filing_id: String,
+    /// The output's name, e.g. `SA`.
+    pub filename: String,
+    /// The output's extension as written to, `csv` for rows whatever the format or
+    /// compression (or `txt` for F99 text).
+    pub extension: String,
+    /// CSV rows written after the header row.
+    pub rows: usize,
+    /// Bytes written, header row included, before compression.
+    pub bytes: u64,
+}
+
+/// How one form's output of one filing was written out during a run, across the parts of its
+/// file if split (see `WriterContext::metrics`), for tuning `buffer_size`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct FileMetrics {
+    /// The filing the output belongs to.
+    pub filing_id: String,
+    /// The output's name, e.g. `SA`.
+    pub filename: String,
+    /// The output's extension as written to, as `OutputStats` has it.
+    pub extension: String,
+    /// Times the buffer was written out: when full, flushed or closed, or for a write too large
+    /// to buffer.
+    pub flushes: u64,
+    /// Writes that didn't fit in what was left of the buffer.
+    pub overflows: u64,
+    /// Bytes written out, before compression.
+    pub bytes: u64,
+}
+
+/// How the writer's output was written out during a run, by output and in total (see
+/// `WriterContext::metrics`).
+///
+/// Times are measured around each write out of a buffer, not each row, so they cost little
+/// enough to be kept always.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WriterMetrics {
+    /// Each output's metrics, sorted by filing, name and extension.
+    pub files: Vec<FileMetrics>,
+    /// Times buffers were written out, over every output.
+    pub flushes: u64,
+    /// Writes that didn't fit in what was left of their buffer, over every output.
+    pub overflows: u64,
+    /// Bytes written out, over every output.
+    pub bytes: u64,
+    /// Time spent writing buffers to the files or sinks (`write_all`), compressing included.
+    pub write_time: Duration,
+    /// Time spent in the custom write function.
+    pub custom_write_time: Duration,
+    /// Time spent syncing to the disk, with `with_sync_on_close`.
+    pub sync_time: Duration,
+    /// Directory syncs made so that files created or renamed in them last, with
+    /// `with_sync_on_close` on Unix.
+    pub directory_syncs: u64,
+}
+
+/// What `WriterContext::close` did with the files of the run.
+#[derive(Debug, Default)]
+pub struct CloseReport {
+    /// The files written whole, with their stats, named as `WriterContext::file_stats` names
+    /// them and in that order.
+    pub closed: Vec<(String, FileStats)>,
+    /// The files whose last rows couldn't be written, with the error; they are incomplete and
+    /// must be written again.
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// How long syncing files and their directories to the disk took over the run, with
+    /// `WriterContext::with_sync_on_close`.
+    pub sync_time: Duration,
+    /// How the run's output was written out, as `WriterContext::metrics` had it once closed.
+    pub metrics: WriterMetrics,
+    /// The files appended to whose header row differs from the columns of the rows appended,
+    /// as `WriterContext::header_mismatches` lists them.
+    pub header_mismatches: Vec<String>,
+}
+
+impl CloseReport {
+    /// Fail with `FecError::IncompleteOutput`, naming each file and its error, if any file
+    /// couldn't be written whole.
+    pub fn check(&self) -> Result<()> {
+        incomplete_output(&self.failed)
+    }
+}
+
+/// The number of days in `month` (1 to 12) of `year`, in the proleptic Gregorian calendar.
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The file `WriterContext::preflight` writes and removes to check output can be written.
+const PROBE_FILENAME: &str = ".fast-fec-rust-probe";
+
+/// The bytes of disk space free to unprivileged users on the file system holding `path`. Only
+/// known on Unix; elsewhere there's always room.
+fn free_space(path: &Path) -> std::io::Result<u64> {
+    #[cfg(unix)]
+    {
+        let stats = rustix::fs::statvfs(path)?;
+        Ok(stats.f_bavail.saturating_mul(stats.f_frsize))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(u64::MAX)
+    }
+}
+
+/// The first row of the CSV file at `path`, delimited by `delimiter`, if it can be read.
+fn read_header_row(path: &Path, delimiter: u8) -> Option<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_path(path)
+        .ok()?;
+    let row = reader.records().next()?.ok()?;
+    Some(row.iter().map(String::from).collect())
+}
+
+/// Sync the directory at `path` to the disk, so the files created or renamed in it are found
+/// there after a crash. Directories can only be opened to be synced on Unix; elsewhere this does
+/// nothing.
+fn sync_directory(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    File::open(path)
+        .and_then(|directory| directory.sync_all())
+        .map_err(|e| anyhow!("Failed to sync {} to disk: {}", path.display(), e))?;
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// `FecError::IncompleteOutput` for the files in `failed`, if there are any.
+fn incomplete_output(failed: &[(String, anyhow::Error)]) -> Result<()> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let files = failed
+        .iter()
+        .map(|(path, error)| (path.clone(), format!("{:#}", error)))
+        .collect();
+    Err(FecError::IncompleteOutput { files }.into())
+}
+
+/// Where an output file's flushed buffers are written on disk.
+struct FileSink {
+    file: File,
+    compression: Compression,
+    level: Option<i32>,     // Compression level, or the compression's default
+    stream: Option<Stream>, // Compressed stream over `file`, open from the first write to `close`
+}
+
+/// A compressed stream being written over an output file.
+enum Stream {
+    Gzip(GzEncoder<File>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, File>),
+}
+
+impl FileSink {
+    fn new(file: File, compression: Compression, level: Option<i32>) -> Self {
+        Self {
+            file,
+            compression,
+            level,
+            stream: None,
+        }
+    }
+
+    /// Open a stream of the sink's compression over its file.
+    fn open_stream(&self) -> std::io::Result<Stream> {
+        let file = self.file.try_clone()?;
+        match self.compression {
+            Compression::None => unreachable!("uncompressed files are written directly"),
+            Compression::Gzip => Ok(Stream::Gzip(GzEncoder::new(
+                file,
+                self.level
+                    .map_or_else(flate2::Compression::default, |level| {
+                        flate2::Compression::new(level as u32)
+                    }),
+            ))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(Stream::Zstd(zstd::stream::write::Encoder::new(
+                file,
+                self.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL),
+            )?)),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => Err(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "writing zstd output requires building with the `zstd` feature",
+            )),
+        }
+    }
+
+    fn write_data(&mut self, data: &[u8]) -> std::io::Result<()> {
+        if self.compression == Compression::None {
+            return self.file.write_all(data);
+        }
+        // A file written to again after `finish` gets a second gzip member or zstd frame, which
+        // readers decompress as if it were one stream
+        if self.stream.is_none() {
+            self.stream = Some(self.open_stream()?);
+        }
+        match self.stream.as_mut() {
+            Some(Stream::Gzip(encoder)) => encoder.write_all(data),
+            #[cfg(feature = "zstd")]
+            Some(Stream::Zstd(encoder)) => encoder.write_all(data),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush what has been written to the file, finishing its compressed stream if one is open.
+    ///
+    /// A file nothing was written to since the last `finish` gets no empty stream.
+    fn finish(&mut self) -> std::io::Result<()> {
+        match self.stream.take() {
+            Some(Stream::Gzip(encoder)) => {
+                encoder.finish()?;
+            }
+            #[cfg(feature = "zstd")]
+            Some(Stream::Zstd(encoder)) => {
+                encoder.finish()?;
+            }
+            None => {}
+        }
+        self.file.flush()
+    }
+}
+
+impl Write for FileSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_data(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.stream.as_mut() {
+            Some(Stream::Gzip(encoder)) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            Some(Stream::Zstd(encoder)) => encoder.flush(),
+            None => self.file.flush(),
+        }
+    }
+}
+
+impl OutputSink for FileSink {
+    /// Flush what has been written, with a sync flush of the compressed stream: it is finished
+    /// only once, by `close`, so a compressed file holds a single gzip member or zstd frame.
+    fn finalize(&mut self) -> Result<()> {
+        Ok(self.flush()?)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        Ok(self.finish()?)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        Ok(self.file.sync_all()?)
+    }
+}
+
 /// Represents an entry in the open files map, containing the buffer and file handle.
 struct FileEntry {
     buffer_file: BufferFile,
-    file: Option<File>, // Actual file handle if writing to disk
+    file: Option<Box<dyn OutputSink>>, // The file's sink if written, unless closed to make room
+    path: Option<PathBuf>,             // Where the file is on disk, to reopen it once closed
+    last_used: u64,                    // When the entry was last used, by `WriterContext::uses`
+    stats: FileStats,
+    part: usize,          // Which part of the output the file holds, from 1
+    parts: Vec<FilePart>, // The parts before it, once the output is split
+    /// The header row written to the file, written again at the top of each later part.
+    header_row: Option<Vec<u8>>,
+    /// What was written to the file since its last line ended, for the custom line function.
+    line: String,
+    /// Lines written since the buffer was last written out, under `FlushPolicy::PerNRows`.
+    buffered_lines: usize,
+    /// How the file's buffer was written out, for `WriterContext::metrics`.
+    flushes: u64,
+    overflows: u64,
+    bytes_flushed: u64,
 }
 
+/// The capacity a file's line buffer keeps once its line ends; a longer line's buffer is freed.
+const LINE_BUFFER_CAPACITY: usize = 4096;
+
+/// A finished part of a split file: where it is on disk, if written there, and its stats.
+type FilePart = (Option<PathBuf>, FileStats);
+
+/// A file just opened: its sink, if it's written, and its path, if that's on disk.
+type OpenedFile = (Option<Box<dyn OutputSink>>, Option<PathBuf>);
+
 impl FileEntry {
-    fn new(buffer_capacity: usize, file: Option<File>) -> Self {
+    fn new(
+        buffer_capacity: usize,
+        file: Option<Box<dyn OutputSink>>,
+        path: Option<PathBuf>,
+    ) -> Self {
         Self {
             buffer_file: BufferFile::new(buffer_capacity),
             file,
+            path,
+            last_used: 0,
+            stats: FileStats::default(),
+            part: 1,
+            parts: Vec::new(),
+            line: String::new(),
+            header_row: None,
+            buffered_lines: 0,
+            flushes: 0,
+            overflows: 0,
+            bytes_flushed: 0,
         }
     }
+
+    /// The paths and stats of each part of the file, in order, the current one last.
+    fn all_parts(&self) -> impl Iterator<Item = (Option<&Path>, FileStats)> {
+        self.parts
+            .iter()
+            .map(|(path, stats)| (path.as_deref(), *stats))
+            .chain(std::iter::once((self.path.as_deref(), self.stats)))
+    }
+}
+
+/// The longest file name, in bytes and before its extension, `sanitize_filename` returns.
+pub const MAX_FILENAME_LENGTH: usize = 100;
+
+/// Names Windows reserves for devices, whatever their case (`con.csv` is a device too).
+const RESERVED_FILENAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Make `name` (a form type, or any name rows are written under) safe to use as a file name on
+/// any filesystem, before its extension is added.
+///
+/// `/`, dots, the characters Windows reserves (`\ : * ? " < > |`) and control characters
+/// become `-`, trailing spaces are dropped and names are cut to `MAX_FILENAME_LENGTH` bytes. A
+/// name changed by any of these, an empty name or a device name Windows reserves (`CON`, `NUL`,
+/// `COM1`...) ends with `_` and a hash of the original (`A?B:C` => `A-B-C_ad104176`, `CON` =>
+/// `CON_3367e86b`), so no two names end up the same: `SC1/10` doesn't take `SC1-10`'s file.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '.' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    sanitized.truncate(sanitized.trim_end_matches(' ').len());
+    let reserved = RESERVED_FILENAMES
+        .iter()
+        .any(|reserved| sanitized.eq_ignore_ascii_case(reserved));
+    if sanitized == name && !sanitized.is_empty() && !reserved && name.len() <= MAX_FILENAME_LENGTH
+    {
+        return sanitized;
+    }
+
+    // Room for the hash that tells the name apart from others sanitized alike
+    let mut end = sanitized.len().min(MAX_FILENAME_LENGTH - 9);
+    while !sanitized.is_char_boundary(end) {
+        end -= 1;
+    }
+    sanitized.truncate(end);
+    format!("{}_{:08x}", sanitized, fnv1a(name.as_bytes()))
+}
+
+/// The 32-bit FNV-1a hash of `bytes`, the same from run to run and build to build.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// `extension` as files are keyed by it: without a leading `.`, so `.csv` and `csv` name the
+/// same file.
+fn normalize_extension(extension: &str) -> &str {
+    extension.strip_prefix('.').unwrap_or(extension)
+}
+
+/// The path of part `part` of the file at `path`: `path` itself for the first part, then with
+/// `_002`, `_003` and so on before the file name's extension (`SA_002.csv`).
+fn part_path(path: PathBuf, part: usize) -> PathBuf {
+    if part == 1 {
+        return path;
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let (stem, extension) = name.split_at(name.find('.').unwrap_or(name.len()));
+    let name = format!("{}_{:03}{}", stem, part, extension);
+    path.with_file_name(name)
 }
 
 /// The main writer context, replicating `WRITE_CONTEXT`.
@@ -102,13 +863,110 @@ pub struct WriterContext {
     pub write_to_disk: bool,
     /// The buffer size for each file (akin to `bufferSize`).
     pub buffer_size: usize,
+    /// When files' buffers are written out before they fill up.
+    flush_policy: FlushPolicy,
+    /// The flush policies of files that don't follow `flush_policy`, by file name.
+    file_flush_policies: HashMap<String, FlushPolicy>,
+    /// How files written to disk are compressed.
+    compression: Compression,
+    /// The level files are compressed at, or the compression's default.
+    compression_level: Option<i32>,
+    /// What happens to output files that already exist.
+    output_mode: OutputMode,
+    /// The paths of the files opened during this run, appended to if opened again.
+    opened_paths: HashSet<PathBuf>,
+    /// The format rows are written in.
+    format: OutputFormat,
+    /// The byte separating the fields of CSV rows.
+    output_delimiter: u8,
+    /// The line break ending each row.
+    terminator: Terminator,
+    /// Which fields of CSV rows are quoted.
+    quote_style: QuoteStyle,
+    /// What the delimiter and line breaks in fields are replaced with under `QuoteStyle::Never`,
+    /// or `None` to fail on them.
+    unquotable_replacement: Option<char>,
+    /// What empty fields of CSV rows are written as, unquoted, if not as nothing.
+    null_string: Option<String>,
+    /// Encodes CSV rows, reusing its buffer from row to row.
+    row_encoder: RowEncoder,
+    /// How `write_double` formats values.
+    double_format: DoubleFormat,
+    /// How `write_date` formats dates.
+    date_format: DateFormat,
+    /// What `write_bool` writes for each value.
+    bool_format: BoolFormat,
+    /// The column names rows of each file are keyed by in NDJSON or SQLite format, by file name.
+    row_columns: HashMap<String, Vec<String>>,
+    /// Where SQLite output goes, if not `DEFAULT_DATABASE` in the output directory.
+    database_path: Option<PathBuf>,
+    /// The database SQLite output is being written to, opened by the first row.
+    database: Option<SqliteWriter>,
 
     /// A map of `(filename, extension)` => FileEntry (which holds `BufferFile` + `File`).
     open_files: HashMap<(String, String), FileEntry>,
+    /// The entries of files closed with `close_file`, without their buffer, taken back to
+    /// reopen the file where it left off once it's written to again.
+    closed_files: HashMap<(String, String), FileEntry>,
+
+    /// Whether `flush_all` writes `MANIFEST_FILENAME` to the filing's output directory.
+    manifest: bool,
+    /// Whether files are written under temporary names until `finalize` renames them.
+    atomic_writes: bool,
+    /// Whether `flush_all` and `close` sync each file and its directory to the disk.
+    sync_on_close: bool,
+    /// How long syncing to the disk has taken so far.
+    sync_time: Duration,
+    /// How many times a directory has been synced so far.
+    directory_syncs: u64,
+    /// How long writing buffers to files or sinks has taken so far.
+    write_time: Duration,
+    /// How long the custom write function has taken so far.
+    custom_write_time: Duration,
+    /// The temporary and final paths of the files written since the last `finalize`.
+    temp_files: Vec<(PathBuf, PathBuf)>,
+    /// Where each output file goes under the output directory, if not `<filing_id>/<form>`.
+    filename_template: Option<FilenameTemplate>,
+    /// The file each path opened during this run was opened for, to catch two files at one path.
+    path_owners: HashMap<PathBuf, (String, String)>,
+    /// How many rows a row file holds before the rest go to its next part, if limited.
+    max_rows_per_file: Option<usize>,
+    /// How many bytes a row file holds before the rest go to its next part, if limited.
+    max_bytes_per_file: Option<u64>,
+
+    /// The bytes written to each `(filename, extension)`, if kept for `into_outputs`.
+    collected: Option<HashMap<(String, String), Vec<u8>>>,
+    collected_bytes: u64,             // Held in `collected`, across every file
+    max_collected_bytes: Option<u64>, // How many `collected` may hold, if limited
+
+    /// How many file handles may be open at once; the least recently used is closed past it.
+    max_open_files: usize,
+    /// How many entries of `open_files` hold an open file handle.
+    open_handles: usize,
+    /// How many times entries have been used, stamping each entry's `last_used`.
+    uses: u64,
 
     /// The "last" file we wrote to, used for optimization.
     last_file_key: Option<(String, String)>,
 
+    /// The free disk space `preflight` asks for where output goes, in bytes.
+    min_free_space: Option<u64>,
+
+    /// The `(filename, extension)` keys whose header row has been written (or already existed).
+    headers_written: HashSet<(String, String)>,
+    /// The header rows of files appended to that held rows from an earlier run, with their
+    /// paths, until checked against the columns of the rows appended.
+    existing_headers: HashMap<(String, String), (PathBuf, Vec<String>)>,
+    /// The files whose existing header row didn't match the columns of the rows appended.
+    header_mismatches: Vec<String>,
+
+    /// What was written to the files of filings finished with `start_filing`, by path.
+    finished_stats: Vec<(String, FileStats)>,
+    /// The same, for each form's output (see `stats`).
+    finished_outputs: Vec<OutputStats>,
+    /// The same, for how each form's output was written out (see `metrics`).
+    finished_metrics: Vec<FileMetrics>,
+
     /// A local buffer mode (if `local` in the original code is set).
     local_mode: bool,
     local_buffer: String,
@@ -116,14 +974,101 @@ pub struct WriterContext {
 
     /// The custom line function, if any (like `customLineFunction`).
     custom_line_fn: Option<Box<CustomLineFn>>,
-    /// A buffer that accumulates the current line. Once we call `end_line`, we pass it to `custom_line_fn`.
-    custom_line_buffer: String,
 
     /// The custom write function, if any (like `customWriteFunction`).
     custom_write_fn: Option<Box<CustomWriteFn>>,
+
+    /// Creates the sink of each file in place of a file on disk, if set.
+    sink_factory: Option<Box<dyn SinkFactory>>,
+
+    /// Told when each file is opened and closed, if set.
+    on_file_open: Option<Box<FileOpenFn>>,
+    on_file_close: Option<Box<FileCloseFn>>,
+
+    /// Where rows are streamed instead of files in print mode, if enabled.
+    print: Option<PrintTarget>,
+}
+
+/// A single stream that print mode writes rows to instead of per-form files.
+struct PrintTarget {
+    out: Box<dyn Write + Send>,
+    form: Option<String>, // Only rows of this form, or every row with a leading form type
+    closed: bool,         // Whether the reader went away (a broken pipe)
+}
+
+impl PrintTarget {
+    /// Write a CSV row of `filename`, unless it is filtered out or the stream is closed.
+    ///
+    /// - `row`: the row as written to `filename`'s file.
+    /// - `header`: whether the row is `filename`'s header row.
+    /// - `bare`: whether `row` gets no leading column: it continues a row already started, or is
+    ///   a JSON object carrying its form itself.
+    /// - `delimiter`, `quote_style`: how the leading column is separated from the row and
+    ///   quoted, as the row's fields are.
+    fn write_row(
+        &mut self,
+        filename: &str,
+        row: &[u8],
+        header: bool,
+        bare: bool,
+        delimiter: u8,
+        quote_style: QuoteStyle,
+    ) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let mut line = Vec::with_capacity(row.len() + filename.len() + 1);
+        match &self.form {
+            Some(form) if !form.eq_ignore_ascii_case(filename) => return Ok(()),
+            Some(_) => {}
+            None if bare => {}
+            None => {
+                let leading = if header { PRINT_FORM_COLUMN } else { filename };
+                let mut wtr = WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .quote_style(match quote_style {
+                        QuoteStyle::Always => csv::QuoteStyle::Always,
+                        QuoteStyle::Necessary => csv::QuoteStyle::Necessary,
+                        QuoteStyle::Never => csv::QuoteStyle::Never,
+                    })
+                    .from_writer(&mut line);
+                wtr.write_field(leading)?;
+                wtr.flush()?;
+                drop(wtr);
+                line.push(delimiter);
+            }
+        }
+        line.extend_from_slice(row);
+        let result = self.out.write_all(&line);
+        self.check(result)
+    }
+
+    /// Flush the stream, unless it is closed.
+    fn flush(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        let result = self.out.flush();
+        self.check(result)
+    }
+
+    /// Stop writing once the stream is closed, failing with the broken pipe error once.
+    fn check(&mut self, result: std::io::Result<()>) -> Result<()> {
+        if let Err(e) = &result {
+            if e.kind() == ErrorKind::BrokenPipe {
+                self.closed = true;
+            }
+        }
+        Ok(result?)
+    }
 }
 
 impl WriterContext {
+    /// Start building a `WriterContext` from named settings, each with a default.
+    pub fn builder() -> WriterContextBuilder {
+        WriterContextBuilder::default()
+    }
+
     /// Create a new `WriterContext`, replacing `newWriteContext`.
     /// - `output_directory`: e.g. "output/"
     /// - `filing_id`: e.g. "12345"
@@ -131,6 +1076,7 @@ impl WriterContext {
     /// - `buffer_size`: each file buffer capacity
     /// - `custom_write_fn`: optional closure for custom writes
     /// - `custom_line_fn`: optional closure for custom lines
+    #[deprecated(note = "use `WriterContext::builder()` instead")]
     pub fn new(
         output_directory: String,
         filing_id: String,
@@ -138,105 +1084,706 @@ impl WriterContext {
         buffer_size: usize,
         custom_write_fn: Option<Box<CustomWriteFn>>,
         custom_line_fn: Option<Box<CustomLineFn>>,
+    ) -> Self {
+        Self::create(
+            output_directory,
+            filing_id,
+            write_to_disk,
+            buffer_size,
+            custom_write_fn,
+            custom_line_fn,
+        )
+    }
+
+    /// Create a `WriterContext` with every other setting at its default.
+    fn create(
+        output_directory: String,
+        filing_id: String,
+        write_to_disk: bool,
+        buffer_size: usize,
+        custom_write_fn: Option<Box<CustomWriteFn>>,
+        custom_line_fn: Option<Box<CustomLineFn>>,
     ) -> Self {
         Self {
             output_directory,
             filing_id,
             write_to_disk,
             buffer_size,
+            flush_policy: FlushPolicy::Size,
+            file_flush_policies: HashMap::new(),
+            compression: Compression::None,
+            compression_level: None,
+            output_mode: OutputMode::Truncate,
+            opened_paths: HashSet::new(),
+            format: OutputFormat::Csv,
+            output_delimiter: b',',
+            terminator: Terminator::Lf,
+            quote_style: QuoteStyle::Necessary,
+            unquotable_replacement: None,
+            null_string: None,
+            row_encoder: RowEncoder::new(b',', Terminator::Lf, QuoteStyle::Necessary, None, None),
+            double_format: DoubleFormat::default(),
+            date_format: DateFormat::default(),
+            bool_format: BoolFormat::default(),
+            row_columns: HashMap::new(),
+            database_path: None,
+            database: None,
             open_files: HashMap::new(),
+            closed_files: HashMap::new(),
+            manifest: false,
+            atomic_writes: false,
+            sync_on_close: false,
+            sync_time: Duration::ZERO,
+            directory_syncs: 0,
+            write_time: Duration::ZERO,
+            custom_write_time: Duration::ZERO,
+            temp_files: Vec::new(),
+            filename_template: None,
+            path_owners: HashMap::new(),
+            max_rows_per_file: None,
+            max_bytes_per_file: None,
+            collected: None,
+            collected_bytes: 0,
+            max_collected_bytes: None,
+            max_open_files: DEFAULT_MAX_OPEN_FILES,
+            open_handles: 0,
+            uses: 0,
             last_file_key: None,
+            min_free_space: None,
+            headers_written: HashSet::new(),
+            existing_headers: HashMap::new(),
+            header_mismatches: Vec::new(),
+            finished_stats: Vec::new(),
+            finished_outputs: Vec::new(),
+            finished_metrics: Vec::new(),
             local_mode: false,
             local_buffer: String::new(),
             local_buffer_pos: 0,
             custom_line_fn,
-            custom_line_buffer: String::new(),
             custom_write_fn,
+            sink_factory: None,
+            on_file_open: None,
+            on_file_close: None,
+            print: None,
         }
     }
 
-    /// Enable local buffer mode.
-    pub fn start_local_buffer_mode(&mut self) {
-        self.local_mode = true;
-        self.local_buffer.clear();
-        self.local_buffer_pos = 0;
-    }
-
-    /// Disable local buffer mode and retrieve the buffer content.
-    pub fn finish_local_buffer_mode(&mut self) -> String {
-        self.local_mode = false;
-        let content = self.local_buffer.clone();
-        self.local_buffer.clear();
-        self.local_buffer_pos = 0;
-        content
+    /// Stream rows to `out` instead of writing per-form files (print mode).
+    ///
+    /// - `form`: only write rows whose form file name (e.g. `SA`) matches, with their usual
+    ///   header row; `None` writes every row with its form type as a leading column, preceded
+    ///   by each form's header row the first time the form appears.
+    ///
+    /// Nothing is written to disk. Once `out` reports a broken pipe, the write fails with it
+    /// and later writes and flushes are dropped.
+    pub fn with_print(mut self, out: Box<dyn Write + Send>, form: Option<String>) -> Self {
+        self.write_to_disk = false;
+        self.print = Some(PrintTarget {
+            out,
+            form,
+            closed: false,
+        });
+        self
     }
 
-    /// End the current line and call the custom line function if set.
-    /// `types` is a string describing the field types for this line.
-    pub fn end_line(&mut self, types: &str) -> Result<()> {
-        if let Some(ref line_fn) = self.custom_line_fn {
-            line_fn(
-                self.last_file_key
-                    .as_ref()
-                    .map(|(f, _)| f.as_str())
-                    .unwrap_or(""),
-                &self.custom_line_buffer,
-                types,
-            )?;
-        }
-        self.custom_line_buffer.clear();
-        Ok(())
+    /// Decide what happens to output files that already exist when this run first opens them;
+    /// see `OutputMode`. Files opened again later in the run, e.g. by a filing with the same ID,
+    /// are always appended to. An SQLite database is always appended to.
+    pub fn with_output_mode(mut self, mode: OutputMode) -> Self {
+        self.output_mode = mode;
+        self
     }
 
-    /// Retrieve an existing or create a new `FileEntry`.
+    /// Fail if output would go over files that already exist and the output mode is
+    /// `OutputMode::FailIfExists`: the current filing's output directory or the SQLite
+    /// database. Call it before parsing, so nothing is written or parsed in vain; files of later
+    /// filings, and all files named by a filename template, are checked as they are opened.
+    pub fn check_existing_output(&self) -> Result<()> {
+        if self.output_mode != OutputMode::FailIfExists
+            || !self.write_to_disk
+            || self.print.is_some()
+        {
+            return Ok(());
+        }
+        if self.format == OutputFormat::Sqlite {
+            let path = self
+                .database_path
+                .clone()
+                .unwrap_or_else(|| Path::new(&self.output_directory).join(DEFAULT_DATABASE));
+            if path.exists() {
+                return Err(anyhow!("Output file {} already exists", path.display()));
+            }
+        }
+        if self.filename_template.is_some() || self.sink_factory.is_some() {
+            return Ok(());
+        }
+        let dir_path = Path::new(&self.output_directory).join(&self.filing_id);
+        let mut existing: Vec<PathBuf> = match std::fs::read_dir(&dir_path) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<_>>()?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        existing.sort();
+        match existing.first() {
+            Some(path) => Err(anyhow!("Output file {} already exists", path.display())),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that output can be written where it goes before parsing, so a run doesn't fail on
+    /// its first flush after parsing for a while: create the current filing's output directory
+    /// (the output directory itself under a filename template, or the SQLite database's
+    /// directory), write a probe file there and remove it, and check the free disk space against
+    /// `with_min_free_space`.
+    ///
+    /// Fails with `FecError::OutputNotWritable`, naming the path and the kind of I/O error, or
+    /// `FecError::InsufficientSpace`. Does nothing unless writing to disk, nor for printed rows
+    /// or a sink factory's output.
+    pub fn preflight(&self) -> Result<()> {
+        if !self.write_to_disk || self.print.is_some() || self.sink_factory.is_some() {
+            return Ok(());
+        }
+        let output_directory = Path::new(&self.output_directory);
+        let dir_path = if self.writes_database() {
+            let database = self
+                .database_path
+                .clone()
+                .unwrap_or_else(|| output_directory.join(DEFAULT_DATABASE));
+            match database.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            }
+        } else if self.filename_template.is_some() {
+            output_directory.to_path_buf()
+        } else {
+            output_directory.join(&self.filing_id)
+        };
+        let not_writable =
+            |path: &Path, action: &str, e: std::io::Error| FecError::OutputNotWritable {
+                path: path.display().to_string(),
+                action: action.to_string(),
+                kind: e.kind(),
+            };
+
+        std::fs::create_dir_all(&dir_path)
+            .map_err(|e| not_writable(&dir_path, "create the output directory", e))?;
+        let probe = dir_path.join(PROBE_FILENAME);
+        File::create(&probe)
+            .and_then(|mut file| file.write_all(b"probe"))
+            .map_err(|e| not_writable(&probe, "write the probe file", e))?;
+        std::fs::remove_file(&probe)
+            .map_err(|e| not_writable(&probe, "remove the probe file", e))?;
+
+        if let Some(required) = self.min_free_space {
+            let available = free_space(&dir_path)
+                .map_err(|e| not_writable(&dir_path, "check the free space of", e))?;
+            if available < required {
+                return Err(FecError::InsufficientSpace {
+                    path: dir_path.display().to_string(),
+                    available,
+                    required,
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Have `preflight` fail unless at least `bytes` bytes of disk space are free where output
+    /// goes. `None`, as by default, doesn't check. The space is only checked on Unix.
+    pub fn with_min_free_space(mut self, bytes: Option<u64>) -> Self {
+        self.min_free_space = bytes;
+        self
+    }
+
+    /// Have `flush_all` write `MANIFEST_FILENAME` to the filing's output directory, listing each
+    /// file written there (the manifest aside) with its form, the rows written to it after its
+    /// header row, its size on disk in bytes and whether it got a header row (`true`/`false`).
+    ///
+    /// The manifest is rewritten by every `flush_all`, so it lists the filing's files as they are
+    /// once the writer is done. Rows are the ones written during this run, as `file_stats` counts
+    /// them, while the size includes what files appended to already held.
+    pub fn with_manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Write each output file under a temporary name in its directory (its name plus
+    /// `TEMP_SUFFIX`), renamed to its own by `finalize` once the parse has succeeded. If the
+    /// writer is dropped without being finalized, e.g. because the parse failed, the temporary
+    /// files are removed instead, so no output file is left half-written under its final name.
+    ///
+    /// Files can't be appended to this way: `WriterContextBuilder::build` rejects atomic writes
+    /// with `OutputMode::Append` or SQLite output.
+    pub fn with_atomic_writes(mut self, atomic_writes: bool) -> Self {
+        self.atomic_writes = atomic_writes;
+        self
+    }
+
+    /// Name output files by `template` instead of the default `<filing_id>/<form>.<extension>`
+    /// under the output directory, creating the directories it names as needed. See
+    /// `FilenameTemplate`.
+    ///
+    /// Opening a file at a path another file of this run was already opened at fails, e.g. for
+    /// two forms under a template without `{form}`. The same form of later filings is appended
+    /// to its file instead, after a single header row.
+    pub fn with_filename_template(mut self, template: Option<FilenameTemplate>) -> Self {
+        self.filename_template = template;
+        self
+    }
+
+    /// Write each file to a sink from `factory` instead of to a file on disk.
+    ///
+    /// Sinks are created where files would be opened, for each path that would be written to
+    /// (see `SinkFactory::create`), and get the same bytes; nothing is written to the output
+    /// directory, bar a manifest or database if asked for. Sinks stay open until the file is
+    /// closed, whatever `with_max_open_files` says, and are given uncompressed bytes whatever
+    /// the output mode: a sink compresses its output or checks for existing output itself.
+    pub fn with_sink_factory(mut self, factory: Box<dyn SinkFactory>) -> Self {
+        self.sink_factory = Some(factory);
+        self
+    }
+
+    /// Call `f` with the name and extension of each file as it is opened, before anything is
+    /// written to it (or passed to the custom write function for it).
+    ///
+    /// A file here is what the custom write function sees: the rows of one form of one filing,
+    /// whether they go to one file on disk, to split parts of it, or nowhere. If `f` fails, so
+    /// does the write that opened the file.
+    pub fn with_on_file_open(mut self, f: Box<FileOpenFn>) -> Self {
+        self.on_file_open = Some(f);
+        self
+    }
+
+    /// Call `f` with the name and extension of each file opened as `with_on_file_open` says,
+    /// and the total bytes written to it, once it is written whole: when the writer is closed
+    /// (`close`, or dropped), or the next filing is started (`start_filing`).
+    ///
+    /// `flush_all` and closing a file to make room for others (see `with_max_open_files`) don't
+    /// close the file in this sense, as more can be written to it. A file that fails to be
+    /// written isn't closed either; `close` reports it instead, as it does a file `f` fails for.
+    pub fn with_on_file_close(mut self, f: Box<FileCloseFn>) -> Self {
+        self.on_file_close = Some(f);
+        self
+    }
+
+    /// Have `flush_all` and `close` sync each file to the disk (`File::sync_all`) before they
+    /// return, and the directories holding them on Unix, so the output survives a crash or power
+    /// loss right after the run instead of being left empty. Files closed along the way, to make
+    /// room or to split them, are synced as they close. The time it takes is reported in
+    /// `CloseReport::sync_time`.
+    pub fn with_sync_on_close(mut self, sync_on_close: bool) -> Self {
+        self.sync_on_close = sync_on_close;
+        self
+    }
+
+    /// Make the output durable: the same as `with_sync_on_close`, so `flush_all` and `close`
+    /// sync every file with `File::sync_all` and the directories holding them before they
+    /// return. Off by default.
+    pub fn with_durable(self, durable: bool) -> Self {
+        self.with_sync_on_close(durable)
+    }
+
+    /// Split each row file once it holds `max` rows: the rows after go to a new part, named for
+    /// its number (`SA_002.csv`, then `SA_003.csv`), which starts with the header row again.
+    /// `None` (or 0) leaves files whole, as by default.
+    ///
+    /// Rows are never split across parts. Rows printed or written to a database aren't split.
+    pub fn with_max_rows_per_file(mut self, max: Option<usize>) -> Self {
+        self.max_rows_per_file = max.filter(|&max| max > 0);
+        self
+    }
+
+    /// Split each row file before a row that would take it past `max` bytes (before
+    /// compression), as `with_max_rows_per_file` splits by rows. A part always holds at least
+    /// one row, so a row larger than `max` gets a part of its own.
+    pub fn with_max_bytes_per_file(mut self, max: Option<u64>) -> Self {
+        self.max_bytes_per_file = max.filter(|&max| max > 0);
+        self
+    }
+
+    /// Keep the bytes written to each file in memory, uncompressed, to be handed back by
+    /// `into_outputs`: for callers with no disk to write to, such as WASM, or tests.
+    ///
+    /// Works alongside any other output; with `write_to_disk` off, it is the only one. The rows
+    /// of later filings are added to the same form's bytes, after their own header row.
+    pub fn with_collect_outputs(mut self, collect_outputs: bool) -> Self {
+        self.collected = collect_outputs.then(HashMap::new);
+        self
+    }
+
+    /// Fail a write with `FecError::OutputLimitExceeded` once the output collected by
+    /// `with_collect_outputs` would hold more than `max` bytes, across every file.
+    pub fn with_max_collected_bytes(mut self, max: Option<u64>) -> Self {
+        self.max_collected_bytes = max;
+        self
+    }
+
+    /// Flush every file and hand back the output collected by `with_collect_outputs`: the
+    /// bytes written to each file, by name and extension (`("SA", "csv")`).
+    ///
+    /// Without `with_collect_outputs`, the map is empty.
+    pub fn into_outputs(mut self) -> Result<HashMap<(String, String), Vec<u8>>> {
+        self.flush_all()?;
+        Ok(self.collected.take().unwrap_or_default())
+    }
+
+    /// Write out each file's buffer as `policy` says, besides whenever it fills up, e.g. after
+    /// every line for consumers reading files as they are written. Files written to disk with
+    /// compression keep what their compressed stream holds until flushed.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    /// Write out the buffers of `filename`'s files (e.g. `SA`) as `policy` says, whatever the
+    /// policy set by `with_flush_policy`.
+    pub fn with_file_flush_policy(
+        mut self,
+        filename: impl Into<String>,
+        policy: FlushPolicy,
+    ) -> Self {
+        self.file_flush_policies.insert(filename.into(), policy);
+        self
+    }
+
+    /// Keep at most `max` output files open at once (at least one). Past it, the least recently
+    /// used file is flushed and closed, and reopened for appending the next time its buffer is
+    /// written out; its buffered rows and header state are kept. Compressed files are never
+    /// closed to make room, as that would finish their stream.
+    pub fn with_max_open_files(mut self, max: usize) -> Self {
+        self.max_open_files = max.max(1);
+        self
+    }
+
+    /// Compress the files written to disk with `compression`, adding its suffix to their names
+    /// (`SA.csv.gz`). Custom write functions still receive the uncompressed bytes. Set it before
+    /// anything is written; files already open keep how they were opened.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Compress files at `level` instead of the compression's default level; see
+    /// `Compression::check_level` for the levels each compression accepts.
+    pub fn with_compression_level(mut self, level: Option<i32>) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Format the values `write_double` writes as `format` says instead of with two decimal
+    /// places.
+    pub fn with_double_format(mut self, format: DoubleFormat) -> Self {
+        self.double_format = format;
+        self
+    }
+
+    /// Format the dates `write_date` writes as `format` says instead of as ISO `YYYY-MM-DD`.
+    pub fn with_date_format(mut self, format: DateFormat) -> Self {
+        self.date_format = format;
+        self
+    }
+
+    /// Write the tokens of `format` for the booleans `write_bool` writes instead of `true` and
+    /// `false`.
+    pub fn with_bool_format(mut self, format: BoolFormat) -> Self {
+        self.bool_format = format;
+        self
+    }
+
+    /// Write rows in `format` instead of CSV. Row files are still keyed by the `csv` extension
+    /// (as in `header_written`), but named for the format on disk (`SA.ndjson`). Set it before
+    /// anything is written.
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Separate the fields of CSV rows with `delimiter` instead of a comma; fields holding it are
+    /// quoted. Row files written with a tab are named `.tsv`. Set it before anything is written.
+    pub fn with_output_delimiter(mut self, delimiter: u8) -> Self {
+        self.output_delimiter = delimiter;
+        self.row_encoder = self.new_row_encoder();
+        self
+    }
+
+    /// End rows with `terminator` instead of `\n`, as well as the lines of text written with
+    /// `write_text`. Set it before anything is written.
+    pub fn with_terminator(mut self, terminator: Terminator) -> Self {
+        self.terminator = terminator;
+        self.row_encoder = self.new_row_encoder();
+        self
+    }
+
+    /// Quote the fields of CSV rows as `style` says instead of only where needed. Set it before
+    /// anything is written.
+    pub fn with_quote_style(mut self, style: QuoteStyle) -> Self {
+        self.quote_style = style;
+        self.row_encoder = self.new_row_encoder();
+        self
+    }
+
+    /// Under `QuoteStyle::Never`, replace the delimiter and line breaks in fields with
+    /// `replacement` instead of failing on fields holding them. Set it before anything is
+    /// written.
+    pub fn with_unquotable_replacement(mut self, replacement: Option<char>) -> Self {
+        self.unquotable_replacement = replacement;
+        self.row_encoder = self.new_row_encoder();
+        self
+    }
+
+    /// Write empty fields of CSV rows as `null_string`, never quoted, e.g. `NULL` or `\N` for
+    /// PostgreSQL's `COPY`, instead of as nothing. Set it before anything is written.
+    ///
+    /// Every empty field is written as it, since fields don't record whether they were quoted in
+    /// the filing. Unless the quote style is `QuoteStyle::Always`, a field whose value is the
+    /// token itself can't be told apart from it.
+    pub fn with_null_string(mut self, null_string: Option<String>) -> Self {
+        self.null_string = null_string.filter(|s| !s.is_empty());
+        self.row_encoder = self.new_row_encoder();
+        self
+    }
+
+    /// An encoder of CSV rows as the writer's settings say.
+    fn new_row_encoder(&self) -> RowEncoder {
+        RowEncoder::new(
+            self.output_delimiter,
+            self.terminator,
+            self.quote_style,
+            self.unquotable_replacement,
+            self.null_string.clone(),
+        )
+    }
+
+    /// The extension of row files, as named on disk before any compression suffix: the
+    /// format's, or `.tsv` for CSV written with a tab.
+    pub fn row_extension(&self) -> &'static str {
+        if self.format == OutputFormat::Csv && self.output_delimiter == b'\t' {
+            TSV_EXTENSION
+        } else {
+            self.format.extension()
+        }
+    }
+
+    /// Write SQLite output (`OutputFormat::Sqlite`) to the database at `path` instead of
+    /// `DEFAULT_DATABASE` in the output directory.
+    pub fn with_database(mut self, path: impl Into<PathBuf>) -> Self {
+        self.database_path = Some(path.into());
+        self
+    }
+
+    /// Whether rows go to an SQLite database rather than files or the print target.
+    fn writes_database(&self) -> bool {
+        self.format == OutputFormat::Sqlite
+            && self.write_to_disk
+            && self.print.is_none()
+            && !self.local_mode
+    }
+
+    /// The database SQLite output goes to, opened (and created, if needed) the first time.
+    fn database(&mut self) -> Result<&mut SqliteWriter> {
+        if self.database.is_none() {
+            let path = self
+                .database_path
+                .clone()
+                .unwrap_or_else(|| Path::new(&self.output_directory).join(DEFAULT_DATABASE));
+            self.database = Some(SqliteWriter::open(&path)?);
+        }
+        self.database
+            .as_mut()
+            .ok_or_else(|| anyhow!("Database not opened"))
+    }
+
+    /// Whether rows are streamed to a print target instead of written to per-form files.
+    pub fn is_printing(&self) -> bool {
+        self.print.is_some()
+    }
+
+    /// Enable local buffer mode.
+    pub fn start_local_buffer_mode(&mut self) {
+        self.local_mode = true;
+        self.local_buffer.clear();
+        self.local_buffer_pos = 0;
+    }
+
+    /// Disable local buffer mode and retrieve the buffer content.
+    pub fn finish_local_buffer_mode(&mut self) -> String {
+        self.local_mode = false;
+        let content = self.local_buffer.clone();
+        self.local_buffer.clear();
+        self.local_buffer_pos = 0;
+        content
+    }
+
+    /// End the current line of the file last written to, as `end_line_of` does.
+    pub fn end_line(&mut self, types: &str) -> Result<()> {
+        let (filename, extension) = self.last_file_key.clone().unwrap_or_default();
+        self.end_line_of(&filename, &extension, types)
+    }
+
+    /// End the current line of `(filename, extension)`'s file and call the custom line function
+    /// if set, with what was written to that file since its last line ended: files written to in
+    /// turn each get their own lines. `types` is a string describing the field types for this
+    /// line, one FastFEC type code (`s`, `d` or `f`) per field.
+    ///
+    /// Lines written in local buffer mode never reach the line function, so ending one there
+    /// does nothing.
+    pub fn end_line_of(&mut self, filename: &str, extension: &str, types: &str) -> Result<()> {
+        let extension = normalize_extension(extension);
+        if self.local_mode {
+            return Ok(());
+        }
+        if let Some(database) = &mut self.database {
+            database.end_row(&self.row_columns, types)?;
+        }
+        if let Some(ref line_fn) = self.custom_line_fn {
+            let key = (filename.to_string(), extension.to_string());
+            let mut line = match self.open_files.get_mut(&key) {
+                Some(entry) => std::mem::take(&mut entry.line),
+                None => String::new(),
+            };
+            line_fn(filename, &line, types)?;
+            if line.capacity() <= LINE_BUFFER_CAPACITY {
+                if let Some(entry) = self.open_files.get_mut(&key) {
+                    line.clear();
+                    entry.line = line;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `s` to the current line of `(filename, extension)`'s file, if a custom line function
+    /// is set to be given it.
+    fn push_line(&mut self, filename: &str, extension: &str, s: &str) -> Result<()> {
+        if self.custom_line_fn.is_some() {
+            self.get_file_entry(filename, extension)?.0.line.push_str(s);
+        }
+        Ok(())
+    }
+
+    /// Whether a file for `(filename, extension)` has already been opened during this run,
+    /// even if closed since with `close_file`.
+    pub fn has_file(&self, filename: &str, extension: &str) -> bool {
+        let extension = normalize_extension(extension);
+        self.all_entries()
+            .any(|((name, ext), _)| name == filename && ext == extension)
+    }
+
+    /// The capacity of the buffer of `(filename, extension)`, if the file has been written to.
+    pub fn buffer_capacity(&self, filename: &str, extension: &str) -> Option<usize> {
+        let extension = normalize_extension(extension);
+        self.open_files
+            .get(&(filename.to_string(), extension.to_string()))
+            .map(|entry| entry.buffer_file.buffer.capacity())
+    }
+
+    /// Whether the header row for `(filename, extension)` has been written during this run,
+    /// or the file already held content from an earlier run.
+    pub fn header_written(&self, filename: &str, extension: &str) -> bool {
+        let extension = normalize_extension(extension);
+        self.headers_written
+            .contains(&(filename.to_string(), extension.to_string()))
+    }
+
+    /// The paths of the files appended to from an earlier run whose header row differs from the
+    /// columns of the rows appended, e.g. after the schema changed between runs. Only
+    /// uncompressed CSV files on disk are checked, once each as they are first opened.
+    pub fn header_mismatches(&self) -> &[String] {
+        &self.header_mismatches
+    }
+
+    /// Write `columns` as the header row of `filename`'s CSV file, unless it already has one.
+    ///
+    /// Files appended to from an earlier run keep their existing header instead of gaining a
+    /// second one mid-file. If that header differs from `columns`, the rows of the two runs
+    /// don't line up: the file is listed by `header_mismatches`. A written header row is ended
+    /// as a line of strings. Returns whether the header row was written.
+    ///
+    /// In NDJSON format, `columns` become the keys of the file's rows and no header row is
+    /// written, but the file is still created. In SQLite format, they name the columns of the
+    /// form's table.
+    pub fn write_header_once(&mut self, filename: &str, columns: &[String]) -> Result<bool> {
+        let extension = normalize_extension(CSV_EXTENSION);
+        // Open the entry first so a pre-existing, non-empty file is detected
+        self.get_file_entry(filename, extension)?;
+        if self.format != OutputFormat::Csv {
+            self.row_columns
+                .insert(filename.to_string(), columns.to_vec());
+        }
+        if self.header_written(filename, extension) {
+            let key = (filename.to_string(), extension.to_string());
+            if let Some((path, existing)) = self.existing_headers.remove(&key) {
+                if existing != columns {
+                    self.header_mismatches.push(path.display().to_string());
+                }
+            }
+            return Ok(false);
+        }
+
+        if self.format == OutputFormat::Csv {
+            self.write_row(filename, columns, true)?;
+            self.end_line(&"s".repeat(columns.len()))?;
+        }
+        self.headers_written
+            .insert((filename.to_string(), extension.to_string()));
+        Ok(true)
+    }
+
+    /// Retrieve an existing or create a new `FileEntry`.
     fn get_file_entry(
         &mut self,
         filename: &str,
         extension: &str,
     ) -> Result<(&mut FileEntry, bool)> {
+        self.uses += 1;
         if let Some(ref key) = self.last_file_key {
             if key.0 == filename && key.1 == extension {
-                return Ok((
-                    self.open_files
-                        .get_mut(key)
-                        .map(|fe| fe)
-                        .ok_or_else(|| anyhow!("File entry not found in open_files!"))?,
-                    false,
-                ));
+                let entry = self
+                    .open_files
+                    .get_mut(key)
+                    .ok_or_else(|| anyhow!("File entry not found in open_files!"))?;
+                entry.last_used = self.uses;
+                return Ok((entry, false));
             }
         }
 
         let key = (filename.to_string(), extension.to_string());
         if self.open_files.contains_key(&key) {
             self.last_file_key = Some(key.clone());
-            return Ok((
-                self.open_files
-                    .get_mut(&key)
-                    .map(|fe| fe)
-                    .ok_or_else(|| anyhow!("File entry not found in open_files!"))?,
-                false,
-            ));
+            let entry = self
+                .open_files
+                .get_mut(&key)
+                .ok_or_else(|| anyhow!("File entry not found in open_files!"))?;
+            entry.last_used = self.uses;
+            return Ok((entry, false));
         }
 
-        let file = if self.write_to_disk {
-            let dir_path = Path::new(&self.output_directory).join(&self.filing_id);
-            std::fs::create_dir_all(&dir_path)?;
-            let normalized_filename = filename.replace('/', "-");
-            let fullpath = dir_path
-                .join(&normalized_filename)
-                .with_extension(extension.trim_start_matches('.'));
-            Some(
-                OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .append(true) // Changed from truncate(true) to append(true) to avoid overwriting
-                    .open(fullpath)?,
-            )
-        } else {
-            None
+        let mut entry = match self.closed_files.remove(&key) {
+            // A file closed with `close_file` is reopened to be appended to where it left off
+            Some(mut entry) => match self.open_file(filename, extension, entry.part) {
+                Ok((file, path)) => {
+                    entry.file = file;
+                    entry.path = path;
+                    entry.buffer_file = BufferFile::new(self.buffer_size);
+                    entry
+                }
+                Err(e) => {
+                    self.closed_files.insert(key, entry);
+                    return Err(e);
+                }
+            },
+            None => {
+                let (file, path) = self.open_file(filename, extension, 1)?;
+                FileEntry::new(self.buffer_size, file, path)
+            }
         };
-
-        let entry = FileEntry::new(self.buffer_size, file);
+        if let Some(on_file_open) = &self.on_file_open {
+            on_file_open(filename, extension)?;
+        }
+        entry.last_used = self.uses;
         self.open_files.insert(key.clone(), entry);
         self.last_file_key = Some(key.clone());
         Ok((
@@ -247,65 +1794,302 @@ impl WriterContext {
         ))
     }
 
-    /// Internal flush logic that writes the buffer out to disk or to the custom write fn.
-    fn flush_buffer(&mut self, filename: &str, extension: &str) -> Result<()> {
-        // Attempt to get the file entry
-        let (buffer, file_option) = {
-            let (entry, _) = self.get_file_entry(filename, extension)?;
+    /// Open the file of part `part` of `(filename, extension)` for writing, if it's written to
+    /// disk, returning it with its path.
+    fn open_file(&mut self, filename: &str, extension: &str, part: usize) -> Result<OpenedFile> {
+        // Rows written to a database have no file of their own
+        let database_rows =
+            self.writes_database() && extension == normalize_extension(CSV_EXTENSION);
+        let mut path = None;
+        let file = if self.write_to_disk && !database_rows {
+            if self.sink_factory.is_none() {
+                self.make_room_for_file()?;
+            }
+            let relative = self.file_path(filename, extension, part);
+            let mut fullpath = Path::new(&self.output_directory).join(&relative);
+            let key = (filename.to_string(), extension.to_string());
+            match self.path_owners.get(&fullpath) {
+                Some(owner) if *owner != key => {
+                    return Err(anyhow!(
+                        "Output files of {}.{} and {}.{} would both be written to {}",
+                        owner.0,
+                        owner.1,
+                        filename,
+                        extension,
+                        fullpath.display()
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    self.path_owners.insert(fullpath.clone(), key);
+                }
+            }
+            if let Some(factory) = &self.sink_factory {
+                let sink = factory.create(filename, extension, &relative)?;
+                // A file continued by a later filing already starts with its header row
+                if !self.opened_paths.insert(fullpath) {
+                    self.headers_written
+                        .insert((filename.to_string(), extension.to_string()));
+                }
+                return Ok((Some(sink), None));
+            }
+            if let Some(dir_path) = fullpath.parent() {
+                std::fs::create_dir_all(dir_path)?;
+            }
+            let reopened = self.opened_paths.contains(&temp_path(&fullpath));
+            if self.atomic_writes && !reopened {
+                if self.output_mode == OutputMode::FailIfExists && fullpath.exists() {
+                    return Err(anyhow!("Output file {} already exists", fullpath.display()));
+                }
+                self.temp_files
+                    .push((temp_path(&fullpath), fullpath.clone()));
+            }
+            if self.atomic_writes {
+                fullpath = temp_path(&fullpath);
+            }
+            let mut options = OpenOptions::new();
+            match self.output_mode {
+                _ if self.opened_paths.contains(&fullpath) => options.create(true).append(true),
+                // A temporary file left by an interrupted run is overwritten
+                _ if self.atomic_writes => options.create(true).write(true).truncate(true),
+                OutputMode::Append => options.create(true).append(true),
+                OutputMode::Truncate => options.create(true).write(true).truncate(true),
+                OutputMode::FailIfExists => options.create_new(true).write(true),
+            };
+            let file = options.open(&fullpath).map_err(|e| match e.kind() {
+                ErrorKind::AlreadyExists => {
+                    anyhow!("Output file {} already exists", fullpath.display())
+                }
+                _ => anyhow!("Failed to open {}: {}", fullpath.display(), e),
+            })?;
+            let earlier_run = self.opened_paths.insert(fullpath.clone());
+            self.open_handles += 1;
+            // An appended file left by an earlier run already starts with its header row, read
+            // once to be checked against this run's columns
+            if file.metadata()?.len() > 0 {
+                let key = (filename.to_string(), extension.to_string());
+                let checked = self.format == OutputFormat::Csv
+                    && self.compression == Compression::None
+                    && extension == normalize_extension(CSV_EXTENSION);
+                if earlier_run && checked {
+                    if let Some(header) = read_header_row(&fullpath, self.output_delimiter) {
+                        self.existing_headers
+                            .insert(key.clone(), (fullpath.clone(), header));
+                    }
+                }
+                self.headers_written.insert(key);
+            }
+            path = Some(fullpath);
+            Some(Box::new(FileSink::new(
+                file,
+                self.compression,
+                self.compression_level,
+            )) as Box<dyn OutputSink>)
+        } else {
+            None
+        };
+        Ok((file, path))
+    }
 
-            if entry.buffer_file.is_empty() {
-                return Ok(()); // Nothing to flush
+    /// Close the least recently used file if `max_open_files` are open, flushing what was
+    /// written to it; its entry keeps its buffer, to be written once the file is reopened.
+    fn make_room_for_file(&mut self) -> Result<()> {
+        // Closing a compressed file would finish its stream, so compressed files stay open
+        if self.open_handles < self.max_open_files || self.compression != Compression::None {
+            return Ok(());
+        }
+        let oldest = self
+            .open_files
+            .values_mut()
+            .filter(|entry| entry.file.is_some())
+            .min_by_key(|entry| entry.last_used);
+        if let Some(entry) = oldest {
+            if let Some(mut sink) = entry.file.take() {
+                sink.finalize()
+                    .map_err(|e| anyhow!("Failed to flush file before closing it: {}", e))?;
+                self.sync_sink(sink.as_mut())?;
             }
+            self.open_handles -= 1;
+        }
+        Ok(())
+    }
 
-            // Clone the buffer content to write it
-            let buffer_contents = entry.buffer_file.buffer.clone();
+    /// Sync `sink` to the disk if `with_sync_on_close` is set.
+    fn sync_sink(&mut self, sink: &mut dyn OutputSink) -> Result<()> {
+        if !self.sync_on_close {
+            return Ok(());
+        }
+        let started = Instant::now();
+        let synced = sink
+            .sync()
+            .map_err(|e| anyhow!("Failed to sync file to disk: {:#}", e));
+        self.sync_time += started.elapsed();
+        synced
+    }
 
-            // Clear the buffer after cloning
-            entry.buffer_file.clear();
+    /// Sync the directories holding the files of the open entries to the disk, up to the output
+    /// directory, if `with_sync_on_close` is set, so files just created or renamed in them are
+    /// still there after a crash.
+    fn sync_directories(&mut self) -> Result<()> {
+        if !self.sync_on_close {
+            return Ok(());
+        }
+        let output_directory = Path::new(&self.output_directory);
+        let mut directories = BTreeSet::new();
+        for (_, entry) in self.all_entries() {
+            for path in entry.all_parts().filter_map(|(path, _)| path) {
+                let parents = path.ancestors().skip(1);
+                for directory in parents.take_while(|dir| dir.starts_with(output_directory)) {
+                    if !directory.as_os_str().is_empty() {
+                        directories.insert(directory.to_path_buf());
+                    }
+                }
+            }
+        }
+        let started = Instant::now();
+        let synced = directories.iter().try_for_each(|dir| sync_directory(dir));
+        self.sync_time += started.elapsed();
+        if cfg!(unix) {
+            self.directory_syncs += directories.len() as u64;
+        }
+        synced
+    }
 
-            // Get a cloned file handle (if writing to disk)
-            let file_clone = entry.file.as_ref().map(|f| f.try_clone());
+    /// Reopen `(filename, extension)`'s file for appending if it was closed to make room.
+    fn reopen_file(&mut self, filename: &str, extension: &str) -> Result<()> {
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        let path = match (&entry.file, &entry.path) {
+            (None, Some(path)) => path.clone(),
+            _ => return Ok(()),
+        };
+        self.make_room_for_file()?;
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| anyhow!("Failed to reopen {}: {}", path.display(), e))?;
+        let sink = FileSink::new(file, self.compression, self.compression_level);
+        self.get_file_entry(filename, extension)?.0.file = Some(Box::new(sink));
+        self.open_handles += 1;
+        Ok(())
+    }
 
-            (buffer_contents, file_clone)
+    /// Internal flush logic that writes the buffer out to disk or to the custom write fn.
+    fn flush_buffer(&mut self, filename: &str, extension: &str) -> Result<()> {
+        // Taken out of the entry for the write, which needs `self`, and put back after, so the
+        // bytes aren't copied and the buffer keeps its capacity
+        let buffer = {
+            let (entry, _) = self.get_file_entry(filename, extension)?;
+            if entry.buffer_file.is_empty() {
+                entry.buffered_lines = 0;
+                return Ok(()); // Nothing to flush
+            }
+            entry.buffered_lines = 0;
+            std::mem::take(&mut entry.buffer_file.buffer)
         };
+        let written = self.write_buffer(filename, extension, &buffer);
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        entry.buffer_file.buffer = buffer;
+        entry.buffer_file.clear();
+        written
+    }
 
-        // Use the custom write function if set
+    /// Write a flushed buffer to the custom write function and the collected output, then to
+    /// the file (through its compressed stream, if any), so all see the same bytes.
+    fn write_buffer(&mut self, filename: &str, extension: &str, buffer: &[u8]) -> Result<()> {
+        // Use the custom write function if set, with the uncompressed bytes
         if let Some(custom_fn) = &self.custom_write_fn {
-            custom_fn(filename, extension, &buffer)?;
+            let started = Instant::now();
+            let written = custom_fn(filename, extension, buffer);
+            self.custom_write_time += started.elapsed();
+            written?;
+        }
+        if let Some(collected) = &mut self.collected {
+            let total = self.collected_bytes + buffer.len() as u64;
+            if let Some(limit) = self.max_collected_bytes.filter(|&limit| total > limit) {
+                return Err(FecError::OutputLimitExceeded { limit }.into());
+            }
+            collected
+                .entry((filename.to_string(), extension.to_string()))
+                .or_default()
+                .extend_from_slice(buffer);
+            self.collected_bytes = total;
         }
 
-        // Write to the file if a file handle exists
-        if let Some(file_result) = file_option {
-            let mut file =
-                file_result.map_err(|e| anyhow!("Failed to clone file handle: {}", e))?;
-            file.write_all(&buffer)
-                .map_err(|e| anyhow!("Failed to write to file: {}", e))?;
+        // Write to the file (through its gzip stream, if compressed) if a file handle exists
+        self.reopen_file(filename, extension)?;
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        entry.flushes += 1;
+        entry.bytes_flushed += buffer.len() as u64;
+        if let Some(sink) = &mut entry.file {
+            let started = Instant::now();
+            let written = sink.write_all(buffer);
+            self.write_time += started.elapsed();
+            written.map_err(|e| anyhow!("Failed to write to file: {}", e))?;
         }
 
         Ok(())
     }
 
     /// Write raw bytes, potentially buffering and flushing if necessary.
+    ///
+    /// Data that would fill the buffer more than once (e.g. a long F99 text) isn't buffered:
+    /// what is pending is flushed, then the data written in one piece, in order.
     fn write_bytes(&mut self, filename: &str, extension: &str, data: &[u8]) -> Result<()> {
-        let mut overflow = data.to_vec();
-        while !overflow.is_empty() {
-            let leftover = {
-                let (entry, _) = self.get_file_entry(filename, extension)?;
-                entry.buffer_file.write_bytes(&overflow)
-            };
-            if leftover.is_empty() {
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        entry.stats.bytes += data.len() as u64;
+        let space_left = entry.buffer_file.space_left();
+        if data.len() > space_left {
+            entry.overflows += 1;
+        }
+        if data.len() > space_left + entry.buffer_file.capacity {
+            self.flush_buffer(filename, extension)?;
+            self.write_buffer(filename, extension, data)?;
+            // The rows written count toward the policy, as if they had gone through the buffer
+            return self.apply_flush_policy(filename, extension, data);
+        }
+        let mut overflow = data;
+        loop {
+            let (entry, _) = self.get_file_entry(filename, extension)?;
+            overflow = entry.buffer_file.write_bytes(overflow);
+            if overflow.is_empty() {
                 break;
-            } else {
-                // Buffer is full. Flush, then write leftover
-                self.flush_buffer(filename, extension)?;
-                overflow = leftover;
             }
+            // Buffer is full. Flush, then write leftover
+            self.flush_buffer(filename, extension)?;
+        }
+        self.apply_flush_policy(filename, extension, data)
+    }
+
+    /// Write out the buffer of `(filename, extension)`'s file if its flush policy says to, now
+    /// that `data` was written to it.
+    fn apply_flush_policy(&mut self, filename: &str, extension: &str, data: &[u8]) -> Result<()> {
+        let policy = match self.file_flush_policies.get(filename) {
+            Some(&policy) => policy,
+            None => self.flush_policy,
+        };
+        let every = match policy {
+            FlushPolicy::Size => return Ok(()),
+            FlushPolicy::PerLine => 1,
+            FlushPolicy::PerNRows(rows) => rows.max(1) as usize,
+        };
+        let lines = data.iter().filter(|&&byte| byte == b'\n').count();
+        if lines == 0 {
+            return Ok(());
+        }
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        entry.buffered_lines += lines;
+        if entry.buffered_lines >= every {
+            self.flush_buffer(filename, extension)?;
         }
         Ok(())
     }
 
     /// Write a string, handling local buffer mode and custom line accumulation.
+    ///
+    /// `extension` may have a leading `.` or not: `.txt` and `txt` are the same file, as they
+    /// are to every method taking an extension.
     pub fn write_string(&mut self, filename: &str, extension: &str, s: &str) -> Result<()> {
+        let extension = normalize_extension(extension);
         if self.local_mode {
             // Write to local buffer
             self.local_buffer.push_str(s);
@@ -314,15 +2098,26 @@ impl WriterContext {
             // Write to file or custom
             self.write_bytes(filename, extension, s.as_bytes())?;
             // Also handle custom line accumulation
-            if let Some(ref mut _custom_fn) = self.custom_line_fn {
-                self.custom_line_buffer.push_str(s);
-            }
+            self.push_line(filename, extension, s)?;
         }
         Ok(())
     }
 
+    /// Write `text`, whose lines end with `\n` or `\r\n`, as `write_string` does, with its line
+    /// breaks turned into `\r\n` if rows end with it. Otherwise the text is written as is.
+    pub fn write_text(&mut self, filename: &str, extension: &str, text: &str) -> Result<()> {
+        match self.terminator {
+            Terminator::Lf => self.write_string(filename, extension, text),
+            Terminator::Crlf => {
+                let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+                self.write_string(filename, extension, &text)
+            }
+        }
+    }
+
     /// Write a character, handling local buffer mode and custom line accumulation.
     pub fn write_char(&mut self, filename: &str, extension: &str, c: char) -> Result<()> {
+        let extension = normalize_extension(extension);
         let mut buf = [0; 4];
         let cbytes = c.encode_utf8(&mut buf); // Convert char to UTF-8
         if self.local_mode {
@@ -330,80 +2125,936 @@ impl WriterContext {
             self.local_buffer_pos += cbytes.len();
         } else {
             self.write_bytes(filename, extension, cbytes.as_bytes())?;
-            if let Some(ref mut _custom_fn) = self.custom_line_fn {
-                self.custom_line_buffer.push_str(cbytes);
-            }
+            self.push_line(filename, extension, cbytes)?;
         }
         Ok(())
     }
 
     /// Write a double, formatting it and handling local buffer mode and custom line accumulation.
+    ///
+    /// The value is formatted as `with_double_format` set, with two decimal places by default.
     pub fn write_double(&mut self, filename: &str, extension: &str, value: f64) -> Result<()> {
-        let mut s = String::new();
-        write!(&mut s, "{:.2}", value)?; // Format with two decimal places
-        self.write_string(filename, extension, &s)?;
-        Ok(())
+        self.write_double_with(filename, extension, value, self.double_format)
+    }
+
+    /// Write a double formatted as `format` says, whatever the writer's format for doubles.
+    pub fn write_double_with(
+        &mut self,
+        filename: &str,
+        extension: &str,
+        value: f64,
+        format: DoubleFormat,
+    ) -> Result<()> {
+        let s = format_double_with(value, format);
+        self.write_string(filename, extension, &s)
+    }
+
+    /// Write the date of `(year, month, day)`, formatted as `with_date_format` set (ISO
+    /// `YYYY-MM-DD` by default), handling local buffer mode and custom line accumulation as
+    /// `write_string` does. Fails, writing nothing, if there is no such date.
+    pub fn write_date(
+        &mut self,
+        filename: &str,
+        extension: &str,
+        (year, month, day): (i32, u32, u32),
+    ) -> Result<()> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(anyhow!("Invalid date {}-{}-{}", year, month, day));
+        }
+        let s = format_date_with(year, month, day, self.date_format);
+        self.write_string(filename, extension, &s)
+    }
+
+    /// Write an integer, with all its digits, handling local buffer mode and custom line
+    /// accumulation as `write_string` does.
+    pub fn write_integer(&mut self, filename: &str, extension: &str, value: i64) -> Result<()> {
+        self.write_string(filename, extension, &format_integer(value))
+    }
+
+    /// Write a boolean as the token `with_bool_format` set for it (`true` or `false` by
+    /// default), handling local buffer mode and custom line accumulation as `write_string` does.
+    pub fn write_bool(&mut self, filename: &str, extension: &str, value: bool) -> Result<()> {
+        let token = self.bool_format.format(value).to_string();
+        self.write_string(filename, extension, &token)
     }
 
     /// Flush all buffers for all open files, akin to `freeWriteContext` calls to bufferFlush.
+    ///
+    /// Compressed files have their gzip or zstd stream flushed, so what was written so far can
+    /// be decompressed from disk; the stream is only finished once the file is closed.
+    ///
+    /// A file that fails to flush doesn't keep the others from being flushed: every file is
+    /// tried, and the failures are returned together as `FecError::IncompleteOutput`, naming
+    /// each incomplete file by its path under the output directory.
     pub fn flush_all(&mut self) -> Result<()> {
-        // Clone the keys to avoid holding an immutable borrow while mutably borrowing self
-        let keys: Vec<(String, String)> = self.open_files.keys().cloned().collect();
+        let failed = self.flush_files(false);
+        self.flush_outputs()?;
+        self.sync_directories()?;
+        incomplete_output(&failed)
+    }
 
+    /// Flush (or close, if `close` is set) the file of each open entry in turn, returning the
+    /// path and error of each that failed.
+    fn flush_files(&mut self, close: bool) -> Vec<(String, anyhow::Error)> {
+        // Sorted, so failures are listed in the same order from run to run
+        let mut keys: Vec<(String, String)> = self.open_files.keys().cloned().collect();
+        keys.sort();
+        let mut failed = Vec::new();
         for (filename, extension) in keys {
-            self.flush_buffer(&filename, &extension)
-                .map_err(|e| anyhow!("Error flushing {}{}: {}", filename, extension, e))?;
+            let flushed = match close {
+                true => self.close_sink(&filename, &extension),
+                false => self.flush_file(&filename, &extension),
+            };
+            if let Err(error) = flushed {
+                failed.push((self.current_path(&filename, &extension), error));
+            }
+        }
+        failed
+    }
 
-            // After flushing the buffer, flush the actual file if it exists
-            if let Some(entry) = self
-                .open_files
-                .get_mut(&(filename.clone(), extension.clone()))
-            {
-                if let Some(ref mut file) = entry.file {
-                    file.flush().map_err(|e| {
-                        anyhow!("Failed to flush file {}{}: {}", filename, extension, e)
-                    })?;
+    /// Tell `on_file_close` each open file is closed, bar those listed in `failed`, adding to
+    /// `failed` those it fails for.
+    fn notify_file_closes(&self, failed: &mut Vec<(String, anyhow::Error)>) {
+        let Some(on_file_close) = &self.on_file_close else {
+            return;
+        };
+        let mut keys: Vec<&(String, String)> = self.open_files.keys().collect();
+        keys.sort();
+        for key @ (filename, extension) in keys {
+            let path = self.current_path(filename, extension);
+            if failed.iter().any(|(failed, _)| *failed == path) {
+                continue;
+            }
+            let bytes = self.open_files[key]
+                .all_parts()
+                .map(|(_, stats)| stats.bytes)
+                .sum();
+            if let Err(error) = on_file_close(filename, extension, bytes) {
+                failed.push((path, error));
+            }
+        }
+    }
+
+    /// The path of the current part of `(filename, extension)`'s open file, as `file_stats`
+    /// lists it.
+    fn current_path(&self, filename: &str, extension: &str) -> String {
+        let part = self.open_files[&(filename.to_string(), extension.to_string())].part;
+        let path = self.file_path(filename, extension, part);
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Write what is left in the buffer of `(filename, extension)` and flush its file, if open.
+    fn flush_file(&mut self, filename: &str, extension: &str) -> Result<()> {
+        self.flush_buffer(filename, extension)?;
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        // Taken out of the entry to be synced, which needs `self`
+        let Some(mut sink) = entry.file.take() else {
+            return Ok(());
+        };
+        let flushed = sink
+            .finalize()
+            .map_err(|e| anyhow!("Failed to flush file: {:#}", e))
+            .and_then(|_| self.sync_sink(sink.as_mut()));
+        self.get_file_entry(filename, extension)?.0.file = Some(sink);
+        flushed
+    }
+
+    /// Flush the outputs that aren't files of their own: the print target, the database and the
+    /// manifest.
+    fn flush_outputs(&mut self) -> Result<()> {
+        if let Some(print) = &mut self.print {
+            print.flush()?;
+        }
+        if let Some(database) = &mut self.database {
+            database.commit()?;
+        }
+        if self.manifest {
+            self.write_manifest()?;
+        }
+        Ok(())
+    }
+
+    /// Flush everything, as `flush_all` does, and, with atomic writes, give every file written
+    /// since the last call its final name. Call it once the parse has succeeded.
+    pub fn finalize(&mut self) -> Result<()> {
+        self.flush_all()?;
+        self.rename_temp_files()
+    }
+
+    /// Flush and close every file, as `finalize` does, and report what became of each.
+    ///
+    /// A file whose last rows can't be written (its custom write function or the disk fails)
+    /// doesn't stop the others from being closed; it is listed in the report's `failed` files
+    /// instead, and `CloseReport::check` turns those into an error. Other failures, such as
+    /// committing a database or writing the manifest, fail the call. With atomic writes, the
+    /// files are only renamed to their final names if every file was written whole; otherwise
+    /// they are removed.
+    ///
+    /// Files and their directories are synced to the disk first if `with_sync_on_close` is set,
+    /// and `on_file_close` is told of each file written whole.
+    pub fn close(mut self) -> Result<CloseReport> {
+        let mut failed = self.flush_files(true);
+        self.notify_file_closes(&mut failed);
+        self.flush_outputs()?;
+        if failed.is_empty() {
+            self.rename_temp_files()?;
+        }
+        self.sync_directories()?;
+
+        let closed = self
+            .file_stats()
+            .into_iter()
+            .filter(|(path, _)| !failed.iter().any(|(failed, _)| failed == path))
+            .collect();
+        let metrics = self.metrics();
+        // Nothing is left for `Drop` to flush, bar temporary files to remove
+        self.open_files.clear();
+        self.closed_files.clear();
+        Ok(CloseReport {
+            closed,
+            failed,
+            sync_time: self.sync_time,
+            metrics,
+            header_mismatches: self.header_mismatches.clone(),
+        })
+    }
+
+    /// Write what is left in the buffer of `(filename, extension)` and close its file, syncing
+    /// it to the disk if `with_sync_on_close` is set.
+    fn close_sink(&mut self, filename: &str, extension: &str) -> Result<()> {
+        self.flush_buffer(filename, extension)?;
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        // A file closed to make room was flushed and synced then
+        let Some(mut sink) = entry.file.take() else {
+            return Ok(());
+        };
+        // Only files on disk count against `max_open_files`
+        if entry.path.is_some() {
+            self.open_handles = self.open_handles.saturating_sub(1);
+        }
+        sink.close()
+            .map_err(|e| anyhow!("Failed to flush file: {:#}", e))?;
+        self.sync_sink(sink.as_mut())
+    }
+
+    /// The `(filename, extension)` keys of the current filing's files that are open, i.e.
+    /// written to and not closed with `close_file`, sorted. Files closed for a while to make
+    /// room for others (see `with_max_open_files`) are listed, as they still hold rows.
+    pub fn open_files(&self) -> Vec<(String, String)> {
+        let mut keys: Vec<(String, String)> = self.open_files.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Write what is left in the buffer of `(filename, extension)`, close its file and free its
+    /// buffer, e.g. once a long-running writer is done with a form, without closing the other
+    /// files. The file is synced if `with_sync_on_close` is set, and `on_file_close` told of it.
+    /// Does nothing if the file isn't open.
+    ///
+    /// Writing to the file again reopens it, appending to it where it left off (in the same
+    /// part, if split) with no second header row; its stats carry on. A sink factory's file is
+    /// continued by a new sink, as for a later filing writing to the same path.
+    pub fn close_file(&mut self, filename: &str, extension: &str) -> Result<()> {
+        let extension = normalize_extension(extension);
+        let key = (filename.to_string(), extension.to_string());
+        if !self.open_files.contains_key(&key) {
+            return Ok(());
+        }
+        self.close_sink(filename, extension)?;
+        if let Some(on_file_close) = &self.on_file_close {
+            let bytes = self.open_files[&key]
+                .all_parts()
+                .map(|(_, stats)| stats.bytes)
+                .sum();
+            on_file_close(filename, extension, bytes)?;
+        }
+        if let Some(mut entry) = self.open_files.remove(&key) {
+            entry.buffer_file = BufferFile::new(0);
+            self.closed_files.insert(key.clone(), entry);
+        }
+        if self.last_file_key.as_ref() == Some(&key) {
+            self.last_file_key = None;
+        }
+        Ok(())
+    }
+
+    /// The entries of the current filing's files, open or closed with `close_file`.
+    fn all_entries(&self) -> impl Iterator<Item = (&(String, String), &FileEntry)> {
+        self.open_files.iter().chain(&self.closed_files)
+    }
+
+    /// Give every file written under a temporary name since the last call its final name.
+    fn rename_temp_files(&mut self) -> Result<()> {
+        for (temp, path) in std::mem::take(&mut self.temp_files) {
+            std::fs::rename(&temp, &path).map_err(|e| {
+                anyhow!(
+                    "Failed to rename {} to {}: {}",
+                    temp.display(),
+                    path.display(),
+                    e
+                )
+            })?;
+            // Anything written later is appended to the file under its final name
+            if self.opened_paths.remove(&temp) {
+                self.opened_paths.insert(path.clone());
+            }
+            let entries = self.open_files.values_mut();
+            for entry in entries.chain(self.closed_files.values_mut()) {
+                let paths = entry.parts.iter_mut().map(|(path, _)| path);
+                for entry_path in paths.chain([&mut entry.path]) {
+                    if entry_path.as_ref() == Some(&temp) {
+                        *entry_path = Some(path.clone());
+                    }
                 }
             }
         }
         Ok(())
     }
 
+    /// Write `MANIFEST_FILENAME`, listing the files written to disk for the current filing, if
+    /// there are any.
+    fn write_manifest(&mut self) -> Result<()> {
+        // Borrowed apart from `temp_files`, which the manifest may join
+        let entries = self.open_files.iter().chain(&self.closed_files);
+        let mut files: Vec<(&str, &str, FileStats, &Path)> = entries
+            .flat_map(|((filename, extension), entry)| {
+                entry.all_parts().filter_map(|(path, stats)| {
+                    Some((filename.as_str(), extension.as_str(), stats, path?))
+                })
+            })
+            .collect();
+        if files.is_empty() {
+            return Ok(());
+        }
+        files.sort_by(|a, b| a.3.cmp(b.3));
+
+        let manifest_file = match &self.filename_template {
+            Some(template) => {
+                let (stem, extension) = MANIFEST_FILENAME.split_once('.').unwrap_or_default();
+                template.expand(&self.filing_id, stem, extension)
+            }
+            None => Path::new(&self.filing_id).join(MANIFEST_FILENAME),
+        };
+        let manifest_dir = manifest_file
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let mut manifest_path = Path::new(&self.output_directory).join(manifest_file);
+        if let Some(dir_path) = manifest_path.parent() {
+            std::fs::create_dir_all(dir_path)?;
+        }
+        if self.atomic_writes {
+            let temp = temp_path(&manifest_path);
+            if !self.temp_files.iter().any(|(path, _)| *path == temp) {
+                self.temp_files.push((temp.clone(), manifest_path));
+            }
+            manifest_path = temp;
+        }
+        let mut wtr = WriterBuilder::new()
+            .from_path(&manifest_path)
+            .map_err(|e| anyhow!("Failed to write {}: {}", manifest_path.display(), e))?;
+        wtr.write_record(MANIFEST_COLUMNS)?;
+        for (filename, extension, stats, path) in files {
+            // Listed relative to the manifest, as `../SA/12345.csv` under some templates
+            let relative = path.strip_prefix(&self.output_directory).unwrap_or(path);
+            let relative = template::relative_to(relative, &manifest_dir);
+            let name = relative.to_string_lossy();
+            let name = match self.atomic_writes {
+                true => name.strip_suffix(TEMP_SUFFIX).unwrap_or(&name),
+                false => &name,
+            };
+            let bytes = std::fs::metadata(path)
+                .map_err(|e| anyhow!("Failed to read the size of {}: {}", path.display(), e))?
+                .len();
+            let header = self.header_written(filename, extension);
+            wtr.write_record([
+                name,
+                filename,
+                &stats.rows.to_string(),
+                &bytes.to_string(),
+                &header.to_string(),
+            ])?;
+        }
+        wtr.flush()?;
+        if self.sync_on_close {
+            let file = wtr
+                .into_inner()
+                .map_err(|e| anyhow!("Failed to write {}: {}", manifest_path.display(), e))?;
+            let started = Instant::now();
+            let synced = file.sync_all();
+            self.sync_time += started.elapsed();
+            synced.map_err(|e| {
+                anyhow!("Failed to sync {} to disk: {}", manifest_path.display(), e)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Finish the current filing and write the rows that follow under `filing_id`.
+    ///
+    /// Flushes and closes every open file, so the next filing's files are opened in its own
+    /// directory and start with their own header rows. In print mode, each form's header row is
+    /// printed again the first time the new filing has a row of it.
+    pub fn start_filing(&mut self, filing_id: String) -> Result<()> {
+        if let Some(template) = &self.filename_template {
+            template.check(&filing_id)?;
+        }
+        let mut failed = self.flush_files(true);
+        self.flush_outputs()?;
+        self.sync_directories()?;
+        self.notify_file_closes(&mut failed);
+        incomplete_output(&failed)?;
+        let finished = self.current_stats();
+        self.finished_stats.extend(finished);
+        let finished = self.current_outputs();
+        self.finished_outputs.extend(finished);
+        let finished = self.current_metrics();
+        self.finished_metrics.extend(finished);
+        self.open_files.clear();
+        self.closed_files.clear();
+        self.open_handles = 0;
+        self.last_file_key = None;
+        self.headers_written.clear();
+        self.existing_headers.clear();
+        self.row_columns.clear();
+        self.filing_id = filing_id;
+        Ok(())
+    }
+
+    /// The rows and bytes written to each output file during this run, by its path under the
+    /// output directory (e.g. `12345/SA.csv`, or `12345/SA.csv.gz` if compressed), sorted by
+    /// path. Bytes are counted before compression. Rows written to a database are listed by
+    /// table (`12345/SA`), with no bytes.
+    ///
+    /// Counts are kept as rows are written, so files appended to by a resumed parse count only
+    /// what this run added. Nothing is counted in local buffer mode.
+    pub fn file_stats(&self) -> Vec<(String, FileStats)> {
+        let mut stats = self.finished_stats.clone();
+        stats.extend(self.current_stats());
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    /// The rows and bytes written to each form's output during this run, by filing, form and
+    /// extension: what `file_stats` lists by file, with the parts of split files added up.
+    ///
+    /// Counts are exact as rows are written, before they are flushed from their buffer, and
+    /// whatever files are closed to make room for others.
+    pub fn stats(&self) -> Vec<OutputStats> {
+        let mut stats = self.finished_outputs.clone();
+        stats.extend(self.current_outputs());
+        stats.sort_by(|a, b| {
+            (&a.filing_id, &a.filename, &a.extension).cmp(&(
+                &b.filing_id,
+                &b.filename,
+                &b.extension,
+            ))
+        });
+        stats
+    }
+
+    /// How the output of each form was written out during this run (flushes, overflowing writes
+    /// and bytes, by filing, form and extension, as `stats` lists them), and the time spent
+    /// writing and syncing it, to tune `buffer_size` by.
+    pub fn metrics(&self) -> WriterMetrics {
+        let mut files = self.finished_metrics.clone();
+        files.extend(self.current_metrics());
+        files.sort_by(|a, b| {
+            (&a.filing_id, &a.filename, &a.extension).cmp(&(
+                &b.filing_id,
+                &b.filename,
+                &b.extension,
+            ))
+        });
+        WriterMetrics {
+            flushes: files.iter().map(|file| file.flushes).sum(),
+            overflows: files.iter().map(|file| file.overflows).sum(),
+            bytes: files.iter().map(|file| file.bytes).sum(),
+            files,
+            write_time: self.write_time,
+            custom_write_time: self.custom_write_time,
+            sync_time: self.sync_time,
+            directory_syncs: self.directory_syncs,
+        }
+    }
+
+    /// The metrics of the current filing's outputs, as `metrics` lists them.
+    fn current_metrics(&self) -> Vec<FileMetrics> {
+        self.all_entries()
+            .map(|((filename, extension), entry)| FileMetrics {
+                filing_id: self.filing_id.clone(),
+                filename: filename.clone(),
+                extension: extension.clone(),
+                flushes: entry.flushes,
+                overflows: entry.overflows,
+                bytes: entry.bytes_flushed,
+            })
+            .collect()
+    }
+
+    /// The stats of the current filing's outputs, as `stats` lists them.
+    fn current_outputs(&self) -> Vec<OutputStats> {
+        self.all_entries()
+            .map(|((filename, extension), entry)| OutputStats {
+                filing_id: self.filing_id.clone(),
+                filename: filename.clone(),
+                extension: extension.clone(),
+                rows: entry.all_parts().map(|(_, stats)| stats.rows).sum(),
+                bytes: entry.all_parts().map(|(_, stats)| stats.bytes).sum(),
+            })
+            .collect()
+    }
+
+    /// The stats of the current filing's files, as `file_stats` lists them.
+    fn current_stats(&self) -> Vec<(String, FileStats)> {
+        self.all_entries()
+            .flat_map(|((filename, extension), entry)| {
+                entry.all_parts().enumerate().map(|(index, (_, stats))| {
+                    let path = self.file_path(filename, extension, index + 1);
+                    (path.to_string_lossy().into_owned(), stats)
+                })
+            })
+            .collect()
+    }
+
+    /// The path of part `part` of `(filename, extension)`'s file, relative to the output
+    /// directory: `<filing_id>/<filename>.<extension>`, or as the filename template says.
+    fn file_path(&self, filename: &str, extension: &str, part: usize) -> PathBuf {
+        let extension = self.disk_extension(extension);
+        let path = match &self.filename_template {
+            Some(template) => template.expand(&self.filing_id, filename, &extension),
+            None => Path::new(&self.filing_id)
+                .join(sanitize_filename(filename))
+                .with_extension(&extension),
+        };
+        part_path(path, part)
+    }
+
+    /// The extension of the file for `extension` on disk: row files are named for the output
+    /// format, and files written to disk get the compression's suffix.
+    fn disk_extension(&self, extension: &str) -> String {
+        let extension = normalize_extension(extension);
+        let extension = if extension == normalize_extension(CSV_EXTENSION) {
+            normalize_extension(self.row_extension())
+        } else {
+            extension
+        };
+        let suffix = if self.write_to_disk && !extension.is_empty() {
+            self.compression.suffix()
+        } else {
+            ""
+        };
+        format!("{}{}", extension, suffix)
+    }
+
     /// Write a CSV record using the `csv` crate. This automatically handles quotes, commas, etc.
     ///
     /// * `filename`: The base name of the file (no extension). We'll append `.csv`.
     /// * `fields`: A list of string fields to write as one CSV row.
-    pub fn write_csv_record(&mut self, filename: &str, fields: &Vec<String>) -> Result<()> {
-        let mut buffer = Vec::new();
+    ///
+    /// Fields are separated by the output delimiter (see `with_output_delimiter`). In NDJSON
+    /// format, the record is written as a JSON object instead (see `encode_json`).
+    pub fn write_csv_record(&mut self, filename: &str, fields: &[String]) -> Result<()> {
+        self.write_row(filename, fields, false)
+    }
+
+    /// Start a CSV row of `fields` whose last field is written piece by piece, for a value too
+    /// large to hold in memory at once.
+    ///
+    /// The value is written with `write_streamed_field` and the row ended with
+    /// `finish_streamed_row`; it is always quoted, since its content isn't known up front, unless
+    /// the quote style is `QuoteStyle::Never`.
+    pub fn start_streamed_row(&mut self, filename: &str, fields: &[String]) -> Result<()> {
+        if self.writes_database() {
+            let mut fields = fields.to_vec();
+            fields.push(String::new());
+            return self.write_database_row(filename, fields);
+        }
+        let buffer = match self.format {
+            OutputFormat::Csv | OutputFormat::Sqlite => {
+                let mut buffer = self.row_encoder.encode(fields)?.to_vec();
+                buffer.truncate(buffer.len() - self.terminator.as_str().len()); // The line break
+                buffer.push(self.output_delimiter);
+                if self.quote_style != QuoteStyle::Never {
+                    buffer.push(b'"');
+                }
+                buffer
+            }
+            OutputFormat::Ndjson => {
+                let mut buffer = self.encode_json(filename, fields)?;
+                if buffer != b"{" {
+                    buffer.push(b',');
+                }
+                let key = column_name(self.row_columns.get(filename), fields.len());
+                serde_json::to_writer(&mut buffer, &key)?;
+                buffer.extend_from_slice(b":\"");
+                buffer
+            }
+        };
+        self.write_row_bytes(filename, &buffer, false, false)
+    }
+
+    /// Write the next piece of the value of a row started with `start_streamed_row`.
+    pub fn write_streamed_field(&mut self, filename: &str, piece: &str) -> Result<()> {
+        if self.writes_database() {
+            self.database()?.append_to_row(piece);
+            return Ok(());
+        }
+        let escaped = match self.format {
+            OutputFormat::Csv | OutputFormat::Sqlite if self.quote_style == QuoteStyle::Never => {
+                self.row_encoder.unquoted(piece)?.into_owned()
+            }
+            OutputFormat::Csv | OutputFormat::Sqlite => piece.replace('"', "\"\""),
+            OutputFormat::Ndjson => {
+                let quoted = serde_json::to_string(piece)?;
+                quoted[1..quoted.len() - 1].to_string()
+            }
+        };
+        self.write_row_bytes(filename, escaped.as_bytes(), false, true)
+    }
+
+    /// End a row started with `start_streamed_row`.
+    pub fn finish_streamed_row(&mut self, filename: &str) -> Result<()> {
+        if self.writes_database() {
+            // The row is inserted once `end_line` gives its types
+            return Ok(());
+        }
+        let end = match self.format {
+            OutputFormat::Csv | OutputFormat::Sqlite if self.quote_style == QuoteStyle::Never => "",
+            OutputFormat::Csv | OutputFormat::Sqlite => "\"",
+            OutputFormat::Ndjson => "\"}",
+        };
+        let end = [end, self.terminator.as_str()].concat();
+        self.write_row_bytes(filename, end.as_bytes(), false, true)
+    }
+
+    /// Write a CSV row (or JSON object in NDJSON format) to `filename`, or to the print target in
+    /// print mode.
+    fn write_row(&mut self, filename: &str, fields: &[String], header: bool) -> Result<()> {
+        if self.writes_database() && !header {
+            return self.write_database_row(filename, fields.to_vec());
+        }
+        match self.format {
+            OutputFormat::Csv | OutputFormat::Sqlite => {
+                // Taken for the write, which needs `self`, and put back after
+                let mut encoder = std::mem::take(&mut self.row_encoder);
+                let written = match encoder.encode(fields) {
+                    Ok(row) => self.write_row_bytes(filename, row, header, false),
+                    Err(e) => Err(e.context(format!("Failed to write a row of {}", filename))),
+                };
+                self.row_encoder = encoder;
+                written
+            }
+            OutputFormat::Ndjson => {
+                let mut buffer = self.encode_json(filename, fields)?;
+                buffer.push(b'}');
+                buffer.extend_from_slice(self.terminator.as_str().as_bytes());
+                self.write_row_bytes(filename, &buffer, header, false)
+            }
+        }
+    }
+
+    /// Start the next part of `filename`'s row file if it can't take another row of `row_len`
+    /// bytes (see `with_max_rows_per_file` and `with_max_bytes_per_file`), flushing and closing
+    /// the current part and writing the header row at the top of the new one.
+    fn split_if_full(&mut self, filename: &str, row_len: usize) -> Result<()> {
+        if self.max_rows_per_file.is_none() && self.max_bytes_per_file.is_none() {
+            return Ok(());
+        }
+        let extension = normalize_extension(CSV_EXTENSION);
+        let (max_rows, max_bytes) = (self.max_rows_per_file, self.max_bytes_per_file);
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        let stats = entry.stats;
+        let full = max_rows.is_some_and(|max| stats.rows >= max)
+            || max_bytes.is_some_and(|max| stats.rows > 0 && stats.bytes + row_len as u64 > max);
+        if !full {
+            return Ok(());
+        }
+
+        self.flush_buffer(filename, extension)?;
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        // Only files on disk count as open handles
+        let (sink, on_disk) = (entry.file.take(), entry.path.is_some());
+        let closed = match sink {
+            Some(mut sink) => {
+                sink.close()
+                    .map_err(|e| anyhow!("Failed to flush file before splitting it: {:#}", e))?;
+                self.sync_sink(sink.as_mut())?;
+                on_disk
+            }
+            None => false,
+        };
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        let path = entry.path.take();
+        entry.parts.push((path, stats));
+        entry.stats = FileStats::default();
+        entry.part += 1;
+        let part = entry.part;
+        let header_row = entry.header_row.clone();
+        if closed {
+            self.open_handles -= 1;
+        }
+
+        let (file, path) = self.open_file(filename, extension, part)?;
+        let (entry, _) = self.get_file_entry(filename, extension)?;
+        entry.file = file;
+        entry.path = path;
+        if let Some(header_row) = header_row {
+            self.write_bytes(filename, extension, &header_row)?;
+        }
+        Ok(())
+    }
+
+    /// Start a row of `fields` in `filename`'s database table, inserted by `end_line`.
+    fn write_database_row(&mut self, filename: &str, fields: Vec<String>) -> Result<()> {
+        let extension = normalize_extension(CSV_EXTENSION);
+        self.get_file_entry(filename, extension)?.0.stats.rows += 1;
+        self.database()?.start_row(filename, fields);
+        Ok(())
+    }
+
+    /// Encode `fields` as the members of a JSON object, keyed by `filename`'s columns, without
+    /// the closing brace. Fields past the columns are keyed `col_N` by their position.
+    ///
+    /// Rows printed without a form filter lead with a `form` member, as CSV rows lead with a
+    /// `form` column. Values are written as strings; typed values would be encoded here.
+    fn encode_json(&self, filename: &str, fields: &[String]) -> Result<Vec<u8>> {
+        let mut buffer = vec![b'{'];
+        if self
+            .print
+            .as_ref()
+            .is_some_and(|print| print.form.is_none())
         {
-            let mut wtr = WriterBuilder::new()
-                .has_headers(false)
-                .from_writer(&mut buffer);
-            wtr.write_record(fields)?;
-            wtr.flush()?;
+            serde_json::to_writer(&mut buffer, PRINT_FORM_COLUMN)?;
+            buffer.push(b':');
+            serde_json::to_writer(&mut buffer, filename)?;
+        }
+        let keys = self.row_columns.get(filename);
+        for (index, field) in fields.iter().enumerate() {
+            if buffer.len() > 1 {
+                buffer.push(b',');
+            }
+            serde_json::to_writer(&mut buffer, &column_name(keys, index))?;
+            buffer.push(b':');
+            serde_json::to_writer(&mut buffer, field)?;
         }
+        Ok(buffer)
+    }
 
-        let extension = CSV_EXTENSION;
-        if self.local_mode {
-            let line = String::from_utf8_lossy(&buffer);
+    /// Write CSV-encoded row bytes to `filename`, or to the print target in print mode.
+    ///
+    /// - `continued`: whether `bytes` continue a row already started.
+    fn write_row_bytes(
+        &mut self,
+        filename: &str,
+        bytes: &[u8],
+        header: bool,
+        continued: bool,
+    ) -> Result<()> {
+        let extension = normalize_extension(CSV_EXTENSION);
+        if !header && !continued && !self.local_mode {
+            if self.print.is_none() {
+                self.split_if_full(filename, bytes.len())?;
+            }
+            self.get_file_entry(filename, extension)?.0.stats.rows += 1;
+        }
+        let bare = continued || self.format == OutputFormat::Ndjson;
+        if let Some(print) = &mut self.print {
+            let (delimiter, quote_style) = (self.output_delimiter, self.quote_style);
+            print.write_row(filename, bytes, header, bare, delimiter, quote_style)?;
+            self.get_file_entry(filename, extension)?.0.stats.bytes += bytes.len() as u64;
+        } else if self.local_mode {
+            let line = String::from_utf8_lossy(bytes);
             self.local_buffer.push_str(&line);
             self.local_buffer_pos += line.len();
         } else {
-            // Trim the '.' from CSV_EXTENSION when passing to write_bytes
-            let trimmed_extension = extension.trim_start_matches('.');
-            self.write_bytes(filename, trimmed_extension, &buffer)?;
+            if header {
+                let (entry, _) = self.get_file_entry(filename, extension)?;
+                entry.header_row = Some(bytes.to_vec());
+            }
+            self.write_bytes(filename, extension, bytes)?;
+            // Also handle custom line accumulation
+            if self.custom_line_fn.is_some() {
+                let line = String::from_utf8_lossy(bytes);
+                self.push_line(filename, extension, &line)?;
+            }
         }
         Ok(())
     }
 }
 
+/// The JSON key of the field at `index` of a row: its column name, or `col_N` (1-based) past the
+/// columns.
+fn column_name(keys: Option<&Vec<String>>, index: usize) -> String {
+    keys.and_then(|keys| keys.get(index))
+        .cloned()
+        .unwrap_or_else(|| format!("col_{}", index + 1))
+}
+
+/// The size a `RowEncoder`'s buffer starts at, in bytes; it doubles whenever a row outgrows it.
+const ROW_ENCODER_BUFFER_SIZE: usize = 1024;
+
+/// Encodes CSV rows as the `csv` crate's writer does, into a buffer kept from one row to the
+/// next instead of a writer and buffer allocated per row.
+#[derive(Default)]
+struct RowEncoder {
+    core: csv_core::Writer,
+    buffer: Vec<u8>, // Always at its full length; only `..len` holds the row
+    len: usize,
+    delimiter: u8,
+    terminator: &'static [u8],
+    never_quote: bool, // Whether fields are written unquoted (`QuoteStyle::Never`)
+    replacement: Option<char>, // What unquoted fields' delimiters and line breaks become
+    null_string: Option<String>, // What empty fields are written as, bypassing quoting
+}
+
+impl RowEncoder {
+    /// An encoder of rows whose fields are separated by `delimiter`, quoted as `quote_style`
+    /// says and ended by `terminator`. Under `QuoteStyle::Never`, the delimiter and line breaks
+    /// in fields are replaced with `replacement`, or fail the row if it is `None`. Empty fields
+    /// are written as `null_string`, unquoted, if set.
+    fn new(
+        delimiter: u8,
+        terminator: Terminator,
+        quote_style: QuoteStyle,
+        replacement: Option<char>,
+        null_string: Option<String>,
+    ) -> Self {
+        let core_terminator = match terminator {
+            Terminator::Lf => csv_core::Terminator::Any(b'\n'),
+            Terminator::Crlf => csv_core::Terminator::CRLF,
+        };
+        let core_style = match quote_style {
+            QuoteStyle::Always => csv_core::QuoteStyle::Always,
+            QuoteStyle::Necessary => csv_core::QuoteStyle::Necessary,
+            QuoteStyle::Never => csv_core::QuoteStyle::Never,
+        };
+        Self {
+            core: csv_core::WriterBuilder::new()
+                .delimiter(delimiter)
+                .terminator(core_terminator)
+                .quote_style(core_style)
+                .build(),
+            buffer: vec![0; ROW_ENCODER_BUFFER_SIZE],
+            len: 0,
+            delimiter,
+            never_quote: quote_style == QuoteStyle::Never,
+            replacement,
+            null_string,
+            terminator: terminator.as_str().as_bytes(),
+        }
+    }
+
+    /// Encode `fields` as one CSV row, line break included.
+    fn encode(&mut self, fields: &[String]) -> Result<&[u8]> {
+        self.len = 0;
+        if let (Some(null_string), [field]) = (&self.null_string, fields) {
+            if field.is_empty() {
+                // Written whole, since the writer would quote a row's only field if empty
+                push(&mut self.buffer, &mut self.len, null_string.as_bytes());
+                push(&mut self.buffer, &mut self.len, self.terminator);
+                return Ok(&self.buffer[..self.len]);
+            }
+        }
+        for (index, field) in fields.iter().enumerate() {
+            if index > 0 {
+                self.write(|core, out| core.delimiter(out));
+            }
+            if let Some(null_string) = self.null_string.as_ref().filter(|_| field.is_empty()) {
+                push(&mut self.buffer, &mut self.len, null_string.as_bytes());
+                continue;
+            }
+            let field = if self.never_quote {
+                self.unquoted(field)?
+            } else {
+                Cow::Borrowed(field.as_str())
+            };
+            let mut input = field.as_bytes();
+            self.write(|core, out| {
+                let (result, read, written) = core.field(input, out);
+                input = &input[read..];
+                (result, written)
+            });
+        }
+        self.write(|core, out| core.terminator(out));
+        Ok(&self.buffer[..self.len])
+    }
+
+    /// `field` as it can be written unquoted: with the delimiter and line breaks replaced, or an
+    /// error if it holds any and there is no replacement.
+    fn unquoted<'a>(&self, field: &'a str) -> Result<Cow<'a, str>> {
+        let delimiter = char::from(self.delimiter);
+        let unquotable = |c: char| c == delimiter || c == '\n' || c == '\r';
+        if !field.contains(unquotable) {
+            return Ok(Cow::Borrowed(field));
+        }
+        match self.replacement {
+            Some(replacement) => Ok(Cow::Owned(
+                field.replace(unquotable, &replacement.to_string()),
+            )),
+            None => Err(anyhow!(
+                "Field {:?} holds the delimiter or a line break, which can't be written unquoted \
+                 (set a replacement to write it)",
+                field
+            )),
+        }
+    }
+
+    /// Run `step` on the free part of the buffer, growing it until `step` runs out of input.
+    fn write(
+        &mut self,
+        mut step: impl FnMut(&mut csv_core::Writer, &mut [u8]) -> (csv_core::WriteResult, usize),
+    ) {
+        loop {
+            let (result, written) = step(&mut self.core, &mut self.buffer[self.len..]);
+            self.len += written;
+            match result {
+                csv_core::WriteResult::InputEmpty => return,
+                csv_core::WriteResult::OutputFull => {
+                    let size = (self.buffer.len() * 2).max(ROW_ENCODER_BUFFER_SIZE);
+                    self.buffer.resize(size, 0);
+                }
+            }
+        }
+    }
+}
+
+/// Append `bytes` to the row in `buffer[..len]`, growing the buffer as `RowEncoder::write` does.
+fn push(buffer: &mut Vec<u8>, len: &mut usize, bytes: &[u8]) {
+    let end = *len + bytes.len();
+    if end > buffer.len() {
+        buffer.resize(end.max(buffer.len() * 2).max(ROW_ENCODER_BUFFER_SIZE), 0);
+    }
+    buffer[*len..end].copy_from_slice(bytes);
+    *len = end;
+}
+
+/// The temporary path `path` is written to with atomic writes.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(TEMP_SUFFIX);
+    PathBuf::from(temp)
+}
+
+/// Flushes what is still buffered and closes every file on a best-effort basis, only logging a
+/// failure: call `close` (or `flush_all`) to learn whether everything was written.
 impl Drop for WriterContext {
     fn drop(&mut self) {
-        if let Err(e) = self.flush_all() {
-            #[cfg(debug_assertions)]
-            panic!("Error during WriterContext drop: {}", e);
-            #[cfg(not(debug_assertions))]
-            eprintln!("Error during WriterContext drop: {}", e);
+        // Temporary files never finalized hold incomplete output, so they aren't flushed
+        if self.temp_files.is_empty() {
+            let mut failed = self.flush_files(true);
+            let flushed = self.flush_outputs().and_then(|_| self.sync_directories());
+            self.notify_file_closes(&mut failed);
+            if let Err(e) = flushed.and_then(|_| incomplete_output(&failed)) {
+                eprintln!("Warning: output may be incomplete: {:#}", e);
+            }
+        }
+        // They are removed instead, as is a manifest the flush rewrote after `finalize`
+        if !self.temp_files.is_empty() {
+            self.open_files.clear();
+            for (temp, _) in &self.temp_files {
+                let _ = std::fs::remove_file(temp);
+            }
         }
     }
 }
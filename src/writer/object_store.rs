@@ -0,0 +1,200 @@
+//! `ObjectStoreSinkFactory`: writes each output file to an object in S3 or Google Cloud Storage
+//! (`object_store` feature), with a multipart upload, instead of to disk.
+//!
+//! ```no_run
+//! use fast_fec_rust::writer::{ObjectStoreSinkFactory, WriterContext};
+//!
+//! // Credentials and the region come from the usual AWS_* environment variables
+//! let mut writer = WriterContext::builder()
+//!     .filing_id("12345")
+//!     .sink_factory(ObjectStoreSinkFactory::from_url("s3://bucket/fec")?)
+//!     .build()?;
+//! writer.write_csv_record("SA", &["SA11AI".to_string()])?;
+//! // Uploads s3://bucket/fec/12345/SA.csv
+//! writer.close()?.check()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use ::object_store::aws::AmazonS3Builder;
+use ::object_store::gcp::GoogleCloudStorageBuilder;
+use ::object_store::path::Path as ObjectPath;
+use ::object_store::{ObjectStore, WriteMultipart};
+use anyhow::{anyhow, Result};
+use tokio::runtime::Runtime;
+
+use super::sink::OBJECT_STORE_SCHEMES;
+use super::{OutputSink, SinkFactory};
+
+/// How many bytes of a file are uploaded at a time, as one part of its multipart upload.
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// How many parts of a file are uploaded at once before writing it waits for one to finish.
+const MAX_CONCURRENT_PARTS: usize = 4;
+
+/// Creates a sink for each output file that uploads it to an object under a prefix, named for
+/// the file's path: `s3://bucket/prefix/<filing_id>/<form>.csv`.
+///
+/// Each file is uploaded in parts of `DEFAULT_PART_SIZE` bytes as it is written, and its
+/// upload completed as it is closed (`WriterContext::close`, or the next filing started); the
+/// object only appears then. `flush_all` waits for the parts being uploaded, but can't complete
+/// the upload of a file that may still be written to. A file that isn't closed, e.g. after an
+/// error, has its upload aborted.
+///
+/// Uploads run on a runtime of the factory's own, so the writer mustn't be used from within
+/// another async runtime's tasks.
+pub struct ObjectStoreSinkFactory {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+    part_size: usize,
+    runtime: Arc<Runtime>,
+}
+
+impl ObjectStoreSinkFactory {
+    /// Upload files to `store`, under `prefix`.
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: ObjectPath) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(MAX_CONCURRENT_PARTS)
+            .enable_all()
+            .build()
+            .map_err(|e| anyhow!("Failed to start the upload runtime: {}", e))?;
+        Ok(Self {
+            store,
+            prefix,
+            part_size: DEFAULT_PART_SIZE,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    /// Upload files to the bucket and prefix of `url`, such as `s3://bucket/prefix` or
+    /// `gs://bucket/prefix`, with credentials and settings from the environment (`AWS_*` or
+    /// `GOOGLE_*` variables).
+    pub fn from_url(url: &str) -> Result<Self> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| anyhow!("Invalid object store URL {:?}", url))?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return Err(anyhow!("The object store URL {:?} names no bucket", url));
+        }
+        let store: Arc<dyn ObjectStore> = match scheme {
+            "s3" => Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            "gs" => Arc::new(
+                GoogleCloudStorageBuilder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            _ => {
+                return Err(anyhow!(
+                    "Unknown object store URL scheme {:?} (expected {})",
+                    scheme,
+                    OBJECT_STORE_SCHEMES.map(|s| format!("{}://", s)).join(", ")
+                ))
+            }
+        };
+        let prefix = ObjectPath::parse(prefix.trim_end_matches('/'))
+            .map_err(|e| anyhow!("Invalid object store URL {:?}: {}", url, e))?;
+        Self::new(store, prefix)
+    }
+
+    /// Upload files in parts of `bytes` bytes instead of `DEFAULT_PART_SIZE`. Stores have limits
+    /// of their own: S3 needs parts of 5 MiB at least.
+    pub fn with_part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(1);
+        self
+    }
+
+    /// The object a file at `path` relative to the output directory is uploaded to.
+    fn location(&self, path: &Path) -> ObjectPath {
+        let parts = path
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned().into());
+        self.prefix.parts().chain(parts).collect()
+    }
+}
+
+impl SinkFactory for ObjectStoreSinkFactory {
+    fn create(&self, _: &str, _: &str, path: &Path) -> Result<Box<dyn OutputSink>> {
+        let location = self.location(path);
+        let upload = self
+            .runtime
+            .block_on(self.store.put_multipart(&location))
+            .map_err(|e| anyhow!("Failed to start uploading {}: {}", location, e))?;
+        Ok(Box::new(ObjectSink {
+            upload: Some(WriteMultipart::new_with_chunk_size(upload, self.part_size)),
+            location,
+            runtime: self.runtime.clone(),
+        }))
+    }
+}
+
+/// The multipart upload of one output file.
+struct ObjectSink {
+    upload: Option<WriteMultipart>, // Until completed by `close`
+    location: ObjectPath,
+    runtime: Arc<Runtime>,
+}
+
+impl ObjectSink {
+    fn upload(&mut self) -> io::Result<&mut WriteMultipart> {
+        let location = &self.location;
+        self.upload
+            .as_mut()
+            .ok_or_else(|| io::Error::other(format!("The upload of {} is complete", location)))
+    }
+}
+
+impl Write for ObjectSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let runtime = self.runtime.clone();
+        let upload = self.upload()?;
+        // Full parts are uploaded by tasks spawned on the runtime
+        let _guard = runtime.enter();
+        upload.write(buf);
+        runtime
+            .block_on(upload.wait_for_capacity(MAX_CONCURRENT_PARTS))
+            .map_err(io::Error::other)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl OutputSink for ObjectSink {
+    fn finalize(&mut self) -> Result<()> {
+        let runtime = self.runtime.clone();
+        let upload = self.upload()?;
+        runtime
+            .block_on(upload.wait_for_capacity(0))
+            .map_err(|e| anyhow!("Failed to upload {}: {}", self.location, e))
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let upload = self
+            .upload
+            .take()
+            .ok_or_else(|| anyhow!("The upload of {} is already complete", self.location))?;
+        self.runtime
+            .block_on(upload.finish())
+            .map_err(|e| anyhow!("Failed to upload {}: {}", self.location, e))?;
+        Ok(())
+    }
+}
+
+/// A file not closed holds incomplete output, which isn't left in the store.
+impl Drop for ObjectSink {
+    fn drop(&mut self) {
+        if let Some(upload) = self.upload.take() {
+            let _ = self.runtime.block_on(upload.abort());
+        }
+    }
+}
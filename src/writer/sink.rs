@@ -0,0 +1,97 @@
+//! `OutputSink` and `SinkFactory`: where the bytes of each output file go, for output that
+//! isn't written to files on disk (memory, a remote store, another format).
+//!
+//! ```
+//! use std::io::Cursor;
+//! use fast_fec_rust::writer::{OutputSink, WriterContext};
+//!
+//! struct MemorySink(Cursor<Vec<u8>>);
+//!
+//! impl std::io::Write for MemorySink {
+//!     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+//!         self.0.write(buf)
+//!     }
+//!     fn flush(&mut self) -> std::io::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! impl OutputSink for MemorySink {
+//!     fn finalize(&mut self) -> anyhow::Result<()> {
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let writer = WriterContext::builder()
+//!     .filing_id("12345")
+//!     .sink_factory(|_: &str, _: &str, _: &std::path::Path| {
+//!         Ok(Box::new(MemorySink(Cursor::new(Vec::new()))) as Box<dyn OutputSink>)
+//!     })
+//!     .build()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Where the bytes of one output file are written, in place of a file on disk.
+///
+/// Rows are written to it as each file's buffer fills up, through `Write`.
+pub trait OutputSink: Write + Send {
+    /// Complete what has been written so far, e.g. finish a compressed stream or upload what is
+    /// buffered: called by `WriterContext::flush_all`. More may be written after it.
+    fn finalize(&mut self) -> Result<()>;
+
+    /// Complete the file once nothing more will be written to it, e.g. complete an upload:
+    /// called once, as the file is closed. By default, `finalize`.
+    ///
+    /// A sink dropped without being closed holds incomplete output, e.g. after an error, and may
+    /// discard it.
+    fn close(&mut self) -> Result<()> {
+        self.finalize()
+    }
+
+    /// Commit what has been written to lasting storage, for `WriterContext::with_sync_on_close`.
+    /// Does nothing by default.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates the `OutputSink` of each output file, in place of opening a file on disk; see
+/// `WriterContext::with_sink_factory`.
+///
+/// A closure taking the same arguments as `create` is a factory too.
+pub trait SinkFactory: Send + Sync {
+    /// Create the sink of `filename`'s file with `extension` (e.g. `SA` and `csv`), at `path`
+    /// relative to the output directory as `WriterContext::file_stats` lists it (e.g.
+    /// `12345/SA.csv`, or the path of a part of a split file).
+    ///
+    /// Called once per path, unless a later filing writes to the same path (under a filename
+    /// template without `{filing_id}`): the new sink then continues the file, without a second
+    /// header row.
+    fn create(&self, filename: &str, extension: &str, path: &Path) -> Result<Box<dyn OutputSink>>;
+}
+
+/// The URL schemes of object stores the output directory can name (`s3://bucket/prefix`), with
+/// the `object_store` feature.
+pub const OBJECT_STORE_SCHEMES: [&str; 2] = ["s3", "gs"];
+
+/// Whether `output_directory` names an object store rather than a directory, e.g.
+/// `s3://bucket/prefix`; see `ObjectStoreSinkFactory`.
+pub fn is_object_store_url(output_directory: &str) -> bool {
+    output_directory
+        .split_once("://")
+        .is_some_and(|(scheme, _)| OBJECT_STORE_SCHEMES.contains(&scheme))
+}
+
+impl<F> SinkFactory for F
+where
+    F: Fn(&str, &str, &Path) -> Result<Box<dyn OutputSink>> + Send + Sync,
+{
+    fn create(&self, filename: &str, extension: &str, path: &Path) -> Result<Box<dyn OutputSink>> {
+        self(filename, extension, path)
+    }
+}
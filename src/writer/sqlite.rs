@@ -0,0 +1,207 @@
+//! SQLite output (`OutputFormat::Sqlite`): rows go to one table per form in a database instead
+//! of one file per form.
+//!
+//! Tables are named like the files would be (`SA`, `F3XN`, `header`) and created with the
+//! columns of the first row written to them, typed from the row's FastFEC type codes: `REAL` for
+//! amounts, `DATE` for dates and `TEXT` otherwise. Columns later rows need that a table lacks,
+//! e.g. in a database appended to by filings of another version, are added to it. Inserts are
+//! grouped into transactions of `ROWS_PER_TRANSACTION` rows.
+//!
+//! Without the `sqlite` feature, opening a database fails.
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+
+#[cfg(feature = "sqlite")]
+use rusqlite::{types::Value, Connection};
+use std::collections::HashMap;
+
+/// How many rows are inserted in one transaction before it is committed.
+pub const ROWS_PER_TRANSACTION: usize = 5000;
+
+/// A database rows are written to, one table per form.
+#[cfg(feature = "sqlite")]
+pub struct SqliteWriter {
+    conn: Connection,
+    tables: HashMap<String, Vec<String>>, // Columns of each table written to, as in the database
+    pending: Option<(String, Vec<String>)>, // Table and fields of the row waiting for its types
+    rows_in_transaction: usize,           // Rows inserted since the open transaction began
+}
+
+/// Without the `sqlite` feature there is no database to write to.
+#[cfg(not(feature = "sqlite"))]
+pub enum SqliteWriter {}
+
+#[cfg(feature = "sqlite")]
+impl SqliteWriter {
+    /// Open the database at `path`, creating it if needed. Tables already in it are appended to.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)
+            .map_err(|e| anyhow!("Failed to open database {}: {}", path.display(), e))?;
+        Ok(Self {
+            conn,
+            tables: HashMap::new(),
+            pending: None,
+            rows_in_transaction: 0,
+        })
+    }
+
+    /// Start a row of `fields` in `table`, inserted once `end_row` gives its types.
+    pub fn start_row(&mut self, table: &str, fields: Vec<String>) {
+        self.pending = Some((table.to_string(), fields));
+    }
+
+    /// Append `piece` to the last field of the row started with `start_row`.
+    pub fn append_to_row(&mut self, piece: &str) {
+        if let Some(field) = self
+            .pending
+            .as_mut()
+            .and_then(|(_, fields)| fields.last_mut())
+        {
+            field.push_str(piece);
+        }
+    }
+
+    /// Insert the row started with `start_row`, if any, under its table's entry in `columns`
+    /// (fields past them are named `col_N`), typing new columns from `types`, one FastFEC type
+    /// code per field.
+    pub fn end_row(&mut self, columns: &HashMap<String, Vec<String>>, types: &str) -> Result<()> {
+        let Some((table, fields)) = self.pending.take() else {
+            return Ok(());
+        };
+        let types: Vec<char> = types.chars().collect();
+        let names: Vec<String> = (0..fields.len())
+            .map(|index| super::column_name(columns.get(&table), index))
+            .collect();
+        self.ensure_columns(&table, &names, &types)?;
+
+        if self.rows_in_transaction == 0 {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        let sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            quote(&table),
+            names
+                .iter()
+                .map(|n| quote(n))
+                .collect::<Vec<_>>()
+                .join(", "),
+            vec!["?"; names.len()].join(", ")
+        );
+        let values = fields.into_iter().enumerate().map(|(index, field)| {
+            match types.get(index).copied().unwrap_or('s') {
+                's' => Value::Text(field),
+                _ if field.is_empty() => Value::Null,
+                'f' => field
+                    .parse::<f64>()
+                    .map_or_else(|_| Value::Text(field), Value::Real),
+                _ => Value::Text(field),
+            }
+        });
+        self.conn
+            .prepare_cached(&sql)?
+            .execute(rusqlite::params_from_iter(values))
+            .map_err(|e| anyhow!("Failed to insert a row into {}: {}", table, e))?;
+
+        self.rows_in_transaction += 1;
+        if self.rows_in_transaction >= ROWS_PER_TRANSACTION {
+            self.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Commit the rows inserted since the last commit.
+    pub fn commit(&mut self) -> Result<()> {
+        if self.rows_in_transaction > 0 {
+            self.conn.execute_batch("COMMIT")?;
+            self.rows_in_transaction = 0;
+        }
+        Ok(())
+    }
+
+    /// Create `table` or add the columns of `names` it lacks, typed by `types`.
+    fn ensure_columns(&mut self, table: &str, names: &[String], types: &[char]) -> Result<()> {
+        if self
+            .tables
+            .get(table)
+            .is_some_and(|columns| names.iter().all(|name| columns.contains(name)))
+        {
+            return Ok(());
+        }
+
+        let definitions: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| column_definition(name, types.get(index).copied()))
+            .collect();
+        self.conn.execute_batch(&format!(
+            "CREATE TABLE IF NOT EXISTS {} ({})",
+            quote(table),
+            definitions.join(", ")
+        ))?;
+        let mut columns: Vec<String> = self
+            .conn
+            .prepare(&format!("PRAGMA table_info({})", quote(table)))?
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (name, definition) in names.iter().zip(&definitions) {
+            if !columns.contains(name) {
+                self.conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {}",
+                    quote(table),
+                    definition
+                ))?;
+                columns.push(name.clone());
+            }
+        }
+        self.tables.insert(table.to_string(), columns);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+impl SqliteWriter {
+    /// Fail: writing a database requires the `sqlite` feature.
+    pub fn open(path: &Path) -> Result<Self> {
+        Err(anyhow!(
+            "Writing {} requires building with the `sqlite` feature",
+            path.display()
+        ))
+    }
+
+    pub fn start_row(&mut self, _table: &str, _fields: Vec<String>) {
+        match *self {}
+    }
+
+    pub fn append_to_row(&mut self, _piece: &str) {
+        match *self {}
+    }
+
+    pub fn end_row(&mut self, _columns: &HashMap<String, Vec<String>>, _types: &str) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        match *self {}
+    }
+}
+
+/// Quote `name` as an SQL identifier.
+#[cfg(feature = "sqlite")]
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// The definition of column `name` for values of FastFEC type code `code`.
+#[cfg(feature = "sqlite")]
+fn column_definition(name: &str, code: Option<char>) -> String {
+    let affinity = match code {
+        Some('f') => "REAL",
+        Some('d') => "DATE",
+        _ => "TEXT",
+    };
+    format!("{} {}", quote(name), affinity)
+}
@@ -0,0 +1,155 @@
+//! `SyncWriter`: a writer that threads share, for writing rows parsed in parallel.
+//!
+//! Each output file gets a `WriterContext` of its own, made on first use and guarded by its own
+//! lock, so threads writing different files never wait on each other, and a row is always
+//! written whole before another thread's row to the same file starts. The map from file names
+//! to writers is only locked for writing when a file is first seen.
+//!
+//! ```no_run
+//! use fast_fec_rust::writer::{SyncWriter, WriterContext};
+//!
+//! let writer = SyncWriter::new(|| {
+//!     WriterContext::builder()
+//!         .output_directory("output")
+//!         .filing_id("12345")
+//!         .build()
+//! });
+//! std::thread::scope(|scope| {
+//!     for thread in 0..4 {
+//!         let writer = &writer;
+//!         scope.spawn(move || writer.write_csv_record("SA", &[format!("SA11AI.{thread}")]));
+//!     }
+//! });
+//! writer.flush_all()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+use anyhow::{anyhow, Result};
+
+use super::{FileStats, WriterContext};
+
+/// Makes the writer of a file a `SyncWriter` writes to for the first time.
+type NewWriterFn = dyn Fn() -> Result<WriterContext> + Send + Sync;
+
+/// A writer `write_csv_record`, `write_string` and `flush_all` can be called on from any
+/// number of threads at once, locking only the file being written.
+///
+/// Every file is written by its own `WriterContext`, from the function given to `new`; custom
+/// callbacks of those writers are called from whichever thread writes a row. Since each file's
+/// writer is separate, print mode and SQLite output, which every file shares, are better written
+/// from one thread with a plain `WriterContext`.
+pub struct SyncWriter {
+    files: RwLock<HashMap<String, Arc<Mutex<WriterContext>>>>,
+    new_writer: Box<NewWriterFn>,
+}
+
+impl SyncWriter {
+    /// Create a writer whose files are each written by a `WriterContext` from `new_writer`,
+    /// called the first time a file is written to.
+    pub fn new(new_writer: impl Fn() -> Result<WriterContext> + Send + Sync + 'static) -> Self {
+        Self {
+            files: RwLock::new(HashMap::new()),
+            new_writer: Box::new(new_writer),
+        }
+    }
+
+    /// Write `columns` as the header row of `filename`'s file, unless it already has one; see
+    /// `WriterContext::write_header_once`.
+    pub fn write_header_once(&self, filename: &str, columns: &[String]) -> Result<bool> {
+        self.with_writer(filename, |writer| {
+            writer.write_header_once(filename, columns)
+        })
+    }
+
+    /// Write `fields` as one row of `filename`'s file, ended as a line of strings.
+    pub fn write_csv_record(&self, filename: &str, fields: &[String]) -> Result<()> {
+        self.write_row(filename, fields, &"s".repeat(fields.len()))
+    }
+
+    /// Write `fields` as one row of `filename`'s file, ended as a line of `types`, one FastFEC
+    /// type code per field (see `WriterContext::end_line`).
+    pub fn write_row(&self, filename: &str, fields: &[String], types: &str) -> Result<()> {
+        self.with_writer(filename, |writer| {
+            writer.write_csv_record(filename, fields)?;
+            writer.end_line(types)
+        })
+    }
+
+    /// Write `s` to `filename`'s file with `extension`; see `WriterContext::write_string`.
+    pub fn write_string(&self, filename: &str, extension: &str, s: &str) -> Result<()> {
+        self.with_writer(filename, |writer| {
+            writer.write_string(filename, extension, s)
+        })
+    }
+
+    /// Flush every file written so far; see `WriterContext::flush_all`. Threads writing
+    /// meanwhile only wait for the file being flushed, and their rows either make it into this
+    /// flush or stay buffered for the next.
+    pub fn flush_all(&self) -> Result<()> {
+        for writer in self.writers()? {
+            lock(&writer)?.flush_all()?;
+        }
+        Ok(())
+    }
+
+    /// The rows and bytes written to each file, as `WriterContext::file_stats` lists them.
+    pub fn file_stats(&self) -> Result<Vec<(String, FileStats)>> {
+        let mut stats = Vec::new();
+        for writer in self.writers()? {
+            stats.extend(lock(&writer)?.file_stats());
+        }
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(stats)
+    }
+
+    /// The writers of the files written so far.
+    fn writers(&self) -> Result<Vec<Arc<Mutex<WriterContext>>>> {
+        let files = self.files.read().map_err(|_| poisoned())?;
+        Ok(files.values().cloned().collect())
+    }
+
+    /// Run `f` on the writer of `filename`, locked, making it if the file hasn't been written
+    /// to yet.
+    fn with_writer<T>(
+        &self,
+        filename: &str,
+        f: impl FnOnce(&mut WriterContext) -> Result<T>,
+    ) -> Result<T> {
+        let existing = self
+            .files
+            .read()
+            .map_err(|_| poisoned())?
+            .get(filename)
+            .cloned();
+        let writer = match existing {
+            Some(writer) => writer,
+            None => {
+                let mut files = self.files.write().map_err(|_| poisoned())?;
+                // Another thread may have made it while the map was unlocked
+                match files.get(filename) {
+                    Some(writer) => writer.clone(),
+                    None => {
+                        let writer = Arc::new(Mutex::new((self.new_writer)()?));
+                        files.insert(filename.to_string(), writer.clone());
+                        writer
+                    }
+                }
+            }
+        };
+        let mut writer = lock(&writer)?;
+        f(&mut writer)
+    }
+}
+
+/// Lock `writer`, failing if a thread panicked while holding it.
+fn lock(writer: &Mutex<WriterContext>) -> Result<MutexGuard<'_, WriterContext>> {
+    writer.lock().map_err(|_| poisoned())
+}
+
+/// The error of a lock a panicking thread left poisoned.
+fn poisoned() -> anyhow::Error {
+    anyhow!("A thread panicked while writing output")
+}
@@ -0,0 +1,206 @@
+//! `FilenameTemplate`: where output files go under the output directory, as a pattern of
+//! placeholders instead of the default `<filing_id>/<form>.<extension>` layout.
+//!
+//! ```
+//! use fast_fec_rust::writer::FilenameTemplate;
+//!
+//! let template: FilenameTemplate = "{form}/{filing_id}.csv".parse()?;
+//! assert_eq!(template.expand("12345", "SA", "csv.gz").to_str(), Some("SA/12345.csv.gz"));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use super::sanitize_filename;
+
+/// The placeholders a filename template can hold, each written in braces (`{form}`).
+pub const FILENAME_PLACEHOLDERS: [&str; 4] = ["filing_id", "form", "extension", "date"];
+
+/// A piece of a parsed template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    FilingId,
+    Form,
+    Extension,
+    Date,
+}
+
+/// The path of each output file relative to the output directory, expanded from a template
+/// such as `{filing_id}_{form}.csv` or `{form}/{filing_id}.csv`.
+///
+/// Placeholders expand to the filing ID, the file's form (`SA`, as `sanitize_filename` makes it),
+/// the file's extension on disk (`csv`, or `csv.gz` if compressed) and the date the template
+/// was parsed (`20240131`, UTC). A template without `{extension}` has the extension of its
+/// expansion replaced by the file's own, so `{filing_id}_{form}.csv` names compressed files
+/// `12345_SA.csv.gz` and F99 text files `12345_F99.txt`.
+///
+/// Templates are checked when parsed: only known placeholders, and no expansion that would be
+/// empty, absolute or reach outside the output directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    template: String,
+    pieces: Vec<Piece>,
+    date: String,
+}
+
+impl FilenameTemplate {
+    /// Expand the template into the path of `form`'s file of `filing_id` with `extension` (on
+    /// disk, without the leading `.`), relative to the output directory.
+    ///
+    /// The expansion isn't checked; `check` tells whether it makes a usable path.
+    pub fn expand(&self, filing_id: &str, form: &str, extension: &str) -> PathBuf {
+        let path = PathBuf::from(self.expand_placeholders(filing_id, form, extension));
+        match self.pieces.contains(&Piece::Extension) {
+            true => path,
+            false => path.with_extension(extension),
+        }
+    }
+
+    /// The template with its placeholders expanded.
+    fn expand_placeholders(&self, filing_id: &str, form: &str, extension: &str) -> String {
+        let mut expanded = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => expanded.push_str(text),
+                Piece::FilingId => expanded.push_str(filing_id),
+                Piece::Form => expanded.push_str(&sanitize_filename(form)),
+                Piece::Extension => expanded.push_str(extension),
+                Piece::Date => expanded.push_str(&self.date),
+            }
+        }
+        expanded
+    }
+
+    /// Fail unless the template expands to a usable path for `filing_id`: one with no empty
+    /// file or directory names, not absolute and staying inside the output directory.
+    pub fn check(&self, filing_id: &str) -> Result<()> {
+        let expanded = self.expand_placeholders(filing_id, "SA", "csv");
+        let path = Path::new(&expanded);
+        let invalid = |reason: &str| {
+            anyhow!(
+                "Invalid filename template {:?}: {} (it expands to {:?})",
+                self.template,
+                reason,
+                expanded
+            )
+        };
+        if path.has_root() {
+            return Err(invalid("it must be relative to the output directory"));
+        }
+        // A file name of just an extension (`.csv`) counts as empty
+        let file_name = expanded.rsplit('/').next().unwrap_or_default();
+        if expanded.split('/').any(|name| name.is_empty()) || file_name.starts_with('.') {
+            return Err(invalid("a file or directory name would be empty"));
+        }
+        for component in path.components() {
+            match component {
+                Component::Normal(_) => {}
+                Component::ParentDir | Component::CurDir => {
+                    return Err(invalid("it can't hold . or .. directories"))
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(invalid("it must be relative to the output directory"))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = anyhow::Error;
+
+    /// Parse a template such as `{filing_id}_{form}.csv`, failing on unknown placeholders,
+    /// unmatched braces and templates that expand to no usable path.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let Some(start) = rest.find(['{', '}']) else {
+                pieces.push(Piece::Text(rest.to_string()));
+                break;
+            };
+            if start > 0 {
+                pieces.push(Piece::Text(rest[..start].to_string()));
+            }
+            let end = match rest[start..].starts_with('{') {
+                true => rest[start..].find('}').map(|end| start + end),
+                false => None,
+            };
+            let Some(end) = end else {
+                return Err(anyhow!("Unmatched brace in filename template {:?}", s));
+            };
+            pieces.push(match &rest[start + 1..end] {
+                "filing_id" => Piece::FilingId,
+                "form" => Piece::Form,
+                "extension" => Piece::Extension,
+                "date" => Piece::Date,
+                name => {
+                    return Err(anyhow!(
+                        "Unknown placeholder {{{}}} in filename template {:?} (expected {})",
+                        name,
+                        s,
+                        FILENAME_PLACEHOLDERS
+                            .map(|name| format!("{{{}}}", name))
+                            .join(", ")
+                    ))
+                }
+            });
+            rest = &rest[end + 1..];
+        }
+        if pieces.is_empty() {
+            return Err(anyhow!("The filename template is empty"));
+        }
+        let template = FilenameTemplate {
+            template: s.to_string(),
+            pieces,
+            date: utc_date(SystemTime::now()),
+        };
+        template.check("12345")?;
+        Ok(template)
+    }
+}
+
+impl fmt::Display for FilenameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+/// `path` relative to the directory `dir`, both relative to the same directory: `..` for each
+/// of `dir`'s directories `path` isn't in.
+pub(crate) fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative: PathBuf = dir.components().skip(common).map(|_| "..").collect();
+    relative.extend(path.components().skip(common));
+    relative
+}
+
+/// The UTC date of `time` as `YYYYMMDD`.
+fn utc_date(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}{:02}{:02}", year, month, day)
+}
@@ -1,121 +1,14 @@
-use clap::{ArgAction, Command};
-use fast_fec_rust::cli::args::CliConfig;
-
-/// Helper function to create a Command instance identical to the one in `parse_args`.
-fn get_command() -> Command {
-    Command::new("fast-fec-rust")
-        .version("0.1.0")
-        .about("Rust port of FastFEC with no persistent memory context")
-        .arg(
-            clap::Arg::new("filing-id-or-file")
-                .help("Filing ID or file path")
-                .required(false)
-                .index(1),
-        )
-        .arg(
-            clap::Arg::new("include-filing-id")
-                .long("include-filing-id")
-                .short('f')
-                .help("Include a filing_id column in the output CSV")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("silent")
-                .long("silent")
-                .short('s')
-                .help("Suppress output messages")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("warn")
-                .long("warn")
-                .short('w')
-                .help("Show warning messages")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("disable-stdin")
-                .long("disable-stdin")
-                .help("Force reading from a file even if STDIN is piped")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("usage")
-                .long("usage")
-                .help("Show usage information")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("output-directory")
-                .long("output-directory")
-                .short('o')
-                .help("Specify the directory for output files (default: 'output')")
-                .default_value("output"),
-        )
-        .arg(
-            clap::Arg::new("write-to-disk")
-                .long("write-to-disk")
-                .help("Write output to disk (default: true)")
-                .action(ArgAction::SetTrue),
-        )
-        .arg(
-            clap::Arg::new("buffer-size")
-                .long("buffer-size")
-                .help("Set the buffer size for WriterContext (default: 4096)")
-                .default_value("4096"),
-        )
-}
-
-/// Helper function to simulate parse_args with given arguments.
+use fast_fec_rust::cli::args::{parse_args_from, CliConfig};
+use fast_fec_rust::encoding::FallbackEncoding;
+use fast_fec_rust::writer::{Compression, OutputFormat, OutputMode, QuoteStyle, Terminator};
+
+/// Helper function to run the real argument parser with STDIN treated as not piped.
 fn simulate_parse_args<I, T>(args: I) -> Result<CliConfig, anyhow::Error>
 where
     I: IntoIterator<Item = T>,
     T: Into<std::ffi::OsString> + Clone,
 {
-    let cmd = get_command();
-    let matches = cmd.try_get_matches_from(args)?;
-
-    let fec_id = matches
-        .get_one::<String>("filing-id-or-file")
-        .cloned()
-        .unwrap_or_else(|| "".to_string());
-
-    let include_filing_id = matches.get_flag("include-filing-id");
-    let silent = matches.get_flag("silent");
-    let warn = matches.get_flag("warn");
-    let disable_stdin = matches.get_flag("disable-stdin");
-    let show_usage = matches.get_flag("usage");
-    let output_directory = matches
-        .get_one::<String>("output-directory")
-        .cloned()
-        .unwrap_or_else(|| "output".to_string());
-    let write_to_disk = matches.get_flag("write-to-disk");
-
-    let buffer_size = matches
-        .get_one::<String>("buffer-size")
-        .map(|s| s.parse::<usize>())
-        .transpose() // Handle potential parse errors
-        .map_err(|_| anyhow::anyhow!("Invalid buffer size"))? // Return error for invalid input
-        .unwrap_or(4096); // Default value if not provided
-
-    let stdin_piped = false;
-    let use_stdin = stdin_piped && !disable_stdin && fec_id.is_empty();
-
-    Ok(CliConfig {
-        fec_id: if use_stdin && fec_id.is_empty() {
-            "STDIN_DATA".to_string()
-        } else {
-            fec_id
-        },
-        include_filing_id,
-        silent,
-        warn,
-        use_stdin,
-        show_usage,
-        output_directory,
-        write_to_disk,
-        buffer_size,
-    })
+    parse_args_from(args, false)
 }
 
 #[test]
@@ -133,6 +26,7 @@ fn test_no_arguments() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -153,6 +47,7 @@ fn test_with_filing_id() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -173,6 +68,7 @@ fn test_include_filing_id_flag() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -193,6 +89,7 @@ fn test_silent_flag() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -213,6 +110,7 @@ fn test_warn_flag() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -233,6 +131,7 @@ fn test_disable_stdin_flag() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -253,6 +152,7 @@ fn test_usage_flag() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -273,6 +173,7 @@ fn test_output_directory_custom_value() {
         output_directory: "custom_dir".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -293,6 +194,7 @@ fn test_write_to_disk_flag() {
         output_directory: "output".to_string(),
         write_to_disk: true,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -313,6 +215,7 @@ fn test_custom_buffer_size() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 8192,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -324,7 +227,414 @@ fn test_invalid_buffer_size() {
     let result = simulate_parse_args(args);
 
     assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("Invalid buffer size"));
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid buffer size"));
+}
+
+#[test]
+fn test_format_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--format", "ndjson"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.format, OutputFormat::Ndjson);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.format, OutputFormat::Csv);
+
+    let args = vec!["fast-fec-rust", "12345", "--format", "xml"];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid output format"));
+}
+
+#[test]
+fn test_output_delimiter_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--output-delimiter", "tab"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.output_delimiter, b'\t');
+
+    let args = vec!["fast-fec-rust", "12345", "--output-delimiter", ";"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.output_delimiter, b';');
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.output_delimiter, b',');
+
+    for invalid in ["::", "\"", "é"] {
+        let args = vec!["fast-fec-rust", "12345", "--output-delimiter", invalid];
+        let result = simulate_parse_args(args);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid output delimiter"));
+    }
+}
+
+#[test]
+fn test_crlf_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.terminator, Terminator::Lf);
+
+    let args = vec!["fast-fec-rust", "--crlf", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.terminator, Terminator::Crlf);
+}
+
+#[test]
+fn test_quote_style_flags() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.quote_style, QuoteStyle::Necessary);
+    assert_eq!(config.replace_with, None);
+
+    let args = vec!["fast-fec-rust", "--quote-style", "always", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.quote_style, QuoteStyle::Always);
+
+    let args = vec![
+        "fast-fec-rust",
+        "--quote-style",
+        "never",
+        "--replace-unquotable",
+        " ",
+        "12345",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.quote_style, QuoteStyle::Never);
+    assert_eq!(config.replace_with, Some(' '));
+
+    for args in [
+        vec!["fast-fec-rust", "--quote-style", "sometimes", "12345"],
+        vec!["fast-fec-rust", "--replace-unquotable", " ", "12345"],
+        vec![
+            "fast-fec-rust",
+            "--quote-style",
+            "never",
+            "--replace-unquotable",
+            ",",
+            "12345",
+        ],
+    ] {
+        let result = simulate_parse_args(args);
+        assert!(result.unwrap_err().to_string().starts_with("Invalid"));
+    }
+}
+
+#[test]
+fn test_null_string_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.null_token, None);
+
+    for token in ["NULL", "\\N"] {
+        let args = vec!["fast-fec-rust", "--null-string", token, "12345"];
+        let config = simulate_parse_args(args).expect("Failed to parse args");
+        assert_eq!(config.null_token.as_deref(), Some(token));
+    }
+
+    for invalid in ["", "A,B", "\"NULL\""] {
+        let args = vec!["fast-fec-rust", "--null-string", invalid, "12345"];
+        let result = simulate_parse_args(args);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid null string"));
+    }
+}
+
+#[test]
+fn test_manifest_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.manifest);
+
+    let args = vec!["fast-fec-rust", "--manifest", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.manifest);
+}
+
+#[test]
+fn test_max_per_file_flags() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.max_rows, None);
+    assert_eq!(config.max_bytes, None);
+
+    let args = vec![
+        "fast-fec-rust",
+        "--max-rows-per-file",
+        "10",
+        "--max-bytes-per-file",
+        "65536",
+        "12345",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.max_rows, Some(10));
+    assert_eq!(config.max_bytes, Some(65536));
+
+    for value in ["0", "1.5", "ten"] {
+        let args = vec!["fast-fec-rust", "--max-rows-per-file", value, "12345"];
+        assert!(simulate_parse_args(args)
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid max rows per file"));
+    }
+}
+
+#[test]
+fn test_filename_template_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.template, None);
+
+    let args = vec![
+        "fast-fec-rust",
+        "--filename-template",
+        "{filing_id}_{form}.csv",
+        "12345",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.template.as_deref(), Some("{filing_id}_{form}.csv"));
+
+    // Rejected before anything is parsed
+    let args = vec!["fast-fec-rust", "--filename-template", "{id}.csv", "12345"];
+    assert!(simulate_parse_args(args)
+        .unwrap_err()
+        .to_string()
+        .contains("Unknown placeholder {id}"));
+}
+
+#[test]
+fn test_fsync_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.fsync);
+
+    let args = vec!["fast-fec-rust", "--fsync", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.fsync);
+}
+
+#[test]
+fn test_combined_flags() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.combined);
+    assert!(!config.ragged);
+
+    let args = vec!["fast-fec-rust", "--combined", "--ragged", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.combined);
+    assert!(config.ragged);
+
+    let args = vec!["fast-fec-rust", "--combined", "--print", "12345"];
+    assert!(simulate_parse_args(args).is_err());
+}
+
+#[test]
+fn test_min_free_space_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.min_free, None);
+
+    let args = vec!["fast-fec-rust", "--min-free-space", "1048576", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.min_free, Some(1048576));
+
+    let args = vec!["fast-fec-rust", "--min-free-space", "1MB", "12345"];
+    assert!(simulate_parse_args(args).is_err());
+}
+
+#[test]
+fn test_unwritable_output_fails_before_reading_input() {
+    // The filing's output directory can't be created where a file already is
+    let dir = tempfile::tempdir().expect("Failed to create output directory");
+    let blocked = dir.path().join("blocked");
+    std::fs::write(&blocked, "").expect("Failed to create file");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_fast-fec-rust"))
+        .args(["missing.fec", "--write-to-disk", "--output-directory"])
+        .arg(&blocked)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run the binary");
+
+    // The input, which doesn't exist, was never opened
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("can't create the output directory"),
+        "{stderr}"
+    );
+    assert!(stderr.contains(&blocked.display().to_string()), "{stderr}");
+    assert!(!stderr.contains("Opening file"), "{stderr}");
+}
+
+#[test]
+fn test_stray_ascii28_only_warns_when_validating() {
+    let dir = tempfile::tempdir().expect("Failed to create directory");
+    let filing = dir.path().join("stray.fec");
+    let mut row = vec![""; 44];
+    row[..3].copy_from_slice(&["SA11A1", "C00123456", "JO\x1cNES"]);
+    std::fs::write(
+        &filing,
+        format!("HDR,FEC,3.00,FECfile,3.0\n{}\n", row.join(",")),
+    )
+    .expect("Failed to write filing");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_fast-fec-rust"))
+        .arg(&filing)
+        .arg("--validate")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run the binary");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "{stdout}");
+    assert!(
+        stdout.contains("line 2: warning: Keeping a stray ASCII28 byte"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("valid with 1 warning(s)"), "{stdout}");
+}
+
+#[test]
+fn test_verbose_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.verbose);
+
+    let args = vec!["fast-fec-rust", "--verbose", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.verbose);
+}
+
+#[test]
+fn test_atomic_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.atomic);
+
+    let args = vec!["fast-fec-rust", "--atomic", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.atomic);
+}
+
+#[test]
+fn test_output_mode_flags() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.output_mode, OutputMode::Truncate);
+
+    for (flag, mode) in [
+        ("--overwrite", OutputMode::Truncate),
+        ("--append", OutputMode::Append),
+        ("--no-overwrite", OutputMode::FailIfExists),
+    ] {
+        let args = vec!["fast-fec-rust", "12345", flag];
+        let config = simulate_parse_args(args).expect("Failed to parse args");
+        assert_eq!(config.output_mode, mode);
+    }
+
+    let args = vec!["fast-fec-rust", "12345", "--overwrite", "--append"];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Only one of --overwrite, --append and --no-overwrite"));
+}
+
+#[test]
+fn test_sqlite_format_and_output_flags() {
+    let args = vec![
+        "fast-fec-rust",
+        "12345",
+        "--format",
+        "sqlite",
+        "--output",
+        "filings.db",
+    ];
+    let result = simulate_parse_args(args);
+    if cfg!(feature = "sqlite") {
+        let config = result.expect("Failed to parse args");
+        assert_eq!(config.format, OutputFormat::Sqlite);
+        assert_eq!(config.output.as_deref(), Some("filings.db"));
+    } else {
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("requires building with the `sqlite` feature"));
+    }
+}
+
+#[test]
+fn test_compress_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--compress", "gzip"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.compression, Compression::Gzip);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.compression, Compression::None);
+
+    let args = vec![
+        "fast-fec-rust",
+        "12345",
+        "--compress",
+        "gzip",
+        "--compress-level",
+        "9",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.level, Some(9));
+
+    // Levels outside what the compression accepts are rejected
+    let args = vec![
+        "fast-fec-rust",
+        "12345",
+        "--compress",
+        "gzip",
+        "--compress-level",
+        "12",
+    ];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid gzip compression level 12"));
+    let args = vec!["fast-fec-rust", "12345", "--compress-level", "3"];
+    assert!(simulate_parse_args(args).is_err());
+
+    let args = vec![
+        "fast-fec-rust",
+        "12345",
+        "--compress",
+        "zstd",
+        "--compress-level",
+        "6",
+    ];
+    let result = simulate_parse_args(args);
+    if cfg!(feature = "zstd") {
+        let config = result.expect("Failed to parse args");
+        assert_eq!(config.compression, Compression::Zstd);
+        assert_eq!(config.level, Some(6));
+    } else {
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("requires building with the `zstd` feature"));
+    }
+
+    let args = vec!["fast-fec-rust", "12345", "--compress", "bzip2"];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid compression"));
 }
 
 #[test]
@@ -355,6 +665,7 @@ fn test_all_flags_combined() {
         output_directory: "custom_output".to_string(),
         write_to_disk: true,
         buffer_size: 16384,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -375,6 +686,7 @@ fn test_no_filing_id_with_stdin_disabled() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
@@ -395,7 +707,289 @@ fn test_filing_id_with_disable_stdin() {
         output_directory: "output".to_string(),
         write_to_disk: false,
         buffer_size: 4096,
+        ..Default::default()
+    };
+
+    assert_eq!(config, expected);
+}
+
+#[test]
+fn test_summary_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--summary"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+
+    let expected = CliConfig {
+        fec_id: "12345".to_string(),
+        summary: true,
+        ..Default::default()
+    };
+
+    assert_eq!(config, expected);
+}
+
+#[test]
+fn test_piped_stdin_without_filing_id() {
+    let config = parse_args_from(vec!["fast-fec-rust"], true).expect("Failed to parse args");
+
+    assert!(config.use_stdin);
+    assert_eq!(config.fec_id, "STDIN_DATA");
+}
+
+#[test]
+fn test_skip_and_limit_flags() {
+    let args = vec![
+        "fast-fec-rust",
+        "big.fec",
+        "--skip",
+        "1500000",
+        "--limit",
+        "100",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+
+    let expected = CliConfig {
+        fec_id: "big.fec".to_string(),
+        skip: 1_500_000,
+        limit: 100,
+        ..Default::default()
     };
 
     assert_eq!(config, expected);
+
+    let args = vec!["fast-fec-rust", "big.fec", "--limit", "-1"];
+    assert!(simulate_parse_args(args).is_err());
+}
+
+#[test]
+fn test_max_line_length_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--max-line-length", "1048576"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.max_line_length, 1_048_576);
+
+    let config = simulate_parse_args(vec!["fast-fec-rust", "12345"]).expect("Failed to parse args");
+    assert_eq!(config.max_line_length, 64 * 1024 * 1024);
+}
+
+#[test]
+fn test_strict_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--strict"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+
+    let expected = CliConfig {
+        fec_id: "12345".to_string(),
+        strict: true,
+        ..Default::default()
+    };
+
+    assert_eq!(config, expected);
+}
+
+#[test]
+fn test_fallback_encoding_flag() {
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.fallback, FallbackEncoding::Windows1252);
+
+    let args = vec![
+        "fast-fec-rust",
+        "12345",
+        "--fallback-encoding",
+        "utf8-lossy",
+    ];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.fallback, FallbackEncoding::Utf8Lossy);
+
+    let args = vec!["fast-fec-rust", "12345", "--fallback-encoding", "ascii"];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid fallback encoding"));
+}
+
+#[test]
+fn test_threads_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--threads", "8"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.threads, 8);
+
+    let args = vec!["fast-fec-rust", "12345", "--threads", "0"];
+    let result = simulate_parse_args(args);
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid thread count"));
+}
+
+#[test]
+fn test_print_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--print=sa"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.print);
+    assert_eq!(config.print_form.as_deref(), Some("SA"));
+
+    // A value after a space is the input, not a form
+    let args = vec!["fast-fec-rust", "--print", "t.fec"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.print);
+    assert_eq!(config.print_form, None);
+    assert_eq!(config.fec_id, "t.fec");
+
+    let args = vec!["fast-fec-rust", "12345", "--print"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(
+        config,
+        CliConfig {
+            fec_id: "12345".to_string(),
+            print: true,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_validate_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--validate"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.validate);
+}
+
+#[test]
+fn test_f99_text_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--f99-text"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.f99_text);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.f99_text);
+}
+
+#[test]
+fn test_full_form_names_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--full-form-names"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.full_form_names);
+}
+
+#[test]
+fn test_paper_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--paper"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.paper);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.paper);
+}
+
+#[test]
+fn test_map_csv_exports_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--map-csv-exports"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.map_csv_exports);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.map_csv_exports);
+}
+
+#[test]
+fn test_single_filing_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--single-filing"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.single_filing);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.single_filing);
+}
+
+#[test]
+fn test_truncate_extra_fields_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--truncate-extra-fields"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.truncate);
+}
+
+#[test]
+fn test_row_numbers_flag() {
+    let args = vec!["fast-fec-rust", "12345", "-f", "--row-numbers"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.include_filing_id);
+    assert!(config.row_numbers);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.row_numbers);
+}
+
+#[test]
+fn test_raw_headers_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--raw-headers"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.raw_headers);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(!config.raw_headers);
+}
+
+#[test]
+fn test_forms_flag() {
+    let args = vec!["fast-fec-rust", "12345", "--forms", "sa, SB,"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert_eq!(config.forms, ["SA", "SB"]);
+
+    let args = vec!["fast-fec-rust", "12345"];
+    let config = simulate_parse_args(args).expect("Failed to parse args");
+    assert!(config.forms.is_empty());
+}
+
+#[test]
+fn test_run_summary_lists_each_output_file() {
+    let filing = "tests/fixtures/multi_form_8.3.fec";
+    let dir = tempfile::tempdir().expect("Failed to create output directory");
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_fast-fec-rust"))
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .args([filing, "--write-to-disk", "--output-directory"])
+            .arg(dir.path())
+            .args(extra)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("Failed to run the binary")
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let table: Vec<Vec<&str>> = stderr
+        .lines()
+        .skip_while(|line| !line.starts_with("File "))
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+
+    // Each file's size is what ended up on disk
+    let row = |form: &str, rows: &str| {
+        let path = format!("{filing}/{form}");
+        let size = std::fs::metadata(dir.path().join(&path))
+            .expect("Output file exists")
+            .len();
+        vec![path, rows.to_string(), size.to_string()]
+    };
+    assert_eq!(table[0], ["File", "Rows", "Bytes"]);
+    assert_eq!(table[1], row("F3XN.csv", "1"));
+    assert_eq!(table[2], row("SA.csv", "2"));
+    assert_eq!(table[3], row("SB.csv", "1"));
+    assert_eq!(table[4], row("SE.csv", "1"));
+    assert_eq!(table[5], row("cover.csv", "1"));
+    assert_eq!(table[6], row("header.csv", "1"));
+    assert_eq!(table[7][..2], ["Total", "7"]);
+    assert_eq!(table[8][1], "warning(s)");
+
+    // Silent runs print nothing
+    let output = run(&["--silent"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
 }
@@ -0,0 +1,104 @@
+#![cfg(feature = "http")]
+
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::download::{filing_url, is_filing_id, open_filing};
+use fast_fec_rust::errors::FecError;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::writer::WriterContext;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serve a single HTTP response on a local port, returning the base URL and a handle
+    /// yielding the request line that was received.
+    fn serve_once(
+        status: &'static str,
+        body: &'static str,
+    ) -> Result<(String, JoinHandle<String>)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}/posted/", listener.local_addr()?);
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // Drain the request headers
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            request_line.trim_end().to_string()
+        });
+        Ok((base_url, handle))
+    }
+
+    #[test]
+    fn test_filing_ids() {
+        assert!(is_filing_id("1690664"));
+        assert!(!is_filing_id("tests/fixtures/multi_form_8.3.fec"));
+        assert!(!is_filing_id(""));
+        assert!(!is_filing_id("12a"));
+        assert_eq!(
+            filing_url("https://docquery.fec.gov/dcdev/posted/", "1690664"),
+            "https://docquery.fec.gov/dcdev/posted/1690664.fec"
+        );
+    }
+
+    #[test]
+    fn test_download_is_parsed() -> Result<()> {
+        let (base_url, server) = serve_once(
+            "200 OK",
+            "HDR,FEC,8.3,Vendor,1.0\nSA11AI,C00123456,SA.1\nSA11AI,C00123456,SA.2\n",
+        )?;
+
+        let mut reader = open_filing(&base_url, "1690664", true)?;
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("1690664".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1690664")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        assert_eq!(server.join().unwrap(), "GET /posted/1690664.fec HTTP/1.1");
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+        assert_eq!(ctx.line_number, 3);
+        assert!(dir.path().join("1690664").join("SA.csv").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_error_status() -> Result<()> {
+        let (base_url, server) = serve_once("404 Not Found", "no such filing")?;
+
+        let err = open_filing(&base_url, "999", true)
+            .err()
+            .expect("a 404 should fail the download");
+        server.join().unwrap();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::Download { url, status }) => {
+                assert!(url.ends_with("/posted/999.fec"));
+                assert_eq!(*status, Some(404));
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        Ok(())
+    }
+}
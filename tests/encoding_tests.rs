@@ -0,0 +1,202 @@
+extern crate fast_fec_rust;
+
+use fast_fec_rust::encoding::{
+    decode_line, decode_line_with, FallbackEncoding, LineInfo, LineInfoAccumulator, NulPolicy,
+    Utf8Validator, ValidationState,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `data` decoded as `fallback`, NUL bytes stripped.
+    fn decode(data: &[u8], fallback: FallbackEncoding) -> String {
+        decode_line_with(data, NulPolicy::Strip, fallback)
+            .0
+            .into_owned()
+    }
+
+    #[test]
+    fn test_smart_quotes_decoded_as_windows_1252() {
+        let line = b"\x93Thank you\x94 \x91guest\x92";
+        assert_eq!(
+            decode_line(line).0,
+            "\u{201C}Thank you\u{201D} \u{2018}guest\u{2019}"
+        );
+        assert_eq!(
+            decode(line, FallbackEncoding::Windows1252),
+            "“Thank you” ‘guest’"
+        );
+        // The `fec` module's decoder reads the line the same way
+        let (decoded, info) = fast_fec_rust::fec::decode_line(line);
+        assert_eq!(decoded, decode_line(line).0);
+        assert!(!info.valid_utf8);
+    }
+
+    #[test]
+    fn test_euro_sign_and_undefined_bytes() {
+        assert_eq!(decode(b"\x80100", FallbackEncoding::Windows1252), "€100");
+        // Bytes Windows-1252 leaves undefined keep their C1 control characters
+        assert_eq!(
+            decode(b"\x81\x8D\x8F\x90\x9D", FallbackEncoding::Windows1252),
+            "\u{81}\u{8D}\u{8F}\u{90}\u{9D}"
+        );
+    }
+
+    #[test]
+    fn test_mixed_line() {
+        // ASCII28 delimiters, Latin-1 letters past 0xA0, dashes and an ellipsis
+        let line = b"SA11AI\x1cJOS\xc9 \x96 CAF\xc9 \x97 \xa9 2024\x85\x1c\xff";
+        let (decoded, info) = decode_line_with(line, NulPolicy::Strip, FallbackEncoding::default());
+        assert_eq!(decoded, "SA11AI\x1cJOSÉ – CAFÉ — © 2024…\x1cÿ");
+        assert!(info.ascii28);
+        assert!(!info.valid_utf8);
+
+        // Latin-1 decodes the same bytes past 0xA0 alike, and 0x80..0x9F as C1 controls
+        assert_eq!(
+            decode(line, FallbackEncoding::Latin1),
+            "SA11AI\x1cJOSÉ \u{96} CAFÉ \u{97} © 2024\u{85}\x1cÿ"
+        );
+    }
+
+    #[test]
+    fn test_valid_utf8_left_alone() {
+        let line = "“Quoted” – €5".as_bytes();
+        for fallback in [FallbackEncoding::Windows1252, FallbackEncoding::Latin1] {
+            assert_eq!(decode(line, fallback), "“Quoted” – €5");
+        }
+    }
+
+    #[test]
+    fn test_each_fallback_on_the_same_line() {
+        let line = b"\x93A\x80\xff";
+        let decoded: Vec<String> = [
+            FallbackEncoding::Windows1252,
+            FallbackEncoding::Latin1,
+            FallbackEncoding::Utf8Lossy,
+            FallbackEncoding::Error,
+        ]
+        .into_iter()
+        .map(|fallback| decode(line, fallback))
+        .collect();
+        assert_eq!(
+            decoded,
+            [
+                "“A€ÿ",
+                "\u{93}A\u{80}ÿ",
+                "\u{FFFD}A\u{FFFD}\u{FFFD}",
+                // Decoding can't fail; the parser fails on the line instead
+                "\u{FFFD}A\u{FFFD}\u{FFFD}",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fallback_encoding_names() {
+        for (name, fallback) in [
+            ("windows-1252", FallbackEncoding::Windows1252),
+            ("CP1252", FallbackEncoding::Windows1252),
+            ("latin1", FallbackEncoding::Latin1),
+            ("iso-8859-1", FallbackEncoding::Latin1),
+            ("utf8-lossy", FallbackEncoding::Utf8Lossy),
+            (" error ", FallbackEncoding::Error),
+        ] {
+            assert_eq!(name.parse::<FallbackEncoding>().unwrap(), fallback);
+        }
+        assert!("utf-16".parse::<FallbackEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_every_byte_past_0xa0_matches_latin1() {
+        let line: Vec<u8> = (0xA0..=0xFF).collect();
+        assert_eq!(
+            decode(&line, FallbackEncoding::Windows1252),
+            decode(&line, FallbackEncoding::Latin1)
+        );
+        let expected: String = (0xA0..=0xFFu32).filter_map(char::from_u32).collect();
+        assert_eq!(decode(&line, FallbackEncoding::Windows1252), expected);
+    }
+
+    /// Lines with multi-byte characters (`é`, `€`, an emoji), and ones that aren't valid UTF-8:
+    /// an invalid byte, an overlong encoding, a surrogate, and characters cut short.
+    const SAMPLES: &[&[u8]] = &[
+        b"\xEF\xBB\xBFHDR\x1cFEC\x1c8.3\r\n",
+        "SA11AI\x1cJOSÉ\x1c€100\x1c\u{1F600}\0".as_bytes(),
+        b"CAF\xC9\x1c\x93quoted\x94",
+        b"\xC0\xAF\xED\xA0\x80",
+        b"\xE2\x82",
+        b"\xF0\x9F\x98",
+        b"",
+    ];
+
+    /// The `LineInfo` of `data` fed to an accumulator in `chunks`.
+    fn accumulate<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> LineInfo {
+        let mut accumulator = LineInfoAccumulator::new();
+        for chunk in chunks {
+            accumulator.feed(chunk);
+        }
+        accumulator.finish()
+    }
+
+    #[test]
+    fn test_validator_split_at_every_boundary() {
+        for &data in SAMPLES {
+            let expected = match std::str::from_utf8(data) {
+                Ok(_) => ValidationState::Valid,
+                Err(_) => ValidationState::Invalid,
+            };
+            let mut whole = Utf8Validator::new();
+            whole.feed(data);
+            assert_eq!(whole.finish(), expected, "{data:?}");
+
+            for split in 0..=data.len() {
+                let mut validator = Utf8Validator::new();
+                validator.feed(&data[..split]);
+                validator.feed(&data[split..]);
+                assert_eq!(validator.finish(), expected, "{data:?} split at {split}");
+            }
+            let mut validator = Utf8Validator::new();
+            for byte in data {
+                validator.feed(std::slice::from_ref(byte));
+            }
+            assert_eq!(validator.finish(), expected, "{data:?} a byte at a time");
+        }
+    }
+
+    #[test]
+    fn test_validator_state_between_chunks() {
+        let mut validator = Utf8Validator::new();
+        assert_eq!(validator.feed(b"A\xE2"), ValidationState::Incomplete);
+        assert_eq!(validator.feed(b"\x82"), ValidationState::Incomplete);
+        assert_eq!(validator.feed(b"\xAC"), ValidationState::Valid);
+        // A character cut short is only invalid once the input is known to end there
+        assert_eq!(validator.feed(b"\xC3"), ValidationState::Incomplete);
+        assert_eq!(validator.finish(), ValidationState::Invalid);
+
+        let mut validator = Utf8Validator::new();
+        assert_eq!(validator.feed(b"\xFF"), ValidationState::Invalid);
+        assert_eq!(validator.feed(b"ABC"), ValidationState::Invalid);
+    }
+
+    #[test]
+    fn test_line_info_split_at_every_boundary() {
+        for &data in SAMPLES {
+            let (_, expected) =
+                decode_line_with(data, NulPolicy::Strip, FallbackEncoding::default());
+            assert_eq!(
+                expected.valid_utf8,
+                std::str::from_utf8(data).is_ok(),
+                "{data:?}"
+            );
+            for split in 0..=data.len() {
+                let (head, tail) = data.split_at(split);
+                assert_eq!(
+                    accumulate([head, tail]),
+                    expected,
+                    "{data:?} split at {split}"
+                );
+            }
+            assert_eq!(accumulate(data.chunks(1)), expected, "{data:?}");
+        }
+    }
+}
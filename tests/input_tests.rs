@@ -0,0 +1,288 @@
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::errors::FecError;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::input::{detect_utf16, open_file, ByteOrder, Utf16Input};
+use fast_fec_rust::writer::WriterContext;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Parse the input file at `path` through `open_file`, returning the filing's output directory.
+    fn parse_path(path: &str, output_dir: &Path, filing_id: &str) -> Result<PathBuf> {
+        parse_reader(open_file(path)?, output_dir, filing_id)
+    }
+
+    /// Parse the filing read from `reader`, returning the filing's output directory.
+    fn parse_reader<R: BufRead>(
+        mut reader: R,
+        output_dir: &Path,
+        filing_id: &str,
+    ) -> Result<PathBuf> {
+        let mut ctx = FecContext::new(filing_id.to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(output_dir.to_string_lossy())
+            .filing_id(filing_id)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+        Ok(output_dir.join(filing_id))
+    }
+
+    /// Assert two output directories hold the same files with the same contents, but for the
+    /// filing ID (the directory's name) in the header and cover files.
+    fn assert_same_output(expected: &Path, actual: &Path) -> Result<()> {
+        let mut names: Vec<_> = fs::read_dir(expected)?
+            .map(|e| e.map(|e| e.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        names.sort();
+        assert!(!names.is_empty());
+        let id = |dir: &Path| format!("\n{},", dir.file_name().unwrap().to_string_lossy());
+        for name in names {
+            let mut actual_content = fs::read_to_string(actual.join(&name))?;
+            if name == "header.csv" || name == "cover.csv" {
+                actual_content = actual_content.replacen(&id(actual), &id(expected), 1);
+            }
+            assert_eq!(
+                fs::read_to_string(expected.join(&name))?,
+                actual_content,
+                "{name:?} differs"
+            );
+        }
+        assert_eq!(
+            fs::read_dir(actual)?.count(),
+            fs::read_dir(expected)?.count()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_files_are_read_as_is() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let copy = dir.path().join("1001.FEC");
+        fs::copy(fixture("multi_form_8.3.fec"), &copy)?;
+
+        let expected = parse_path(&fixture("multi_form_8.3.fec"), dir.path(), "original")?;
+        let actual = parse_path(&copy.to_string_lossy(), dir.path(), "copy")?;
+        assert_same_output(&expected, &actual)
+    }
+
+    #[test]
+    #[cfg(feature = "zip")]
+    fn test_zip_member_is_parsed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let expected = parse_path(&fixture("multi_form_8.3.fec"), dir.path(), "plain")?;
+        let actual = parse_path(&fixture("multi_form_8.3.zip"), dir.path(), "zipped")?;
+        assert_same_output(&expected, &actual)
+    }
+
+    #[test]
+    #[cfg(feature = "zip")]
+    fn test_zip_without_fec_member() {
+        let err = open_file(&fixture("no_fec_member.zip"))
+            .err()
+            .expect("an archive without a .fec file should be rejected");
+        assert!(err.to_string().contains("contains no .fec file"), "{err:#}");
+    }
+
+    #[test]
+    #[cfg(not(feature = "zip"))]
+    fn test_zip_requires_feature() {
+        let err = open_file(&fixture("multi_form_8.3.zip"))
+            .err()
+            .expect("zip input should need the zip feature");
+        assert!(err.to_string().contains("`zip` feature"), "{err:#}");
+    }
+
+    /// Gzip the fixture `name` into `dir`, returning the compressed file's path.
+    fn gzip_fixture(name: &str, dir: &Path) -> Result<PathBuf> {
+        let path = dir.join(format!("{name}.gz"));
+        let mut encoder = GzEncoder::new(fs::File::create(&path)?, Compression::default());
+        encoder.write_all(&fs::read(fixture(name))?)?;
+        encoder.finish()?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_gzip_input_is_parsed() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["multi_form_8.3.fec", "f99_text_8.3.fec", "legacy_2.02.fec"] {
+            let gz = gzip_fixture(name, dir.path())?;
+            let expected = parse_path(&fixture(name), dir.path(), &format!("{name}-plain"))?;
+            let actual = parse_path(&gz.to_string_lossy(), dir.path(), &format!("{name}-gz"))?;
+            assert_same_output(&expected, &actual)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_gzip_reports_offset() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let gz = gzip_fixture("multi_form_8.3.fec", dir.path())?;
+        // Garble the compressed body, keeping the gzip header intact
+        let mut bytes = fs::read(&gz)?;
+        let len = bytes.len();
+        bytes[len / 2..].iter_mut().for_each(|b| *b = !*b);
+        fs::write(&gz, bytes)?;
+
+        let err = parse_path(&gz.to_string_lossy(), dir.path(), "corrupt")
+            .expect_err("a corrupt gzip stream should fail the parse");
+        let io = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<FecError>())
+            .expect("the decode error should be an FecError");
+        assert!(matches!(io, FecError::Io(_)), "{io:?}");
+        assert!(io.to_string().contains("at decompressed byte"), "{io}");
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mapped_files_match_buffered_reads() -> Result<()> {
+        use fast_fec_rust::input::MappedFile;
+
+        let dir = tempfile::tempdir()?;
+        for name in [
+            "multi_form_8.3.fec",
+            "crlf_8.3.fec",
+            "f99_text_8.3.fec",
+            "line_numbers_8.3.fec",
+            "comma_3.00.fec",
+            "legacy_2.02.fec",
+        ] {
+            let file = fs::File::open(fixture(name))?;
+            let mapped = MappedFile::new(&file).expect("fixtures should be mappable");
+            let expected =
+                parse_reader(BufReader::new(&file), dir.path(), &format!("read-{name}"))?;
+            let actual = parse_reader(mapped, dir.path(), &format!("mapped-{name}"))?;
+            assert_same_output(&expected, &actual)?;
+        }
+
+        // Empty files can't be mapped and are streamed instead
+        let empty = dir.path().join("empty.fec");
+        fs::write(&empty, "")?;
+        assert!(MappedFile::new(&fs::File::open(&empty)?).is_none());
+
+        Ok(())
+    }
+
+    /// `text` encoded as UTF-16 in `order`, led by a byte-order mark if `bom` is set.
+    fn utf16(text: &str, order: ByteOrder, bom: bool) -> Vec<u8> {
+        let bom = bom.then_some('\u{FEFF}');
+        bom.into_iter()
+            .chain(text.chars())
+            .collect::<String>()
+            .encode_utf16()
+            .flat_map(|unit| match order {
+                ByteOrder::Little => unit.to_le_bytes(),
+                ByteOrder::Big => unit.to_be_bytes(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_utf16_filings_match_utf8() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        for name in ["multi_form_8.3.fec", "crlf_8.3.fec", "f99_text_8.3.fec"] {
+            let text = fs::read_to_string(fixture(name))?;
+            for bom in [false, true] {
+                // The header records a byte-order mark, whichever encoding it was in
+                let original = dir.path().join(format!("original-{bom}-{name}"));
+                let bom_text = if bom { "\u{FEFF}" } else { "" };
+                fs::write(&original, format!("{bom_text}{text}"))?;
+                let expected = parse_path(
+                    &original.to_string_lossy(),
+                    dir.path(),
+                    &format!("utf8-{bom}-{name}"),
+                )?;
+                for order in [ByteOrder::Little, ByteOrder::Big] {
+                    let id = format!("{order:?}-{bom}-{name}");
+                    let path = dir.path().join(format!("{id}.fec"));
+                    fs::write(&path, utf16(&text, order, bom))?;
+                    let actual = parse_path(&path.to_string_lossy(), dir.path(), &id)?;
+                    assert_same_output(&expected, &actual)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_utf16_split_across_reads() -> Result<()> {
+        // An emoji is a surrogate pair, split between reads of 3 bytes along with each code unit
+        let text = "HDR,FEC,8.3,Caf\u{E9} \u{1F600}\n";
+        for order in [ByteOrder::Little, ByteOrder::Big] {
+            let data = utf16(text, order, true);
+            let mut input = Utf16Input::new(BufReader::with_capacity(3, &data[..]));
+            let mut decoded = String::new();
+            input.read_to_string(&mut decoded)?;
+            assert_eq!(decoded, format!("\u{FEFF}{text}"));
+            assert_eq!(input.byte_order(), Some(order));
+        }
+
+        // A lone surrogate and a dangling byte can't be decoded
+        let mut data = utf16("A", ByteOrder::Little, true);
+        data.extend_from_slice(&[0x3D, 0xD8, b'B', 0, b'C']);
+        let mut decoded = String::new();
+        Utf16Input::new(&data[..]).read_to_string(&mut decoded)?;
+        assert_eq!(decoded, "\u{FEFF}A\u{FFFD}B\u{FFFD}");
+
+        // Anything else is passed through as is
+        let mut decoded = String::new();
+        let mut input = Utf16Input::new(&b"HDR,FEC,8.3\nSA11AI,C1\n"[..]);
+        input.read_to_string(&mut decoded)?;
+        assert_eq!(decoded, "HDR,FEC,8.3\nSA11AI,C1\n");
+        assert_eq!(input.byte_order(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_utf16_bom_read_a_byte_at_a_time() -> Result<()> {
+        let text = "HDR,FEC,8.3\n";
+        for order in [ByteOrder::Little, ByteOrder::Big] {
+            let data = utf16(text, order, true);
+            let mut input = Utf16Input::new(BufReader::with_capacity(1, &data[..]));
+            let mut decoded = String::new();
+            input.read_to_string(&mut decoded)?;
+            assert_eq!(decoded, format!("\u{FEFF}{text}"));
+            assert_eq!(input.byte_order(), Some(order));
+        }
+
+        // The byte read to look for a byte-order mark isn't lost
+        let mut decoded = String::new();
+        let mut input = Utf16Input::new(BufReader::with_capacity(1, text.as_bytes()));
+        input.read_to_string(&mut decoded)?;
+        assert_eq!(decoded, text);
+        assert_eq!(input.byte_order(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_utf16() {
+        assert_eq!(detect_utf16(b"\xFF\xFE"), Some(ByteOrder::Little));
+        assert_eq!(detect_utf16(b"\xFE\xFFH"), Some(ByteOrder::Big));
+        assert_eq!(detect_utf16(b"H\0D\0R\0,\0"), Some(ByteOrder::Little));
+        assert_eq!(detect_utf16(b"\0H\0D\0R\0,"), Some(ByteOrder::Big));
+        // Too short to tell, or NULs here and there in UTF-8 text
+        assert_eq!(detect_utf16(b"H\0D\0"), None);
+        assert_eq!(detect_utf16(b"HDR,FEC,\08.3,Vendor\0"), None);
+    }
+}
@@ -0,0 +1,233 @@
+extern crate fast_fec_rust;
+
+use fast_fec_rust::fec::mappings::{
+    column_double_format, column_type, generic_columns, get_column_types, get_columns,
+    is_known_version, normalize_column_name, normalize_column_names, normalize_form_type,
+    resolve_form_type, resolve_form_type_in, ColumnType, MappingSet, MAPPINGS, PAPER_MAPPINGS,
+};
+use fast_fec_rust::writer::DoubleFormat;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_columns_known_form() {
+        let columns = get_columns("SA", "8.3").expect("SA 8.3 should be mapped");
+        assert_eq!(
+            &columns[..3],
+            &["form_type", "filer_committee_id_number", "transaction_id"]
+        );
+        assert_eq!(columns.len(), 45);
+    }
+
+    #[test]
+    fn test_get_columns_version_specific() {
+        let old = get_columns("SA", "5.00").expect("SA 5.00 should be mapped");
+        let new = get_columns("SA", "8.3").unwrap();
+        assert_ne!(old, new);
+        assert_eq!(get_columns("F3XN", "8.1"), get_columns("F3XA", "8.4"));
+
+        // TEXT records only gained a transaction id of their own in version 6
+        let old = get_columns("TEXT", "5.00").expect("TEXT 5.00 should be mapped");
+        assert_eq!(old[2], "back_reference_tran_id_number");
+        let new = get_columns("TEXT", "8.3").unwrap();
+        assert_eq!(
+            new[2..4],
+            ["transaction_id_number", "back_reference_tran_id_number"]
+        );
+    }
+
+    #[test]
+    fn test_form_types_resolve_by_longest_prefix() {
+        assert_eq!(resolve_form_type("SA11AI"), Some("SA"));
+        assert_eq!(resolve_form_type("SB28A"), Some("SB"));
+        assert_eq!(resolve_form_type("SC1/10"), Some("SC1"));
+        assert_eq!(resolve_form_type("SC/10"), Some("SC"));
+        assert_eq!(resolve_form_type("H4"), Some("H4"));
+        assert_eq!(resolve_form_type("F3XN"), Some("F3XN"));
+        assert_eq!(resolve_form_type("ZZ9"), None);
+        // Only schedules match by prefix
+        assert_eq!(resolve_form_type("H4X"), None);
+        assert_eq!(resolve_form_type("F3XNZ"), None);
+
+        assert_eq!(get_columns("SA11AI", "8.3"), get_columns("SA", "8.3"));
+        assert_eq!(get_columns("SA11AI", "5.00"), get_columns("SA", "5.00"));
+        assert_eq!(get_columns("SB28A", "8.3"), get_columns("SB", "8.3"));
+        assert_eq!(
+            get_column_types("SB28A", "8.3"),
+            get_column_types("SB", "8.3")
+        );
+        assert!(get_columns("H4", "8.3").is_some());
+        assert!(get_columns("ZZ9", "8.3").is_none());
+    }
+
+    #[test]
+    fn test_form_types_normalized() {
+        for raw in [
+            "SA11AI",
+            "sa11ai",
+            " SA11AI ",
+            "\"Sa11ai\"",
+            " \" sa11ai \" ",
+        ] {
+            assert_eq!(
+                normalize_form_type(raw).as_deref(),
+                Some("SA11AI"),
+                "{raw:?}"
+            );
+        }
+        assert_eq!(normalize_form_type("sc1/10").as_deref(), Some("SC1/10"));
+        assert_eq!(normalize_form_type("\"\""), None);
+        assert_eq!(normalize_form_type("  "), None);
+        assert_eq!(normalize_form_type("S@11"), None);
+
+        // The lookups normalize the codes they are given
+        assert_eq!(resolve_form_type(" sa11ai"), Some("SA"));
+        assert_eq!(get_columns("\"f3xn\"", "8.3"), get_columns("F3XN", "8.3"));
+    }
+
+    #[test]
+    fn test_versions_matched_by_pattern() {
+        let sa_8 = get_columns("SA", "8.3").expect("SA has 8.x columns");
+        assert_eq!(sa_8.len(), 45);
+        assert_eq!(get_columns("SA", "8.4"), Some(sa_8));
+        assert_eq!(get_columns("SA", " 8.4 "), Some(sa_8));
+        let sa_old = get_columns("SA", "5.00").expect("SA has 5.x columns");
+        assert_ne!(sa_old, sa_8);
+        assert_eq!(get_columns("SA", "3.00"), Some(sa_old));
+        assert!(get_columns("H4", "8.4").is_some());
+
+        // Versions some mapping knows, but not this form's, have no columns
+        assert!(is_known_version("2.02"));
+        assert!(get_columns("SA", "2.02").is_none());
+        assert!(get_columns("H4", "5.00").is_none());
+
+        // Unknown versions fall back to the newest mapping of each form
+        assert!(!is_known_version("9.0"));
+        assert_eq!(get_columns("SA", "9.0"), Some(sa_8));
+        assert_eq!(get_columns("HDR", "9.0"), get_columns("HDR", "8.3"));
+        assert_eq!(get_column_types("SB", "9.0"), get_column_types("SB", "8.3"));
+        assert!(get_columns("ZZ9", "9.0").is_none());
+    }
+
+    #[test]
+    fn test_get_columns_unknown() {
+        assert!(get_columns("ZZ9", "8.3").is_none());
+        assert!(get_columns("SA", "").is_none());
+        assert_eq!(generic_columns(3), vec!["col_1", "col_2", "col_3"]);
+    }
+
+    #[test]
+    fn test_column_types() {
+        let columns = get_columns("SA", "8.3").unwrap();
+        let types = get_column_types("SA", "8.3").unwrap();
+        assert_eq!(columns.len(), types.len());
+        assert_eq!(
+            (columns[19], types[19]),
+            ("contribution_date", ColumnType::Date)
+        );
+        assert_eq!(
+            (columns[20], types[20]),
+            ("contribution_amount", ColumnType::Float)
+        );
+        assert_eq!(
+            (columns[7], types[7]),
+            ("contributor_last_name", ColumnType::String)
+        );
+
+        assert_eq!(column_type("loan_payment_to_date"), ColumnType::Float);
+        assert_eq!(column_type("loan_due_date_terms"), ColumnType::String);
+        assert_eq!(column_type("col_b_year"), ColumnType::String);
+        assert_eq!(ColumnType::Float.code(), 'f');
+    }
+
+    #[test]
+    fn test_column_double_formats() {
+        assert_eq!(
+            column_double_format("contribution_amount"),
+            DoubleFormat::Fixed(2)
+        );
+        assert_eq!(
+            column_double_format("federal_share"),
+            DoubleFormat::Fixed(2)
+        );
+        assert_eq!(
+            column_double_format("interest_rate"),
+            DoubleFormat::RoundTrip
+        );
+        assert_eq!(
+            column_double_format("federal_percent"),
+            DoubleFormat::RoundTrip
+        );
+        assert_eq!(column_double_format("latitude"), DoubleFormat::RoundTrip);
+        // Only whole words of the name count
+        assert_eq!(
+            column_double_format("corporate_total"),
+            DoubleFormat::Fixed(2)
+        );
+    }
+
+    #[test]
+    fn test_column_names_normalized() {
+        assert_eq!(
+            normalize_column_name("Contributor Organization Name"),
+            "contributor_organization_name"
+        );
+        assert_eq!(normalize_column_name("Date/Time"), "date_time");
+        assert_eq!(normalize_column_name(" Amount ($) "), "amount");
+        assert_eq!(normalize_column_name("FEC-ID  #"), "fec_id");
+        assert_eq!(normalize_column_name("already_snake_2"), "already_snake_2");
+        assert_eq!(normalize_column_name("--"), "column");
+
+        // Names that normalize the same are told apart, skipping suffixes already taken
+        assert_eq!(
+            normalize_column_names(&["Amount", "amount", "AMOUNT_2", "Amount!"]),
+            ["amount", "amount_2", "amount_2_2", "amount_3"]
+        );
+    }
+
+    #[test]
+    fn test_mapping_column_names_already_normalized() {
+        // Every mapping's header row is written unchanged, with no two columns colliding
+        for mapping in MAPPINGS.iter().chain(PAPER_MAPPINGS) {
+            assert_eq!(
+                normalize_column_names(mapping.columns),
+                mapping.columns,
+                "{:?} {}",
+                mapping.forms,
+                mapping.version
+            );
+        }
+    }
+
+    #[test]
+    fn test_paper_versions_use_paper_mappings() {
+        assert_eq!(MappingSet::for_version("P3.00"), MappingSet::Paper);
+        assert_eq!(MappingSet::for_version(" p1 "), MappingSet::Paper);
+        assert_eq!(MappingSet::for_version("3.00"), MappingSet::Electronic);
+        assert!(is_known_version("P1"));
+        assert!(is_known_version("P2.1"));
+        assert!(is_known_version("P3.00"));
+        assert!(!is_known_version("P4.0"));
+
+        // Paper Schedule A keeps the contributor's name in one column
+        let columns = get_columns("SA11AI", "P3.00").expect("paper SA should be mapped");
+        assert_eq!(&columns[2..4], &["transaction_id", "contributor_name"]);
+        assert_eq!(columns.last(), Some(&"image_number"));
+        let types = get_column_types("SA11AI", "P3.00").expect("paper SA should be typed");
+        assert_eq!(types.len(), columns.len());
+        assert_eq!(types[12], ColumnType::Float); // contribution_amount
+
+        // An unknown paper version still gets the paper columns
+        assert_eq!(get_columns("SB17", "P9"), get_columns("SB", "P3"));
+
+        // Forms never keyed in from paper have no paper mapping
+        assert_eq!(resolve_form_type_in("SC/9", MappingSet::Paper), None);
+        assert_eq!(
+            resolve_form_type_in("SC/9", MappingSet::Electronic),
+            Some("SC")
+        );
+        assert_eq!(get_columns("SC/9", "P3.00"), None);
+    }
+}
@@ -0,0 +1,135 @@
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::writer::WriterContext;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::io::{BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The system allocator, keeping count of the bytes allocated and the most at any one time.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+impl CountingAllocator {
+    fn grow(by: usize) {
+        let now = ALLOCATED.fetch_add(by, Ordering::SeqCst) + by;
+        PEAK.fetch_max(now, Ordering::SeqCst);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+            Self::grow(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader producing an F99 filing whose text block is `remaining` bytes of `line`s,
+    /// without ever holding it in memory.
+    struct LongTextFiling {
+        prefix: &'static [u8],
+        line: &'static [u8],
+        remaining: usize,
+        suffix: &'static [u8],
+        pos: usize, // Position in `line`
+    }
+
+    /// Move as much of `source` as fits into `buf`.
+    fn take(source: &mut &[u8], buf: &mut [u8]) -> usize {
+        let n = source.len().min(buf.len());
+        buf[..n].copy_from_slice(&source[..n]);
+        *source = &source[n..];
+        n
+    }
+
+    impl Read for LongTextFiling {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.prefix.is_empty() {
+                return Ok(take(&mut self.prefix, buf));
+            }
+            if self.remaining == 0 {
+                return Ok(take(&mut self.suffix, buf));
+            }
+            let mut line = &self.line[self.pos..];
+            line = &line[..line.len().min(self.remaining)];
+            let n = take(&mut line, buf);
+            self.pos = (self.pos + n) % self.line.len();
+            self.remaining -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_long_f99_text_parsed_in_constant_memory() -> Result<()> {
+        const LINE: &[u8] = b"A long letter to the Commission, with \"quotes\", going on and on.\n";
+        const TEXT_LENGTH: usize = 50 * 1024 * 1024 / LINE.len() * LINE.len();
+        let mut input = BufReader::new(LongTextFiling {
+            prefix:
+                b"HDR,FEC,8.3,Vendor,1.0\nF99,C00123456,TEST,,,,,,,,20230101,MSM\n[BEGINTEXT]\n",
+            line: LINE,
+            remaining: TEXT_LENGTH,
+            suffix: b"[ENDTEXT]\nSA11AI,C00123456,SA.1\n",
+            pos: 0,
+        });
+
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("3001".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("3001")
+            .buffer_size(64 * 1024)
+            .build()?;
+
+        let baseline = ALLOCATED.load(Ordering::SeqCst);
+        PEAK.store(baseline, Ordering::SeqCst);
+        parse_fec(&mut ctx, &mut input, &mut writer)?;
+        writer.flush_all()?;
+        let peak = PEAK.load(Ordering::SeqCst) - baseline;
+        assert!(peak < 8 * 1024 * 1024, "peaked at {peak} bytes");
+
+        // The whole block still made it into the F99 row's text column
+        let mut rdr = csv::Reader::from_path(dir.path().join("3001/F99.csv"))?;
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 1);
+        let text = rows[0].iter().next_back().unwrap_or_default();
+        assert_eq!(text.len(), TEXT_LENGTH - 1);
+        assert!(text.starts_with("A long letter to the Commission, with \"quotes\""));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("3001/SA.csv"))?
+                .lines()
+                .count(),
+            2
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+#![cfg(feature = "object_store")]
+
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::writer::{
+    is_object_store_url, ObjectStoreSinkFactory, SinkFactory, WriterContext, WriterContextBuilder,
+};
+use object_store::memory::InMemory;
+use object_store::path::Path as ObjectPath;
+use object_store::ObjectStore;
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse the split fixture (filing 1053, with 25 SA rows) with `builder`'s writer, then
+    /// close it.
+    fn parse_fixture(builder: WriterContextBuilder) -> Result<()> {
+        let mut writer = builder.filing_id("1053").build()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/split_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1053".to_string(), false, true, false);
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.close()?.check()
+    }
+
+    /// Those of the objects at `locations` that are in `store`, with their contents.
+    fn objects(store: &InMemory, locations: &[String]) -> Result<Vec<(String, Vec<u8>)>> {
+        let runtime = Runtime::new()?;
+        runtime.block_on(async {
+            let mut objects = Vec::new();
+            for location in locations {
+                if let Ok(object) = store.get(&ObjectPath::from(location.as_str())).await {
+                    objects.push((location.clone(), object.bytes().await?.to_vec()));
+                }
+            }
+            Ok(objects)
+        })
+    }
+
+    #[test]
+    fn test_files_uploaded_in_parts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture(
+            WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .max_rows_per_file(Some(20)),
+        )?;
+
+        let store = Arc::new(InMemory::new());
+        // Parts far smaller than the files, so each is uploaded in several
+        let factory =
+            ObjectStoreSinkFactory::new(store.clone(), ObjectPath::from("fec"))?.with_part_size(64);
+        parse_fixture(
+            WriterContext::builder()
+                .output_directory(dir.path().join("unused").to_string_lossy())
+                .max_rows_per_file(Some(20))
+                .sink_factory(factory),
+        )?;
+
+        // The same bytes as on disk, and nothing on disk
+        let mut locations = Vec::new();
+        for entry in fs::read_dir(dir.path().join("1053"))? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            locations.push(format!("fec/1053/{name}"));
+        }
+        assert!(locations.contains(&"fec/1053/SA_002.csv".to_string()));
+        let objects = objects(&store, &locations)?;
+        assert_eq!(objects.len(), locations.len());
+        for (location, bytes) in objects {
+            let path = dir.path().join(location.strip_prefix("fec/").unwrap());
+            assert_eq!(bytes, fs::read(&path)?, "{location}");
+        }
+        assert!(!dir.path().join("unused").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_uploads_only_complete_once_closed() -> Result<()> {
+        let store = Arc::new(InMemory::new());
+        let factory =
+            ObjectStoreSinkFactory::new(store.clone(), ObjectPath::from("fec"))?.with_part_size(4);
+
+        let locations = [
+            "fec/1053/SA.csv".to_string(),
+            "fec/1053/SA_002.csv".to_string(),
+        ];
+
+        // Only a closed sink leaves an object, whatever was uploaded before
+        let mut sink = factory.create("SA", "csv", Path::new("1053/SA.csv"))?;
+        sink.write_all(b"SA11AI,SA.1\n")?;
+        sink.finalize()?;
+        assert!(objects(&store, &locations)?.is_empty());
+        sink.close()?;
+        assert_eq!(
+            objects(&store, &locations)?,
+            [("fec/1053/SA.csv".to_string(), b"SA11AI,SA.1\n".to_vec())]
+        );
+
+        // A sink dropped unclosed, e.g. after an error, has its upload aborted
+        let mut sink = factory.create("SA", "csv", Path::new("1053/SA_002.csv"))?;
+        sink.write_all(b"SA11AI,SA.2\n")?;
+        drop(sink);
+        assert_eq!(objects(&store, &locations)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_store_urls() {
+        assert!(is_object_store_url("s3://bucket/prefix"));
+        assert!(is_object_store_url("gs://bucket"));
+        assert!(!is_object_store_url("output"));
+        assert!(!is_object_store_url("ftp://host/output"));
+
+        let err = ObjectStoreSinkFactory::from_url("s3:///prefix")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("names no bucket"), "{err}");
+        let err = ObjectStoreSinkFactory::from_url("azure://bucket")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Unknown object store"), "{err}");
+    }
+}
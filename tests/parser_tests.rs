@@ -0,0 +1,2854 @@
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::encoding::{FallbackEncoding, NulPolicy};
+use fast_fec_rust::errors::FecError;
+use fast_fec_rust::fec::context::{
+    delimiter_for_version, Delimiter, FecContext, TextEvent, TextState,
+};
+use fast_fec_rust::fec::header::{is_csv_export_header, normalize_date, CoverInfo, HeaderVariant};
+use fast_fec_rust::fec::mappings::{get_columns, widest_columns, MappingSet, NEWEST_VERSION};
+use fast_fec_rust::fec::parser::{
+    parse_fec, parse_fec_seekable, parse_fec_with_callback, parse_fec_with_options, parse_line,
+    read_header, ParseOptions, HEADER_COLUMNS,
+};
+use fast_fec_rust::fec::records::{FecRecords, RecordKind, MAX_QUOTED_RECORD_LINES};
+use fast_fec_rust::fec::validation::Severity;
+use fast_fec_rust::writer::{
+    sanitize_filename, Compression, DateFormat, OutputMode, Terminator, WriterContext,
+    MANIFEST_COLUMNS, MANIFEST_FILENAME,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a fixture from `tests/fixtures` into `output_dir`, returning once all buffers are flushed.
+    fn parse_fixture(fixture: &str, output_dir: &Path, filing_id: &str) -> Result<FecContext> {
+        let mut ctx = FecContext::new(filing_id.to_string(), false, true, false);
+        parse_fixture_with(&mut ctx, fixture, output_dir)?;
+        Ok(ctx)
+    }
+
+    /// Like `parse_fixture`, but with a caller-configured context (its `fec_id` is the filing id).
+    fn parse_fixture_with(ctx: &mut FecContext, fixture: &str, output_dir: &Path) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(fixture);
+        let mut reader = BufReader::new(fs::File::open(path)?);
+
+        let mut writer = WriterContext::builder()
+            .output_directory(output_dir.to_string_lossy())
+            .filing_id(ctx.fec_id.clone())
+            .build()?;
+
+        parse_fec(ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+        Ok(())
+    }
+
+    /// Parse in-memory input into `output_dir`, returning once all buffers are flushed.
+    fn parse_input(input: &[u8], output_dir: &Path, filing_id: &str) -> Result<FecContext> {
+        let mut reader = BufReader::new(input);
+        let mut ctx = FecContext::new(filing_id.to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(output_dir.to_string_lossy())
+            .filing_id(filing_id)
+            .build()?;
+
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+        Ok(ctx)
+    }
+
+    /// Read an output CSV and return its non-empty lines.
+    fn read_lines(path: &Path) -> Result<Vec<String>> {
+        Ok(fs::read_to_string(path)?
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_rows_routed_by_form_type() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture("multi_form_8.3.fec", dir.path(), "1001")?;
+
+        let filing_dir = dir.path().join("1001");
+        assert!(!filing_dir.join("output.csv").exists());
+
+        // Each file holds a header row followed by its form's rows
+        let f3x = read_lines(&filing_dir.join("F3XN.csv"))?;
+        assert_eq!(f3x.len(), 2);
+        assert!(f3x[1].starts_with("F3XN,C00123456,FRIENDS OF TEST"));
+
+        let sa = read_lines(&filing_dir.join("SA.csv"))?;
+        assert_eq!(sa.len(), 3);
+        assert!(sa[1].contains("SA11AI.4126"));
+        assert!(sa[2].contains("SA11AI.4127"));
+
+        let sb = read_lines(&filing_dir.join("SB.csv"))?;
+        assert_eq!(sb.len(), 2);
+        assert!(sb[1].contains("PRINT SHOP LLC"));
+
+        let se = read_lines(&filing_dir.join("SE.csv"))?;
+        assert_eq!(se.len(), 2);
+        assert!(se[1].contains("AD AGENCY"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hostile_form_types_get_safe_file_names() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hostile_forms_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1054".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1054")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let filing_dir = dir.path().join("1054");
+        // Neither `A?B:C` nor `CON` is a form type with a mapping, so their rows are kept whole
+        // with the unknown ones
+        let unknown = read_lines(&filing_dir.join("unknown_forms.csv"))?;
+        assert_eq!(unknown.len(), 3, "{unknown:?}");
+        assert!(unknown[1].starts_with("4,A?B:C,"), "{unknown:?}");
+        assert!(unknown[2].starts_with("5,CON,"), "{unknown:?}");
+
+        // Rows written under those names directly get files with safe names
+        writer.write_csv_record("A?B:C", &["A?B:C".to_string()])?;
+        writer.write_csv_record("CON", &["CON".to_string()])?;
+        writer.flush_all()?;
+        let names = file_names(&filing_dir)?;
+        assert!(names.contains(&format!("{}.csv", sanitize_filename("A?B:C"))));
+        assert!(names.contains(&format!("{}.csv", sanitize_filename("CON"))));
+        for name in names {
+            let stem = name.split('.').next().unwrap_or_default();
+            assert_eq!(sanitize_filename(stem), stem, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrecognized_form_type_goes_to_fallback_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = "HDR,FEC,5.00,TEST,1.0\n,C00123456,NO FORM\nSA11AI,C00123456,SA.1\n";
+        let mut reader = BufReader::new(input.as_bytes());
+
+        let mut ctx = FecContext::new("1002".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1002")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let filing_dir = dir.path().join("1002");
+        let unknown = read_lines(&filing_dir.join("unknown_forms.csv"))?;
+        assert_eq!(
+            unknown,
+            vec!["line_number,form_type,line", "2,,\",C00123456,NO FORM\""]
+        );
+        assert_eq!(ctx.unknown_form_rows, 1);
+        let sa = read_lines(&filing_dir.join("SA.csv"))?;
+        assert_eq!(sa[1].trim_end_matches(','), "SA11AI,C00123456,SA.1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_form_rows_quarantined_verbatim() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("unknown_form_8.3.fec", dir.path(), "1044")?;
+        assert_eq!(ctx.unknown_form_rows, 1);
+
+        let filing_dir = dir.path().join("1044");
+        assert!(!filing_dir.join("ZZ9.csv").exists());
+        assert_eq!(read_lines(&filing_dir.join("SA.csv"))?.len(), 3);
+
+        let mut rdr = csv::Reader::from_path(filing_dir.join("unknown_forms.csv"))?;
+        assert_eq!(rdr.headers()?, vec!["line_number", "form_type", "line"]);
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(
+            rows[0],
+            vec!["3", "ZZ9", "ZZ9\x1cC00123456\x1cMADE UP, \"FORM\"\x1c12.50"]
+        );
+
+        // The quarantine file is only created when needed
+        parse_fixture("multi_form_8.3.fec", dir.path(), "1045")?;
+        assert!(!dir.path().join("1045/unknown_forms.csv").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mapped_header_row() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+
+        let mut ctx = FecContext::new("1003".to_string(), false, true, false);
+        ctx.version = Some("8.3".to_string());
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1003")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let sa = read_lines(&dir.path().join("1003/SA.csv"))?;
+        assert!(sa[0].starts_with("form_type,filer_committee_id_number,transaction_id,"));
+        let sb = read_lines(&dir.path().join("1003/SB.csv"))?;
+        assert!(sb[0].starts_with("form_type,filer_committee_id_number,transaction_id_number,"));
+
+        Ok(())
+    }
+
+    /// Parse an in-memory filing without writing anything to disk.
+    fn parse_str(input: &str) -> Result<FecContext> {
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut ctx = FecContext::new("test".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .filing_id("test")
+            .write_to_disk(false)
+            .discard_output()
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        Ok(ctx)
+    }
+
+    #[test]
+    fn test_header_info_ascii28() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("multi_form_8.3.fec", dir.path(), "1004")?;
+
+        let header = ctx.header.expect("header should be parsed");
+        assert_eq!(header.record_type, "HDR");
+        assert_eq!(header.ef_type, "FEC");
+        assert_eq!(header.version, "8.3");
+        assert_eq!(header.soft_name.as_deref(), Some("NGP VAN"));
+        assert_eq!(header.soft_ver.as_deref(), Some("8.3.0.0(f1)"));
+        assert_eq!(header.report_id, None);
+        assert_eq!(header.report_number.as_deref(), Some("001"));
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_file_written_for_each_filing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture("multi_form_8.3.fec", dir.path(), "1007")?;
+
+        let path = dir.path().join("1007/header.csv");
+        assert_eq!(read_lines(&path)?.len(), 2);
+        let mut rdr = csv::Reader::from_path(path)?;
+        let columns: Vec<String> = rdr.headers()?.iter().map(String::from).collect();
+        assert_eq!(columns, HEADER_COLUMNS);
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        let row = |column: &str| {
+            let i = HEADER_COLUMNS.iter().position(|c| *c == column).unwrap();
+            rows[0].get(i).unwrap_or_default().to_string()
+        };
+        assert_eq!(row("filing_id"), "1007");
+        assert_eq!(row("record_type"), "HDR");
+        assert_eq!(row("fec_version"), "8.3");
+        assert_eq!(row("soft_name"), "NGP VAN");
+        assert_eq!(row("soft_ver"), "8.3.0.0(f1)");
+        assert_eq!(row("delimiter"), "ascii28");
+        assert_eq!(row("byte_order_mark"), "false");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_info_quoted_fields() -> Result<()> {
+        let ctx = parse_str(
+            "\"HDR\",\"FEC\",\" 5.00 \",\"Vendor, Inc.\",\"2.1\",\"^\",\"FEC-424242\",\"002\"\n",
+        )?;
+
+        let header = ctx.header.expect("header should be parsed");
+        assert_eq!(header.fec_version, " 5.00 ");
+        assert_eq!(header.version, "5.00");
+        assert_eq!(header.soft_name.as_deref(), Some("Vendor, Inc."));
+        assert_eq!(header.report_id.as_deref(), Some("FEC-424242"));
+        assert_eq!(header.report_number.as_deref(), Some("002"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_info_missing_trailing_fields() -> Result<()> {
+        let ctx = parse_str("HDR\x1cFEC\x1c8.1\n")?;
+
+        let header = ctx.header.expect("header should be parsed");
+        assert_eq!(header.version, "8.1");
+        assert_eq!(header.soft_name, None);
+        assert_eq!(header.soft_ver, None);
+        assert_eq!(header.report_id, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_header_block() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("legacy_2.02.fec", dir.path(), "1005")?;
+
+        assert_eq!(ctx.version.as_deref(), Some("2.02"));
+        let header = ctx.header.expect("legacy header should be parsed");
+        assert_eq!(header.soft_name.as_deref(), Some("FECfile"));
+        assert_eq!(header.soft_ver.as_deref(), Some("3.0"));
+        assert_eq!(header.report_id.as_deref(), Some("FEC-12345"));
+        assert_eq!(
+            header.schedule_counts,
+            vec![("SA11A1".to_string(), 2), ("SB17".to_string(), 1)]
+        );
+
+        // Only the body rows are written: no files for the header's key/value lines
+        let mut files: Vec<String> = fs::read_dir(dir.path().join("1005"))?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<_>>()?;
+        files.sort();
+        assert_eq!(files, vec!["F3N.csv", "SA.csv", "SB.csv", "header.csv"]);
+        assert_eq!(read_lines(&dir.path().join("1005/SA.csv"))?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_header_lines_decoded_like_body_rows() -> Result<()> {
+        let input = b"/* Header\nFEC_Ver_# = 2.02\nSoft_Name = FEC\x93file\0\n/* End Header */\n\
+                      F3N,C00100000,COMMITTEE TO ELECT\n";
+        let parse = |ctx: &mut FecContext| -> Result<()> {
+            let mut writer = WriterContext::builder()
+                .filing_id("legacy")
+                .write_to_disk(false)
+                .discard_output()
+                .build()?;
+            parse_fec(ctx, &mut &input[..], &mut writer)
+        };
+        let context = || FecContext::new("legacy".to_string(), false, true, false);
+        let soft_name = |ctx: FecContext| ctx.header.and_then(|header| header.soft_name);
+
+        // Read as Windows-1252, with the NUL byte stripped or replaced and counted
+        let mut ctx = context();
+        parse(&mut ctx)?;
+        assert_eq!(ctx.nul_bytes, 1);
+        assert_eq!(soft_name(ctx).as_deref(), Some("FEC\u{201C}file"));
+        let mut ctx = context().with_nul_policy(NulPolicy::Replace);
+        parse(&mut ctx)?;
+        assert_eq!(ctx.nul_bytes, 1);
+        assert_eq!(soft_name(ctx).as_deref(), Some("FEC\u{201C}file\u{FFFD}"));
+
+        // Lines that aren't UTF-8 fail without a fallback, or in strict mode
+        let mut ctx = context().with_fallback_encoding(FallbackEncoding::Error);
+        let mut strict = context().with_strict(true);
+        for ctx in [&mut ctx, &mut strict] {
+            let err = parse(ctx).unwrap_err();
+            match err.downcast_ref::<FecError>() {
+                Some(FecError::InvalidEncoding { line }) => assert_eq!(*line, 3),
+                other => panic!("expected InvalidEncoding, got {other:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_header_leaves_reader_at_body() -> Result<()> {
+        let fixture = |name: &str| -> Result<(Vec<u8>, BufReader<fs::File>)> {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(name);
+            Ok((fs::read(&path)?, BufReader::new(fs::File::open(&path)?)))
+        };
+
+        // Modern: only the HDR line is consumed
+        let (bytes, mut reader) = fixture("multi_form_8.3.fec")?;
+        let header = read_header(&mut reader)?;
+        assert_eq!(header.version, "8.3");
+        assert_eq!(header.soft_name.as_deref(), Some("NGP VAN"));
+        assert_eq!(header.delimiter, Delimiter::Ascii28);
+        let first_line_end = bytes.iter().position(|b| *b == b'\n').unwrap() + 1;
+        assert_eq!(header.body_offset, first_line_end as u64);
+        let mut next = String::new();
+        reader.read_line(&mut next)?;
+        assert!(next.starts_with("F3XN\x1c"), "{next:?}");
+
+        // Legacy: the whole block is consumed, up to the first body row
+        let (bytes, mut reader) = fixture("legacy_2.02.fec")?;
+        let header = read_header(&mut reader)?;
+        assert_eq!(header.version, "2.02");
+        assert_eq!(header.report_id.as_deref(), Some("FEC-12345"));
+        assert_eq!(header.delimiter, Delimiter::Comma);
+        let body = &bytes[header.body_offset as usize..];
+        assert!(body.starts_with(b"F3N,C00100000,"));
+        next.clear();
+        reader.read_line(&mut next)?;
+        assert!(next.starts_with("F3N,C00100000,"), "{next:?}");
+
+        // The offset counts a byte-order mark; input without a header is an error
+        let mut input = &b"\xEF\xBB\xBFHDR,FEC,5.00,Vendor\nSA11AI,C00123456\n"[..];
+        assert_eq!(read_header(&mut input)?.body_offset, 23);
+        assert_eq!(input, b"SA11AI,C00123456\n");
+        assert!(read_header(&mut &b"SA11AI,C00123456\n"[..]).is_err());
+        assert!(read_header(&mut &b""[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_variants_recognized() -> Result<()> {
+        let cases = [
+            (
+                "HDR,FEC,8.3,NGP VAN,8.3.0.0(f1),,001\n",
+                HeaderVariant::Hdr,
+                "8.3",
+            ),
+            (
+                "HDRFEC\x1c8.3\x1cNGP VAN\x1c8.3.0.0(f1)\x1c\x1c001\n",
+                HeaderVariant::HdrFec,
+                "8.3",
+            ),
+            (
+                "\"FEC\",\"5.00\",\"NGP VAN\",\"8.3.0.0(f1)\"\n",
+                HeaderVariant::Fec,
+                "5.00",
+            ),
+            (
+                "/* Header\nFEC_Ver_# = 2.02\nSoft_Name = NGP VAN\n/* End Header */\n",
+                HeaderVariant::Legacy,
+                "2.02",
+            ),
+        ];
+        for (line, variant, version) in cases {
+            let header = read_header(&mut line.as_bytes())?;
+            assert_eq!(header.variant, variant, "{line:?}");
+            assert_eq!(header.version, version, "{line:?}");
+            assert_eq!(header.soft_name.as_deref(), Some("NGP VAN"), "{line:?}");
+        }
+
+        // The header record's fields are rewritten into the `HDR,FEC,version` layout
+        let mut ctx = FecContext::new("1060".to_string(), false, true, false);
+        let header = FecRecords::new(&mut ctx, "FEC,5.00,NGP VAN\n".as_bytes())
+            .next()
+            .transpose()?
+            .expect("header record");
+        assert_eq!(header.kind, RecordKind::Header);
+        assert_eq!(header.fields[..4], ["HDR", "FEC", "5.00", "NGP VAN"]);
+
+        // Anything else is named in the error
+        let garbage = "GARBAGE\x1c8.3\x1cThis line goes on well past forty bytes\n";
+        let err = parse_str(garbage).unwrap_err();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::UnrecognizedHeader { line, start }) => {
+                assert_eq!(*line, 1);
+                assert_eq!(start, &garbage[..40]);
+            }
+            other => panic!("expected UnrecognizedHeader, got {other:?}"),
+        }
+        assert!(read_header(&mut garbage.as_bytes()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_exports_detected() {
+        assert!(is_csv_export_header(
+            "form_type,filer_committee_id_number,transaction_id"
+        ));
+        assert!(is_csv_export_header(
+            "\"FILER_COMMITTEE_ID_NUMBER\",\"FORM_TYPE\",\"DONOR_COMMITTEE_FEC_ID\""
+        ));
+        assert!(!is_csv_export_header("HDR,FEC,8.3,NGP VAN"));
+        assert!(!is_csv_export_header("record_type,FEC,8.3"));
+        assert!(!is_csv_export_header(
+            "form_type\x1cfiler_committee_id_number"
+        ));
+        assert!(!is_csv_export_header("/* Header"));
+        assert!(!is_csv_export_header("SA11AI,C00123456,SA.1"));
+    }
+
+    #[test]
+    fn test_csv_exports_rejected_or_mapped_by_name() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = "filer_committee_id_number,form_type,contribution_amount,transaction_id,fec_report_id\n\
+                     C00123456,SA11AI,250.00,SA.1,1234\n\
+                     C00123456,sb23,,SB.1,1234\n";
+
+        // By default the input is refused with an explanation
+        let err = parse_input(input.as_bytes(), dir.path(), "1052").unwrap_err();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::CsvExport { line, first_column }) => {
+                assert_eq!(
+                    (*line, first_column.as_str()),
+                    (1, "filer_committee_id_number")
+                );
+            }
+            other => panic!("expected CsvExport, got {other:?}"),
+        }
+        assert!(err
+            .to_string()
+            .contains("looks like a CSV export, not a raw .fec filing"));
+        assert!(read_header(&mut input.as_bytes()).is_err());
+
+        // Mapped: each row lands in its form's file with its fields moved into place by name
+        let mut ctx =
+            FecContext::new("1053".to_string(), false, true, false).with_map_csv_exports(true);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1053")
+            .build()?;
+        parse_fec(&mut ctx, &mut input.as_bytes(), &mut writer)?;
+        writer.flush_all()?;
+        assert_eq!(ctx.version.as_deref(), Some(NEWEST_VERSION));
+        assert_eq!(ctx.header, None);
+        assert_eq!(ctx.mismatched_rows, 0);
+
+        let sa = csv::Reader::from_path(dir.path().join("1053/SA.csv"))?
+            .into_deserialize::<std::collections::HashMap<String, String>>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(sa.len(), 1);
+        assert_eq!(sa[0]["form_type"], "SA11AI");
+        assert_eq!(sa[0]["filer_committee_id_number"], "C00123456");
+        assert_eq!(sa[0]["transaction_id"], "SA.1");
+        assert_eq!(sa[0]["contribution_amount"], "250.00");
+        assert_eq!(sa[0]["contributor_last_name"], "");
+        let sb = read_lines(&dir.path().join("1053/SB.csv"))?;
+        assert_eq!(sb.len(), 2);
+        // SB names its id column `transaction_id_number`, which the export lacks
+        assert!(sb[1].starts_with("sb23,C00123456,,"), "{}", sb[1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_f99_text_block_attached_to_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture("f99_text_8.3.fec", dir.path(), "1006")?;
+
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(dir.path().join("1006/F99.csv"))?;
+        let headers = rdr.headers()?.clone();
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), headers.len());
+        assert_eq!(headers.get(headers.len() - 1), Some("text"));
+        assert_eq!(
+            rows[0].get(rows[0].len() - 1),
+            Some(
+                "This letter responds to \"your\" inquiry, dated March 1.\n\
+                 Second line, with commas, and \"quotes\"."
+            )
+        );
+        assert_eq!(rows[0].get(14), Some("MSI"));
+
+        // The row after the text block is parsed normally
+        assert_eq!(read_lines(&dir.path().join("1006/SA.csv"))?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_f99_text_written_verbatim_to_text_file() -> Result<()> {
+        let input = "HDR,FEC,8.3,Vendor,1.0\n\
+                     F99,C00123456,TEST,,,,,,,,20230101,MSM\n\
+                     [BEGINTEXT]\r\nFirst, \"quoted\" line.\r\n\r\nThird line.\r\n[ENDTEXT]\r\n\
+                     SA11AI,C00123456,SA.1\n\
+                     F99,C00123456,TEST,,,,,,,,20230102,MSM\n\
+                     [BEGINTEXT]\nNo terminator\nat the end";
+
+        for threads in [1, 4] {
+            let dir = tempfile::tempdir()?;
+            let mut ctx =
+                FecContext::new("1040".to_string(), false, true, false).with_f99_text_file(true);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("1040")
+                .build()?;
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(16);
+            parse_fec_with_options(&mut ctx, &mut input.as_bytes(), &mut writer, &options)?;
+            writer.flush_all()?;
+
+            // Each block is written as read, one after the other
+            let text = fs::read_to_string(dir.path().join("1040/F99.txt"))?;
+            assert_eq!(
+                text,
+                "First, \"quoted\" line.\r\n\r\nThird line.\r\nNo terminator\nat the end"
+            );
+
+            // The CSV still holds each block as one escaped field
+            let mut rdr = csv::Reader::from_path(dir.path().join("1040/F99.csv"))?;
+            let texts: Vec<String> = rdr
+                .records()
+                .map(|r| Ok(r?.iter().next_back().unwrap_or_default().to_string()))
+                .collect::<Result<_>>()?;
+            assert_eq!(
+                texts,
+                [
+                    "First, \"quoted\" line.\n\nThird line.",
+                    "No terminator\nat the end"
+                ]
+            );
+        }
+
+        // Without the option only the CSV is written
+        let dir = tempfile::tempdir()?;
+        parse_input(input.as_bytes(), dir.path(), "1041")?;
+        assert!(dir.path().join("1041/F99.csv").exists());
+        assert!(!dir.path().join("1041/F99.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_f99_text_streamed_like_buffered_text() -> Result<()> {
+        let mut input = String::from(
+            "HDR,FEC,8.3,Vendor,1.0\n\
+             F99,C00123456,TEST,,,,,,,,20230101,MSM\n\
+             [BEGINTEXT]\r\n",
+        );
+        for i in 0..40 {
+            input.push_str(&format!("Line {i}, with \"quotes\" and commas.\r\n"));
+        }
+        input.push_str(
+            "[ENDTEXT]\r\n\
+             SA11AI,C00123456,SA.1\n\
+             F99,C00123456,TEST,,,,,,,,20230102,MSM\n\
+             [BEGINTEXT]\nShort text\n[ENDTEXT]\n\
+             F99,C00123456,TEST,,,,,,,,20230103,MSM\n\
+             [BEGINTEXT]\n",
+        );
+        input.push_str(&"Unterminated at the end\n".repeat(10));
+
+        let parse = |dir: &Path, max_f99_text: usize, threads: usize| -> Result<FecContext> {
+            let mut ctx = FecContext::new("1042".to_string(), false, true, false)
+                .with_f99_text_file(true)
+                .with_max_f99_text(max_f99_text);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.to_string_lossy())
+                .filing_id("1042")
+                .build()?;
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(256);
+            parse_fec_with_options(&mut ctx, &mut input.as_bytes(), &mut writer, &options)?;
+            writer.flush_all()?;
+            Ok(ctx)
+        };
+
+        let buffered = tempfile::tempdir()?;
+        parse(buffered.path(), usize::MAX, 1)?;
+        let expected = read_output(&buffered.path().join("1042"))?;
+        assert_eq!(expected.len(), 3);
+        for threads in [1, 4] {
+            let streamed = tempfile::tempdir()?;
+            parse(streamed.path(), 64, threads)?;
+            assert_eq!(read_output(&streamed.path().join("1042"))?, expected);
+        }
+
+        // Callbacks see the F99 record without its text, then the text in chunks
+        let mut ctx = FecContext::new("1043".to_string(), false, true, false).with_max_f99_text(64);
+        let mut records = Vec::new();
+        parse_fec_with_callback(&mut ctx, input.as_bytes(), |record| {
+            records.push(record.clone());
+            Ok(ControlFlow::Continue(()))
+        })?;
+        let f99 = &records[0];
+        assert!(f99.text_continues);
+        assert_eq!(
+            f99.fields.len() + 1,
+            get_columns("F99", "8.3").unwrap().len()
+        );
+        let chunks: Vec<_> = records
+            .iter()
+            .skip(1)
+            .take_while(|r| r.kind == RecordKind::TextChunk)
+            .collect();
+        assert!(chunks.len() > 10);
+        assert!(chunks.iter().rev().skip(1).all(|c| c.text_continues));
+        assert!(!chunks[chunks.len() - 1].text_continues);
+
+        // Together they span the input the buffered record was read from
+        let text: String = chunks
+            .iter()
+            .map(|c| c.raw_text.as_deref().unwrap_or_default())
+            .collect();
+        assert!(text.starts_with("Line 0, with"));
+        assert!(text.ends_with("Line 39, with \"quotes\" and commas.\r\n"));
+        let mut offset = f99.byte_end;
+        for chunk in &chunks {
+            assert_eq!(chunk.byte_start, offset);
+            offset = chunk.byte_end;
+        }
+        assert_eq!(&input[offset as usize..][..21], "SA11AI,C00123456,SA.1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_marker_state_machine() {
+        let ctx = FecContext::new("1020".to_string(), false, true, false);
+        let lines = [
+            "[ENDTEXT]",
+            " [begin text] ",
+            "[BEGINTEXT]",
+            "Text [with brackets]",
+            "[END TEXT]",
+            "SA11AI,C00123456,SA.1",
+        ];
+        let mut state = TextState::Outside;
+        let events: Vec<TextEvent> = lines
+            .iter()
+            .map(|line| {
+                let event = ctx.text_event(state, line);
+                state = event.next_state();
+                event
+            })
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                TextEvent::StrayEnd,
+                TextEvent::Begin,
+                TextEvent::NestedBegin,
+                TextEvent::Text,
+                TextEvent::End,
+                TextEvent::Record,
+            ]
+        );
+        assert_eq!(state, TextState::Outside);
+    }
+
+    #[test]
+    fn test_unbalanced_text_markers() -> Result<()> {
+        let input = "HDR,FEC,8.3,Vendor,1.0\n\
+                     SA11AI,C00123456,SA.1\n\
+                     [ENDTEXT]\n\
+                     F99,C00123456,TEST,,,,,,,,20230101,MSM\n\
+                     [BEGINTEXT]\n\
+                     [BEGINTEXT]\n\
+                     Body\n\
+                     [ENDTEXT]\n\
+                     SA11AI,C00123456,SA.2\n\
+                     F99,C00123456,TEST,,,,,,,,20230102,MSM\n\
+                     [BEGINTEXT]\n\
+                     Unterminated\n";
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("1020".to_string(), false, true, false);
+        ctx.issues = Some(Vec::new());
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1020")
+            .build()?;
+        parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+        writer.flush_all()?;
+
+        // The stray end marker is skipped rather than quarantined as a row
+        let filing_dir = dir.path().join("1020");
+        assert!(!filing_dir.join("unknown_forms.csv").exists());
+        assert_eq!(read_lines(&filing_dir.join("SA.csv"))?.len(), 3);
+
+        // A nested begin marker is text, and an unterminated block is kept at the end of input
+        let mut rdr = csv::Reader::from_path(filing_dir.join("F99.csv"))?;
+        let texts: Vec<String> = rdr
+            .records()
+            .map(|row| Ok(row?.iter().next_back().unwrap_or_default().to_string()))
+            .collect::<Result<_>>()?;
+        assert_eq!(texts, vec!["[BEGINTEXT]\nBody", "Unterminated"]);
+
+        let issues: Vec<(usize, &str)> = ctx
+            .issues
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|issue| !issue.message.contains("fields, expected"))
+            .map(|issue| (issue.line, issue.message.as_str()))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (3, "Skipping an [ENDTEXT] outside any F99 text block."),
+                (6, "Keeping a [BEGINTEXT] inside an F99 text block as text."),
+                (
+                    12,
+                    "F99 text block was not terminated before the end of input."
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_records_written_with_mapped_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture("text_8.3.fec", dir.path(), "1042")?;
+
+        let mut rdr = csv::Reader::from_path(dir.path().join("1042/TEXT.csv"))?;
+        assert_eq!(
+            rdr.headers()?,
+            vec![
+                "rec_type",
+                "filer_committee_id_number",
+                "transaction_id_number",
+                "back_reference_tran_id_number",
+                "back_reference_sched_form_name",
+                "text",
+            ]
+        );
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].get(3), Some("SA11AI.4126"));
+        assert_eq!(
+            rows[0].get(5),
+            Some("Earmarked through ACTBLUE, \"per\" donor")
+        );
+        assert_eq!(rows[1].get(3), Some("SA11AI.4127"));
+
+        // TEXT rows stay out of their parents' files
+        assert_eq!(read_lines(&dir.path().join("1042/SA.csv"))?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_full_form_names_keep_schedule_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx =
+            FecContext::new("1043".to_string(), false, true, false).with_full_form_names(true);
+        parse_fixture_with(&mut ctx, "multi_form_8.3.fec", dir.path())?;
+
+        let filing_dir = dir.path().join("1043");
+        assert!(!filing_dir.join("SA.csv").exists());
+        let sa = read_lines(&filing_dir.join("SA11AI.csv"))?;
+        assert_eq!(sa.len(), 3);
+        assert!(sa[0].starts_with("form_type,filer_committee_id_number,transaction_id,"));
+        let sb = read_lines(&filing_dir.join("SB23.csv"))?;
+        assert!(sb[0].starts_with("form_type,filer_committee_id_number,transaction_id_number,"));
+        assert!(filing_dir.join("F3XN.csv").exists());
+        assert!(filing_dir.join("SE.csv").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_form_types_normalized_before_routing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR\x1cFEC\x1c8.3\x1cTEST\x1c1.0\n");
+        for (i, form) in ["SA11AI", "sa11ai", "SA11AI  ", "\"Sa11ai\""]
+            .iter()
+            .enumerate()
+        {
+            input.push_str(&format!("{form}\x1cC00123456\x1cSA.{i}\x1c\x1c\x1cIND\n"));
+        }
+        let ctx = parse_input(input.as_bytes(), dir.path(), "1050")?;
+        assert_eq!(ctx.unknown_form_rows, 0);
+
+        // Every spelling lands in the same file, with the form type kept as written
+        let filing_dir = dir.path().join("1050");
+        assert!(!filing_dir.join("unknown_forms.csv").exists());
+        let rows: Vec<_> = csv::Reader::from_path(filing_dir.join("SA.csv"))?
+            .records()
+            .map(|r| Ok(r?[0].to_string()))
+            .collect::<Result<_>>()?;
+        assert_eq!(rows, vec!["SA11AI", "sa11ai", "SA11AI  ", "\"Sa11ai\""]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_version_uses_newest_mappings() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut fields = vec![""; 45];
+        fields[..3].copy_from_slice(&["SA11AI", "C00123456", "SA.1"]);
+        let input = format!("HDR,FEC,9.0,Vendor,1.0\n{}\n", fields.join(","));
+
+        let mut ctx = FecContext::new("1051".to_string(), false, true, false);
+        ctx.issues = Some(Vec::new());
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1051")
+            .build()?;
+        parse_fec(&mut ctx, &mut input.as_bytes(), &mut writer)?;
+        writer.flush_all()?;
+
+        // The version is warned about once, and the row fits the 8.x columns
+        let issues = ctx.issues.as_deref().unwrap_or_default();
+        assert_eq!(issues.len(), 1, "{issues:?}");
+        assert_eq!(issues[0].line, 1);
+        assert!(issues[0].message.contains("Unknown version 9.0"));
+        assert_eq!(ctx.mismatched_rows, 0);
+        let sa = read_lines(&dir.path().join("1051/SA.csv"))?;
+        assert_eq!(sa[0], get_columns("SA", "8.3").unwrap().join(","));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_row_written_once_per_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR,FEC,8.3,TEST,1.0\n");
+        for i in 0..500 {
+            input.push_str(&format!("SA11AI,C00123456,SA.{i},,,IND,,DOE,JANE\n"));
+        }
+
+        let header_lines = |path: &Path| -> Result<usize> {
+            Ok(read_lines(path)?
+                .iter()
+                .filter(|l| l.starts_with("form_type,"))
+                .count())
+        };
+
+        // Parse the same filing twice into the same directory: the second run appends
+        for run in 1..=2 {
+            let mut reader = BufReader::new(input.as_bytes());
+            let mut ctx = FecContext::new("1007".to_string(), false, true, false);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("1007")
+                .output_mode(OutputMode::Append)
+                .build()?;
+            parse_fec(&mut ctx, &mut reader, &mut writer)?;
+            writer.flush_all()?;
+
+            let sa = dir.path().join("1007/SA.csv");
+            assert_eq!(header_lines(&sa)?, 1);
+            assert_eq!(read_lines(&sa)?.len(), 1 + 500 * run);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_columns_normalized() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let row = |id: &str, amount: &str, aggregate: &str| {
+            format!(
+                "SA11AI,C00123456,{id},,,IND,,DOE,JANE,,,,1 MAIN ST,,ATLANTA,GA,30303,P2024,,\
+                 20230115,\"{amount}\",\"{aggregate}\",,ACME,ENGINEER\n"
+            )
+        };
+        let mut input = String::from("HDR,FEC,8.3,TEST,1.0\n");
+        input.push_str(&row("SA.1", "1,000.00", "$1,250.5"));
+        input.push_str(&row("SA.2", "-500", ""));
+        input.push_str(&row("SA.3", "N/A", " 42 "));
+        input.push_str(&row("SA.4", "0.30000000000000004", "$98,765,432,109.99"));
+
+        let mut reader = BufReader::new(input.as_bytes());
+        let mut ctx = FecContext::new("1008".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1008")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let sa_path = dir.path().join("1008/SA.csv");
+        let mut rdr = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_path(&sa_path)?;
+        let amounts: Vec<(String, String)> = rdr
+            .records()
+            .map(|r| r.map(|r| (r[20].to_string(), r[21].to_string())))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            amounts,
+            vec![
+                ("1000.00".to_string(), "1250.50".to_string()),
+                ("-500.00".to_string(), "".to_string()),
+                ("N/A".to_string(), "42.00".to_string()),
+                ("0.30".to_string(), "98765432109.99".to_string()),
+            ]
+        );
+
+        // Normalized amounts no longer need quoting
+        let raw = fs::read_to_string(&sa_path)?;
+        assert!(raw.contains(",1000.00,1250.50,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_columns_formatted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR,FEC,8.3,TEST,1.0\n");
+        for (id, date) in [
+            ("SA.1", "20230115"),
+            ("SA.2", "1/2/2023"),
+            ("SA.3", "SOON"),
+            ("SA.4", ""),
+        ] {
+            input.push_str(&format!(
+                "SA11AI,C00123456,{id},,,IND,,DOE,JANE,,,,1 MAIN ST,,ATLANTA,GA,30303,P2024,,\
+                 {date},250.00,250.00,,ACME,ENGINEER\n"
+            ));
+        }
+        let dates = |filing_id: &str, ctx: FecContext| -> Result<(Vec<String>, usize)> {
+            let mut ctx = ctx;
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .build()?;
+            parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+            writer.flush_all()?;
+            let mut rdr = csv::Reader::from_path(dir.path().join(filing_id).join("SA.csv"))?;
+            let dates = rdr
+                .records()
+                .map(|r| r.map(|r| r[19].to_string()))
+                .collect::<Result<_, _>>()?;
+            Ok((dates, ctx.warnings))
+        };
+
+        // As filed by default
+        let ctx = FecContext::new("1009".to_string(), false, true, false);
+        let (as_filed, warnings) = dates("1009", ctx)?;
+        assert_eq!(as_filed, ["20230115", "1/2/2023", "SOON", ""]);
+
+        for (filing_id, format, expected) in [
+            (
+                "1010",
+                DateFormat::Iso,
+                ["2023-01-15", "2023-01-02", "SOON", ""],
+            ),
+            (
+                "1011",
+                DateFormat::Compact,
+                ["20230115", "20230102", "SOON", ""],
+            ),
+        ] {
+            let ctx =
+                FecContext::new(filing_id.to_string(), false, true, false).with_date_format(format);
+            assert_eq!(
+                dates(filing_id, ctx)?,
+                // Only `SOON` is warned about besides what was before
+                (expected.map(String::from).to_vec(), warnings + 1)
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows_and_text_end_with_the_terminator() -> Result<()> {
+        for terminator in [Terminator::Lf, Terminator::Crlf] {
+            let dir = tempfile::tempdir()?;
+            let mut ctx =
+                FecContext::new("1050".to_string(), false, true, false).with_f99_text_file(true);
+            let path =
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/f99_text_8.3.fec");
+            let mut reader = BufReader::new(fs::File::open(path)?);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("1050")
+                .terminator(terminator)
+                .build()?;
+            parse_fec(&mut ctx, &mut reader, &mut writer)?;
+            writer.flush_all()?;
+
+            let end = terminator.as_str().as_bytes();
+            let ends_every_line = |bytes: &[u8]| {
+                bytes.ends_with(end)
+                    && bytes.split_inclusive(|&b| b == b'\n').all(|line| {
+                        line.ends_with(end) && !line[..line.len() - end.len()].contains(&b'\r')
+                    })
+            };
+            // Header and rows of plain forms, and each line of the F99 text file
+            let sa = fs::read(dir.path().join("1050/SA.csv"))?;
+            assert_eq!(sa.split_inclusive(|&b| b == b'\n').count(), 2);
+            assert!(ends_every_line(&sa), "{:?}", String::from_utf8_lossy(&sa));
+            let text = fs::read(dir.path().join("1050/F99.txt"))?;
+            assert!(
+                ends_every_line(&text),
+                "{:?}",
+                String::from_utf8_lossy(&text)
+            );
+
+            // The F99 row's text column keeps `\n` between its lines, inside its quotes
+            let f99 = fs::read(dir.path().join("1050/F99.csv"))?;
+            let header = f99.split_inclusive(|&b| b == b'\n').next().unwrap();
+            assert!(header.ends_with(end) && !header[..header.len() - end.len()].contains(&b'\r'));
+            assert!(f99.ends_with(&[b"\"", end].concat()));
+            let f99 = String::from_utf8(f99)?;
+            assert!(f99.contains("inquiry, dated March 1.\nSecond line"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_lists_each_file_written() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1052".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1052")
+            .manifest(true)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let filing_dir = dir.path().join("1052");
+        let mut rdr = csv::Reader::from_path(filing_dir.join(MANIFEST_FILENAME))?;
+        assert_eq!(
+            rdr.headers()?,
+            &csv::StringRecord::from(MANIFEST_COLUMNS.to_vec())
+        );
+        let mut listed = Vec::new();
+        for record in rdr.records() {
+            let record = record?;
+            let file = filing_dir.join(&record[0]);
+            assert_eq!(record[0], format!("{}.csv", &record[1]));
+            assert_eq!(record[3].parse::<u64>()?, fs::metadata(&file)?.len());
+            assert_eq!(&record[4], "true");
+            let rows = csv::ReaderBuilder::new()
+                .flexible(true)
+                .from_path(&file)?
+                .records()
+                .count();
+            assert_eq!(record[2].parse::<usize>()?, rows, "{}", &record[0]);
+            listed.push(record[0].to_string());
+        }
+
+        // Every file but the manifest itself is listed, in order
+        let mut files: Vec<String> = fs::read_dir(&filing_dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        files.retain(|name| name != MANIFEST_FILENAME);
+        files.sort();
+        assert_eq!(listed, files);
+        assert!(listed.len() > 2);
+        Ok(())
+    }
+
+    /// The names of the files in `dir`, sorted.
+    fn file_names(dir: &Path) -> Result<Vec<String>> {
+        let mut names: Vec<String> = fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        names.sort();
+        Ok(names)
+    }
+
+    /// Parse `split_8.3.fec`, whose 25 SA rows are numbered `SA11AI.1` on, with `writer`.
+    fn parse_split_fixture(writer: &mut WriterContext) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/split_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1053".to_string(), false, true, false);
+        parse_fec(&mut ctx, &mut reader, writer)?;
+        writer.flush_all()
+    }
+
+    #[test]
+    fn test_files_split_by_row_count() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1053")
+            .max_rows_per_file(Some(10))
+            .manifest(true)
+            .build()?;
+        parse_split_fixture(&mut writer)?;
+
+        let filing_dir = dir.path().join("1053");
+        let parts = ["SA.csv", "SA_002.csv", "SA_003.csv"];
+        let mut ids = Vec::new();
+        let mut header = None;
+        for (part, expected_rows) in parts.iter().zip([10, 10, 5]) {
+            let lines = read_lines(&filing_dir.join(part))?;
+            // Each part starts with the same header row
+            assert!(lines[0].starts_with("form_type,"), "{part}");
+            assert_eq!(header.get_or_insert(lines[0].clone()), &lines[0]);
+            assert_eq!(lines.len() - 1, expected_rows, "{part}");
+            ids.extend(
+                lines[1..]
+                    .iter()
+                    .map(|line| line.split(',').nth(2).unwrap().to_string()),
+            );
+        }
+        let expected: Vec<String> = (1..=25).map(|i| format!("SA11AI.{i}")).collect();
+        assert_eq!(ids, expected);
+        assert!(!filing_dir.join("SA_004.csv").exists());
+        assert!(!filing_dir.join("F3XN_002.csv").exists());
+
+        // Every part is counted and listed on its own
+        let stats = writer.file_stats();
+        let rows: Vec<(&str, usize)> = stats
+            .iter()
+            .filter(|(path, _)| path.contains("/SA"))
+            .map(|(path, stats)| (path.as_str(), stats.rows))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("1053/SA.csv", 10),
+                ("1053/SA_002.csv", 10),
+                ("1053/SA_003.csv", 5)
+            ]
+        );
+        let mut rdr = csv::Reader::from_path(filing_dir.join(MANIFEST_FILENAME))?;
+        let listed: Vec<(String, String, String)> = rdr
+            .records()
+            .map(|record| {
+                let record = record?;
+                Ok((
+                    record[0].to_string(),
+                    record[1].to_string(),
+                    record[2].to_string(),
+                ))
+            })
+            .collect::<Result<_>>()?;
+        for (part, rows) in parts.iter().zip(["10", "10", "5"]) {
+            let entry = (part.to_string(), "SA".to_string(), rows.to_string());
+            assert!(listed.contains(&entry), "{listed:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_files_split_by_size() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let max_bytes = 2000;
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1053")
+            .max_bytes_per_file(Some(max_bytes))
+            .build()?;
+        parse_split_fixture(&mut writer)?;
+
+        let filing_dir = dir.path().join("1053");
+        let mut parts: Vec<String> = file_names(&filing_dir)?
+            .into_iter()
+            .filter(|name| name.starts_with("SA"))
+            .collect();
+        parts.sort();
+        assert!(parts.len() > 1, "{parts:?}");
+        let mut rows = 0;
+        for (index, part) in parts.iter().enumerate() {
+            let expected = match index {
+                0 => "SA.csv".to_string(),
+                _ => format!("SA_{:03}.csv", index + 1),
+            };
+            assert_eq!(part, &expected);
+            let path = filing_dir.join(part);
+            let lines = read_lines(&path)?;
+            assert!(lines[0].starts_with("form_type,"), "{part}");
+            assert!(lines.len() > 1, "{part} has no rows");
+            // Only a part holding a single row may go past the limit
+            assert!(fs::metadata(&path)?.len() <= max_bytes || lines.len() == 2);
+            rows += lines.len() - 1;
+        }
+        assert_eq!(rows, 25);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filename_templates_nest_and_flatten_output() -> Result<()> {
+        // Nested by form, with the manifest listing files relative to itself
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1052".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1052")
+            .filename_template(Some("{form}/{filing_id}.csv".to_string()))
+            .manifest(true)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        let sa = read_lines(&dir.path().join("SA/1052.csv"))?;
+        assert!(sa[0].starts_with("form_type,"));
+        assert!(dir.path().join("F3XN/1052.csv").exists());
+        assert!(!dir.path().join("1052").exists());
+        let stats = writer.file_stats();
+        assert!(
+            stats.iter().any(|(path, _)| path == "SA/1052.csv"),
+            "{stats:?}"
+        );
+        let manifest = read_lines(&dir.path().join("manifest/1052.csv"))?;
+        assert!(manifest
+            .iter()
+            .any(|line| line.starts_with("../SA/1052.csv,SA,")));
+
+        // Flat, with split files numbered after the whole name
+        let dir = tempfile::tempdir()?;
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1053")
+            .filename_template(Some("{filing_id}_{form}.csv".to_string()))
+            .max_rows_per_file(Some(10))
+            .build()?;
+        parse_split_fixture(&mut writer)?;
+        let names = file_names(dir.path())?;
+        assert_eq!(
+            names,
+            [
+                "1053_F3XN.csv",
+                "1053_SA.csv",
+                "1053_SA_002.csv",
+                "1053_SA_003.csv",
+                "1053_cover.csv",
+                "1053_header.csv",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_files_rejected_when_appending() {
+        let err = WriterContext::builder()
+            .output_mode(OutputMode::Append)
+            .max_rows_per_file(Some(10))
+            .build()
+            .err()
+            .expect("split files can't be appended to");
+        assert!(err.to_string().contains("Split output files"), "{err}");
+    }
+
+    #[test]
+    fn test_atomic_writes_renamed_on_finalize() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1052".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1052")
+            .compression(Compression::Gzip)
+            .manifest(true)
+            .atomic_writes(true)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+
+        // Until finalized, every file is only there under its temporary name
+        writer.flush_all()?;
+        let filing_dir = dir.path().join("1052");
+        let temp_names = file_names(&filing_dir)?;
+        assert!(temp_names.len() > 2);
+        assert!(temp_names.iter().all(|name| name.ends_with(".tmp")));
+        assert!(temp_names.contains(&"SA.csv.gz.tmp".to_string()));
+
+        writer.finalize()?;
+        let names = file_names(&filing_dir)?;
+        let renamed: Vec<String> = temp_names
+            .iter()
+            .map(|name| name.trim_end_matches(".tmp").to_string())
+            .collect();
+        assert_eq!(names, renamed);
+        assert!(names.contains(&MANIFEST_FILENAME.to_string()));
+        let manifest = fs::read_to_string(filing_dir.join(MANIFEST_FILENAME))?;
+        assert!(manifest.contains("\nSA.csv.gz,"), "{manifest}");
+        assert!(!manifest.contains(".tmp"), "{manifest}");
+
+        // Dropping a finalized writer leaves its files alone
+        drop(writer);
+        assert_eq!(file_names(&filing_dir)?, names);
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_writes_removed_after_failed_parse() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/line_numbers_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("1016".to_string(), false, true, false).with_strict(true);
+        // A tiny buffer, so the rows before the failing one reach the disk mid-parse
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1016")
+            .buffer_size(1)
+            .atomic_writes(true)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)
+            .expect_err("the short SA row on line 7 should fail in strict mode");
+
+        let filing_dir = dir.path().join("1016");
+        let names = file_names(&filing_dir)?;
+        assert!(!names.is_empty());
+        assert!(names.iter().all(|name| name.ends_with(".tmp")), "{names:?}");
+
+        drop(writer);
+        assert_eq!(file_names(&filing_dir)?, Vec::<String>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_writes_rejected_when_appending() {
+        let err = WriterContext::builder()
+            .output_mode(OutputMode::Append)
+            .atomic_writes(true)
+            .build()
+            .err()
+            .expect("atomic writes can't append");
+        assert!(err.to_string().contains("Atomic writes"), "{err}");
+    }
+
+    #[test]
+    fn test_summary_mode_stops_after_cover_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR,FEC,8.3,NGP VAN,8.3.0.0(f1),,001,\n");
+        input.push_str("F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1\n");
+        for i in 0..10_000 {
+            input.push_str(&format!(
+                "SA11AI,C00123456,SA11AI.{i},,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,250.00\n"
+            ));
+        }
+        let total_len = input.len() as u64;
+        let mut reader = Cursor::new(input.into_bytes());
+
+        let mut ctx = FecContext::new("1010".to_string(), false, true, false).with_summary(true);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1010")
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()?;
+
+        assert!(ctx.summary_written);
+        // Only the header and cover lines were consumed
+        assert_eq!(ctx.line_number, 2);
+        assert!(reader.position() < total_len / 100);
+
+        let filing_dir = dir.path().join("1010");
+        let mut files: Vec<_> = fs::read_dir(&filing_dir)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<_>>()?;
+        files.sort();
+        assert_eq!(files, vec!["F3XN.csv", "header.csv"]);
+
+        let f3x = read_lines(&filing_dir.join("F3XN.csv"))?;
+        assert_eq!(f3x.len(), 2);
+        assert!(f3x[0].starts_with("form_type,"));
+        assert!(f3x[1].starts_with("F3XN,C00123456,FRIENDS OF TEST"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_form_type_and_num_fields_tracked() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = parse_fixture("multi_form_8.3.fec", dir.path(), "1011")?;
+
+        // The fixture's last row is a Schedule E
+        assert_eq!(ctx.form_type(), Some("SE"));
+        assert_eq!(ctx.num_fields(), get_columns("SE", "8.3").unwrap().len());
+
+        // Feed further rows directly, comma-delimited this time
+        ctx.delimiter = Delimiter::Comma;
+        let mut writer = WriterContext::builder()
+            .filing_id("1011")
+            .write_to_disk(false)
+            .discard_output()
+            .build()?;
+        parse_line(&mut ctx, "sa11ai,C00123456,SA.1", &mut writer)?;
+        assert_eq!(ctx.form_type(), Some("SA11AI"));
+        assert_eq!(ctx.num_fields(), get_columns("SA", "8.3").unwrap().len());
+
+        parse_line(&mut ctx, "ZZZ9,C00123456", &mut writer)?;
+        assert_eq!(ctx.form_type(), Some("ZZZ9"));
+        assert_eq!(ctx.num_fields(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_from_comma_header() -> Result<()> {
+        let ctx = parse_str("HDR,FEC, 8.1 ,Vendor,1.0\nF3XN,C00123456,TEST\n")?;
+
+        assert_eq!(ctx.version.as_deref(), Some("8.1"));
+        assert_eq!(ctx.version_length, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_from_ascii28_header() -> Result<()> {
+        let ctx = parse_str("HDR\x1cFEC\x1c8.3\x1cVendor\x1c1.0\nF3XN\x1cC00123456\x1cTEST\n")?;
+
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_body_row_mentioning_version_ignored() -> Result<()> {
+        let ctx = parse_str(concat!(
+            "HDR,FEC,8.3,Vendor,1.0\n",
+            "SA11AI,VERSION 2 OF REFUND,SA.1,,,IND,,SMITH,JANE\n",
+            "SB23,C00123456,SB.1,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,SOFTWARE VERSION UPGRADE\n",
+        ))?;
+
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+        assert_eq!(ctx.version_length, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_count_mismatches_counted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("field_counts_8.3.fec", dir.path(), "1013")?;
+
+        // One short SA row and one long SE row; trailing empty fields don't count
+        assert_eq!(ctx.mismatched_rows, 2);
+
+        // Mismatched rows are still written
+        let filing_dir = dir.path().join("1013");
+        assert_eq!(read_lines(&filing_dir.join("SA.csv"))?.len(), 3);
+        assert_eq!(read_lines(&filing_dir.join("SE.csv"))?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_field_count_mismatch_fails_in_strict_mode() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("1014".to_string(), false, true, false).with_strict(true);
+        let err = parse_fixture_with(&mut ctx, "field_counts_8.3.fec", dir.path())
+            .expect_err("strict mode should reject the short row");
+
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::FieldCountMismatch {
+                line,
+                form_type,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(*line, 3);
+                assert_eq!(form_type, "SA11AI");
+                assert_eq!(*expected, 45);
+                assert_eq!(*actual, 9);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_numbers_span_text_blocks_and_quoted_records() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("line_numbers_8.3.fec", dir.path(), "1015")?;
+
+        assert_eq!(ctx.line_number, 7);
+        let f99 = fs::read_to_string(dir.path().join("1015").join("F99.csv"))?;
+        assert!(f99.contains("\"COMMITTEE TO\nTEST THINGS\""));
+        assert!(f99.contains("Please see the attached report."));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_numbers_follow_filing_id() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("1050".to_string(), true, true, false).with_row_numbers(true);
+        ctx.issues = Some(Vec::new());
+        parse_fixture_with(&mut ctx, "row_numbers_8.3.fec", dir.path())?;
+
+        let read = |form: &str| -> Result<(csv::StringRecord, Vec<csv::StringRecord>)> {
+            let mut rdr = csv::Reader::from_path(dir.path().join("1050").join(form))?;
+            let headers = rdr.headers()?.clone();
+            Ok((headers, rdr.records().collect::<Result<_, _>>()?))
+        };
+        let (headers, rows) = read("SA.csv")?;
+        assert_eq!(
+            &headers.iter().take(5).collect::<Vec<_>>(),
+            &[
+                "filing_id",
+                "is_amendment",
+                "original_filing_id",
+                "source_line",
+                "form_type"
+            ]
+        );
+        assert_eq!(rows.len(), 21);
+        for row in &rows {
+            assert_eq!(&row[0], "1050");
+        }
+        let line_of = |rows: &[csv::StringRecord], id: &str| {
+            rows.iter().find(|r| &r[6] == id).map(|r| r[3].to_string())
+        };
+        assert_eq!(line_of(&rows, "SA11AI.1").as_deref(), Some("3"));
+        assert_eq!(line_of(&rows, "SA11AI.20").as_deref(), Some("27"));
+        // A record with a quoted line break, and those after a text block, count every line
+        assert_eq!(line_of(&rows, "SA11AI.11").as_deref(), Some("13"));
+        assert_eq!(line_of(&rows, "SA11AI.12").as_deref(), Some("19"));
+
+        let (_, rows) = read("SB.csv")?;
+        assert_eq!(&rows[0][3], "29");
+        let (_, rows) = read("F99.csv")?;
+        assert_eq!(&rows[0][3], "15");
+        assert_eq!(
+            rows[0].iter().next_back(),
+            Some("Please see the attached report.")
+        );
+
+        // Warnings about a record number it the same way
+        let issues = ctx.issues.as_deref().unwrap_or_default();
+        let non_numeric: Vec<_> = issues
+            .iter()
+            .filter(|issue| issue.message.contains("non-numeric"))
+            .map(|issue| issue.line)
+            .collect();
+        assert_eq!(non_numeric, [13]);
+
+        // Without a filing ID, the source line comes first
+        let dir = tempfile::tempdir()?;
+        let mut ctx =
+            FecContext::new("1051".to_string(), false, true, false).with_row_numbers(true);
+        parse_fixture_with(&mut ctx, "row_numbers_8.3.fec", dir.path())?;
+        let lines = read_lines(&dir.path().join("1051/SB.csv"))?;
+        assert!(lines[0].starts_with("source_line,form_type,"));
+        assert!(lines[1].starts_with("29,SB23,C00123456,SB23.9001,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_error_reports_line_number() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = FecContext::new("1016".to_string(), false, true, false).with_strict(true);
+        let err = parse_fixture_with(&mut ctx, "line_numbers_8.3.fec", dir.path())
+            .expect_err("the short SA row on line 7 should fail in strict mode");
+
+        assert!(err.to_string().contains("line 7"), "{err:#}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bom_stripped_from_ascii28_header() -> Result<()> {
+        let ctx = parse_str("\u{feff}HDR\x1cFEC\x1c8.3\x1cVendor\x1c1.0\nSA11AI\x1cC00123456\n")?;
+
+        let header = ctx.header.expect("header should be parsed");
+        assert_eq!(header.record_type, "HDR");
+        assert_eq!(header.version, "8.3");
+        assert_eq!(ctx.delimiter, Delimiter::Ascii28);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bom_stripped_from_comma_header() -> Result<()> {
+        let ctx = parse_str("\u{feff}HDR,FEC,8.1,Vendor,1.0\nSA11AI,C00123456\n")?;
+
+        assert_eq!(ctx.header.expect("header should be parsed").version, "8.1");
+        assert_eq!(ctx.version.as_deref(), Some("8.1"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bom_on_its_own_line() -> Result<()> {
+        let ctx = parse_str("\u{feff}\nHDR,FEC,8.1,Vendor,1.0\n")?;
+        assert_eq!(ctx.version.as_deref(), Some("8.1"));
+        assert_eq!(ctx.line_number, 2);
+
+        let err = parse_str("\u{feff}").expect_err("a lone BOM is no data");
+        assert!(err.to_string().contains("No data"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_crlf_line_endings_stripped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("crlf_8.3.fec", dir.path(), "1017")?;
+        assert_eq!(
+            ctx.header
+                .expect("header should be parsed")
+                .report_number
+                .as_deref(),
+            Some("001")
+        );
+
+        let filing_dir = dir.path().join("1017");
+        for form in ["F99", "SA", "SB"] {
+            let path = filing_dir.join(format!("{form}.csv"));
+            let mut rdr = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+            for record in rdr.records() {
+                let record = record?;
+                let last = &record[record.len() - 1];
+                assert!(!last.ends_with('\r'), "{form} row ends with \\r: {last:?}");
+            }
+        }
+
+        // Line breaks inside quoted content are kept as-is; F99 text lines are joined with \n
+        let sb = fs::read_to_string(filing_dir.join("SB.csv"))?;
+        assert!(sb.contains("\"PRINTING\r\nAND MAILING\""));
+        let f99 = fs::read_to_string(filing_dir.join("F99.csv"))?;
+        assert!(f99.contains("\"First line of text.\nSecond line of text.\""));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_final_line_without_newline() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        // The only body row is unterminated
+        parse_input(
+            b"HDR,FEC,8.3,Vendor,1.0\nSA11AI,C00123456,SA.1",
+            dir.path(),
+            "1018",
+        )?;
+        let sa = read_lines(&dir.path().join("1018").join("SA.csv"))?;
+        assert_eq!(sa.len(), 2);
+        assert_eq!(sa[1].trim_end_matches(','), "SA11AI,C00123456,SA.1");
+
+        // ASCII28, with the unterminated row following another
+        parse_input(
+            b"HDR\x1cFEC\x1c8.3\nSA11AI\x1cC00123456\x1cSA.1\nSB23\x1cC00123456\x1cSB.1",
+            dir.path(),
+            "1019",
+        )?;
+        let sb = read_lines(&dir.path().join("1019").join("SB.csv"))?;
+        assert_eq!(sb.len(), 2);
+        assert_eq!(sb[1].trim_end_matches(','), "SB23,C00123456,SB.1");
+
+        // A comma record whose quoted field is cut off by the end of input
+        parse_input(b"HDR,FEC,8.3\nSB23,C00123456,\"SB.1", dir.path(), "1020")?;
+        let sb = read_lines(&dir.path().join("1020").join("SB.csv"))?;
+        assert_eq!(sb.len(), 2);
+        assert_eq!(sb[1].trim_end_matches(','), "SB23,C00123456,SB.1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_newline_adds_no_empty_row() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_input(
+            b"HDR,FEC,8.3\r\nSA11AI,C00123456,SA.1\r\n",
+            dir.path(),
+            "1021",
+        )?;
+
+        let filing_dir = dir.path().join("1021");
+        let files = fs::read_dir(&filing_dir)?.count();
+        assert_eq!(files, 2, "only SA.csv and header.csv should be written");
+        let sa = fs::read_to_string(filing_dir.join("SA.csv"))?;
+        assert_eq!(sa.lines().count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_header_only_without_newline() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_input(b"HDR,FEC,8.3,Vendor,1.0,,001", dir.path(), "1022")?;
+
+        assert_eq!(ctx.line_number, 1);
+        let header = ctx.header.expect("header should be parsed");
+        assert_eq!(header.version, "8.3");
+        assert_eq!(header.report_number.as_deref(), Some("001"));
+        // Only the header file is written
+        assert_eq!(fs::read_dir(dir.path().join("1022"))?.count(), 1);
+        assert_eq!(read_lines(&dir.path().join("1022/header.csv"))?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_field_spanning_lines() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = concat!(
+            "HDR,FEC,8.3\n",
+            "SB23,C00123456,SB.1,\"MEMO LINE ONE\nMEMO LINE TWO\",X\n",
+            "SB23,C00123456,SB.2,\"QUOTE \"\",\"\" INSIDE\nAND A BREAK\",Y\n",
+            "SB23,C00123456,SB.3,PLAIN,Z\n",
+        );
+        let ctx = parse_input(input.as_bytes(), dir.path(), "1023")?;
+        assert_eq!(ctx.line_number, 6);
+
+        let path = dir.path().join("1023").join("SB.csv");
+        let mut rdr = csv::ReaderBuilder::new().flexible(true).from_path(path)?;
+        let rows: Vec<csv::StringRecord> = rdr.records().collect::<Result<_, _>>()?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(&rows[0][3], "MEMO LINE ONE\nMEMO LINE TWO");
+        assert_eq!(&rows[0][4], "X");
+        assert_eq!(&rows[1][3], "QUOTE \",\" INSIDE\nAND A BREAK");
+        assert_eq!(&rows[1][4], "Y");
+        assert_eq!(&rows[2][2], "SB.3");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stray_quote_does_not_swallow_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR,FEC,8.3\nSA11AI,C00123456,\"SA.0\n");
+        let rows = MAX_QUOTED_RECORD_LINES + 10;
+        for i in 1..=rows {
+            input.push_str(&format!("SA11AI,C00123456,SA.{i}\n"));
+        }
+        let ctx = parse_input(input.as_bytes(), dir.path(), "1024")?;
+        assert_eq!(ctx.line_number, rows + 2);
+
+        // The stray quote closes at the end of its own line and every later row survives
+        let sa = read_lines(&dir.path().join("1024").join("SA.csv"))?;
+        assert_eq!(sa.len(), rows + 2);
+        assert_eq!(sa[1].trim_end_matches(','), "SA11AI,C00123456,SA.0");
+        assert_eq!(
+            sa[rows + 1].trim_end_matches(','),
+            format!("SA11AI,C00123456,SA.{rows}")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter_locked_by_header() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = b"HDR,FEC,8.3,Vendor,1.0\nSB23,C00123456,SB.1,MEMO\x1cWITH A CONTROL BYTE,X\n";
+        let ctx = parse_input(input, dir.path(), "1025")?;
+        assert_eq!(ctx.delimiter, Delimiter::Comma);
+
+        let sb = read_lines(&dir.path().join("1025").join("SB.csv"))?;
+        assert_eq!(
+            sb[1].trim_end_matches(','),
+            "SB23,C00123456,SB.1,MEMO\x1cWITH A CONTROL BYTE,X"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter_from_version() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        // A v3 filing is comma-delimited even with a stray ASCII28 byte in its header
+        let ctx = parse_fixture("comma_3.00.fec", dir.path(), "1026")?;
+        assert_eq!(ctx.version.as_deref(), Some("3.00"));
+        assert_eq!(ctx.delimiter, Delimiter::Comma);
+        let sa = read_lines(&dir.path().join("1026").join("SA.csv"))?;
+        assert_eq!(sa.len(), 3);
+        assert!(sa[1].starts_with("SA11A1,C00123456,JONES,ROBERT"));
+
+        let ctx = parse_fixture("multi_form_8.3.fec", dir.path(), "1027")?;
+        assert_eq!(ctx.delimiter, Delimiter::Ascii28);
+
+        assert_eq!(
+            delimiter_for_version("5.00", Delimiter::Ascii28),
+            Delimiter::Comma
+        );
+        assert_eq!(
+            delimiter_for_version("8.3", Delimiter::Comma),
+            Delimiter::Comma
+        );
+        assert_eq!(
+            delimiter_for_version("9.0", Delimiter::Ascii28),
+            Delimiter::Ascii28
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_delimiter_override() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = b"HDR\x1cFEC\x1c5.00\x1cVendor\nSA11AI\x1cC00123456\x1cSA.1\n";
+
+        // The version rule says commas, so the ASCII28 row stays in one field...
+        let ctx = parse_input(input, dir.path(), "1028")?;
+        assert_eq!(ctx.delimiter, Delimiter::Comma);
+        assert!(dir.path().join("1028").join("unknown_forms.csv").exists());
+
+        // ...unless the caller knows better
+        let mut ctx = FecContext::new("1029".to_string(), false, true, false)
+            .with_delimiter(Delimiter::Ascii28);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1029")
+            .build()?;
+        parse_fec(&mut ctx, &mut BufReader::new(&input[..]), &mut writer)?;
+        writer.flush_all()?;
+        assert_eq!(ctx.delimiter, Delimiter::Ascii28);
+        assert_eq!(ctx.version.as_deref(), Some("5.00"));
+        let sa = read_lines(&dir.path().join("1029").join("SA.csv"))?;
+        assert_eq!(sa[1].trim_end_matches(','), "SA11AI,C00123456,SA.1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_callback_receives_records_and_can_stop_early() -> Result<()> {
+        let mut input = String::from("HDR,FEC,8.3,NGP VAN,8.3.0.0(f1),,001,\n");
+        input.push_str("F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1\n");
+        for i in 0..10_000 {
+            input.push_str(&format!(
+                "SA11AI,C00123456,SA11AI.{i},,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,250.00\n"
+            ));
+        }
+        let total_len = input.len() as u64;
+        let mut reader = Cursor::new(input.into_bytes());
+
+        let mut ctx = FecContext::new("1030".to_string(), false, true, false);
+        let mut seen = Vec::new();
+        parse_fec_with_callback(&mut ctx, &mut reader, |record| {
+            seen.push((record.form_type.clone(), record.line_number));
+            Ok(if seen.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            })
+        })?;
+
+        assert_eq!(
+            seen,
+            vec![
+                ("F3XN".to_string(), 2),
+                ("SA11AI".to_string(), 3),
+                ("SA11AI".to_string(), 4),
+            ]
+        );
+        assert_eq!(ctx.line_number, 4);
+        assert!(reader.position() < total_len / 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_callback_error_reports_line() {
+        let input = b"HDR,FEC,8.3,Vendor\nF3XN,C00123456\nSA11AI,C00123456,SA.1\n";
+        let mut ctx = FecContext::new("1031".to_string(), false, true, false);
+        let err = parse_fec_with_callback(&mut ctx, &input[..], |record| {
+            if record.form_type == "SA11AI" {
+                anyhow::bail!("database rejected {}", record.fields[2]);
+            }
+            Ok(ControlFlow::Continue(()))
+        })
+        .unwrap_err();
+
+        assert!(err.to_string().contains("line 3"));
+        assert_eq!(err.root_cause().to_string(), "database rejected SA.1");
+    }
+
+    #[test]
+    fn test_skip_and_limit() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut input = String::from("HDR,FEC,8.3,Vendor\n");
+        for i in 1..=50 {
+            input.push_str(&format!("SA11AI,C00123456,SA.{i}\n"));
+        }
+
+        // Parse rows 21 through 25 only
+        let parse = |id: &str, skip: usize, limit: usize| -> Result<FecContext> {
+            let mut ctx = FecContext::new(id.to_string(), false, true, false)
+                .with_skip(skip)
+                .with_limit(limit);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(id)
+                .build()?;
+            parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+            writer.flush_all()?;
+            Ok(ctx)
+        };
+        let ctx = parse("1032", 20, 5)?;
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+        assert_eq!(ctx.line_number, 26);
+        let sa = read_lines(&dir.path().join("1032").join("SA.csv"))?;
+        let ids: Vec<_> = sa[1..]
+            .iter()
+            .map(|l| l.split(',').nth(2).unwrap())
+            .collect();
+        assert_eq!(ids, vec!["SA.21", "SA.22", "SA.23", "SA.24", "SA.25"]);
+
+        // A limit of 0 parses everything after the skipped lines
+        parse("1033", 45, 0)?;
+        let sa = read_lines(&dir.path().join("1033").join("SA.csv"))?;
+        assert_eq!(sa.len(), 1 + 5);
+
+        // Skipping past the end succeeds without output
+        let ctx = parse("1034", 1000, 10)?;
+        assert_eq!(ctx.line_number, 51);
+        assert!(!dir.path().join("1034").join("SA.csv").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_mode_fails_where_lenient_mode_warns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        // Lenient: the unrecognized row is quarantined and parsing carries on
+        let ctx = parse_fixture("malformed_8.3.fec", dir.path(), "1035")?;
+        assert_eq!(ctx.line_number, 4);
+        let unknown = read_lines(&dir.path().join("1035").join("unknown_forms.csv"))?;
+        assert_eq!(unknown.len(), 2);
+        let sb = read_lines(&dir.path().join("1035").join("SB.csv"))?;
+        assert_eq!(sb.len(), 3);
+
+        // Strict: the same row fails the parse
+        let mut ctx = FecContext::new("1036".to_string(), false, true, false).with_strict(true);
+        let err = parse_fixture_with(&mut ctx, "malformed_8.3.fec", dir.path())
+            .expect_err("strict mode should reject the unrecognized form type");
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::UnknownFormType { line, form_type }) => {
+                assert_eq!(*line, 3);
+                assert_eq!(form_type, "S@11");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_unmapped_form_codes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // `ZZ9` reads as a form code, but no mapping knows it
+        let input = b"HDR,FEC,8.3,Vendor\nZZ9,C00123456,ZZ.1\n";
+
+        let mut ctx = FecContext::new("1037".to_string(), false, true, false).with_strict(true);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1037")
+            .build()?;
+        let err = parse_fec(&mut ctx, &mut BufReader::new(&input[..]), &mut writer)
+            .expect_err("strict mode should reject the unmapped form type");
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::UnknownFormType { line, form_type }) => {
+                assert_eq!(*line, 2);
+                assert_eq!(form_type, "ZZ9");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        // Lenient mode quarantines it as an error, not a mere warning
+        let mut ctx = FecContext::new("1038".to_string(), false, true, false);
+        ctx.issues = Some(Vec::new());
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1038")
+            .build()?;
+        parse_fec(&mut ctx, &mut BufReader::new(&input[..]), &mut writer)?;
+        let issues = ctx.issues.unwrap_or_default();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, 2);
+        assert_eq!(issues[0].severity, Severity::Error);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_mode_rejects_bad_encoding_and_malformed_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cases: [(&[u8], &str); 3] = [
+            (
+                b"HDR,FEC,8.3,Vendor\nSA11AI,C00123456,M\xfcller\n",
+                "InvalidEncoding",
+            ),
+            (
+                b"HDR,FEC,8.3,Vendor\nSA11AI,\"never closed\n",
+                "MalformedRow",
+            ),
+            (b"HDR,FEC,8.3,Vendor\nSA11AI,C001\x1c23\n", "MalformedRow"),
+        ];
+        for (i, (input, expected)) in cases.into_iter().enumerate() {
+            let id = format!("104{i}");
+            // Lenient mode reads all of these
+            parse_input(input, dir.path(), &id)?;
+
+            let mut ctx = FecContext::new(id.clone(), false, true, false).with_strict(true);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(id)
+                .build()?;
+            let err = parse_fec(&mut ctx, &mut BufReader::new(input), &mut writer)
+                .expect_err("strict mode should reject the row");
+            let line = match err.downcast_ref::<FecError>() {
+                Some(FecError::InvalidEncoding { line }) if expected == "InvalidEncoding" => *line,
+                Some(FecError::MalformedRow { line, .. }) if expected == "MalformedRow" => *line,
+                other => panic!("expected {expected}, got {other:?}"),
+            };
+            assert_eq!(line, 2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cover_file_from_first_cover_record() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_fixture("multi_form_8.3.fec", dir.path(), "1051")?;
+
+        assert_eq!(
+            read_lines(&dir.path().join("1051/cover.csv"))?,
+            vec![
+                "filing_id,form_type,filer_committee_id_number,coverage_from_date,\
+                 coverage_through_date,report_type",
+                "1051,F3XN,C00123456,2023-01-01,2023-03-31,Q1",
+            ]
+        );
+
+        // Forms without coverage dates have no cover info
+        let f99 = ["F99", "C00123456", "TEST"].map(String::from);
+        assert_eq!(CoverInfo::from_fields("F99", "8.3", &f99), None);
+
+        for (date, expected) in [
+            ("20230331", "2023-03-31"),
+            (" 3/31/2023 ", "2023-03-31"),
+            ("2023-03-31", "2023-03-31"),
+            ("", ""),
+            ("TBD", "TBD"),
+        ] {
+            assert_eq!(normalize_date(date), expected, "{date:?}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paper_filing_mapped_with_paper_columns() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("paper_P3.00.fec", dir.path(), "1052")?;
+        assert_eq!(ctx.unknown_form_rows, 1);
+
+        let filing_dir = dir.path().join("1052");
+        let f3x = read_lines(&filing_dir.join("F3XN.csv"))?;
+        assert!(f3x[0].ends_with(",col_a_cash_on_hand_close_of_period,image_number"));
+        assert!(f3x[1].starts_with("F3XN,C00123456,FRIENDS OF GEORGIA PAC,"));
+        assert_eq!(
+            read_lines(&filing_dir.join("SA.csv"))?,
+            vec![
+                "form_type,filer_committee_id_number,transaction_id,contributor_name,\
+                 contributor_street_1,contributor_street_2,contributor_city,contributor_state,\
+                 contributor_zip_code,contributor_employer,contributor_occupation,\
+                 contribution_date,contribution_amount,contribution_aggregate,\
+                 contribution_purpose_descrip,memo_code,memo_text_description,image_number",
+                "SA11AI,C00123456,SA.1,\"SMITH, JANE\",1 ELM ST,,DECATUR,GA,30030,ACME CORP,\
+                 ENGINEER,02/01/1998,250.00,250.00,,,,98031234568",
+            ]
+        );
+        assert_eq!(
+            read_lines(&filing_dir.join("SB.csv"))?,
+            vec![
+                "form_type,filer_committee_id_number,transaction_id,payee_name,payee_street_1,\
+                 payee_street_2,payee_city,payee_state,payee_zip_code,\
+                 expenditure_purpose_descrip,expenditure_date,expenditure_amount,memo_code,\
+                 memo_text_description,image_number",
+                "SB17,C00123456,SB.1,OFFICE SUPPLY CO,2 OAK ST,,ATLANTA,GA,30303,\
+                 OFFICE SUPPLIES,02/15/1998,75.50,,,98031234569",
+            ]
+        );
+        assert_eq!(
+            read_lines(&filing_dir.join("cover.csv"))?[1],
+            "1052,F3XN,C00123456,1998-01-01,1998-03-31,Q1"
+        );
+
+        // Schedule C was never keyed in from paper, so its rows are quarantined
+        assert!(!filing_dir.join("SC.csv").exists());
+        let unknown = read_lines(&filing_dir.join("unknown_forms.csv"))?;
+        assert_eq!(unknown.len(), 2);
+        assert!(unknown[1].starts_with("5,SC/9,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_paper_flag_maps_electronic_versions_as_paper() -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/paper_P3.00.fec");
+        // The same filing as an electronic version 3.00 one, which is comma-delimited
+        let input = fs::read_to_string(path)?
+            .replacen("P3.00", "3.00", 1)
+            .replace("SMITH, JANE", "\"SMITH, JANE\"")
+            .replace("DOE, JOHN", "\"DOE, JOHN\"")
+            .replace('\x1c', ",");
+
+        // Without the flag, version 3.00 rows get the electronic columns
+        let dir = tempfile::tempdir()?;
+        parse_input(input.as_bytes(), dir.path(), "1053")?;
+        let sa = read_lines(&dir.path().join("1053/SA.csv"))?;
+        assert!(sa[0].starts_with("form_type,filer_committee_id_number,entity_type,"));
+
+        let mut ctx = FecContext::new("1054".to_string(), false, true, false).with_paper(true);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1054")
+            .build()?;
+        parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+        writer.flush_all()?;
+        assert_eq!(ctx.version.as_deref(), Some("P3.00"));
+        assert_eq!(ctx.warnings, 1); // Only for the quarantined Schedule C row
+        let sa = read_lines(&dir.path().join("1054/SA.csv"))?;
+        assert!(sa[0]
+            .starts_with("form_type,filer_committee_id_number,transaction_id,contributor_name,"));
+        let header = read_lines(&dir.path().join("1054/header.csv"))?;
+        assert!(header[1].starts_with("1054,HDR,FEC,3.00,"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amendment_info() -> Result<()> {
+        // A new filing
+        let dir = tempfile::tempdir()?;
+        let ctx = parse_fixture("multi_form_8.3.fec", dir.path(), "1050")?;
+        let amendment = ctx
+            .amendment
+            .expect("cover record should set the amendment info");
+        assert!(!amendment.is_amendment);
+        assert_eq!(amendment.original_filing_id, None);
+
+        // An amendment referring to its original filing in the `FEC-123456` style...
+        let ctx = parse_str("HDR,FEC,8.3,Vendor,1.0,FEC-1234567,001\nF3XA,C00123456,TEST\n")?;
+        let amendment = ctx
+            .amendment
+            .expect("cover record should set the amendment info");
+        assert!(amendment.is_amendment);
+        assert_eq!(amendment.original_filing_id.as_deref(), Some("1234567"));
+
+        // ...or the bare numeric style of older versions
+        let ctx = parse_str("HDR,FEC,5.00,Vendor,1.0,^,654321,001\nF3A,C00123456,TEST\n")?;
+        let amendment = ctx
+            .amendment
+            .expect("cover record should set the amendment info");
+        assert!(amendment.is_amendment);
+        assert_eq!(amendment.original_filing_id.as_deref(), Some("654321"));
+
+        // An amendment without a report id
+        let ctx = parse_str("HDR,FEC,8.3,Vendor,1.0,,001\nF3XA,C00123456,TEST\n")?;
+        let amendment = ctx
+            .amendment
+            .expect("cover record should set the amendment info");
+        assert!(amendment.is_amendment);
+        assert_eq!(amendment.original_filing_id, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amendment_columns_follow_filing_id() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let parse = |filing_id: &str, header: &str, cover: &str| -> Result<Vec<Vec<String>>> {
+            let input = format!("{header}\n{cover},C00123456,TEST\nSA11AI,C00123456,SA.1\n");
+            let mut ctx = FecContext::new(filing_id.to_string(), true, true, false);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .build()?;
+            parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+            writer.flush_all()?;
+            let mut rdr = csv::Reader::from_path(dir.path().join(filing_id).join("SA.csv"))?;
+            let mut rows = vec![rdr.headers()?.iter().take(4).map(String::from).collect()];
+            for row in rdr.records() {
+                rows.push(row?.iter().take(4).map(String::from).collect());
+            }
+            Ok(rows)
+        };
+
+        let new = parse("1060", "HDR,FEC,8.3,Vendor,1.0,,001", "F3XN")?;
+        assert_eq!(
+            new,
+            [
+                [
+                    "filing_id",
+                    "is_amendment",
+                    "original_filing_id",
+                    "form_type"
+                ],
+                ["1060", "false", "", "SA11AI"],
+            ]
+        );
+
+        let amended = parse("1061", "HDR,FEC,8.3,Vendor,1.0,FEC-1234567,001", "F3XA")?;
+        assert_eq!(amended[1], ["1061", "true", "1234567", "SA11AI"]);
+
+        Ok(())
+    }
+
+    /// A comma-delimited filing mixing forms, quoted multi-line records and F99 text blocks.
+    fn mixed_filing(rows: usize) -> String {
+        let mut input = String::from("HDR,FEC,8.3,Vendor,1.0\n");
+        input.push_str("F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1\n");
+        for i in 0..rows {
+            match i % 7 {
+                0 => input.push_str(&format!(
+                    "SA11AI,C00123456,SA.{i},,,IND,,\"SMITH\nJR\",JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,{i}.5,250\n"
+                )),
+                1 => input.push_str(&format!(
+                    "F99,C00123456,TEST,,,,,,,,20230101,MSM\n[BEGINTEXT]\nNote {i}\nSA11AI,not,a,row\n[ENDTEXT]\n"
+                )),
+                2 => input.push_str(&format!("F99,C00123456,TEST,{i}\n")),
+                3 => input.push_str(&format!("SB23,C00123456,SB.{i},,,ORG,PRINT SHOP\n")),
+                4 => input.push('\n'),
+                5 => input.push_str(&format!("ZZ9,C00123456,{i}\n")),
+                _ => input.push_str(&format!(
+                    "SE,C00123456,SE.{i},,,ORG,AD AGENCY,,,,,,4 MAPLE ST,,ATLANTA,GA,30303\n"
+                )),
+            }
+        }
+        input
+    }
+
+    /// Parse `input` with `options` into `output_dir/filing_id`.
+    fn parse_with_options(
+        input: &str,
+        output_dir: &Path,
+        filing_id: &str,
+        options: &ParseOptions,
+        strict: bool,
+    ) -> Result<FecContext> {
+        let mut ctx =
+            FecContext::new(filing_id.to_string(), false, true, false).with_strict(strict);
+        let mut writer = WriterContext::builder()
+            .output_directory(output_dir.to_string_lossy())
+            .filing_id(filing_id)
+            .build()?;
+        let result = parse_fec_with_options(&mut ctx, &mut input.as_bytes(), &mut writer, options);
+        writer.flush_all()?;
+        result.map(|_| ctx)
+    }
+
+    /// The contents of every file in `dir`, by name.
+    fn read_output(dir: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut files = fs::read_dir(dir)?
+            .map(|e| {
+                let e = e?;
+                Ok((
+                    e.file_name().to_string_lossy().into_owned(),
+                    fs::read(e.path())?,
+                ))
+            })
+            // The header and cover files name the filing, which differs between the parses compared
+            .filter(|file| !matches!(file, Ok((name, _)) if name == "header.csv" || name == "cover.csv"))
+            .collect::<Result<Vec<_>>>()?;
+        files.sort();
+        Ok(files)
+    }
+
+    #[test]
+    fn test_forms_filter_writes_only_selected_schedules() -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let input = fs::read_to_string(path)?;
+        let all = tempfile::tempdir()?;
+        let unfiltered =
+            parse_with_options(&input, all.path(), "1060", &ParseOptions::default(), false)?;
+        let all = read_output(&all.path().join("1060"))?;
+
+        for threads in [1, 4] {
+            let dir = tempfile::tempdir()?;
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(64)
+                .with_forms(HashSet::from(["sa".to_string()]));
+            let ctx = parse_with_options(&input, dir.path(), "1060", &options, false)?;
+            let files = read_output(&dir.path().join("1060"))?;
+
+            // The cover record is still written, and the rows left out still counted
+            let names: Vec<_> = files.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["F3XN.csv", "SA.csv"]);
+            for file in &files {
+                assert!(all.contains(file), "{} differs", file.0);
+            }
+            assert_eq!(ctx.version.as_deref(), Some("8.3"));
+            assert_eq!(ctx.mismatched_rows, unfiltered.mismatched_rows);
+        }
+
+        // Full form codes select just those rows
+        let dir = tempfile::tempdir()?;
+        let options = ParseOptions::default().with_forms(HashSet::from(["SB23".to_string()]));
+        parse_with_options(&input, dir.path(), "1061", &options, false)?;
+        let names: Vec<_> = read_output(&dir.path().join("1061"))?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["F3XN.csv", "SB.csv"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_combined_output_writes_every_row_to_one_file() -> Result<()> {
+        let width = widest_columns(MappingSet::for_version("8.3"));
+        // The rows of each CSV file in `dir`, header row left out, by name
+        let rows = |dir: &Path| -> Result<Vec<(String, Vec<csv::StringRecord>)>> {
+            let mut files = Vec::new();
+            for (name, bytes) in read_output(dir)? {
+                let mut rdr = csv::ReaderBuilder::new()
+                    .flexible(true)
+                    .from_reader(bytes.as_slice());
+                files.push((name, rdr.records().collect::<Result<_, _>>()?));
+            }
+            Ok(files)
+        };
+
+        for fixture in ["multi_form_8.3.fec", "f99_text_8.3.fec"] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(fixture);
+            let input = fs::read_to_string(path)?;
+            let dir = tempfile::tempdir()?;
+            let options = ParseOptions::default();
+            parse_with_options(&input, dir.path(), "1070", &options, false)?;
+            let per_form = rows(&dir.path().join("1070"))?;
+
+            // Padded to the widest mapping, each row led by the form whose file it would go to
+            let options = ParseOptions::default().with_combined_output(true);
+            parse_with_options(&input, dir.path(), "1071", &options, false)?;
+            let combined = rows(&dir.path().join("1071"))?;
+            let names: Vec<_> = combined.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, ["filing.csv"], "{fixture}");
+            let filing = &combined[0].1;
+            assert_eq!(
+                filing.len(),
+                per_form.iter().map(|(_, rows)| rows.len()).sum::<usize>(),
+                "{fixture}"
+            );
+            for (name, rows) in &per_form {
+                let form = name.strip_suffix(".csv").unwrap();
+                let combined_rows: Vec<_> = filing.iter().filter(|row| &row[0] == form).collect();
+                assert_eq!(combined_rows.len(), rows.len(), "{fixture} {name}");
+                for (combined_row, row) in combined_rows.iter().zip(rows) {
+                    assert_eq!(combined_row.len(), width + 1);
+                    assert_eq!(
+                        &combined_row
+                            .iter()
+                            .skip(1)
+                            .take(row.len())
+                            .collect::<Vec<_>>(),
+                        &row.iter().collect::<Vec<_>>()
+                    );
+                }
+            }
+            let mut rdr = csv::Reader::from_path(dir.path().join("1071/filing.csv"))?;
+            let header = rdr.headers()?;
+            assert_eq!(header.len(), width + 1);
+            assert_eq!(&header[0], "form_type");
+            assert_eq!(&header[1], "col_1");
+
+            // Left ragged, rows keep the fields they were filed with
+            let options = ParseOptions::default()
+                .with_combined_output(true)
+                .with_pad_rows(false);
+            parse_with_options(&input, dir.path(), "1072", &options, false)?;
+            let ragged = rows(&dir.path().join("1072"))?;
+            assert_eq!(ragged[0].1.len(), filing.len(), "{fixture}");
+            assert!(ragged[0].1.iter().any(|row| row.len() < width + 1));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_rows_padded_to_mapping_width() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let row = |id: &str, fields: usize| {
+            let mut row = format!("SA11AI,C00123456,{id}");
+            row.push_str(&",X".repeat(fields - 3));
+            row.push('\n');
+            row
+        };
+        let input = format!(
+            "HDR,FEC,8.3,Vendor,1.0\n{}{}{}",
+            row("SHORT", 40),
+            row("EXACT", 45),
+            row("LONG", 47)
+        );
+        let widths = |filing_id: &str| -> Result<Vec<usize>> {
+            let mut rdr = csv::ReaderBuilder::new()
+                .flexible(true)
+                .from_path(dir.path().join(filing_id).join("SA.csv"))?;
+            assert_eq!(rdr.headers()?.len(), 45);
+            rdr.records().map(|r| Ok(r?.len())).collect()
+        };
+
+        // Short rows are padded, long rows keep their extra fields and are warned about
+        let ctx = parse_with_options(&input, dir.path(), "1046", &ParseOptions::default(), false)?;
+        assert_eq!(widths("1046")?, vec![45, 45, 47]);
+        assert_eq!(ctx.mismatched_rows, 2);
+
+        // Without padding rows are written as filed
+        let options = ParseOptions::default().with_pad_rows(false);
+        parse_with_options(&input, dir.path(), "1047", &options, false)?;
+        assert_eq!(widths("1047")?, vec![40, 45, 47]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_fields_truncated_to_mapping_width() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // The unquoted comma in the contributor's name splits it into two fields
+        let mut fields = vec![""; 45];
+        fields[..3].copy_from_slice(&["SA11AI", "C00123456", "SA.1"]);
+        fields[7] = "SMITH, JR.";
+        fields[44] = "MEMO";
+        let input = format!("HDR,FEC,8.3,Vendor,1.0\n{}\n", fields.join(","));
+
+        let mut ctx = FecContext::new("1048".to_string(), false, true, false);
+        ctx.issues = Some(Vec::new());
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("1048")
+            .build()?;
+        let options = ParseOptions::default().with_truncate_extra_fields(true);
+        parse_fec_with_options(&mut ctx, &mut input.as_bytes(), &mut writer, &options)?;
+        writer.flush_all()?;
+
+        let mut rdr = csv::Reader::from_path(dir.path().join("1048/SA.csv"))?;
+        let row = rdr.records().next().expect("one SA row")?;
+        assert_eq!(row.len(), 45);
+        assert_eq!((&row[7], &row[8]), ("SMITH", " JR."));
+        assert_eq!(ctx.truncated_rows, 1);
+        let dropped = ctx
+            .issues
+            .iter()
+            .flatten()
+            .find(|issue| issue.message.starts_with("Dropped"))
+            .expect("the dropped field is warned about");
+        assert_eq!(dropped.line, 2);
+        assert!(
+            dropped.message.contains("[\"MEMO\"]"),
+            "{}",
+            dropped.message
+        );
+
+        // Rows of forms without a mapping for the version are left alone
+        let input = format!("HDR,FEC,2.02,Vendor,1.0\n{}\n", fields.join(","));
+        let options = ParseOptions::default().with_truncate_extra_fields(true);
+        let ctx = parse_with_options(&input, dir.path(), "1049", &options, false)?;
+        let mut rdr = csv::Reader::from_path(dir.path().join("1049/SA.csv"))?;
+        assert_eq!(rdr.records().next().expect("one SA row")?.len(), 46);
+        assert_eq!(ctx.truncated_rows, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_threaded_output_matches_sequential() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = mixed_filing(2_000);
+
+        let sequential =
+            parse_with_options(&input, dir.path(), "seq", &ParseOptions::default(), false)?;
+        for (threads, chunk_bytes) in [(2, 64), (4, 1000), (8, 1)] {
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(chunk_bytes);
+            let id = format!("par{threads}");
+            let parallel = parse_with_options(&input, dir.path(), &id, &options, false)?;
+
+            assert_eq!(
+                read_output(&dir.path().join("seq"))?,
+                read_output(&dir.path().join(&id))?
+            );
+            assert_eq!(parallel.line_number, sequential.line_number);
+            assert_eq!(parallel.mismatched_rows, sequential.mismatched_rows);
+            assert_eq!(parallel.form_type, sequential.form_type);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resumed_parse_matches_full_parse() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = mixed_filing(700);
+        let writer = |id: &str| {
+            WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(id)
+                .output_mode(OutputMode::Append)
+                .build()
+        };
+        parse_with_options(&input, dir.path(), "full", &ParseOptions::default(), false)?;
+
+        for (id, seek) in [("read", false), ("seek", true)] {
+            // Stop partway, as if the parse had died
+            let mut ctx = FecContext::new(id.to_string(), false, true, false).with_limit(300);
+            let mut first = writer(id)?;
+            parse_fec_with_options(
+                &mut ctx,
+                &mut input.as_bytes(),
+                &mut first,
+                &ParseOptions::default(),
+            )?;
+            first.flush_all()?;
+            let checkpoint = ctx.checkpoint;
+            assert!(checkpoint.offset > 0 && checkpoint.offset < input.len() as u64);
+            let header = ctx.header.clone().expect("the header was read");
+
+            // Pick up from the checkpoint in a fresh run, on several threads
+            let reported = Arc::new(Mutex::new(None));
+            let options = ParseOptions::default()
+                .with_threads(4)
+                .with_chunk_bytes(1000)
+                .resume_from(checkpoint, header)
+                .on_progress({
+                    let reported = reported.clone();
+                    move |progress| *reported.lock().unwrap() = Some(progress.checkpoint)
+                });
+            let mut ctx = FecContext::new(id.to_string(), false, true, false);
+            let mut second = writer(id)?;
+            if seek {
+                let mut reader = Cursor::new(input.as_bytes());
+                parse_fec_seekable(&mut ctx, &mut reader, &mut second, &options)?;
+            } else {
+                parse_fec_with_options(&mut ctx, &mut input.as_bytes(), &mut second, &options)?;
+            }
+            second.flush_all()?;
+
+            assert_eq!(
+                read_output(&dir.path().join("full"))?,
+                read_output(&dir.path().join(id))?
+            );
+            assert_eq!(ctx.version.as_deref(), Some("8.3"));
+            assert_eq!(ctx.checkpoint.offset, input.len() as u64);
+            assert_eq!(*reported.lock().unwrap(), Some(ctx.checkpoint));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concatenated_filings_split_apart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let fixtures = ["multi_form_8.3.fec", "legacy_2.02.fec", "valid_8.3.fec"];
+        let mut input = String::new();
+        for fixture in fixtures {
+            parse_fixture(fixture, dir.path(), fixture)?;
+            let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures")
+                .join(fixture);
+            input.push_str(&fs::read_to_string(path)?);
+        }
+
+        // Batches end before each later header, so they are handled like a sequential parse
+        for threads in [1, 4] {
+            let out = dir.path().join(format!("threads_{threads}"));
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(64);
+            let ctx = parse_with_options(&input, &out, "cat", &options, false)?;
+
+            // The legacy filing is named by its report id, the others by their position
+            assert_eq!(ctx.filings, ["cat", "12345", "cat-3"]);
+            for (fixture, id) in fixtures.iter().zip(&ctx.filings) {
+                assert_eq!(
+                    read_output(&out.join(id))?,
+                    read_output(&dir.path().join(fixture))?,
+                    "{fixture} on {threads} thread(s)"
+                );
+            }
+            assert_eq!(ctx.fec_id, "cat-3");
+            assert_eq!(ctx.delimiter, Delimiter::Comma);
+            assert_eq!(ctx.line_number, input.lines().count());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concatenated_filings_kept_whole_when_not_split() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = "HDR,FEC,8.3,Vendor,1.0,FEC-111\n\
+                     F99,C00123456,TEST\n\
+                     HDR\x1cFEC\x1c8.3\x1cVendor\x1c1.0\x1cFEC-222\n\
+                     SA11AI\x1cC00123456\x1cSA.1\n";
+        let parse = |split: bool| -> Result<FecContext> {
+            let id = if split { "split" } else { "whole" };
+            let mut ctx =
+                FecContext::new(id.to_string(), false, true, false).with_split_filings(split);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(id)
+                .build()?;
+            parse_fec(&mut ctx, &mut input.as_bytes(), &mut writer)?;
+            writer.flush_all()?;
+            Ok(ctx)
+        };
+
+        // The F99 record waiting for a text block is written before the next filing starts
+        let ctx = parse(true)?;
+        assert_eq!(ctx.filings, ["split", "222"]);
+        assert_eq!(ctx.delimiter, Delimiter::Ascii28);
+        let first = read_output(&dir.path().join("split"))?;
+        assert_eq!(
+            first
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["F99.csv"]
+        );
+        let second = fs::read_to_string(dir.path().join("222/SA.csv"))?;
+        assert!(second
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("SA11AI,C00123456,SA.1,"));
+
+        // Otherwise the second header and everything after it are rows of the first filing,
+        // split with its delimiter
+        let ctx = parse(false)?;
+        assert_eq!(ctx.filings, ["whole"]);
+        assert_eq!(ctx.unknown_form_rows, 2);
+        let names: Vec<_> = read_output(&dir.path().join("whole"))?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["F99.csv", "unknown_forms.csv"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nul_bytes_stripped_or_replaced_before_splitting() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // A NUL inside a quoted field (next to a comma) and a NUL as an entire field
+        let input = b"HDR,FEC,8.3,Vendor,1.0\n\
+                      SA11AI,C00123456,SA.1,,,IND,,\"SM\0ITH, JR\",JANE,\0,,,1 ELM ST\n";
+        let parse = |id: &str, policy: NulPolicy, strict: bool| -> Result<FecContext> {
+            let mut ctx = FecContext::new(id.to_string(), false, true, false)
+                .with_nul_policy(policy)
+                .with_strict(strict);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(id)
+                .build()?;
+            parse_fec(&mut ctx, &mut &input[..], &mut writer)?;
+            writer.flush_all()?;
+            Ok(ctx)
+        };
+        let row = |id: &str| -> Result<Vec<String>> {
+            let output = fs::read(dir.path().join(id).join("SA.csv"))?;
+            let mut reader = csv::Reader::from_reader(&output[..]);
+            let record = reader.records().next().unwrap()?;
+            Ok(record.iter().map(str::to_string).collect())
+        };
+
+        let ctx = parse("strip", NulPolicy::Strip, false)?;
+        assert_eq!(ctx.nul_bytes, 2);
+        let fields = row("strip")?;
+        assert_eq!(fields[7..10], ["SMITH, JR", "JANE", ""]);
+        assert_eq!(fields[12], "1 ELM ST");
+
+        let ctx = parse("replace", NulPolicy::Replace, false)?;
+        assert_eq!(ctx.nul_bytes, 2);
+        assert_eq!(
+            row("replace")?[7..10],
+            ["SM\u{FFFD}ITH, JR", "JANE", "\u{FFFD}"]
+        );
+
+        let err = parse("strict", NulPolicy::Strip, true).unwrap_err();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::NulBytes { line, count }) => assert_eq!((*line, *count), (2, 2)),
+            other => panic!("expected NulBytes, got {other:?}"),
+        }
+
+        // Lines handed to `parse_line` are already decoded, but still scrubbed
+        let mut ctx = FecContext::new("line".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("line")
+            .build()?;
+        parse_line(&mut ctx, "SB23,C00123456,SB.\x001,,,\0", &mut writer)?;
+        writer.flush_all()?;
+        assert_eq!(ctx.nul_bytes, 2);
+        let lines = read_lines(&dir.path().join("line/SB.csv"))?;
+        assert_eq!(lines[1], "SB23,C00123456,SB.1,,,");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_threaded_strict_error_matches_sequential() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let sa = |i: usize| {
+            format!(
+                "SA11AI,C00123456,SA.{i},,,IND,,\"DOE\nJR\",JOHN{}\n",
+                ",".repeat(36)
+            )
+        };
+        let sb = |i: usize| {
+            format!(
+                "SB23,C00123456,SB.{i},,,ORG,PRINT SHOP LLC{}\n",
+                ",".repeat(37)
+            )
+        };
+        let mut input = String::from("HDR,FEC,8.3,Vendor,1.0,,001,\n");
+        for i in 0..1_000 {
+            input.push_str(&sa(i));
+            input.push_str(&sb(i));
+            if i == 600 {
+                input.push_str("SA11AI,C00123456,SA.short,,,IND,,SMITH,JANE\n");
+            }
+        }
+
+        let options = ParseOptions::default()
+            .with_threads(4)
+            .with_chunk_bytes(100);
+        let sequential =
+            parse_with_options(&input, dir.path(), "seq", &ParseOptions::default(), true)
+                .expect_err("strict mode should reject the short row");
+        let parallel = parse_with_options(&input, dir.path(), "par", &options, true)
+            .expect_err("strict mode should reject the short row");
+
+        assert!(
+            sequential.to_string().contains("line 1805"),
+            "{sequential:#}"
+        );
+        assert_eq!(format!("{sequential:#}"), format!("{parallel:#}"));
+        assert_eq!(
+            read_output(&dir.path().join("seq"))?,
+            read_output(&dir.path().join("par"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_reports_increasing_byte_counts() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = mixed_filing(5_000);
+
+        for threads in [1, 4] {
+            let reports = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&reports);
+            let options = ParseOptions::default()
+                .with_threads(threads)
+                .with_chunk_bytes(4096)
+                .with_progress_interval(Duration::ZERO)
+                .with_total_bytes(input.len() as u64)
+                .on_progress(move |progress| sink.lock().unwrap().push(progress));
+            parse_with_options(&input, dir.path(), &format!("{threads}"), &options, false)?;
+
+            let reports = reports.lock().unwrap();
+            assert!(reports.len() > 2, "only {} reports", reports.len());
+            for pair in reports.windows(2) {
+                assert!(pair[0].bytes_read <= pair[1].bytes_read);
+                assert!(pair[0].rows <= pair[1].rows);
+                assert!(pair[0].elapsed <= pair[1].elapsed);
+            }
+            let last = reports.last().unwrap();
+            assert_eq!(last.bytes_read, input.len() as u64);
+            assert_eq!(last.total_bytes, Some(input.len() as u64));
+            // The cover and every generated row but the blank lines
+            assert_eq!(last.rows, 1 + (0..5_000).filter(|i| i % 7 != 4).count());
+        }
+
+        Ok(())
+    }
+}
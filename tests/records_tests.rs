@@ -0,0 +1,268 @@
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::encoding::{decode_line, FallbackEncoding};
+use fast_fec_rust::errors::FecError;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::records::{FecRecord, FecRecords, RecordKind, MAX_QUOTED_RECORD_LINES};
+use std::fs;
+use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_kinds_and_line_numbers() -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("2001".to_string(), false, true, false);
+
+        let records = FecRecords::new(&mut ctx, reader).collect::<Result<Vec<FecRecord>>>()?;
+        let summary: Vec<_> = records
+            .iter()
+            .map(|r| (r.form_type.as_str(), r.kind, r.line_number))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("HDR", RecordKind::Header, 1),
+                ("F3XN", RecordKind::Cover, 2),
+                ("SA11AI", RecordKind::Itemization, 3),
+                ("SA11AI", RecordKind::Itemization, 4),
+                ("SB23", RecordKind::Itemization, 5),
+                ("SE", RecordKind::Itemization, 6),
+            ]
+        );
+        assert_eq!(records[2].fields[2], "SA11AI.4126");
+        assert_eq!(ctx.version.as_deref(), Some("8.3"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_are_read_lazily() -> Result<()> {
+        let mut input = String::from("HDR,FEC,8.3,NGP VAN,8.3.0.0(f1),,001,\n");
+        input.push_str("F3XN,C00123456,FRIENDS OF TEST,,100 MAIN ST,,ATLANTA,GA,30303,Q1\n");
+        for i in 0..10_000 {
+            input.push_str(&format!(
+                "SA11AI,C00123456,SA11AI.{i},,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,250.00\n"
+            ));
+        }
+        let total_len = input.len() as u64;
+        let mut reader = Cursor::new(input.into_bytes());
+
+        let mut ctx = FecContext::new("2002".to_string(), false, true, false);
+        let first: Vec<FecRecord> = FecRecords::new(&mut ctx, &mut reader)
+            .take(4)
+            .collect::<Result<_>>()?;
+
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[3].fields[2], "SA11AI.1");
+        // Only the lines behind the returned records were consumed
+        assert_eq!(ctx.line_number, 4);
+        assert!(reader.position() < total_len / 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_records_join_to_their_parents() -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/text_8.3.fec");
+        let mut ctx = FecContext::new("2005".to_string(), false, true, false);
+        let records = FecRecords::new(&mut ctx, BufReader::new(fs::File::open(path)?))
+            .collect::<Result<Vec<FecRecord>>>()?;
+
+        let joined: Vec<_> = records
+            .iter()
+            .filter(|r| r.form_type == "TEXT")
+            .map(|text| {
+                let parent_id = text.back_reference("8.3").expect("TEXT has a parent");
+                let parent = records
+                    .iter()
+                    .find(|r| r.form_type != "TEXT" && r.transaction_id("8.3") == Some(parent_id))
+                    .expect("parent record is in the filing");
+                (parent.fields[7].as_str(), text.fields[5].as_str())
+            })
+            .collect();
+        assert_eq!(
+            joined,
+            vec![
+                ("SMITH", "Earmarked through ACTBLUE, \"per\" donor"),
+                ("DOE", "Refund issued 2023-03-01"),
+            ]
+        );
+
+        // Records without the column, or with it empty, have no back reference
+        assert_eq!(records[2].back_reference("8.3"), None);
+        assert_eq!(records[1].transaction_id("8.3"), None);
+        assert_eq!(records[4].transaction_id("8.3"), Some("TEXT.1"));
+
+        Ok(())
+    }
+
+    /// Check that the records' byte spans tile `input` and that each one holds its record.
+    fn assert_spans_tile(input: &[u8], records: &[FecRecord]) {
+        let mut offset = 0;
+        for record in records {
+            assert_eq!(
+                record.byte_start, offset,
+                "record on line {}",
+                record.line_number
+            );
+            let (text, _) =
+                decode_line(&input[record.byte_start as usize..record.byte_end as usize]);
+            assert!(
+                text.starts_with(&record.fields[0]),
+                "{text:?} is not line {}",
+                record.line_number
+            );
+            offset = record.byte_end;
+        }
+        assert_eq!(offset, input.len() as u64);
+    }
+
+    #[test]
+    fn test_byte_spans_cover_each_record() -> Result<()> {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/line_numbers_8.3.fec");
+        let input = fs::read(&path)?;
+
+        // Lines split across the reader's buffer take a different path than whole ones
+        for capacity in [8, 8 * 1024] {
+            let mut ctx = FecContext::new("2006".to_string(), false, true, false);
+            let reader = BufReader::with_capacity(capacity, fs::File::open(&path)?);
+            let records = FecRecords::new(&mut ctx, reader).collect::<Result<Vec<FecRecord>>>()?;
+            assert_eq!(records.len(), 3);
+            assert_spans_tile(&input, &records);
+
+            // The F99 record spans its quoted line break and its text block
+            let f99 = &records[1];
+            let raw = &input[f99.byte_start as usize..f99.byte_end as usize];
+            assert!(raw.starts_with(b"F99,C00123456,\"COMMITTEE TO\nTEST THINGS\","));
+            assert!(raw.ends_with(b"Please see the attached report.\n[ENDTEXT]\n"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fallback_encoding_chosen_by_context() -> Result<()> {
+        let input = b"HDR,FEC,8.3\nSA11AI,C00123456,SA.1,,,IND,,\x93O\x92BRIEN\x94\n";
+        let name = |fallback: FallbackEncoding| -> Result<String> {
+            let mut ctx = FecContext::new("2010".to_string(), false, true, false)
+                .with_fallback_encoding(fallback);
+            let records = FecRecords::new(&mut ctx, Cursor::new(&input[..]))
+                .collect::<Result<Vec<FecRecord>>>()?;
+            Ok(records[1].fields[7].clone())
+        };
+        assert_eq!(name(FallbackEncoding::default())?, "“O’BRIEN”");
+        assert_eq!(name(FallbackEncoding::Latin1)?, "\u{93}O\u{92}BRIEN\u{94}");
+        assert_eq!(
+            name(FallbackEncoding::Utf8Lossy)?,
+            "\u{FFFD}O\u{FFFD}BRIEN\u{FFFD}"
+        );
+
+        // Without strict mode, the Error policy still fails on the line
+        let mut ctx = FecContext::new("2010".to_string(), false, true, false)
+            .with_fallback_encoding(FallbackEncoding::Error);
+        let err = FecRecords::new(&mut ctx, Cursor::new(&input[..]))
+            .collect::<Result<Vec<FecRecord>>>()
+            .expect_err("the invalid line should fail");
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::InvalidEncoding { line }) => assert_eq!(*line, 2),
+            other => panic!("expected InvalidEncoding, got {other:?}"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_byte_spans_refer_to_raw_input() -> Result<()> {
+        let mut input =
+            b"HDR,FEC,8.3\r\nSA11AI,C00123456,SA.1,,,IND,,CAF\xC9\r\nZZ9,junk\r\n".to_vec();
+        // A stray quote is given up on, and the lines read past it are read again
+        input.extend_from_slice(b"SA11AI,C00123456,\"SA.2\r\n");
+        for i in 0..MAX_QUOTED_RECORD_LINES {
+            input.extend_from_slice(format!("SB23,C00123456,SB.{i}\r\n").as_bytes());
+        }
+
+        let mut ctx = FecContext::new("2007".to_string(), false, true, false);
+        let records =
+            FecRecords::new(&mut ctx, Cursor::new(&input)).collect::<Result<Vec<FecRecord>>>()?;
+        assert_eq!(records.len(), MAX_QUOTED_RECORD_LINES + 4);
+        assert_spans_tile(&input, &records);
+
+        // Offsets count the Latin-1 byte once, though it decodes to two UTF-8 bytes
+        let latin1 = &records[1];
+        assert_eq!(latin1.fields[7], "CAFÉ");
+        assert_eq!(latin1.byte_end - latin1.byte_start, 35);
+
+        // Quarantined rows carry their span too
+        let quarantined = &records[2];
+        let raw = &input[quarantined.byte_start as usize..quarantined.byte_end as usize];
+        assert_eq!(quarantined.raw_line.as_deref(), Some("ZZ9,junk"));
+        assert_eq!(raw, b"ZZ9,junk\r\n");
+        assert_eq!(records[4].fields[2], "SB.0");
+
+        Ok(())
+    }
+
+    /// A reader producing `header` followed by `remaining` bytes without a line break.
+    struct UnbrokenLine {
+        header: &'static [u8],
+        remaining: usize,
+    }
+
+    impl Read for UnbrokenLine {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.header.is_empty() {
+                let n = self.header.len().min(buf.len());
+                buf[..n].copy_from_slice(&self.header[..n]);
+                self.header = &self.header[n..];
+                return Ok(n);
+            }
+            let n = self.remaining.min(buf.len());
+            buf[..n].fill(b'A');
+            self.remaining -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_long_lines_are_capped_while_reading() -> Result<()> {
+        const LINE_LENGTH: usize = 100 * 1024 * 1024;
+        const MAX: usize = 1024 * 1024;
+        let input = || {
+            BufReader::new(UnbrokenLine {
+                header: b"HDR,FEC,8.3,Vendor\nSA11AI,",
+                remaining: LINE_LENGTH,
+            })
+        };
+
+        // Lenient: the line is cut off at the maximum and the rest is dropped as it is read
+        let mut ctx =
+            FecContext::new("2003".to_string(), false, true, false).with_max_line_length(MAX);
+        let records = FecRecords::new(&mut ctx, input()).collect::<Result<Vec<FecRecord>>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].form_type, "SA11AI");
+        assert_eq!(records[1].fields[1].len(), MAX - "SA11AI,".len());
+        assert_eq!(ctx.line_number, 2);
+
+        // Strict: the line fails as soon as it passes the maximum
+        let mut ctx = FecContext::new("2004".to_string(), false, true, false)
+            .with_max_line_length(MAX)
+            .with_strict(true);
+        let err = FecRecords::new(&mut ctx, input())
+            .collect::<Result<Vec<FecRecord>>>()
+            .unwrap_err();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::LineTooLong { line, length }) => {
+                assert_eq!((*line, *length), (2, MAX));
+            }
+            other => panic!("expected LineTooLong, got {other:?}"),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+extern crate fast_fec_rust;
+
+use anyhow::Result;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::{parse_fec_with_options, validate_fec, ParseOptions};
+use fast_fec_rust::fec::validation::{Severity, ValidationReport, ValidationStatus};
+use fast_fec_rust::writer::WriterContext;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Validate the fixture `name` with `options`.
+    fn validate_fixture(
+        name: &str,
+        strict: bool,
+        options: &ParseOptions,
+    ) -> Result<ValidationReport> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx =
+            FecContext::new("validate".to_string(), false, true, false).with_strict(strict);
+        Ok(validate_fec(&mut ctx, &mut reader, options))
+    }
+
+    #[test]
+    fn test_clean_filing_is_valid() -> Result<()> {
+        let report = validate_fixture("valid_8.3.fec", false, &ParseOptions::default())?;
+        assert_eq!(report.issues, vec![]);
+        assert_eq!(report.status(), ValidationStatus::Valid);
+        assert_eq!(report.status().exit_code(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_broken_filing_lists_every_problem() -> Result<()> {
+        let report = validate_fixture("invalid_8.3.fec", false, &ParseOptions::default())?;
+        let issues: Vec<_> = report
+            .issues
+            .iter()
+            .map(|issue| (issue.line, issue.severity))
+            .collect();
+        assert_eq!(
+            issues,
+            vec![
+                (3, Severity::Error),   // Short SA11AI row
+                (4, Severity::Error),   // Not UTF-8
+                (5, Severity::Warning), // Non-numeric amount
+                (6, Severity::Error),   // Unknown form type
+            ]
+        );
+        assert!(report.issues[0]
+            .message
+            .contains("has 9 fields, expected 45"));
+        assert!(report.issues[2].message.contains("\"TBD\""));
+        assert_eq!(report.status(), ValidationStatus::Invalid);
+        assert_eq!(
+            (
+                report.count(Severity::Error),
+                report.count(Severity::Warning)
+            ),
+            (3, 1)
+        );
+
+        // Parsing on several threads finds the same problems
+        let options = ParseOptions::default().with_threads(3).with_chunk_bytes(1);
+        assert_eq!(
+            validate_fixture("invalid_8.3.fec", false, &options)?,
+            report
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_validation_stops_at_the_first_error() -> Result<()> {
+        let report = validate_fixture("invalid_8.3.fec", true, &ParseOptions::default())?;
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].line, 3);
+        assert_eq!(report.issues[0].severity, Severity::Error);
+        assert!(report.issues[0].message.contains("Failed to parse line 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_only_writes_nothing() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/invalid_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("validate".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("validate")
+            .build()?;
+        let options = ParseOptions::default().with_validate_only(true);
+        parse_fec_with_options(&mut ctx, &mut reader, &mut writer, &options)?;
+        writer.flush_all()?;
+
+        assert_eq!(ctx.issues.map(|issues| issues.len()), Some(4));
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+}
@@ -1,8 +1,22 @@
 extern crate fast_fec_rust;
 
 use anyhow::Result;
-use fast_fec_rust::writer::WriterContext;
+use fast_fec_rust::errors::FecError;
+use fast_fec_rust::fec::context::FecContext;
+use fast_fec_rust::fec::parser::parse_fec;
+use fast_fec_rust::writer::{
+    format_date_with, format_double_with, format_integer, is_broken_pipe, sanitize_filename,
+    BoolFormat, CloseReport, Compression, DateFormat, DoubleFormat, FilenameTemplate, FlushPolicy,
+    OutputFormat, OutputMode, OutputSink, OutputStats, QuoteStyle, SinkFactory, SyncWriter,
+    Terminator, WriterContext, WriterMetrics, MAX_FILENAME_LENGTH,
+};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[cfg(test)]
 mod tests {
@@ -46,23 +60,21 @@ mod tests {
             }
         };
 
-        let mut ctx = WriterContext::new(
-            "".into(),
-            "".into(),
-            false,
-            3,
-            Some(Box::new(to_file)),
-            Some(Box::new(to_line)),
-        );
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(3)
+            .custom_write_fn(to_file)
+            .custom_line_fn(to_line)
+            .build()?;
 
         // Write partial string
         ctx.write_string("test", ".txt", "hi")?;
         assert_eq!(test_output.lock().unwrap().file_output, "");
         assert_eq!(test_output.lock().unwrap().line_output, "");
 
-        // Write more to overflow the buffer
+        // Write more than the buffer holds: written through, after what was buffered
         ctx.write_string("test", ".txt", " there")?;
-        assert_eq!(test_output.lock().unwrap().file_output, "hi the");
+        assert_eq!(test_output.lock().unwrap().file_output, "hi there");
         assert_eq!(test_output.lock().unwrap().line_output, "");
 
         // Write newline and flush
@@ -102,20 +114,18 @@ mod tests {
             }
         };
 
-        let mut ctx = WriterContext::new(
-            "".into(),
-            "".into(),
-            false,
-            3, // Buffer size remains 3
-            Some(Box::new(to_file)),
-            Some(Box::new(to_line)),
-        );
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(3) // Buffer size remains 3
+            .custom_write_fn(to_file)
+            .custom_line_fn(to_line)
+            .build()?;
 
         ctx.write_string("test", ".txt", "hi")?;
         assert_eq!(test_output.lock().unwrap().file_output, ""); // No flush yet
 
         ctx.write_string("test", ".txt", " there!")?;
-        assert_eq!(test_output.lock().unwrap().file_output, "hi the"); // Partial flush
+        assert_eq!(test_output.lock().unwrap().file_output, "hi there!"); // Written through
 
         ctx.write_char("test", ".txt", '\n')?;
         ctx.end_line("")?;
@@ -153,14 +163,12 @@ mod tests {
             }
         };
 
-        let mut ctx = WriterContext::new(
-            "".into(),
-            "".into(),
-            false,
-            300,
-            Some(Box::new(to_file)),
-            Some(Box::new(to_line)),
-        );
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(300)
+            .custom_write_fn(to_file)
+            .custom_line_fn(to_line)
+            .build()?;
 
         ctx.write_string("test", ".txt", "hi")?;
         assert_eq!(test_output.lock().unwrap().file_output, "");
@@ -198,14 +206,12 @@ mod tests {
             }
         };
 
-        let mut ctx = WriterContext::new(
-            "".into(),
-            "".into(),
-            false,
-            300,
-            Some(Box::new(to_file)),
-            Some(Box::new(to_line)),
-        );
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(300)
+            .custom_write_fn(to_file)
+            .custom_line_fn(to_line)
+            .build()?;
 
         ctx.write_string("test", ".txt", "hi there\n")?;
         ctx.end_line("")?;
@@ -220,4 +226,2129 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_line_callback_receives_row_types() -> Result<()> {
+        let lines: Arc<Mutex<Vec<(String, String, String)>>> = Arc::new(Mutex::new(Vec::new()));
+        let to_line = {
+            let lines = Arc::clone(&lines);
+            move |filename: &str, line: &str, types: &str| -> Result<()> {
+                lines.lock().unwrap().push((
+                    filename.to_string(),
+                    line.to_string(),
+                    types.to_string(),
+                ));
+                Ok(())
+            }
+        };
+        let mut writer = WriterContext::builder()
+            .write_to_disk(false)
+            .custom_line_fn(to_line)
+            .build()?;
+
+        let sa = "SA11AI,C00123456,SA.1,,,IND,,SMITH,JANE,,,,1 ELM ST,,DECATUR,GA,30030,P2024,,20230115,250.00,250.00";
+        let input = format!("HDR,FEC,8.3,Vendor\n{sa}\nZZ1,A,B\n{sa}\n");
+        let mut ctx = FecContext::new("3001".to_string(), false, true, false);
+        parse_fec(&mut ctx, &mut input.as_bytes(), &mut writer)?;
+
+        let sa_types = format!("{}dff{}", "s".repeat(19), "s".repeat(23));
+        let lines = lines.lock().unwrap();
+        let summary: Vec<_> = lines
+            .iter()
+            .map(|(f, l, t)| (f.as_str(), l.split(',').next().unwrap(), t.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("header", "filing_id", "s".repeat(11).as_str()),
+                ("header", "3001", "s".repeat(11).as_str()),
+                ("SA", "form_type", "s".repeat(45).as_str()),
+                ("SA", "SA11AI", sa_types.as_str()),
+                ("unknown_forms", "line_number", "sss"),
+                ("unknown_forms", "3", "sss"),
+                ("SA", "SA11AI", sa_types.as_str()),
+            ]
+        );
+        assert_eq!(lines[3].1, format!("{sa}{}\n", ",".repeat(23)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_end_line_in_local_buffer_mode() -> Result<()> {
+        let test_output = reset_output();
+        let to_line = {
+            let test_output = Arc::clone(&test_output);
+            move |_: &str, line: &str, types: &str| -> Result<()> {
+                let mut out = test_output.lock().unwrap();
+                out.line_output.push_str(line);
+                out.line_output.push_str(types);
+                Ok(())
+            }
+        };
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(300)
+            .custom_line_fn(to_line)
+            .build()?;
+
+        // Local lines stay out of the line callback...
+        ctx.start_local_buffer_mode();
+        ctx.write_csv_record("test", &["a".to_string(), "1.00".to_string()])?;
+        ctx.end_line("sf")?;
+        assert_eq!(ctx.finish_local_buffer_mode(), "a,1.00\n");
+        assert_eq!(test_output.lock().unwrap().line_output, "");
+
+        // ...and don't shift the types of the next line
+        ctx.write_csv_record("test", &["b".to_string(), "20230101".to_string()])?;
+        ctx.end_line("sd")?;
+        assert_eq!(test_output.lock().unwrap().line_output, "b,20230101\nsd");
+
+        Ok(())
+    }
+
+    /// A `Write` collecting into a shared buffer, or failing with a broken pipe once it holds
+    /// `capacity` bytes.
+    #[derive(Clone)]
+    struct Pipe {
+        buffer: Arc<Mutex<Vec<u8>>>,
+        capacity: usize,
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            let mut buffer = self.buffer.lock().unwrap();
+            if buffer.len() >= self.capacity {
+                return Err(ErrorKind::BrokenPipe.into());
+            }
+            buffer.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Print rows of two forms to `pipe`, each form's first row preceded by its header row.
+    fn print_rows(pipe: &Pipe, form: Option<&str>) -> Result<()> {
+        let mut ctx = WriterContext::builder()
+            .buffer_size(300)
+            .print(Box::new(pipe.clone()), form.map(str::to_string))
+            .build()?;
+        for (filename, row) in [("SA", "SA11AI,1"), ("SB", "SB23,2"), ("SA", "SA11A1,3")] {
+            let columns: Vec<String> = vec!["form_type".into(), format!("{filename}_value")];
+            ctx.write_header_once(filename, &columns)?;
+            let fields: Vec<String> = row.split(',').map(str::to_string).collect();
+            ctx.write_csv_record(filename, &fields)?;
+        }
+        ctx.flush_all()
+    }
+
+    #[test]
+    fn test_print_mode_streams_rows() -> Result<()> {
+        let sa_only = Pipe {
+            buffer: Arc::default(),
+            capacity: usize::MAX,
+        };
+        print_rows(&sa_only, Some("sa"))?;
+        assert_eq!(
+            String::from_utf8(sa_only.buffer.lock().unwrap().clone())?,
+            "form_type,SA_value\nSA11AI,1\nSA11A1,3\n"
+        );
+
+        let all = Pipe {
+            buffer: Arc::default(),
+            capacity: usize::MAX,
+        };
+        print_rows(&all, None)?;
+        assert_eq!(
+            String::from_utf8(all.buffer.lock().unwrap().clone())?,
+            "form,form_type,SA_value\nSA,SA11AI,1\nform,form_type,SB_value\nSB,SB23,2\nSA,SA11A1,3\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_print_mode_stops_at_a_broken_pipe() {
+        let closed = Pipe {
+            buffer: Arc::default(),
+            capacity: 1,
+        };
+        let err = print_rows(&closed, None).unwrap_err();
+        assert!(is_broken_pipe(&err), "{err:#}");
+        // Only the first row got through, and dropping the writer didn't panic
+        assert_eq!(*closed.buffer.lock().unwrap(), b"form,form_type,SA_value\n");
+        assert!(!is_broken_pipe(&anyhow::anyhow!("other")));
+    }
+
+    #[test]
+    fn test_tab_delimited_output() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6007")
+            .buffer_size(16)
+            .output_delimiter(b'\t')
+            .build()?;
+        let columns = vec!["form_type".to_string(), "memo_text".to_string()];
+        ctx.write_header_once("SA", &columns)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "NOTE\tTABBED".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "A, B".to_string()])?;
+        ctx.flush_all()?;
+
+        assert!(!dir.path().join("6007/SA.csv").exists());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6007/SA.tsv"))?,
+            "form_type\tmemo_text\nSA11AI\t\"NOTE\tTABBED\"\nSA11AI\tA, B\n"
+        );
+        assert_eq!(ctx.file_stats()[0].0, "6007/SA.tsv");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rows_encoded_as_by_a_csv_writer_per_row() -> Result<()> {
+        let fixture = fs::read_to_string(Path::new("tests/fixtures/quoting_8.3.fec"))?;
+        let mut rows: Vec<Vec<String>> = fixture
+            .lines()
+            .map(|line| line.split('\x1c').map(str::to_string).collect())
+            .collect();
+        rows.push(vec!["LINE ONE\nLINE TWO".to_string(), "CR\r".to_string()]);
+        rows.push(vec![String::new()]);
+        rows.push(Vec::new());
+
+        for delimiter in [b',', b'\t', b';'] {
+            // The rows as encoded by a `csv` writer made for each one
+            let mut expected = Vec::new();
+            for row in &rows {
+                let mut wtr = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .delimiter(delimiter)
+                    .from_writer(&mut expected);
+                wtr.write_record(row)?;
+                wtr.flush()?;
+            }
+
+            let written = Arc::new(Mutex::new(Vec::new()));
+            let to_file = {
+                let written = Arc::clone(&written);
+                move |_: &str, _: &str, contents: &[u8]| -> Result<()> {
+                    written.lock().unwrap().extend_from_slice(contents);
+                    Ok(())
+                }
+            };
+            let mut ctx = WriterContext::builder()
+                .write_to_disk(false)
+                .buffer_size(64)
+                .custom_write_fn(to_file)
+                .output_delimiter(delimiter)
+                .build()?;
+            ctx.start_local_buffer_mode();
+            for row in &rows {
+                ctx.write_csv_record("SA", row)?;
+            }
+            assert_eq!(ctx.finish_local_buffer_mode().as_bytes(), expected);
+
+            for row in &rows {
+                ctx.write_csv_record("SA", row)?;
+            }
+            ctx.flush_all()?;
+            assert_eq!(*written.lock().unwrap(), expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_capacity_kept_across_flushes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let to_file = {
+            let written = Arc::clone(&written);
+            move |_: &str, _: &str, contents: &[u8]| -> Result<()> {
+                written.lock().unwrap().extend_from_slice(contents);
+                Ok(())
+            }
+        };
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6010")
+            .buffer_size(64)
+            .custom_write_fn(to_file)
+            .build()?;
+        assert_eq!(ctx.buffer_capacity("F99", "txt"), None);
+
+        ctx.write_string("F99", "txt", "first line\n")?;
+        let capacity = ctx.buffer_capacity("F99", "txt").unwrap();
+        assert!(capacity >= 64);
+        for i in 0..50 {
+            ctx.write_string("F99", "txt", &format!("line {} of a long memo\n", i))?;
+            assert_eq!(ctx.buffer_capacity("F99", "txt"), Some(capacity));
+        }
+        ctx.flush_all()?;
+        assert_eq!(ctx.buffer_capacity("F99", "txt"), Some(capacity));
+
+        // The custom write function and the file saw the same bytes
+        let on_disk = fs::read(dir.path().join("6010/F99.txt"))?;
+        assert!(on_disk.starts_with(b"first line\nline 0 of a long memo\n"));
+        assert_eq!(*written.lock().unwrap(), on_disk);
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_writes_bypass_the_buffer() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let text: String = (0..40_000)
+            .map(|i| format!("line {i} of a long memo\n"))
+            .collect();
+        // The text written whole, and in pieces that each fit the buffer
+        let whole = ["first line\n", &text, "last line\n"];
+        let pieces: Vec<&str> = text.split_inclusive('\n').collect();
+
+        let mut outputs = Vec::new();
+        for (filing_id, writes) in [("6010", &whole[..]), ("6011", &pieces[..])] {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let to_calls = {
+                let calls = Arc::clone(&calls);
+                move |_: &str, _: &str, contents: &[u8]| -> Result<()> {
+                    calls.lock().unwrap().push(contents.to_vec());
+                    Ok(())
+                }
+            };
+            let mut ctx = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .buffer_size(4096)
+                .custom_write_fn(to_calls)
+                .build()?;
+            if filing_id == "6011" {
+                ctx.write_string("F99", "txt", "first line\n")?;
+            }
+            for piece in writes {
+                ctx.write_string("F99", "txt", piece)?;
+            }
+            if filing_id == "6011" {
+                ctx.write_string("F99", "txt", "last line\n")?;
+            }
+            ctx.close()?.check()?;
+            let calls = calls.lock().unwrap().clone();
+            let on_disk = fs::read(dir.path().join(filing_id).join("F99.txt"))?;
+            assert_eq!(calls.concat(), on_disk);
+            outputs.push((calls, on_disk));
+        }
+
+        // The same bytes, in order, with the text passed on in one piece rather than buffered
+        assert_eq!(outputs[0].1, outputs[1].1);
+        let lengths: Vec<usize> = outputs[0].0.iter().map(|call| call.len()).collect();
+        assert_eq!(
+            lengths,
+            ["first line\n".len(), text.len(), "last line\n".len()]
+        );
+        assert!(outputs[1].0.iter().all(|call| call.len() <= 4096));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_policies() -> Result<()> {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .custom_write_fn({
+                let written = Arc::clone(&written);
+                move |filename: &str, _: &str, bytes: &[u8]| -> Result<()> {
+                    let bytes = String::from_utf8_lossy(bytes);
+                    written.lock().unwrap().push(format!("{filename}: {bytes}"));
+                    Ok(())
+                }
+            })
+            .flush_policy(FlushPolicy::PerLine)
+            .file_flush_policy("SB", FlushPolicy::PerNRows(2))
+            .file_flush_policy("SE", FlushPolicy::Size)
+            .build()?;
+        let taken = || std::mem::take(&mut *written.lock().unwrap());
+        let row = |id: &str| [id.to_string(), "100.00".to_string()];
+
+        // Each row as soon as it's written
+        ctx.write_csv_record("SA", &row("SA.1"))?;
+        assert_eq!(taken(), ["SA: SA.1,100.00\n"]);
+        ctx.write_csv_record("SA", &row("SA.2"))?;
+        assert_eq!(taken(), ["SA: SA.2,100.00\n"]);
+
+        // Every second row, per the file's own policy
+        ctx.write_csv_record("SB", &row("SB.1"))?;
+        assert!(taken().is_empty());
+        ctx.write_csv_record("SB", &row("SB.2"))?;
+        assert_eq!(taken(), ["SB: SB.1,100.00\nSB.2,100.00\n"]);
+
+        // Only once full or flushed
+        ctx.write_csv_record("SE", &row("SE.1"))?;
+        assert!(taken().is_empty());
+
+        // Lines of text as their line breaks are written
+        ctx.write_string("F99", "txt", "Dear ")?;
+        assert!(taken().is_empty());
+        ctx.write_string("F99", "txt", "Sir\nThe")?;
+        assert_eq!(taken(), ["F99: Dear Sir\nThe"]);
+
+        ctx.flush_all()?;
+        assert_eq!(taken(), ["SE: SE.1,100.00\n"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_policy_counts_rows_too_big_to_buffer() -> Result<()> {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(16)
+            .custom_write_fn({
+                let written = Arc::clone(&written);
+                move |_: &str, _: &str, bytes: &[u8]| -> Result<()> {
+                    written
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(bytes).into_owned());
+                    Ok(())
+                }
+            })
+            .flush_policy(FlushPolicy::PerNRows(2))
+            .build()?;
+        let taken = || std::mem::take(&mut *written.lock().unwrap());
+        let long = "x".repeat(40);
+
+        // Written past the buffer, but still the first of two rows
+        ctx.write_csv_record("SA", &["SA.1".to_string(), long.clone()])?;
+        assert_eq!(taken(), [format!("SA.1,{long}\n")]);
+        ctx.write_csv_record("SA", &["SA.2".to_string()])?;
+        assert_eq!(taken(), ["SA.2\n"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metrics_count_flushes_and_overflows() -> Result<()> {
+        let calls = Arc::new(Mutex::new(0));
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .filing_id("6017")
+            .buffer_size(4)
+            .custom_write_fn({
+                let calls = Arc::clone(&calls);
+                move |_: &str, _: &str, _: &[u8]| -> Result<()> {
+                    *calls.lock().unwrap() += 1;
+                    Ok(())
+                }
+            })
+            .build()?;
+        let counts = |ctx: &WriterContext| {
+            let metrics = ctx.metrics();
+            (metrics.flushes, metrics.overflows, metrics.bytes)
+        };
+
+        // Filling the buffer exactly doesn't write it out; the next write overflows it
+        ctx.write_string("F99", "txt", "ab")?;
+        ctx.write_string("F99", "txt", "cd")?;
+        assert_eq!(counts(&ctx), (0, 0, 0));
+        ctx.write_string("F99", "txt", "ef")?;
+        assert_eq!(counts(&ctx), (1, 1, 4));
+        // Too large to buffer: what's buffered, then the write itself
+        ctx.write_string("F99", "txt", "0123456789")?;
+        assert_eq!(counts(&ctx), (3, 2, 16));
+        // An empty buffer isn't written out
+        ctx.flush_all()?;
+        assert_eq!(counts(&ctx), (3, 2, 16));
+        ctx.write_string("F99", "txt", "x")?;
+        ctx.flush_all()?;
+        assert_eq!(counts(&ctx), (4, 2, 17));
+        assert_eq!(*calls.lock().unwrap(), 4);
+
+        // Kept by filing and output, and totalled
+        ctx.start_filing("6018".to_string())?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string()])?;
+        let metrics: WriterMetrics = ctx.close()?.metrics;
+        let files: Vec<(&str, &str, u64, u64, u64)> = metrics
+            .files
+            .iter()
+            .map(|file| {
+                let name = (file.filing_id.as_str(), file.filename.as_str());
+                (name.0, name.1, file.flushes, file.overflows, file.bytes)
+            })
+            .collect();
+        assert_eq!(files, [("6017", "F99", 4, 2, 17), ("6018", "SA", 2, 1, 7)]);
+        assert_eq!(
+            (metrics.flushes, metrics.overflows, metrics.bytes),
+            (6, 3, 24)
+        );
+        assert!(metrics.custom_write_time > Duration::ZERO);
+
+        let json = serde_json::to_value(&metrics)?;
+        assert_eq!(json["files"][1]["filename"], "SA");
+        assert_eq!(json["flushes"], 6);
+        Ok(())
+    }
+
+    /// A writer to `dir` whose custom write function fails for the rows of SA.
+    fn writer_failing_for_sa(dir: &Path, atomic_writes: bool) -> Result<WriterContext> {
+        WriterContext::builder()
+            .output_directory(dir.to_string_lossy())
+            .filing_id("6013")
+            .atomic_writes(atomic_writes)
+            .custom_write_fn(|filename: &str, _: &str, _: &[u8]| -> Result<()> {
+                match filename {
+                    "SA" => Err(anyhow::anyhow!("disk full")),
+                    _ => Ok(()),
+                }
+            })
+            .build()
+    }
+
+    #[test]
+    fn test_close_reports_files_whose_final_flush_fails() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = writer_failing_for_sa(dir.path(), false)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        let report = ctx.close()?;
+
+        // The other file is written whole and closed all the same
+        assert_eq!(report.closed.len(), 1);
+        assert_eq!(report.closed[0].0, "6013/SB.csv");
+        assert_eq!(report.closed[0].1.rows, 1);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6013/SB.csv"))?,
+            "SB23,SB.1\n"
+        );
+        assert_eq!(report.failed.len(), 1);
+        let (path, error) = &report.failed[0];
+        assert_eq!(path, "6013/SA.csv");
+        assert!(format!("{error:#}").contains("disk full"), "{error:#}");
+        let err = report.check().unwrap_err().to_string();
+        assert!(err.contains("6013/SA.csv: "), "{err}");
+        assert!(err.contains("disk full"), "{err}");
+
+        // With atomic writes, a failed file keeps every file from its final name
+        let dir = tempfile::tempdir()?;
+        let mut ctx = writer_failing_for_sa(dir.path(), true)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        let report = ctx.close()?;
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(fs::read_dir(dir.path().join("6013"))?.count(), 0);
+
+        // A writer dropped unclosed only warns that its flush failed
+        let dir = tempfile::tempdir()?;
+        let mut ctx = writer_failing_for_sa(dir.path(), false)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        drop(ctx);
+        Ok(())
+    }
+
+    /// What each in-memory sink was given, by path, with how many times it was finalized.
+    type MemoryFiles = Arc<Mutex<HashMap<PathBuf, (Vec<u8>, usize)>>>;
+
+    struct MemorySink {
+        path: PathBuf,
+        files: MemoryFiles,
+    }
+
+    impl Write for MemorySink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut files = self.files.lock().unwrap();
+            files.get_mut(&self.path).unwrap().0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl OutputSink for MemorySink {
+        fn finalize(&mut self) -> Result<()> {
+            self.files.lock().unwrap().get_mut(&self.path).unwrap().1 += 1;
+            Ok(())
+        }
+    }
+
+    struct MemorySinkFactory(MemoryFiles);
+
+    impl SinkFactory for MemorySinkFactory {
+        fn create(&self, _: &str, _: &str, path: &Path) -> Result<Box<dyn OutputSink>> {
+            let mut files = self.0.lock().unwrap();
+            files.entry(path.to_path_buf()).or_default();
+            Ok(Box::new(MemorySink {
+                path: path.to_path_buf(),
+                files: self.0.clone(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_sink_factory_gets_each_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = MemoryFiles::default();
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6013")
+            .buffer_size(16)
+            .max_rows_per_file(Some(2))
+            .sink_factory(MemorySinkFactory(files.clone()))
+            .build()?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.2".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.3".to_string()])?;
+        ctx.write_string("F99", "txt", "Text")?;
+        ctx.flush_all()?;
+        let stats = ctx.file_stats();
+        ctx.close()?.check()?;
+
+        let files = files.lock().unwrap();
+        let file = |path: &str| {
+            let (bytes, finalized) = &files[Path::new(path)];
+            (String::from_utf8(bytes.clone()).unwrap(), *finalized)
+        };
+        // Each is finalized by `flush_all` and as it is closed, the first part of SA as it is split
+        assert_eq!(files.len(), 4);
+        assert_eq!(
+            file("6013/SA.csv"),
+            ("SA11AI,SA.1\nSA11AI,SA.2\n".to_string(), 1)
+        );
+        assert_eq!(file("6013/SA_002.csv"), ("SA11AI,SA.3\n".to_string(), 2));
+        assert_eq!(file("6013/SB.csv"), ("SB23,SB.1\n".to_string(), 2));
+        assert_eq!(file("6013/F99.txt"), ("Text".to_string(), 2));
+        // Stats are kept as for files on disk, but nothing is written there
+        let paths: Vec<&str> = stats.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "6013/F99.txt",
+                "6013/SA.csv",
+                "6013/SA_002.csv",
+                "6013/SB.csv"
+            ]
+        );
+        assert_eq!(fs::read_dir(dir.path())?.count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sink_factory_rejects_disk_only_settings() {
+        let factory = |_: &str, _: &str, _: &Path| -> Result<Box<dyn OutputSink>> {
+            Err(anyhow::anyhow!("unused"))
+        };
+        let err = WriterContext::builder()
+            .sink_factory(factory)
+            .atomic_writes(true)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Atomic writes"), "{err}");
+        let err = WriterContext::builder()
+            .sink_factory(factory)
+            .compression(Compression::Gzip)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Compression"), "{err}");
+    }
+
+    #[test]
+    fn test_extension_with_or_without_dot_is_one_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6020")
+            .build()?;
+        ctx.write_string("x", ".csv", "a,b\n")?;
+        ctx.write_csv_record("x", &["c".to_string(), "d".to_string()])?;
+        ctx.write_string("x", "csv", "e,f\n")?;
+        assert!(ctx.has_file("x", ".csv") && ctx.has_file("x", "csv"));
+        let stats = ctx.file_stats();
+        assert_eq!(stats.len(), 1, "{stats:?}");
+        assert_eq!(stats[0].0, "6020/x.csv");
+        ctx.close()?.check()?;
+
+        let names: Vec<_> = fs::read_dir(dir.path().join("6020"))?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(names, ["x.csv"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6020/x.csv"))?,
+            "a,b\nc,d\ne,f\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_stats_count_every_write() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6013")
+            .max_open_files(1)
+            .max_rows_per_file(Some(2))
+            .build()?;
+        let row = |form: &str, id: &str| [form.to_string(), id.to_string()];
+        ctx.write_csv_record("SA", &row("SA11AI", "SA.1"))?;
+        ctx.write_string("F99", "txt", "Text of the")?;
+        ctx.write_csv_record("SB", &row("SB23", "SB.1"))?;
+        ctx.write_string("F99", "txt", " filing")?;
+        ctx.write_csv_record("SA", &row("SA11AI", "SA.2"))?;
+        ctx.write_csv_record("SA", &row("SA11AI", "SA.3"))?;
+
+        // Exact though nothing is flushed yet, and across both parts of SA
+        let stats = |filing_id: &str, filename: &str, extension: &str, rows, bytes| OutputStats {
+            filing_id: filing_id.to_string(),
+            filename: filename.to_string(),
+            extension: extension.to_string(),
+            rows,
+            bytes,
+        };
+        let first = vec![
+            stats("6013", "F99", "txt", 0, 18),
+            stats("6013", "SA", "csv", 3, 36),
+            stats("6013", "SB", "csv", 1, 10),
+        ];
+        assert_eq!(ctx.stats(), first);
+        let sa_parts: u64 = ctx
+            .file_stats()
+            .iter()
+            .filter(|(path, _)| path.starts_with("6013/SA"))
+            .map(|(_, stats)| stats.bytes)
+            .sum();
+        assert_eq!(sa_parts, 36);
+
+        // Flushing, with files closed to make room, and later filings change nothing
+        ctx.flush_all()?;
+        ctx.start_filing("6014".to_string())?;
+        ctx.write_csv_record("SA", &row("SA11AI", "SA.4"))?;
+        let mut all = first;
+        all.push(stats("6014", "SA", "csv", 1, 12));
+        assert_eq!(ctx.stats(), all);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_open_and_close_events() -> Result<()> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (on_write, on_open, on_close) = (events.clone(), events.clone(), events.clone());
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .filing_id("6013")
+            .buffer_size(16)
+            .custom_write_fn(move |filename: &str, _: &str, bytes: &[u8]| -> Result<()> {
+                let event = format!("write {filename} {}", bytes.len());
+                on_write.lock().unwrap().push(event);
+                Ok(())
+            })
+            .on_file_open(move |filename, extension| {
+                let event = format!("open {filename}.{extension}");
+                on_open.lock().unwrap().push(event);
+                Ok(())
+            })
+            .on_file_close(move |filename, extension, bytes| {
+                let event = format!("close {filename}.{extension} {bytes}");
+                on_close.lock().unwrap().push(event);
+                Ok(())
+            })
+            .build()?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.2".to_string()])?;
+        // Flushing doesn't close anything
+        ctx.flush_all()?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.2".to_string()])?;
+        ctx.close()?;
+
+        let events = events.lock().unwrap();
+        for (filename, bytes) in [("SA", 24), ("SB", 20)] {
+            let own: Vec<&String> = events
+                .iter()
+                .filter(|event| event.split(' ').nth(1).unwrap().starts_with(filename))
+                .collect();
+            assert_eq!(*own[0], format!("open {filename}.csv"), "{events:?}");
+            assert_eq!(*own[own.len() - 1], format!("close {filename}.csv {bytes}"));
+            let written: usize = own[1..own.len() - 1]
+                .iter()
+                .map(|event| {
+                    assert!(event.starts_with("write "), "{events:?}");
+                    event.rsplit(' ').next().unwrap().parse::<usize>().unwrap()
+                })
+                .sum();
+            assert_eq!(written, bytes);
+        }
+        assert_eq!(events.iter().filter(|e| e.starts_with("open")).count(), 2);
+        assert_eq!(events.iter().filter(|e| e.starts_with("close")).count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_close_events_on_start_filing_and_failures() -> Result<()> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let on_close = events.clone();
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6013")
+            .custom_write_fn(|filename: &str, _: &str, _: &[u8]| -> Result<()> {
+                match filename {
+                    "SA" => Err(anyhow::anyhow!("disk full")),
+                    _ => Ok(()),
+                }
+            })
+            .on_file_close(move |filename, _, bytes| {
+                on_close.lock().unwrap().push(format!("{filename} {bytes}"));
+                match filename {
+                    "SC" => Err(anyhow::anyhow!("upload failed")),
+                    _ => Ok(()),
+                }
+            })
+            .build()?;
+        // Each filing's files are closed as the next one starts
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        ctx.start_filing("6014".to_string())?;
+        assert_eq!(*events.lock().unwrap(), ["SB 10"]);
+
+        // A file that fails to be written isn't closed, and one the callback fails for is
+        // reported
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.2".to_string()])?;
+        ctx.write_csv_record("SC", &["SC/10".to_string(), "SC.1".to_string()])?;
+        let report = ctx.close()?;
+        assert_eq!(*events.lock().unwrap(), ["SB 10", "SB 10", "SC 11"]);
+        let failed: Vec<&str> = report.failed.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(failed, ["6014/SA.csv", "6014/SC.csv"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_all_flushes_every_file_and_reports_the_failures() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = writer_failing_for_sa(dir.path(), false)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SA.1".to_string()])?;
+        ctx.write_csv_record("SB", &["SB23".to_string(), "SB.1".to_string()])?;
+        ctx.write_csv_record("SC", &["SC/10".to_string(), "SC.1".to_string()])?;
+        let err = ctx.flush_all().unwrap_err();
+
+        // Only the failing file is reported incomplete
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::IncompleteOutput { files }) => {
+                assert_eq!(files.len(), 1);
+                assert_eq!(files[0].0, "6013/SA.csv");
+                assert!(files[0].1.contains("disk full"), "{}", files[0].1);
+            }
+            _ => panic!("expected IncompleteOutput, got {err:#}"),
+        }
+        assert!(err.to_string().contains("6013/SA.csv: "), "{err}");
+
+        // The files after it are flushed all the same
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6013/SB.csv"))?,
+            "SB23,SB.1\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6013/SC.csv"))?,
+            "SC/10,SC.1\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_close_syncs_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6014")
+            .compression(Compression::Gzip)
+            .max_open_files(1)
+            .sync_on_close(true)
+            .build()?;
+        // Compressed files aren't closed to make room for others, so SA is synced as it's closed
+        ctx.write_csv_record("SA", &["SA11AI".to_string()])?;
+        ctx.flush_all()?;
+        ctx.write_csv_record("SB", &["SB23".to_string()])?;
+        let report = ctx.close()?;
+        report.check()?;
+        let closed: Vec<(&str, usize)> = report
+            .closed
+            .iter()
+            .map(|(path, stats)| (path.as_str(), stats.rows))
+            .collect();
+        assert_eq!(closed, [("6014/SA.csv.gz", 1), ("6014/SB.csv.gz", 1)]);
+        for (form, row) in [("SA", "SA11AI\n"), ("SB", "SB23\n")] {
+            let mut text = String::new();
+            let file = fs::File::open(dir.path().join(format!("6014/{form}.csv.gz")))?;
+            MultiGzDecoder::new(file).read_to_string(&mut text)?;
+            assert_eq!(text, row);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_on_close_with_split_and_atomic_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let write = |sync_on_close: bool, filing_id: &str| -> Result<CloseReport> {
+            let mut ctx = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .max_rows_per_file(Some(2))
+                .atomic_writes(true)
+                .manifest(true)
+                .sync_on_close(sync_on_close)
+                .build()?;
+            for i in 1..=3 {
+                ctx.write_csv_record("SA", &["SA11AI".to_string(), format!("SA.{i}")])?;
+            }
+            ctx.flush_all()?;
+            ctx.write_string("F99", "txt", "Synced\n")?;
+            ctx.finalize()?;
+            let report = ctx.close()?;
+            report.check()?;
+            Ok(report)
+        };
+
+        assert_eq!(write(false, "6015")?.sync_time, Duration::ZERO);
+        let report = write(true, "6016")?;
+        assert!(report.sync_time > Duration::ZERO);
+        let closed: Vec<&str> = report
+            .closed
+            .iter()
+            .map(|(path, _)| path.as_str())
+            .collect();
+        assert_eq!(closed, ["6016/F99.txt", "6016/SA.csv", "6016/SA_002.csv"]);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6016/SA_002.csv"))?,
+            "SA11AI,SA.3\n"
+        );
+        assert!(dir.path().join("6016/manifest.csv").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_durable_syncs_files_and_directory() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let write = |durable: bool, filing_id: &str| -> Result<CloseReport> {
+            let mut ctx = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .durable(durable)
+                .build()?;
+            ctx.write_csv_record("SA", &["SA11AI".to_string()])?;
+            ctx.flush_all()?;
+            let report = ctx.close()?;
+            report.check()?;
+            Ok(report)
+        };
+
+        let report = write(false, "6017")?;
+        assert_eq!(report.sync_time, Duration::ZERO);
+        assert_eq!(report.metrics.directory_syncs, 0);
+
+        let report = write(true, "6018")?;
+        assert!(report.sync_time > Duration::ZERO);
+        // The filing's directory holding SA.csv and the output directory holding it, synced by
+        // `flush_all` and again by `close`
+        #[cfg(unix)]
+        assert_eq!(report.metrics.directory_syncs, 4);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6018/SA.csv"))?,
+            "SA11AI\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_double_formats() -> Result<()> {
+        let cases = [
+            (0.1 + 0.2, ["0.30", "0.30000000000000004", "0.3"]),
+            (3.0, ["3.00", "3", "3"]),
+            (-1250.5, ["-1250.50", "-1250.5", "-1250.5"]),
+            (-0.001, ["-0.00", "-0.001", "0"]),
+            (
+                98_765_432_109.99,
+                ["98765432109.99", "98765432109.99", "98765432109.99"],
+            ),
+            (
+                1e21,
+                [
+                    "1000000000000000000000.00",
+                    "1000000000000000000000",
+                    "1000000000000000000000",
+                ],
+            ),
+        ];
+        for (value, [fixed, round_trip, trimmed]) in cases {
+            assert_eq!(format_double_with(value, DoubleFormat::Fixed(2)), fixed);
+            assert_eq!(
+                format_double_with(value, DoubleFormat::RoundTrip),
+                round_trip
+            );
+            assert_eq!(format_double_with(value, DoubleFormat::Trimmed(2)), trimmed);
+            assert_eq!(round_trip.parse::<f64>()?, value);
+        }
+        assert_eq!(format_double_with(2.5, DoubleFormat::Fixed(0)), "2");
+        assert_eq!(
+            format_double_with(1.23456, DoubleFormat::Trimmed(4)),
+            "1.2346"
+        );
+
+        let mut ctx = WriterContext::builder()
+            .discard_output()
+            .write_to_disk(false)
+            .double_format(DoubleFormat::RoundTrip)
+            .build()?;
+        ctx.start_local_buffer_mode();
+        ctx.write_double("SA", "csv", 0.1 + 0.2)?;
+        ctx.write_char("SA", "csv", ',')?;
+        ctx.write_double_with("SA", "csv", 3.0, DoubleFormat::Fixed(2))?;
+        assert_eq!(ctx.finish_local_buffer_mode(), "0.30000000000000004,3.00");
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_writes() -> Result<()> {
+        assert_eq!(
+            format_date_with(2024, 1, 5, DateFormat::Compact),
+            "20240105"
+        );
+        assert_eq!(format_integer(-12_345_678_901_234), "-12345678901234");
+
+        // Local mode: each helper formats as the writer's formats say
+        let mut ctx = WriterContext::builder()
+            .discard_output()
+            .write_to_disk(false)
+            .bool_format(BoolFormat::new("Y", "N"))
+            .build()?;
+        ctx.start_local_buffer_mode();
+        ctx.write_date("SA", "csv", (2024, 2, 29))?;
+        ctx.write_char("SA", "csv", ',')?;
+        ctx.write_integer("SA", "csv", i64::MAX)?;
+        ctx.write_char("SA", "csv", ',')?;
+        ctx.write_bool("SA", "csv", true)?;
+        ctx.write_bool("SA", "csv", false)?;
+        assert_eq!(
+            ctx.finish_local_buffer_mode(),
+            "2024-02-29,9223372036854775807,YN"
+        );
+
+        // No such dates are refused, with nothing written
+        ctx.start_local_buffer_mode();
+        for date in [(2023, 2, 29), (2024, 13, 1), (2024, 4, 31), (2024, 1, 0)] {
+            assert!(ctx.write_date("SA", "csv", date).is_err(), "{date:?}");
+        }
+        assert_eq!(ctx.finish_local_buffer_mode(), "");
+
+        // File mode: written to the file and the custom line alike
+        let dir = tempfile::tempdir()?;
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6060")
+            .date_format(DateFormat::Compact)
+            .custom_line_fn({
+                let lines = Arc::clone(&lines);
+                move |_: &str, line: &str, types: &str| -> Result<()> {
+                    lines.lock().unwrap().push(format!("{line}{types}"));
+                    Ok(())
+                }
+            })
+            .build()?;
+        ctx.write_date("SA", "csv", (2024, 1, 15))?;
+        ctx.write_char("SA", "csv", ',')?;
+        ctx.write_integer("SA", "csv", -42)?;
+        ctx.write_char("SA", "csv", ',')?;
+        ctx.write_bool("SA", "csv", false)?;
+        ctx.write_char("SA", "csv", '\n')?;
+        ctx.end_line("dss")?;
+        ctx.close()?.check()?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6060/SA.csv"))?,
+            "20240115,-42,false\n"
+        );
+        assert_eq!(*lines.lock().unwrap(), ["20240115,-42,false\ndss"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        for name in ["SA", "F3XN", "unknown_forms", "COM10", "SC1-10", "résumé"] {
+            assert_eq!(sanitize_filename(name), name);
+        }
+        // `/` is replaced like any other character, so it can't collide with a `-`
+        let slash = sanitize_filename("SC/10");
+        assert!(slash.starts_with("SC-10_"), "{slash}");
+        assert_ne!(slash, sanitize_filename("SC-10"));
+
+        for (name, sanitized) in [
+            ("A?B:C", "A-B-C_ad104176"),
+            ("A:B?C", "A-B-C_acfc3d2a"),
+            ("CON", "CON_3367e86b"),
+            ("con", "con_f08dd14b"),
+            ("LPT1", "LPT1_911f443a"),
+            ("NUL.txt", "NUL-txt_22d7f156"),
+            ("a.b", "a-b_108bf50c"),
+            ("..", "--_a3d4a70d"),
+            ("a. ", "a-_d28b9372"),
+            ("x\ty", "x-y_c68d5b89"),
+            ("", "_811c9dc5"),
+        ] {
+            assert_eq!(sanitize_filename(name), sanitized, "{name:?}");
+        }
+        for name in [
+            r"a\b", "a*b", "a\"b", "a<b", "a>b", "a|b", "a\0b", "a\u{7f}b",
+        ] {
+            let sanitized = sanitize_filename(name);
+            assert!(sanitized.starts_with("a-b_"), "{name:?} => {sanitized}");
+        }
+
+        // Long names are cut on a character boundary, keeping room for the hash
+        for name in ["x".repeat(300), "é".repeat(80)] {
+            let sanitized = sanitize_filename(&name);
+            assert!(sanitized.len() <= MAX_FILENAME_LENGTH, "{sanitized}");
+            assert_ne!(sanitize_filename(&format!("{name}y")), sanitized);
+        }
+        assert_eq!(sanitize_filename(&"x".repeat(100)), "x".repeat(100));
+
+        // Sanitized names are left alone, and names sanitized alike stay apart
+        let names = [
+            "A?B", "A:B", "A-B", "A.B", "A B ", "A B", "CON", "con", "A\tB",
+        ];
+        let mut sanitized: Vec<String> = names.iter().map(|name| sanitize_filename(name)).collect();
+        for name in &sanitized {
+            assert_eq!(&sanitize_filename(name), name);
+        }
+        sanitized.sort();
+        sanitized.dedup();
+        assert_eq!(sanitized.len(), names.len());
+    }
+
+    #[test]
+    fn test_filename_templates() -> Result<()> {
+        let expand = |template: &str, extension: &str| -> Result<String> {
+            let template: FilenameTemplate = template.parse()?;
+            let path = template.expand("12345", "SA", extension);
+            Ok(path.to_string_lossy().into_owned())
+        };
+        assert_eq!(expand("{filing_id}_{form}.csv", "csv")?, "12345_SA.csv");
+        assert_eq!(
+            expand("{filing_id}_{form}.csv", "csv.gz")?,
+            "12345_SA.csv.gz"
+        );
+        assert_eq!(expand("{filing_id}_{form}.csv", "txt")?, "12345_SA.txt");
+        assert_eq!(expand("{form}/{filing_id}", "tsv")?, "SA/12345.tsv");
+        assert_eq!(
+            expand("out/{form}.{extension}", "csv.zst")?,
+            "out/SA.csv.zst"
+        );
+        let dated = expand("{date}/{form}.csv", "csv")?;
+        let (date, name) = dated.split_once('/').unwrap();
+        assert!(
+            date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()),
+            "{date}"
+        );
+        assert_eq!(name, "SA.csv");
+
+        for (template, error) in [
+            ("{filing_id}_{from}.csv", "Unknown placeholder {from}"),
+            ("{form.csv", "Unmatched brace"),
+            ("form}.csv", "Unmatched brace"),
+            ("", "empty"),
+            ("{form}//{filing_id}.csv", "would be empty"),
+            ("{form}/", "would be empty"),
+            ("/tmp/{form}.csv", "relative to the output directory"),
+            ("../{form}.csv", ". or .. directories"),
+        ] {
+            let err = template.parse::<FilenameTemplate>().unwrap_err();
+            assert!(err.to_string().contains(error), "{template}: {err}");
+        }
+
+        // The filing ID a writer is built for must expand to a file name
+        let err = WriterContext::builder()
+            .filing_id("")
+            .filename_template(Some("{filing_id}.csv".to_string()))
+            .build()
+            .err()
+            .expect("an empty file name");
+        assert!(err.to_string().contains("would be empty"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_filename_template_collisions_rejected() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6012")
+            .filename_template(Some("{filing_id}.csv".to_string()))
+            .build()?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string()])?;
+        let err = ctx
+            .write_csv_record("SB", &["SB23".to_string()])
+            .expect_err("SA and SB expand to the same path");
+        let message = err.to_string();
+        assert!(message.contains("SA.csv and SB.csv"), "{message}");
+        assert!(message.contains("would both be written to"), "{message}");
+
+        // Other extensions of the same form are files of their own
+        ctx.write_text("F99", "txt", "text")?;
+        ctx.flush_all()?;
+        assert!(dir.path().join("6012.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crlf_ends_streamed_and_ndjson_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6011")
+            .terminator(Terminator::Crlf)
+            .build()?;
+        ctx.write_header_once("F99", &["form_type".to_string(), "text".to_string()])?;
+        ctx.start_streamed_row("F99", &["F99".to_string()])?;
+        ctx.write_streamed_field("F99", "line one\n")?;
+        ctx.write_streamed_field("F99", "line two")?;
+        ctx.finish_streamed_row("F99")?;
+        ctx.write_text("F99", "txt", "line one\nline two\r\n")?;
+        ctx.flush_all()?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6011/F99.csv"))?,
+            "form_type,text\r\nF99,\"line one\nline two\"\r\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6011/F99.txt"))?,
+            "line one\r\nline two\r\n"
+        );
+
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6012")
+            .format(OutputFormat::Ndjson)
+            .terminator(Terminator::Crlf)
+            .build()?;
+        ctx.write_header_once("SA", &["form_type".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string()])?;
+        ctx.write_csv_record("SA", &["SA11B".to_string()])?;
+        ctx.flush_all()?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6012/SA.ndjson"))?,
+            "{\"form_type\":\"SA11AI\"}\r\n{\"form_type\":\"SA11B\"}\r\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_quote_styles() -> Result<()> {
+        let header = ["form_type".to_string(), "name".to_string()];
+        let fields = [
+            "SA11AI".to_string(),
+            "SMITH, JANE".to_string(),
+            String::new(),
+        ];
+        let cases = [
+            (
+                QuoteStyle::Always,
+                None,
+                "\"form_type\",\"name\"\n\"SA11AI\",\"SMITH, JANE\",\"\"\n\"F99\",\"A, B\"\n",
+            ),
+            (
+                QuoteStyle::Necessary,
+                None,
+                "form_type,name\nSA11AI,\"SMITH, JANE\",\nF99,\"A, B\"\n",
+            ),
+            (
+                QuoteStyle::Never,
+                Some(';'),
+                "form_type,name\nSA11AI,SMITH; JANE,\nF99,A; B\n",
+            ),
+        ];
+        for (style, replacement, expected) in cases {
+            let dir = tempfile::tempdir()?;
+            let mut ctx = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("6013")
+                .quote_style(style)
+                .unquotable_replacement(replacement)
+                .build()?;
+            ctx.write_header_once("SA", &header)?;
+            ctx.write_csv_record("SA", &fields)?;
+            ctx.start_streamed_row("SA", &["F99".to_string()])?;
+            ctx.write_streamed_field("SA", "A, B")?;
+            ctx.finish_streamed_row("SA")?;
+            ctx.flush_all()?;
+            let written = fs::read(dir.path().join("6013/SA.csv"))?;
+            assert_eq!(String::from_utf8(written)?, expected, "{:?}", style);
+        }
+
+        // Without a replacement, a field unquoted output can't hold fails the row
+        let mut ctx = WriterContext::builder()
+            .discard_output()
+            .write_to_disk(false)
+            .quote_style(QuoteStyle::Never)
+            .build()?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "SMITH JANE".to_string()])?;
+        let error = ctx.write_csv_record("SA", &fields).unwrap_err();
+        assert!(format!("{:#}", error).contains("\"SMITH, JANE\" holds the delimiter"));
+        assert!(ctx.write_streamed_field("SA", "LINE\nBREAK").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_strings() -> Result<()> {
+        let fields = ["SA11AI".to_string(), String::new(), "A, B".to_string()];
+        let cases = [
+            (None, QuoteStyle::Necessary, "SA11AI,,\"A, B\"\n\"\"\n"),
+            (None, QuoteStyle::Always, "\"SA11AI\",\"\",\"A, B\"\n\"\"\n"),
+            (
+                Some("NULL"),
+                QuoteStyle::Necessary,
+                "SA11AI,NULL,\"A, B\"\nNULL\n",
+            ),
+            (
+                Some("NULL"),
+                QuoteStyle::Always,
+                "\"SA11AI\",NULL,\"A, B\"\nNULL\n",
+            ),
+            (
+                Some("\\N"),
+                QuoteStyle::Necessary,
+                "SA11AI,\\N,\"A, B\"\n\\N\n",
+            ),
+            (
+                Some("\\N"),
+                QuoteStyle::Always,
+                "\"SA11AI\",\\N,\"A, B\"\n\\N\n",
+            ),
+        ];
+        for (null_string, style, expected) in cases {
+            let mut ctx = WriterContext::builder()
+                .discard_output()
+                .write_to_disk(false)
+                .quote_style(style)
+                .null_string(null_string.map(str::to_string))
+                .build()?;
+            ctx.start_local_buffer_mode();
+            ctx.write_csv_record("SA", &fields)?;
+            ctx.write_csv_record("SA", &[String::new()])?;
+            assert_eq!(
+                ctx.finish_local_buffer_mode(),
+                expected,
+                "{:?}",
+                null_string
+            );
+        }
+
+        // Unquoted output has the token where a field is empty, its other fields as they are
+        let mut ctx = WriterContext::builder()
+            .discard_output()
+            .write_to_disk(false)
+            .quote_style(QuoteStyle::Never)
+            .unquotable_replacement(Some(';'))
+            .null_string(Some("\\N".to_string()))
+            .build()?;
+        ctx.start_local_buffer_mode();
+        ctx.write_csv_record("SA", &fields)?;
+        ctx.write_csv_record("SA", &["NULL".to_string(), String::new()])?;
+        assert_eq!(
+            ctx.finish_local_buffer_mode(),
+            "SA11AI,\\N,A; B\nNULL,\\N\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_semicolon_delimiter_in_local_and_streamed_rows() -> Result<()> {
+        let test_output = reset_output();
+        let to_line = {
+            let test_output = Arc::clone(&test_output);
+            move |_: &str, line: &str, _: &str| -> Result<()> {
+                test_output.lock().unwrap().line_output.push_str(line);
+                Ok(())
+            }
+        };
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .buffer_size(300)
+            .custom_line_fn(to_line)
+            .output_delimiter(b';')
+            .build()?;
+        let fields = ["a;b".to_string(), "1,5".to_string()];
+
+        ctx.start_local_buffer_mode();
+        ctx.write_csv_record("test", &fields)?;
+        assert_eq!(ctx.finish_local_buffer_mode(), "\"a;b\";1,5\n");
+
+        ctx.write_csv_record("test", &fields)?;
+        ctx.end_line("ss")?;
+        ctx.start_streamed_row("test", &fields)?;
+        ctx.write_streamed_field("test", "x;y")?;
+        ctx.finish_streamed_row("test")?;
+        ctx.end_line("sss")?;
+        assert_eq!(
+            test_output.lock().unwrap().line_output,
+            "\"a;b\";1,5\n\"a;b\";1,5;\"x;y\"\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_interleaved_files_get_their_own_lines() -> Result<()> {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let to_lines = {
+            let lines = Arc::clone(&lines);
+            move |filename: &str, line: &str, types: &str| -> Result<()> {
+                let line = (filename.to_string(), line.to_string(), types.to_string());
+                lines.lock().unwrap().push(line);
+                Ok(())
+            }
+        };
+        let mut ctx = WriterContext::builder()
+            .write_to_disk(false)
+            .custom_line_fn(to_lines)
+            .build()?;
+        ctx.write_string("SA", "csv", "SA11AI,")?;
+        ctx.write_string("SB", "csv", "SB23,")?;
+        ctx.write_char("SA", "csv", '1')?;
+        ctx.write_string("SB", "csv", "2\n")?;
+        ctx.write_string("SA", "csv", "\n")?;
+        ctx.end_line_of("SB", "csv", "sd")?;
+        ctx.write_string("SB", "csv", "SB23,3\n")?;
+        ctx.end_line_of("SA", "csv", "sd")?;
+        // `end_line` ends the line of the file last written to
+        ctx.end_line("sd")?;
+
+        let line =
+            |filename: &str, line: &str| (filename.to_string(), line.to_string(), "sd".to_string());
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                line("SB", "SB23,2\n"),
+                line("SA", "SA11AI,1\n"),
+                line("SB", "SB23,3\n")
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rerun_in_each_output_mode() -> Result<()> {
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let dir = tempfile::tempdir()?;
+        let run = |mode: OutputMode| -> Result<()> {
+            let mut reader = BufReader::new(fs::File::open(&fixture)?);
+            let mut ctx = FecContext::new("6008".to_string(), false, true, false);
+            let mut writer = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("6008")
+                .output_mode(mode)
+                .build()?;
+            writer.check_existing_output()?;
+            parse_fec(&mut ctx, &mut reader, &mut writer)?;
+            writer.flush_all()
+        };
+        let sa = dir.path().join("6008/SA.csv");
+
+        // Nothing there yet, so no mode refuses to write
+        run(OutputMode::FailIfExists)?;
+        let first = fs::read_to_string(&sa)?;
+        assert_eq!(first.lines().count(), 3);
+
+        // Rerunning replaces the files by default...
+        run(OutputMode::default())?;
+        assert_eq!(fs::read_to_string(&sa)?, first);
+
+        // ...adds the rows again after the existing header when appending...
+        run(OutputMode::Append)?;
+        let appended = fs::read_to_string(&sa)?;
+        assert_eq!(appended.lines().count(), 5);
+        assert_eq!(appended.matches("form_type,").count(), 1);
+
+        // ...and fails before parsing, naming a file that exists, when asked to
+        let err = run(OutputMode::FailIfExists).unwrap_err().to_string();
+        assert!(err.contains("already exists"), "{err}");
+        assert!(
+            err.contains(&dir.path().join("6008").display().to_string()),
+            "{err}"
+        );
+        assert_eq!(fs::read_to_string(&sa)?, appended);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_preflight_checks_output_is_writable() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let writer = |output: &Path| {
+            WriterContext::builder()
+                .output_directory(output.to_string_lossy())
+                .filing_id("6050")
+        };
+        let kind = |err: anyhow::Error| match err.downcast::<FecError>() {
+            Ok(FecError::OutputNotWritable { kind, .. }) => Some(kind),
+            _ => None,
+        };
+
+        // The filing's directory is created, and the probe file removed
+        writer(dir.path()).build()?.preflight()?;
+        let files: Vec<_> = fs::read_dir(dir.path().join("6050"))?.collect();
+        assert!(files.is_empty());
+
+        // A file where a directory has to go
+        let blocked = dir.path().join("blocked");
+        fs::write(&blocked, "")?;
+        let err = writer(&blocked).build()?.preflight().unwrap_err();
+        assert!(
+            err.to_string().contains(&blocked.display().to_string()),
+            "{err}"
+        );
+        assert!(kind(err).is_some());
+
+        // A read-only directory, unless permissions don't apply to this user (root)
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let read_only = dir.path().join("read_only");
+            fs::create_dir(&read_only)?;
+            fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555))?;
+            if fs::write(read_only.join("probe"), "").is_err() {
+                let err = writer(&read_only).build()?.preflight().unwrap_err();
+                assert_eq!(kind(err), Some(ErrorKind::PermissionDenied));
+            }
+            fs::set_permissions(&read_only, fs::Permissions::from_mode(0o755))?;
+
+            // No file system has this much room
+            let err = writer(dir.path())
+                .min_free_space(Some(u64::MAX))
+                .build()?
+                .preflight()
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<FecError>(),
+                Some(FecError::InsufficientSpace {
+                    required: u64::MAX,
+                    ..
+                })
+            ));
+        }
+
+        // Nothing is checked without writing to disk
+        writer(&blocked)
+            .write_to_disk(false)
+            .discard_output()
+            .build()?
+            .preflight()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_second_filing_keeps_one_header_row() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let run = |filing_id: &str, columns: &[&str]| -> Result<Vec<String>> {
+            let mut ctx = WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id(filing_id)
+                .filename_template(Some("{form}.{extension}".to_string()))
+                .output_mode(OutputMode::Append)
+                .max_open_files(1)
+                .build()?;
+            let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+            for (filename, row) in [("SA", "SA11AI"), ("SB", "SB23"), ("SA", "SA11AI")] {
+                ctx.write_header_once(filename, &columns)?;
+                ctx.write_csv_record(filename, &[row.to_string(), filing_id.to_string()])?;
+                ctx.end_line("ss")?;
+            }
+            let mismatches = ctx.header_mismatches().to_vec();
+            let report = ctx.close()?;
+            assert_eq!(report.header_mismatches, mismatches);
+            Ok(mismatches)
+        };
+
+        // The second filing's rows follow the first's under the one header row, however often
+        // the file is reopened
+        assert!(run("6040", &["form_type", "filing_id"])?.is_empty());
+        assert!(run("6041", &["form_type", "filing_id"])?.is_empty());
+        let sa = fs::read_to_string(dir.path().join("SA.csv"))?;
+        assert_eq!(
+            sa,
+            "form_type,filing_id\nSA11AI,6040\nSA11AI,6040\nSA11AI,6041\nSA11AI,6041\n"
+        );
+
+        // Rows appended under other columns than the file's are reported, once per file
+        let mismatches = run("6042", &["form_type", "report_id"])?;
+        let paths: Vec<_> = ["SA.csv", "SB.csv"]
+            .map(|name| dir.path().join(name).display().to_string())
+            .into();
+        assert_eq!(mismatches, paths);
+        let sa = fs::read_to_string(dir.path().join("SA.csv"))?;
+        assert_eq!(sa.matches("form_type,").count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_files_capped_with_lru_eviction() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6009")
+            .buffer_size(24)
+            .max_open_files(2)
+            .build()?;
+        let columns = vec!["form_type".to_string(), "value".to_string()];
+        let names: Vec<String> = (0..10).map(|i| format!("F{i}")).collect();
+
+        // Rows go round all ten files, so every file is closed and reopened along the way
+        for row in 0..5 {
+            for name in &names {
+                ctx.write_header_once(name, &columns)?;
+                ctx.write_csv_record(name, &[name.clone(), row.to_string()])?;
+            }
+        }
+        ctx.flush_all()?;
+
+        for name in &names {
+            let expected: String = std::iter::once("form_type,value\n".to_string())
+                .chain((0..5).map(|row| format!("{name},{row}\n")))
+                .collect();
+            let written = fs::read_to_string(dir.path().join(format!("6009/{name}.csv")))?;
+            assert_eq!(written, expected, "{name}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_close_file_and_write_more() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let closed = Arc::new(Mutex::new(Vec::new()));
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6070")
+            .max_rows_per_file(Some(3))
+            .on_file_close({
+                let closed = Arc::clone(&closed);
+                move |filename: &str, _: &str, bytes: u64| -> Result<()> {
+                    closed.lock().unwrap().push((filename.to_string(), bytes));
+                    Ok(())
+                }
+            })
+            .build()?;
+        let columns = vec!["form_type".to_string(), "value".to_string()];
+        let write = |ctx: &mut WriterContext, name: &str, rows: std::ops::Range<usize>| {
+            for row in rows {
+                ctx.write_header_once(name, &columns)?;
+                ctx.write_csv_record(name, &[name.to_string(), row.to_string()])?;
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+
+        // SA is closed halfway through its second part, and SB left open
+        write(&mut ctx, "SA", 0..4)?;
+        write(&mut ctx, "SB", 0..2)?;
+        assert_eq!(
+            ctx.open_files(),
+            [
+                ("SA".to_string(), "csv".to_string()),
+                ("SB".to_string(), "csv".to_string())
+            ]
+        );
+        ctx.close_file("SA", ".csv")?;
+        assert_eq!(ctx.open_files(), [("SB".to_string(), "csv".to_string())]);
+        assert!(ctx.has_file("SA", "csv"));
+        assert_eq!(ctx.buffer_capacity("SA", "csv"), None);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("6070/SA_002.csv"))?,
+            "form_type,value\nSA,3\n"
+        );
+        assert_eq!(*closed.lock().unwrap(), [("SA".to_string(), 52)]);
+        // Closing a file that isn't open does nothing
+        ctx.close_file("SA", "csv")?;
+        ctx.close_file("SE", "csv")?;
+
+        // Writing again reopens it where it left off, with its stats carrying on
+        write(&mut ctx, "SA", 4..7)?;
+        write(&mut ctx, "SB", 2..3)?;
+        let report = ctx.close()?;
+        report.check()?;
+        let expected = [
+            ("6070/SA.csv", "form_type,value\nSA,0\nSA,1\nSA,2\n"),
+            ("6070/SA_002.csv", "form_type,value\nSA,3\nSA,4\nSA,5\n"),
+            ("6070/SA_003.csv", "form_type,value\nSA,6\n"),
+            ("6070/SB.csv", "form_type,value\nSB,0\nSB,1\nSB,2\n"),
+        ];
+        for (path, contents) in expected {
+            assert_eq!(
+                fs::read_to_string(dir.path().join(path))?,
+                contents,
+                "{path}"
+            );
+        }
+        let rows: Vec<_> = report
+            .closed
+            .iter()
+            .map(|(path, stats)| (path.as_str(), stats.rows))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("6070/SA.csv", 3),
+                ("6070/SA_002.csv", 3),
+                ("6070/SA_003.csv", 1),
+                ("6070/SB.csv", 3)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_rejects_incompatible_settings() -> Result<()> {
+        // Nothing on disk, no callback and no print target: every row would be lost
+        let err = WriterContext::builder()
+            .write_to_disk(false)
+            .build()
+            .err()
+            .expect("a silent writer is rejected");
+        assert!(err.to_string().contains("discard every row"), "{err}");
+        WriterContext::builder()
+            .write_to_disk(false)
+            .discard_output()
+            .build()?;
+
+        let err = WriterContext::builder()
+            .compression(Compression::Gzip)
+            .compression_level(Some(12))
+            .build()
+            .err()
+            .expect("an out-of-range level is rejected");
+        assert!(err
+            .to_string()
+            .contains("Invalid gzip compression level 12"));
+
+        // Defaults are the command line's
+        let writer = WriterContext::builder().filing_id("6010").build()?;
+        assert_eq!(writer.output_directory, "output");
+        assert!(writer.write_to_disk);
+        assert_eq!(writer.buffer_size, 4096);
+        assert_eq!(writer.row_extension(), ".csv");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_writer_keeps_rows_whole_across_threads() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output_directory = dir.path().to_string_lossy().into_owned();
+        let writer = SyncWriter::new(move || {
+            WriterContext::builder()
+                .output_directory(output_directory.clone())
+                .filing_id("6011")
+                .buffer_size(64)
+                .build()
+        });
+        let forms = ["SA", "SB", "SC", "SE"];
+        let columns = vec![
+            "form_type".to_string(),
+            "thread".to_string(),
+            "row".to_string(),
+        ];
+        let padding = "x".repeat(40);
+
+        // 8 threads write to 4 files through small buffers, flushing every file now and then
+        std::thread::scope(|scope| -> Result<()> {
+            let threads: Vec<_> = (0..8)
+                .map(|thread| {
+                    let (writer, columns, padding) = (&writer, &columns, &padding);
+                    scope.spawn(move || -> Result<()> {
+                        for row in 0..200 {
+                            let form = forms[(thread + row) % forms.len()];
+                            writer.write_header_once(form, columns)?;
+                            let fields = [
+                                form.to_string(),
+                                thread.to_string(),
+                                format!("{row}{padding}"),
+                            ];
+                            writer.write_csv_record(form, &fields)?;
+                            if row % 50 == 0 {
+                                writer.flush_all()?;
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .try_for_each(|thread| thread.join().expect("writer thread panicked"))
+        })?;
+        writer.flush_all()?;
+
+        let mut total = 0;
+        for form in forms {
+            let written = fs::read_to_string(dir.path().join(format!("6011/{form}.csv")))?;
+            let mut lines = written.lines();
+            assert_eq!(lines.next(), Some("form_type,thread,row"));
+            for line in lines {
+                let fields: Vec<&str> = line.split(',').collect();
+                assert_eq!(fields.len(), 3, "{line}");
+                assert_eq!(fields[0], form);
+                assert!(fields[1].parse::<usize>()? < 8);
+                assert!(fields[2].ends_with(padding.as_str()), "{line}");
+                total += 1;
+            }
+        }
+        assert_eq!(total, 8 * 200);
+        let stats = writer.file_stats()?;
+        assert_eq!(stats.iter().map(|(_, s)| s.rows).sum::<usize>(), 8 * 200);
+        Ok(())
+    }
+
+    /// Parse the multi-form fixture into `dir` with `compression` and a tiny buffer, so every
+    /// file is flushed many times.
+    fn parse_multi_form(
+        dir: &Path,
+        compression: Compression,
+        write_fn: Option<Box<fast_fec_rust::writer::CustomWriteFn>>,
+    ) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("6001".to_string(), false, true, false);
+        let mut builder = WriterContext::builder()
+            .output_directory(dir.to_string_lossy())
+            .filing_id("6001")
+            .buffer_size(16)
+            .compression(compression);
+        if let Some(write_fn) = write_fn {
+            builder = builder.custom_write_fn(write_fn);
+        }
+        let mut writer = builder.build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()
+    }
+
+    /// Parse the multi-form fixture (filing 6001) with nothing written to disk, collecting the
+    /// output in memory instead.
+    fn parse_multi_form_in_memory(max: Option<u64>) -> Result<HashMap<(String, String), Vec<u8>>> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/multi_form_8.3.fec");
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("6001".to_string(), false, true, false);
+        let mut writer = WriterContext::builder()
+            .filing_id("6001")
+            .write_to_disk(false)
+            .buffer_size(16)
+            .collect_outputs(true)
+            .max_collected_bytes(max)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.into_outputs()
+    }
+
+    #[test]
+    fn test_outputs_collected_in_memory_match_disk() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_multi_form(dir.path(), Compression::None, None)?;
+        let outputs = parse_multi_form_in_memory(None)?;
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir.path().join("6001"))? {
+            files.push(entry?.path());
+        }
+        assert!(files.len() > 1);
+        assert_eq!(outputs.len(), files.len());
+        for path in files {
+            let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let extension = path.extension().unwrap().to_string_lossy().into_owned();
+            assert_eq!(outputs[&(stem, extension)], fs::read(&path)?, "{path:?}");
+        }
+        assert!(!outputs[&("SA".to_string(), "csv".to_string())].is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_collected_outputs_limited() -> Result<()> {
+        let err = parse_multi_form_in_memory(Some(100)).unwrap_err();
+        match err.downcast_ref::<FecError>() {
+            Some(FecError::OutputLimitExceeded { limit: 100 }) => {}
+            _ => panic!("expected OutputLimitExceeded, got {err:#}"),
+        }
+
+        // A writer that neither writes to disk nor collects anything is refused
+        let err = WriterContext::builder()
+            .write_to_disk(false)
+            .collect_outputs(false)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("discard every row"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_output_matches_uncompressed() -> Result<()> {
+        let plain = tempfile::tempdir()?;
+        parse_multi_form(plain.path(), Compression::None, None)?;
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let write_fn = {
+            let written = Arc::clone(&written);
+            move |filename: &str, _: &str, contents: &[u8]| -> Result<()> {
+                if filename == "SA" {
+                    written.lock().unwrap().extend_from_slice(contents);
+                }
+                Ok(())
+            }
+        };
+        let gzip = tempfile::tempdir()?;
+        parse_multi_form(gzip.path(), Compression::Gzip, Some(Box::new(write_fn)))?;
+
+        let mut names = 0;
+        for entry in fs::read_dir(plain.path().join("6001"))? {
+            let name = entry?.file_name().into_string().unwrap();
+            let expected = fs::read(plain.path().join("6001").join(&name))?;
+            assert!(!gzip.path().join("6001").join(&name).exists());
+            let compressed = fs::File::open(gzip.path().join("6001").join(format!("{name}.gz")))?;
+            let mut decompressed = Vec::new();
+            GzDecoder::new(compressed).read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, expected, "{name}");
+            names += 1;
+        }
+        assert!(names > 2);
+
+        // The write callback still sees the uncompressed rows
+        assert_eq!(
+            *written.lock().unwrap(),
+            fs::read(plain.path().join("6001/SA.csv"))?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_gzip_stream_finished_once_on_close() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut ctx = WriterContext::builder()
+            .output_directory(dir.path().to_string_lossy())
+            .filing_id("6002")
+            .buffer_size(4)
+            .compression(Compression::Gzip)
+            .max_open_files(1)
+            .build()?;
+        let row = |value: &str| vec!["SA11AI".to_string(), value.to_string()];
+        let read = |form: &str| -> Result<String> {
+            let file = fs::File::open(dir.path().join(format!("6002/{form}.csv.gz")))?;
+            let mut out = String::new();
+            GzDecoder::new(file).read_to_string(&mut out)?;
+            Ok(out)
+        };
+
+        // Neither flushing nor opening another file past `max_open_files` finishes the stream,
+        // so a reader of the first gzip member only still gets every row
+        ctx.write_csv_record("SA", &row("first"))?;
+        ctx.flush_all()?;
+        ctx.write_csv_record("SB", &row("second"))?;
+        ctx.flush_all()?;
+        ctx.write_csv_record("SA", &row("third"))?;
+        ctx.close()?.check()?;
+        assert_eq!(read("SA")?, "SA11AI,first\nSA11AI,third\n");
+        assert_eq!(read("SB")?, "SA11AI,second\n");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_output_matches_uncompressed() -> Result<()> {
+        let plain = tempfile::tempdir()?;
+        parse_multi_form(plain.path(), Compression::None, None)?;
+        let zstd = tempfile::tempdir()?;
+        parse_multi_form(zstd.path(), Compression::Zstd, None)?;
+
+        // Every file is a complete zstd stream, finished as the writer was dropped
+        let mut expected_names = Vec::new();
+        for entry in fs::read_dir(plain.path().join("6001"))? {
+            let name = entry?.file_name().into_string().unwrap();
+            let expected = fs::read(plain.path().join("6001").join(&name))?;
+            let compressed = fs::read(zstd.path().join("6001").join(format!("{name}.zst")))?;
+            assert_eq!(zstd::decode_all(&compressed[..])?, expected, "{name}");
+            expected_names.push(format!("{name}.zst"));
+        }
+
+        // Forms the filing has no rows of get no file at all, not an empty frame
+        let mut names: Vec<String> = fs::read_dir(zstd.path().join("6001"))?
+            .map(|entry| entry.map(|e| e.file_name().into_string().unwrap()))
+            .collect::<Result<_, _>>()?;
+        names.sort();
+        expected_names.sort();
+        assert_eq!(names, expected_names);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_level_and_no_empty_frames() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let writer = || {
+            WriterContext::builder()
+                .output_directory(dir.path().to_string_lossy())
+                .filing_id("6003")
+                .compression(Compression::Zstd)
+                .compression_level(Some(19))
+                .output_mode(OutputMode::Append)
+                .build()
+        };
+        let columns = vec!["form_type".to_string(), "value".to_string()];
+        let path = dir.path().join("6003/SA.csv.zst");
+
+        let mut ctx = writer()?;
+        ctx.write_header_once("SA", &columns)?;
+        ctx.write_csv_record("SA", &["SA11AI".to_string(), "1".to_string()])?;
+        drop(ctx);
+        let written = fs::read(&path)?;
+        assert_eq!(
+            zstd::decode_all(&written[..])?,
+            b"form_type,value\nSA11AI,1\n"
+        );
+
+        // A later run that finds the header already there and writes nothing adds nothing
+        let mut ctx = writer()?;
+        assert!(!ctx.write_header_once("SA", &columns)?);
+        ctx.flush_all()?;
+        drop(ctx);
+        assert_eq!(fs::read(&path)?, written);
+
+        Ok(())
+    }
+
+    /// Parse the F99 text fixture, with an awkward contributor name, to NDJSON in `dir`.
+    fn parse_ndjson(dir: &Path, filing_id: &str, max_f99_text: usize) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/f99_text_8.3.fec");
+        let input = fs::read_to_string(path)?.replace("SMITH", "O\"BRIEN, ZOË");
+        let mut ctx = FecContext::new(filing_id.to_string(), true, true, false)
+            .with_max_f99_text(max_f99_text);
+        let mut writer = WriterContext::builder()
+            .output_directory(dir.to_string_lossy())
+            .filing_id(filing_id)
+            .buffer_size(64)
+            .format(OutputFormat::Ndjson)
+            .build()?;
+        parse_fec(&mut ctx, &mut BufReader::new(input.as_bytes()), &mut writer)?;
+        writer.flush_all()
+    }
+
+    /// Read the objects of an NDJSON file.
+    fn read_ndjson(path: &Path) -> Result<Vec<serde_json::Map<String, serde_json::Value>>> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(|line| Ok(serde_json::from_str(line)?))
+            .collect()
+    }
+
+    #[test]
+    fn test_ndjson_rows_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        parse_ndjson(dir.path(), "6004", usize::MAX)?;
+        let filing_dir = dir.path().join("6004");
+        assert!(!filing_dir.join("SA.csv").exists());
+
+        let sa = read_ndjson(&filing_dir.join("SA.ndjson"))?;
+        assert_eq!(sa.len(), 1);
+        assert_eq!(sa[0]["filing_id"], "6004");
+        assert_eq!(sa[0]["form_type"], "SA11AI");
+        assert_eq!(sa[0]["contributor_last_name"], "O\"BRIEN, ZOË");
+        assert_eq!(sa[0]["contribution_amount"], "250.00");
+        // Members keep the column order
+        let line = fs::read_to_string(filing_dir.join("SA.ndjson"))?;
+        assert!(line.starts_with(
+            "{\"filing_id\":\"6004\",\"is_amendment\":\"false\",\"original_filing_id\":\"\",\"form_type\":\"SA11AI\","
+        ));
+
+        let text = "This letter responds to \"your\" inquiry, dated March 1.\n\
+                    Second line, with commas, and \"quotes\".";
+        let f99 = read_ndjson(&filing_dir.join("F99.ndjson"))?;
+        assert_eq!(f99.len(), 1);
+        assert_eq!(f99[0]["text"], text);
+
+        // Text blocks streamed in pieces come out the same
+        parse_ndjson(dir.path(), "6005", 8)?;
+        let streamed = read_ndjson(&dir.path().join("6005/F99.ndjson"))?;
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed[0]["text"], text);
+        assert_eq!(streamed[0].len(), f99[0].len());
+
+        Ok(())
+    }
+
+    /// Parse `fixture` into the SQLite database at `db`, with filing_id and source_line columns.
+    #[cfg(feature = "sqlite")]
+    fn parse_sqlite(fixture: &str, db: &Path, max_f99_text: usize) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(fixture);
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut ctx = FecContext::new("6006".to_string(), true, true, false)
+            .with_row_numbers(true)
+            .with_max_f99_text(max_f99_text);
+        let dir = db.parent().unwrap().to_string_lossy().into_owned();
+        let mut writer = WriterContext::builder()
+            .output_directory(dir)
+            .filing_id("6006")
+            .buffer_size(16)
+            .format(OutputFormat::Sqlite)
+            .database(db)
+            .build()?;
+        parse_fec(&mut ctx, &mut reader, &mut writer)?;
+        writer.flush_all()
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_tables_hold_each_forms_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("filings.db");
+        parse_sqlite("multi_form_8.3.fec", &db, usize::MAX)?;
+        // Rows go to the database, not to per-form files
+        assert!(!dir.path().join("6006/SA.csv").exists());
+
+        let conn = rusqlite::Connection::open(&db)?;
+        let count = |table: &str| -> rusqlite::Result<i64> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM \"{table}\""), [], |row| {
+                row.get(0)
+            })
+        };
+        for (table, rows) in [("header", 1), ("F3XN", 1), ("SA", 2), ("SB", 1), ("SE", 1)] {
+            assert_eq!(count(table)?, rows, "{table}");
+        }
+
+        let (filing_id, source_line, name, amount): (String, String, String, f64) = conn
+            .query_row(
+                "SELECT filing_id, source_line, contributor_last_name, contribution_amount \
+             FROM SA ORDER BY source_line DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+        assert_eq!(
+            (filing_id.as_str(), source_line.as_str(), name.as_str()),
+            ("6006", "4", "DOE")
+        );
+        assert_eq!(amount, 100.0);
+        let kinds: Vec<(String, String)> = conn
+            .prepare("SELECT name, type FROM pragma_table_info('SA')")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        assert!(kinds.contains(&("contribution_date".to_string(), "DATE".to_string())));
+        assert!(kinds.contains(&("contributor_city".to_string(), "TEXT".to_string())));
+
+        // A second run appends to the same tables
+        parse_sqlite("multi_form_8.3.fec", &db, usize::MAX)?;
+        assert_eq!(count("SA")?, 4);
+
+        // Text blocks streamed in pieces are stored whole
+        let f99 = dir.path().join("f99.db");
+        parse_sqlite("f99_text_8.3.fec", &f99, 8)?;
+        let text: String =
+            rusqlite::Connection::open(&f99)?
+                .query_row("SELECT text FROM F99", [], |row| row.get(0))?;
+        assert!(text.starts_with("This letter responds to \"your\" inquiry"));
+        assert!(text.ends_with("and \"quotes\"."));
+
+        Ok(())
+    }
 }